@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::{DnsPacket, EDNS_PAYLOAD_SIZE, QueryType, is_answer_to, query, random_id};
+
+// how long to wait for an answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// the queries in flight by id, and who is waiting for the answer to each
+type Pending = Arc<Mutex<HashMap<u16, (DnsPacket, oneshot::Sender<DnsPacket>)>>>;
+
+// Asks `server` over a single UDP socket, with as many queries in flight at
+// once as there are ids to tell them apart by. Must be created and used
+// within a tokio runtime.
+#[derive(Debug)]
+pub struct AsyncResolver {
+    server: SocketAddr,
+    socket: Arc<UdpSocket>,
+    pending: Pending,
+    // hands the answers out to whoever is waiting for them
+    receiver: JoinHandle<()>,
+}
+
+// Forgets about a query once its answer is in, or nobody waits for it any
+// more.
+struct Waiting<'a> {
+    id: u16,
+    pending: &'a Pending,
+}
+
+impl AsyncResolver {
+    pub async fn new(server: SocketAddr) -> io::Result<Self> {
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = Arc::new(UdpSocket::bind(local).await?);
+        let pending = Pending::default();
+        let receiver = tokio::spawn(receive(socket.clone(), server, pending.clone()));
+        Ok(Self {
+            server,
+            socket,
+            pending,
+            receiver,
+        })
+    }
+
+    pub async fn query(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        let mut query = query(name, qtype)?;
+        let (tx, rx) = oneshot::channel();
+        let waiting = self.wait(&mut query, tx)?;
+        let req_buf = query.to_vec()?;
+
+        self.socket.send_to(&req_buf, self.server).await?;
+        let resp = time::timeout(TIMEOUT, rx)
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+            .map_err(|_| io::Error::other("stopped receiving answers"))?;
+        drop(waiting);
+
+        if resp.header.tc {
+            return time::timeout(TIMEOUT, self.query_tcp(&query, &req_buf))
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?;
+        }
+        Ok(resp)
+    }
+
+    // Gives `query` an id no other query in flight has, picked at random so
+    // that answers can't be forged by guessing it.
+    fn wait(
+        &self,
+        query: &mut DnsPacket,
+        tx: oneshot::Sender<DnsPacket>,
+    ) -> io::Result<Waiting<'_>> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() > u16::MAX as usize {
+            return Err(io::Error::other("too many queries in flight"));
+        }
+        let mut id = random_id();
+        while pending.contains_key(&id) {
+            id = random_id();
+        }
+        query.header.id = id;
+        pending.insert(id, (query.clone(), tx));
+        Ok(Waiting {
+            id,
+            pending: &self.pending,
+        })
+    }
+
+    // For an answer that didn't fit in a datagram.
+    async fn query_tcp(&self, query: &DnsPacket, req_buf: &[u8]) -> io::Result<DnsPacket> {
+        let mut stream = TcpStream::connect(self.server).await?;
+        let mut framed = (req_buf.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(req_buf);
+        stream.write_all(&framed).await?;
+
+        let len = stream.read_u16().await?;
+        let mut resp = vec![0u8; len as usize];
+        stream.read_exact(&mut resp).await?;
+        let resp = DnsPacket::from_bytes(&resp)?;
+        if !is_answer_to(query, &resp) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "answer to some other query",
+            ));
+        }
+        Ok(resp)
+    }
+}
+
+async fn receive(socket: Arc<UdpSocket>, server: SocketAddr, pending: Pending) {
+    let mut buf = [0u8; EDNS_PAYLOAD_SIZE];
+    loop {
+        let (len, src_addr) = match socket.recv_from(&mut buf).await {
+            Ok(recv) => recv,
+            Err(e) => {
+                eprintln!("Could not receive an answer: {e}");
+                continue;
+            }
+        };
+        // anyone else could be trying to slip us an answer
+        if src_addr != server {
+            continue;
+        }
+        let Ok(resp) = DnsPacket::from_bytes(&buf[..len]) else {
+            continue;
+        };
+        // nor is the id alone enough to go by
+        let mut pending = pending.lock().unwrap();
+        if pending
+            .get(&resp.header.id)
+            .is_some_and(|(req, _)| is_answer_to(req, &resp))
+        {
+            let (_, tx) = pending.remove(&resp.header.id).unwrap();
+            // it may have stopped waiting just now
+            let _ = tx.send(resp);
+        }
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Drop for AsyncResolver {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{DnsRecord, PACKET_SIZE, RData};
+
+    #[tokio::test]
+    async fn answers_find_their_queries() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let resolver = AsyncResolver::new(server.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let names = ["a.example.com", "b.example.com", "c.example.com"];
+        let answering = async {
+            let mut queries = vec![];
+            for _ in names {
+                let mut buf = [0u8; PACKET_SIZE];
+                let (len, src_addr) = server.recv_from(&mut buf).await.unwrap();
+                queries.push((DnsPacket::from_bytes(&buf[..len]).unwrap(), src_addr));
+            }
+            // the other way round, after one with the id of the first and
+            // the question of another, which is passed over
+            let (first, _) = &queries[0];
+            let mut forged = DnsPacket::new_empty();
+            forged.header.id = first.header.id;
+            forged.header.qr = true;
+            forged.questions = queries[1].0.questions.clone();
+            let forged = forged.to_vec().unwrap();
+            server.send_to(&forged, queries[0].1).await.unwrap();
+            for (req, src_addr) in queries.into_iter().rev() {
+                let mut resp = DnsPacket::new_empty();
+                resp.header.id = req.header.id;
+                resp.header.qr = true;
+                resp.questions = req.questions.clone();
+                resp.header.ancount = 1;
+                resp.answers.push(DnsRecord {
+                    domain: req.questions[0].name.clone(),
+                    r#type: QueryType::A,
+                    class: 1,
+                    ttl: 60,
+                    rdata: RData::A {
+                        ip: Ipv4Addr::LOCALHOST,
+                    },
+                });
+                let resp = resp.to_vec().unwrap();
+                server.send_to(&resp, src_addr).await.unwrap();
+            }
+        };
+
+        let ((), a, b, c) = tokio::join!(
+            answering,
+            resolver.query(names[0], QueryType::A),
+            resolver.query(names[1], QueryType::A),
+            resolver.query(names[2], QueryType::A),
+        );
+        for (resp, name) in [a, b, c].into_iter().zip(names) {
+            assert_eq!(resp.unwrap().answers[0].domain, name);
+        }
+        assert!(resolver.pending.lock().unwrap().is_empty());
+    }
+}
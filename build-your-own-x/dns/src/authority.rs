@@ -0,0 +1,779 @@
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::transfer::is_newer;
+use crate::{
+    Change, DnsName, DnsPacket, DnsQuestion, DnsRecord, Network, Prerequisite, QueryType, RCode,
+    RData, Resolver, Update, Zone, axfr, ixfr, notify,
+};
+
+// how many CNAMEs within a zone to follow before giving up on it
+const MAX_CNAMES: usize = 8;
+
+// Answers for its zones from their records, authoritatively, and asks
+// `fallback` about every other name.
+#[derive(Debug)]
+pub struct Authority<R> {
+    // replaced as a whole whenever one changes
+    zones: RwLock<Vec<Zone>>,
+    fallback: R,
+    // who may update its zones
+    allow_update: Vec<Network>,
+    // the secondaries to tell whenever a zone changes
+    secondaries: Vec<SocketAddr>,
+    // where the zones it's a secondary for come from
+    primaries: Vec<(DnsName, SocketAddr)>,
+    // the origins of those whose primary has said they changed, until
+    // they're refreshed
+    notified: (Mutex<HashSet<DnsName>>, Condvar),
+}
+
+impl<R: Resolver> Authority<R> {
+    pub fn new(zones: Vec<Zone>, fallback: R) -> io::Result<Self> {
+        if let Some(zone) = zones.iter().find(|zone| zone.soa().is_none()) {
+            return Err(no_soa(zone));
+        }
+        Ok(Self {
+            zones: RwLock::new(zones),
+            fallback,
+            allow_update: vec![],
+            secondaries: vec![],
+            primaries: vec![],
+            notified: Default::default(),
+        })
+    }
+
+    // Takes updates to its zones from clients in `networks`, and refuses
+    // them from everyone else.
+    pub fn allow_updates(mut self, networks: Vec<Network>) -> Self {
+        self.allow_update = networks;
+        self
+    }
+
+    // Sends NOTIFY to each of `secondaries` for every change to its zones.
+    pub fn notify_secondaries(mut self, secondaries: Vec<SocketAddr>) -> Self {
+        self.secondaries = secondaries;
+        self
+    }
+
+    // Takes NOTIFY for each zone from its primary, as one of its secondaries,
+    // and ignores it from anyone else.
+    pub fn with_primaries(mut self, primaries: Vec<(DnsName, SocketAddr)>) -> Self {
+        self.primaries = primaries;
+        self
+    }
+
+    pub fn zone(&self, origin: &str) -> Option<Zone> {
+        let zones = self.zones.read().unwrap();
+        zones.iter().find(|zone| *zone.origin() == origin).cloned()
+    }
+
+    // Answers for `zone` from now on, in place of what was there for its
+    // origin before.
+    pub fn set_zone(&self, zone: Zone) -> io::Result<()> {
+        if zone.soa().is_none() {
+            return Err(no_soa(&zone));
+        }
+        let mut zones = self.zones.write().unwrap();
+        zones.retain(|z| z.origin() != zone.origin());
+        self.changed(&zone);
+        zones.push(zone);
+        Ok(())
+    }
+
+    // Lets its secondaries know about the zone as it is now, in the
+    // background, as they may take a while to answer or not at all.
+    fn changed(&self, zone: &Zone) {
+        for &secondary in &self.secondaries {
+            let zone = zone.clone();
+            thread::spawn(move || {
+                if let Err(e) = notify(&zone, secondary) {
+                    eprintln!(
+                        "Could not notify {secondary} of zone {}: {e}",
+                        zone.origin()
+                    );
+                }
+            });
+        }
+    }
+
+    // Waits until the primary of the zone at `origin` says it changed, or
+    // `timeout` runs out first. Returns whether it did.
+    pub fn wait_for_change(&self, origin: &DnsName, timeout: Duration) -> bool {
+        let (notified, cond) = &self.notified;
+        let notified = notified.lock().unwrap();
+        let (mut notified, _) = cond
+            .wait_timeout_while(notified, timeout, |notified| !notified.contains(origin))
+            .unwrap();
+        notified.remove(origin)
+    }
+
+    // Brings the zone at `origin` up to date with `primary`, as its secondary,
+    // pulling the whole of it when there's none yet. Returns how long its SOA
+    // says to wait before checking again: the refresh interval, or the retry
+    // interval when checking failed.
+    pub fn refresh(&self, origin: &DnsName, primary: SocketAddr) -> Duration {
+        let current = self.zone(origin);
+        let newer = match &current {
+            Some(zone) => ixfr(zone, primary),
+            None => axfr(origin, primary).map(Some),
+        };
+        let result = newer.and_then(|newer| match newer {
+            Some(zone) => {
+                println!("Zone {origin} is now at serial {:?}", zone.serial());
+                self.set_zone(zone)
+            }
+            None => Ok(()),
+        });
+
+        let timers = self.zone(origin).and_then(|zone| match zone.soa()?.rdata {
+            RData::SOA { refresh, retry, .. } => Some((refresh, retry)),
+            _ => None,
+        });
+        // for a zone it has never been able to get
+        let (refresh, retry) = timers.unwrap_or((60, 60));
+        match result {
+            Ok(()) => Duration::from_secs(refresh.into()),
+            Err(e) => {
+                eprintln!("Could not refresh zone {origin} from {primary}: {e}");
+                Duration::from_secs(retry.into())
+            }
+        }
+    }
+}
+
+fn no_soa(zone: &Zone) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("zone {:?} has no SOA record at its apex", zone.origin()),
+    )
+}
+
+impl<R: Resolver> Resolver for Authority<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let answered = {
+            let zones = self.zones.read().unwrap();
+            // the zone closest to the name, if any is for it
+            zones
+                .iter()
+                .filter(|zone| question.name.is_within(zone.origin()))
+                .max_by_key(|zone| zone.origin().labels().count())
+                .map(|zone| answer(zone, question))
+        };
+        match answered {
+            Some(resp) => Ok(resp),
+            None => self.fallback.resolve(question),
+        }
+    }
+
+    fn update(&self, update: &Update, src: IpAddr) -> RCode {
+        let mut zones = self.zones.write().unwrap();
+        let Some(zone) = zones.iter_mut().find(|zone| *zone.origin() == update.zone) else {
+            return RCode::Notauth;
+        };
+        if !self.allow_update.iter().any(|net| net.contains(src)) {
+            return RCode::Refused;
+        }
+        match updated(zone, update) {
+            Ok(Some(new)) => {
+                println!("Zone {} is now at serial {:?}", new.origin(), new.serial());
+                self.changed(&new);
+                *zone = new;
+                RCode::Noerror
+            }
+            Ok(None) => RCode::Noerror,
+            Err(rcode) => rcode,
+        }
+    }
+
+    fn notify(&self, zone: &DnsName, src: IpAddr) -> RCode {
+        let Some((_, primary)) = self.primaries.iter().find(|(origin, _)| origin == zone) else {
+            return RCode::Notauth;
+        };
+        if primary.ip().to_canonical() != src.to_canonical() {
+            return RCode::Refused;
+        }
+        let (notified, cond) = &self.notified;
+        notified.lock().unwrap().insert(zone.clone());
+        cond.notify_all();
+        RCode::Noerror
+    }
+
+    // to the secondaries it sends NOTIFY to, and no one else
+    fn transfer(&self, zone: &DnsName, src: IpAddr) -> Result<Zone, RCode> {
+        let zone = self.zone(zone).ok_or(RCode::Notauth)?;
+        let src = src.to_canonical();
+        if !self
+            .secondaries
+            .iter()
+            .any(|s| s.ip().to_canonical() == src)
+        {
+            return Err(RCode::Refused);
+        }
+        Ok(zone)
+    }
+}
+
+// `zone` with the changes in `update` made to it, with a serial to show for
+// them, or None when there was nothing to change. Fails with the rcode to
+// answer with when the prerequisites don't hold.
+fn updated(zone: &Zone, update: &Update) -> Result<Option<Zone>, RCode> {
+    let names = update.prerequisites.iter().map(|p| match p {
+        Prerequisite::NameInUse(name)
+        | Prerequisite::NameNotInUse(name)
+        | Prerequisite::RRsetExists(name, _)
+        | Prerequisite::RRsetDoesNotExist(name, _) => name,
+        Prerequisite::RRsetHas(rec) => &rec.domain,
+    });
+    let changed = update.changes.iter().map(|c| match c {
+        Change::DeleteRRset(name, _) | Change::DeleteName(name) => name,
+        Change::Add(rec) | Change::Delete(rec) => &rec.domain,
+    });
+    if !names
+        .chain(changed)
+        .all(|name| name.is_within(zone.origin()))
+    {
+        return Err(RCode::Notzone);
+    }
+
+    check(zone, &update.prerequisites)?;
+
+    let apex = zone.origin();
+    let is_apex = |name: &DnsName| name == apex;
+    // what the zone can't be without
+    let keep =
+        |r: &DnsRecord| is_apex(&r.domain) && matches!(r.r#type, QueryType::SOA | QueryType::NS);
+    let mut records = zone.records().to_vec();
+    for change in &update.changes {
+        match change {
+            Change::Add(rec) if rec.r#type == QueryType::SOA => {
+                let newer = match (&rec.rdata, zone.serial()) {
+                    (RData::SOA { serial, .. }, Some(current)) => is_newer(*serial, current),
+                    _ => false,
+                };
+                if is_apex(&rec.domain) && newer {
+                    records.retain(|r| !(keep(r) && r.r#type == QueryType::SOA));
+                    records.push(rec.clone());
+                }
+            }
+            Change::Add(rec) => {
+                // a CNAME can't share its name with records of other types
+                let clashes = records.iter().any(|r| {
+                    r.domain == rec.domain
+                        && (r.r#type == QueryType::CNAME) != (rec.r#type == QueryType::CNAME)
+                });
+                if !clashes {
+                    records.retain(|r| !r.is_same(rec));
+                    records.push(rec.clone());
+                }
+            }
+            Change::DeleteRRset(name, r#type) => {
+                records.retain(|r| keep(r) || !(r.domain == *name && r.r#type == *r#type))
+            }
+            Change::DeleteName(name) => records.retain(|r| keep(r) || r.domain != *name),
+            Change::Delete(rec) => {
+                let last_ns = keep(rec)
+                    && records
+                        .iter()
+                        .filter(|r| keep(r) && r.r#type == QueryType::NS)
+                        .count()
+                        == 1;
+                if rec.r#type != QueryType::SOA && !last_ns {
+                    records.retain(|r| !r.is_same(rec));
+                }
+            }
+        }
+    }
+    if records == zone.records() {
+        return Ok(None);
+    }
+
+    // a serial of its own, unless the update came with one
+    let mut updated = Zone::new(apex.clone(), records);
+    if updated.serial() == zone.serial() {
+        let mut records = updated.records().to_vec();
+        for rec in records.iter_mut().filter(|r| keep(r)) {
+            if let RData::SOA { serial, .. } = &mut rec.rdata {
+                *serial = serial.wrapping_add(1);
+            }
+        }
+        updated = Zone::new(apex.clone(), records);
+    }
+    Ok(Some(updated))
+}
+
+// Whether the zone is as `prerequisites` require it to be, or the rcode to
+// answer with when not.
+fn check(zone: &Zone, prerequisites: &[Prerequisite]) -> Result<(), RCode> {
+    for prerequisite in prerequisites {
+        match prerequisite {
+            Prerequisite::NameInUse(name) if at(zone, name).next().is_none() => {
+                return Err(RCode::Nxdomain);
+            }
+            Prerequisite::NameNotInUse(name) if at(zone, name).next().is_some() => {
+                return Err(RCode::Yxdomain);
+            }
+            Prerequisite::RRsetExists(name, r#type)
+                if rrset(zone, name, *r#type).next().is_none() =>
+            {
+                return Err(RCode::Nxrrset);
+            }
+            Prerequisite::RRsetDoesNotExist(name, r#type)
+                if rrset(zone, name, *r#type).next().is_some() =>
+            {
+                return Err(RCode::Yxrrset);
+            }
+            Prerequisite::RRsetHas(rec) => {
+                // all of those required of the same RRset, against all of it
+                let required: Vec<&DnsRecord> = prerequisites
+                    .iter()
+                    .filter_map(|p| match p {
+                        Prerequisite::RRsetHas(r)
+                            if r.domain == rec.domain && r.r#type == rec.r#type =>
+                        {
+                            Some(r)
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                let present: Vec<&DnsRecord> = rrset(zone, &rec.domain, rec.r#type).collect();
+                let same = present
+                    .iter()
+                    .all(|p| required.iter().any(|r| r.is_same(p)))
+                    && required
+                        .iter()
+                        .all(|r| present.iter().any(|p| p.is_same(r)));
+                if !same {
+                    return Err(RCode::Nxrrset);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn answer(zone: &Zone, question: &DnsQuestion) -> DnsPacket {
+    let mut resp = DnsPacket::new_empty();
+    resp.header.aa = true;
+
+    let mut name = question.name.clone();
+    for _ in 0..MAX_CNAMES {
+        if let Some(cut) = delegation(zone, &name) {
+            refer(zone, cut, &mut resp);
+            break;
+        }
+
+        let Some(records) = records_at(zone, &name) else {
+            resp.header.rcode = RCode::Nxdomain;
+            add_soa(zone, &mut resp);
+            break;
+        };
+
+        let matching: Vec<DnsRecord> = records
+            .iter()
+            .filter(|r| r.r#type == question.r#type)
+            .cloned()
+            .collect();
+        if !matching.is_empty() {
+            resp.answers.extend(matching);
+            break;
+        }
+
+        let cname = records.iter().find_map(|r| match &r.rdata {
+            RData::CNAME { host } => Some((r, host)),
+            _ => None,
+        });
+        let Some((cname, host)) = cname else {
+            // the name is there, but without records of that type
+            add_soa(zone, &mut resp);
+            break;
+        };
+        resp.answers.push(cname.clone());
+        // where it leads is for the client to ask about elsewhere
+        if !host.is_within(zone.origin()) {
+            break;
+        }
+        name = host.clone();
+    }
+
+    resp.header.ancount = resp.answers.len() as u16;
+    resp.header.nscount = resp.authorities.len() as u16;
+    resp.header.arcount = resp.resources.len() as u16;
+    resp
+}
+
+// The records `name` has in `zone`, or as made up from the wildcard that
+// covers it when it doesn't exist itself (RFC 4592), or None when neither.
+fn records_at(zone: &Zone, name: &DnsName) -> Option<Vec<DnsRecord>> {
+    if exists(zone, name) {
+        return Some(at(zone, name).cloned().collect());
+    }
+
+    // the deepest of the names above it that does exist
+    let mut encloser = name.clone();
+    while !exists(zone, &encloser) {
+        encloser = encloser.parent()?;
+    }
+    let wildcard = match encloser.is_root() {
+        true => "*".to_string(),
+        false => format!("*.{encloser}"),
+    };
+    let wildcard = DnsName::new(&wildcard).ok()?;
+    let synthesised: Vec<DnsRecord> = at(zone, &wildcard)
+        .map(|r| DnsRecord {
+            domain: name.clone(),
+            ..r.clone()
+        })
+        .collect();
+    (!synthesised.is_empty()).then_some(synthesised)
+}
+
+// Names with nothing of their own but names below them still exist, just
+// without records of any type (RFC 8020).
+fn exists(zone: &Zone, name: &DnsName) -> bool {
+    zone.records().iter().any(|r| r.domain.is_within(name))
+}
+
+// The records `name` has in `zone`.
+fn at<'a>(zone: &'a Zone, name: &'a DnsName) -> impl Iterator<Item = &'a DnsRecord> + 'a {
+    zone.records().iter().filter(move |r| r.domain == *name)
+}
+
+// The records `name` has in `zone` of the given type.
+fn rrset<'a>(
+    zone: &'a Zone,
+    name: &'a DnsName,
+    r#type: QueryType,
+) -> impl Iterator<Item = &'a DnsRecord> + 'a {
+    at(zone, name).filter(move |r| r.r#type == r#type)
+}
+
+// The name below the apex that `name` has been delegated under, if any.
+fn delegation<'a>(zone: &'a Zone, name: &DnsName) -> Option<&'a DnsName> {
+    zone.records()
+        .iter()
+        .filter(|r| r.r#type == QueryType::NS)
+        .map(|r| &r.domain)
+        .filter(|cut| *cut != zone.origin() && name.is_within(cut))
+        .max_by_key(|cut| cut.labels().count())
+}
+
+// Points the client at the nameservers for `cut`, with their addresses where
+// the zone has them.
+fn refer(zone: &Zone, cut: &DnsName, resp: &mut DnsPacket) {
+    resp.header.aa = false;
+    for ns in at(zone, cut).filter(|r| r.r#type == QueryType::NS) {
+        resp.authorities.push(ns.clone());
+        let RData::NS { host } = &ns.rdata else {
+            continue;
+        };
+        let glue = at(zone, host).filter(|r| matches!(r.r#type, QueryType::A | QueryType::AAAA));
+        resp.resources.extend(glue.cloned());
+    }
+}
+
+// For the client to know how long to remember there's nothing (RFC 2308).
+fn add_soa(zone: &Zone, resp: &mut DnsPacket) {
+    let Some(soa) = zone.soa() else {
+        return;
+    };
+    let mut soa = soa.clone();
+    if let RData::SOA { minimum, .. } = soa.rdata {
+        soa.ttl = soa.ttl.min(minimum);
+    }
+    resp.authorities.push(soa);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{MockResolver, handle_connection, query};
+    use std::net::{IpAddr, Ipv4Addr, TcpListener};
+    use std::sync::Arc;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@           SOA  ns1 hostmaster 1 3600 900 604800 300
+@           NS   ns1
+ns1         A    192.0.2.1
+www         A    192.0.2.2
+alias       CNAME www
+away        CNAME www.example.net.
+a.b.c       A    192.0.2.3
+sub         NS   ns.sub
+ns.sub      A    192.0.2.53
+";
+
+    fn question(name: &str, qtype: QueryType) -> DnsQuestion {
+        query(name, qtype).unwrap().questions.remove(0)
+    }
+
+    fn authority() -> Authority<MockResolver> {
+        let zone = Zone::parse(ZONE, "").unwrap();
+        let mut elsewhere = DnsPacket::new_empty();
+        elsewhere.header.ancount = 1;
+        elsewhere.answers.push(DnsRecord {
+            domain: "example.net".parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 60,
+            rdata: RData::A {
+                ip: Ipv4Addr::new(198, 51, 100, 1),
+            },
+        });
+        let fallback = MockResolver::default().with_answer("example.net", QueryType::A, elsewhere);
+        Authority::new(vec![zone], fallback).unwrap()
+    }
+
+    #[test]
+    fn answers_for_its_zones() {
+        let authority = authority();
+        let resolve = |name, qtype| authority.resolve(&question(name, qtype)).unwrap();
+
+        let resp = resolve("WWW.example.com", QueryType::A);
+        assert!(resp.header.aa);
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert_eq!(
+            resp.answers[0].rdata,
+            RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, 2)
+            }
+        );
+
+        let resp = resolve("alias.example.com", QueryType::A);
+        let types: Vec<_> = resp.answers.iter().map(|r| r.r#type).collect();
+        assert_eq!(types, [QueryType::CNAME, QueryType::A]);
+        let resp = resolve("away.example.com", QueryType::A);
+        assert_eq!(resp.answers.len(), 1);
+
+        // no such name
+        let resp = resolve("nowhere.example.com", QueryType::A);
+        assert!(resp.header.aa);
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+        assert!(resp.answers.is_empty());
+        assert_eq!(resp.authorities[0].r#type, QueryType::SOA);
+        assert_eq!(resp.authorities[0].ttl, 300);
+
+        // no records of that type, or of any for a name between others
+        for (name, qtype) in [
+            ("www.example.com", QueryType::MX),
+            ("b.c.example.com", QueryType::A),
+        ] {
+            let resp = resolve(name, qtype);
+            assert_eq!(resp.header.rcode, RCode::Noerror);
+            assert!(resp.answers.is_empty());
+            assert_eq!(resp.authorities[0].r#type, QueryType::SOA);
+        }
+
+        // delegated
+        let resp = resolve("www.sub.example.com", QueryType::A);
+        assert!(!resp.header.aa);
+        assert!(resp.answers.is_empty());
+        assert_eq!(resp.authorities[0].r#type, QueryType::NS);
+        assert_eq!(resp.resources[0].domain, "ns.sub.example.com");
+
+        assert_eq!(authority.fallback.asked(), 0);
+        let resp = resolve("example.net", QueryType::A);
+        assert_eq!(resp.answers[0].domain, "example.net");
+        assert_eq!(authority.fallback.asked(), 1);
+    }
+
+    #[test]
+    fn wildcards_cover_names_that_dont_exist() {
+        let text = format!("{ZONE}*   A    192.0.2.99\n*.www TXT \"www\"\n");
+        let zone = Zone::parse(&text, "").unwrap();
+        let authority = Authority::new(vec![zone], MockResolver::default()).unwrap();
+        let resolve = |name, qtype| authority.resolve(&question(name, qtype)).unwrap();
+        let wildcard = RData::A {
+            ip: Ipv4Addr::new(192, 0, 2, 99),
+        };
+
+        for name in ["anything.example.com", "a.b.anything.example.com"] {
+            let resp = resolve(name, QueryType::A);
+            assert!(resp.header.aa);
+            assert_eq!(resp.answers[0].domain, name);
+            assert_eq!(resp.answers[0].rdata, wildcard);
+        }
+        // a wildcard of its own, closer than the one at the apex
+        let resp = resolve("x.www.example.com", QueryType::TXT);
+        assert_eq!(resp.answers[0].r#type, QueryType::TXT);
+        let resp = resolve("x.www.example.com", QueryType::A);
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert!(resp.answers.is_empty());
+
+        // names that exist, records or not, aren't covered
+        for name in ["www.example.com", "b.c.example.com"] {
+            let resp = resolve(name, QueryType::MX);
+            assert_eq!(resp.header.rcode, RCode::Noerror);
+            assert!(resp.answers.is_empty());
+        }
+        // nor are names below them, without a wildcard of their own
+        let resp = resolve("x.b.c.example.com", QueryType::A);
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+    }
+
+    #[test]
+    fn zones_need_an_soa() {
+        let zone = Zone::parse("$TTL 60\nwww A 192.0.2.1", "example.com").unwrap();
+        assert!(Authority::new(vec![zone.clone()], MockResolver::default()).is_err());
+        assert!(authority().set_zone(zone).is_err());
+    }
+
+    #[test]
+    fn zones_can_be_replaced() {
+        let authority = authority();
+        let resolve = |name, qtype| authority.resolve(&question(name, qtype)).unwrap();
+        assert!(resolve("new.example.com", QueryType::A).answers.is_empty());
+
+        let text = ZONE.replace("1 3600", "2 3600") + "new A 192.0.2.4\n";
+        authority.set_zone(Zone::parse(&text, "").unwrap()).unwrap();
+        assert_eq!(resolve("new.example.com", QueryType::A).answers.len(), 1);
+        assert_eq!(authority.zone("EXAMPLE.com").unwrap().serial(), Some(2));
+        assert_eq!(authority.zones.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn zones_can_be_updated() {
+        let authority = authority().allow_updates(vec!["192.0.2.0/24".parse().unwrap()]);
+        let client = IpAddr::from([192, 0, 2, 10]);
+        let resolve = |name, qtype| authority.resolve(&question(name, qtype)).unwrap();
+        let host = |name: &str, last| DnsRecord {
+            domain: name.parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 300,
+            rdata: RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, last),
+            },
+        };
+        let register = Update::new("example.com".parse().unwrap())
+            .require(Prerequisite::NameNotInUse(
+                "laptop.example.com".parse().unwrap(),
+            ))
+            .change(Change::Add(host("laptop.example.com", 100)));
+
+        assert_eq!(
+            authority.update(&register, IpAddr::from([198, 51, 100, 1])),
+            RCode::Refused
+        );
+        let mut elsewhere = register.clone();
+        elsewhere.zone = "example.org".parse().unwrap();
+        assert_eq!(authority.update(&elsewhere, client), RCode::Notauth);
+        let outside =
+            Update::new("example.com".parse().unwrap()).change(Change::Add(host("example.org", 1)));
+        assert_eq!(authority.update(&outside, client), RCode::Notzone);
+
+        assert_eq!(authority.update(&register, client), RCode::Noerror);
+        assert_eq!(resolve("laptop.example.com", QueryType::A).answers.len(), 1);
+        assert_eq!(authority.zone("example.com").unwrap().serial(), Some(2));
+        // only once
+        assert_eq!(authority.update(&register, client), RCode::Yxdomain);
+
+        // replaced, as long as it's still what it was
+        let moved = Update::new("example.com".parse().unwrap())
+            .require(Prerequisite::RRsetHas(host("laptop.example.com", 100)))
+            .change(Change::Delete(host("laptop.example.com", 100)))
+            .change(Change::Add(host("laptop.example.com", 101)));
+        assert_eq!(authority.update(&moved, client), RCode::Noerror);
+        assert_eq!(authority.update(&moved, client), RCode::Nxrrset);
+        let resp = resolve("laptop.example.com", QueryType::A);
+        assert_eq!(resp.answers, [host("laptop.example.com", 101)]);
+
+        // nothing goes when anything doesn't hold
+        let failing = Update::new("example.com".parse().unwrap())
+            .require(Prerequisite::RRsetExists(
+                "laptop.example.com".parse().unwrap(),
+                QueryType::A,
+            ))
+            .require(Prerequisite::NameInUse(
+                "desktop.example.com".parse().unwrap(),
+            ))
+            .change(Change::DeleteName("laptop.example.com".parse().unwrap()));
+        assert_eq!(authority.update(&failing, client), RCode::Nxdomain);
+        assert_eq!(resolve("laptop.example.com", QueryType::A).answers.len(), 1);
+
+        // the apex keeps its SOA and NS records, and a CNAME stays alone
+        let wiping = Update::new("example.com".parse().unwrap())
+            .change(Change::DeleteName("example.com".parse().unwrap()))
+            .change(Change::Add(DnsRecord {
+                r#type: QueryType::CNAME,
+                rdata: RData::CNAME {
+                    host: "www.example.com".parse().unwrap(),
+                },
+                ..host("laptop.example.com", 0)
+            }));
+        assert_eq!(authority.update(&wiping, client), RCode::Noerror);
+        assert_eq!(resolve("example.com", QueryType::NS).answers.len(), 1);
+        let resp = resolve("laptop.example.com", QueryType::A);
+        assert_eq!(resp.answers, [host("laptop.example.com", 101)]);
+        // nothing changed, so the serial stays where it was
+        assert_eq!(authority.zone("example.com").unwrap().serial(), Some(3));
+    }
+
+    #[test]
+    fn secondaries_transfer_from_primaries() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        // NOTIFY goes to a port nobody's on, which is neither here nor there
+        let primary =
+            Arc::new(authority().notify_secondaries(vec![(Ipv4Addr::LOCALHOST, 9).into()]));
+        let serving = primary.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = handle_connection(stream.unwrap(), &*serving, |_| {});
+            }
+        });
+
+        let secondary = Authority::new(vec![], MockResolver::default())
+            .unwrap()
+            .with_primaries(vec![("example.com".parse().unwrap(), addr)]);
+        assert_eq!(
+            secondary.refresh(&"example.com".parse().unwrap(), addr),
+            Duration::from_secs(3600)
+        );
+        let zone = secondary.zone("example.com").unwrap();
+        assert_eq!(zone.serial(), Some(1));
+        assert_eq!(
+            zone.records().len(),
+            primary.zone("example.com").unwrap().records().len()
+        );
+        let resp = secondary
+            .resolve(&question("www.example.com", QueryType::A))
+            .unwrap();
+        assert!(resp.header.aa);
+        assert_eq!(resp.answers.len(), 1);
+
+        // then only what changed, as the whole of it
+        let text = ZONE.replace("1 3600", "2 3600") + "new A 192.0.2.4\n";
+        primary.set_zone(Zone::parse(&text, "").unwrap()).unwrap();
+        secondary.refresh(&"example.com".parse().unwrap(), addr);
+        assert_eq!(secondary.zone("example.com").unwrap().serial(), Some(2));
+        let resp = secondary
+            .resolve(&question("new.example.com", QueryType::A))
+            .unwrap();
+        assert_eq!(resp.answers.len(), 1);
+
+        // nobody else may, nor for zones it doesn't have
+        let from = IpAddr::from([192, 0, 2, 10]);
+        assert_eq!(
+            primary
+                .transfer(&"example.com".parse().unwrap(), from)
+                .unwrap_err(),
+            RCode::Refused
+        );
+        assert_eq!(
+            primary
+                .transfer(&"example.org".parse().unwrap(), Ipv4Addr::LOCALHOST.into())
+                .unwrap_err(),
+            RCode::Notauth
+        );
+        assert!(axfr(&"example.org".parse().unwrap(), addr).is_err());
+    }
+}
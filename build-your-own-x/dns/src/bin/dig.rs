@@ -0,0 +1,140 @@
+use std::io;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use dns::{LookupConfig, QueryType, ResolvConf, Transport, lookup};
+
+#[derive(Parser)]
+struct Args {
+    /// What to ask, the way dig takes it: the name, then the type (A when not
+    /// given), with @SERVER for the server to ask rather than the first
+    /// nameserver in /etc/resolv.conf, and any of +tcp, +norecurse and +short
+    /// anywhere among them
+    #[clap(required = true)]
+    query: Vec<String>,
+
+    /// The port the server listens on
+    #[clap(short, long, default_value_t = 53)]
+    port: u16,
+
+    /// How to print the answer
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// The way dig prints it
+    Text,
+    /// The packet as a JSON object, with how the query went besides; with
+    /// +short, just the answer records as an array
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+struct Query {
+    name: String,
+    qtype: QueryType,
+    server: Option<IpAddr>,
+    tcp: bool,
+    recurse: bool,
+    // only the data of the answers
+    short: bool,
+}
+
+fn parse_query(args: &[String]) -> Result<Query, String> {
+    let mut query = Query {
+        name: String::new(),
+        qtype: QueryType::A,
+        server: None,
+        tcp: false,
+        recurse: true,
+        short: false,
+    };
+    let mut rest = vec![];
+    for arg in args {
+        if let Some(server) = arg.strip_prefix('@') {
+            let ip = server
+                .parse()
+                .map_err(|_| format!("{server:?} isn't an IP address"))?;
+            query.server = Some(ip);
+        } else if let Some(option) = arg.strip_prefix('+') {
+            match option {
+                "tcp" => query.tcp = true,
+                "notcp" => query.tcp = false,
+                "recurse" => query.recurse = true,
+                "norecurse" => query.recurse = false,
+                "short" => query.short = true,
+                "noshort" => query.short = false,
+                _ => return Err(format!("unknown option {arg:?}")),
+            }
+        } else {
+            rest.push(arg);
+        }
+    }
+    match &rest[..] {
+        [name] => query.name = name.to_string(),
+        [name, qtype] => {
+            query.name = name.to_string();
+            query.qtype = qtype.parse()?;
+        }
+        _ => return Err("expected a name and at most a type".to_string()),
+    }
+    Ok(query)
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let query =
+        parse_query(&args.query).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let server = match query.server {
+        Some(server) => server,
+        None => ResolvConf::system()?.nameservers[0],
+    };
+    let config = LookupConfig {
+        transport: if query.tcp {
+            Transport::Tcp
+        } else {
+            Transport::Udp
+        },
+        recursion_desired: query.recurse,
+        // the answer as it is, CNAMEs and all
+        max_cnames: 0,
+        ..LookupConfig::default()
+    };
+
+    let start = Instant::now();
+    let resp = lookup(&query.name, query.qtype, (server, args.port), &config)?;
+    let elapsed = start.elapsed();
+
+    let transport = if query.tcp { "TCP" } else { "UDP" };
+    #[cfg(feature = "serde")]
+    if args.format == Format::Json {
+        use serde_json::json;
+
+        if query.short {
+            println!("{}", json!(resp.answers));
+            return Ok(());
+        }
+        let mut answer = json!(resp);
+        answer["query_time_ms"] = json!(elapsed.as_millis() as u64);
+        answer["server"] = json!(format!("{server}#{}", args.port));
+        answer["transport"] = json!(transport);
+        println!("{answer}");
+        return Ok(());
+    }
+    if query.short {
+        for record in &resp.answers {
+            println!("{}", record.data());
+        }
+        return Ok(());
+    }
+
+    println!("; <<>> dig <<>> {}", args.query.join(" "));
+    println!("{resp}");
+    println!(";; Query time: {} msec", elapsed.as_millis());
+    println!(";; SERVER: {server}#{}({server}) ({transport})", args.port);
+    println!(";; MSG SIZE  rcvd: {}", resp.to_vec()?.len());
+    Ok(())
+}
@@ -1,64 +1,309 @@
+use std::fs;
 use std::io;
-use std::net::UdpSocket;
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use dns::{DnsPacket, PACKET_SIZE, RCode, recursive_lookup};
+use clap::{ArgAction, Parser};
+use dns::{
+    Authority, BlockWith, Blocklist, Cache, DnsName, DnsPacket, Failover, Hosts, Network,
+    Recursive, Resolver, Upstream, Validator, Zone, handle_connection, handle_datagram,
+};
 
-fn handle_query(socket: &UdpSocket) -> io::Result<DnsPacket> {
-    let mut req_buf = [0u8; PACKET_SIZE];
-    let (_, src_addr) = socket.recv_from(&mut req_buf)?;
+#[derive(Parser)]
+struct Args {
+    /// The address to listen on, over both UDP and TCP
+    #[clap(long, env = "DNS_HOST", default_value = "0.0.0.0")]
+    host: IpAddr,
 
-    let mut req = DnsPacket::from_bytes(&req_buf).unwrap();
+    /// The port to listen on
+    #[clap(long, env = "DNS_PORT", default_value_t = 2053)]
+    port: u16,
 
-    let mut resp = DnsPacket::new_empty();
-    resp.header.id = req.header.id;
-    resp.header.qr = true;
-    resp.header.rd = true;
-    resp.header.ra = true;
+    /// Forward queries to this server instead of resolving them from the
+    /// root: udp://IP[:PORT], tcp://IP[:PORT], tls://IP[:PORT]#NAME (with NAME
+    /// what its certificate is for), quic://IP[:PORT]#NAME when built with
+    /// the doq feature, or a DNS-over-HTTPS URL. Given more than once, or as a
+    /// list separated by commas, each is tried in turn until one answers,
+    /// with those that time out or answer SERVFAIL three times in a row
+    /// passed over until they answer again
+    #[clap(long, env = "DNS_UPSTREAM", value_delimiter = ',')]
+    upstream: Vec<Upstream>,
 
-    if let Some(ques) = req.questions.pop() {
-        // println!("Received query: {ques:?}");
+    /// How many seconds to wait for an answer from a udp:// or tcp://
+    /// upstream before trying the next
+    #[clap(long, env = "DNS_TIMEOUT", default_value_t = 5)]
+    timeout: u64,
 
-        if let Ok(result) = recursive_lookup(&ques.name, ques.r#type) {
-            resp.questions.push(ques);
-            resp.header.rcode = result.header.rcode;
+    /// How many seconds to leave between asking the upstreams being passed
+    /// over whether they answer again
+    #[clap(long, env = "DNS_PROBE_INTERVAL", default_value_t = 30)]
+    probe_interval: u64,
 
-            for rec in result.answers {
-                //println!("Answer: {:?}", rec);
-                resp.answers.push(rec);
-            }
-            for rec in result.authorities {
-                //println!("Authority: {:?}", rec);
-                resp.authorities.push(rec);
-            }
-            for rec in result.resources {
-                //println!("Resource: {:?}", rec);
-                resp.resources.push(rec);
-            }
-        } else {
-            resp.header.rcode = RCode::Servfail;
+    /// How many upstreams to ask at once, answering with whichever answers
+    /// first, for when how long they take varies a lot
+    #[clap(long, env = "DNS_FAN_OUT", default_value_t = 1)]
+    fan_out: usize,
+
+    /// Answer authoritatively for the zone in this master file, whose apex is
+    /// where its SOA record is. Given more than once, for each of the zones
+    #[clap(long)]
+    zone: Vec<PathBuf>,
+
+    /// Answer authoritatively for the zone at ORIGIN as a secondary of the
+    /// server at IP[:PORT], keeping up with it by zone transfers as often as
+    /// the zone's SOA says, or as soon as the primary sends NOTIFY:
+    /// ORIGIN@IP[:PORT]. Given more than once, for each of the zones
+    #[clap(long, value_parser = parse_secondary)]
+    secondary: Vec<(DnsName, SocketAddr)>,
+
+    /// Send NOTIFY to the secondary at IP[:PORT] whenever a zone changes, for
+    /// it to transfer the zone again straight away, over TCP, which only the
+    /// secondaries given here may. Given more than once, to each of them
+    #[clap(long, value_parser = parse_addr)]
+    notify: Vec<SocketAddr>,
+
+    /// Take dynamic updates (RFC 2136) to the zones from clients in this
+    /// network: IP[/PREFIX]. Given more than once, from clients in any of
+    /// them. Updates are refused from everyone when not given
+    #[clap(long)]
+    allow_update: Vec<Network>,
+
+    /// Check the signatures of answers from elsewhere (DNSSEC), setting the
+    /// AD bit on those that check out and answering SERVFAIL for those that
+    /// don't
+    #[clap(long)]
+    dnssec: bool,
+
+    /// Trust the keys of the DS records in this master file for --dnssec,
+    /// instead of those of the root's key-signing keys
+    #[clap(long, requires = "dnssec")]
+    trust_anchor: Option<PathBuf>,
+
+    /// How many queries over UDP to answer at once, each on a thread of its
+    /// own, so that a slow lookup holds up only the queries behind it on its
+    /// thread
+    #[clap(long, default_value_t = NonZeroUsize::new(16).unwrap())]
+    workers: NonZeroUsize,
+
+    /// How many connections over TCP to answer on at once, each on a thread
+    /// of its own. Those that come in beyond it are closed straight away
+    #[clap(long, default_value_t = NonZeroUsize::new(64).unwrap())]
+    tcp_connections: NonZeroUsize,
+
+    /// Answer for the names in this file from the records in it, before
+    /// looking anywhere else: an address and its names on each line, as in
+    /// /etc/hosts, or NAME CNAME NAME
+    #[clap(long)]
+    hosts: Option<PathBuf>,
+
+    /// Block the names in this list, and those below them, in hosts format or
+    /// a name to a line. Given more than once, those in any of them
+    #[clap(long)]
+    blocklist: Vec<PathBuf>,
+
+    /// How to answer for blocked names: nxdomain, or null for 0.0.0.0 and ::
+    #[clap(long, default_value = "nxdomain")]
+    block_with: BlockWith,
+
+    /// How many seconds to leave between reading the blocklists again
+    #[clap(long, default_value_t = 300)]
+    blocklist_reload: u64,
+
+    /// Print each answer in full, rather than a line for each
+    #[clap(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Print nothing but errors
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+impl Args {
+    fn log(
+        &self,
+        resp: &DnsPacket,
+        cache: &Cache<impl Resolver>,
+        blocklist: &Blocklist<impl Resolver>,
+    ) {
+        if self.quiet {
+            return;
+        }
+        if self.verbose > 0 {
+            println!(
+                "Sent back\n{resp}\n(cache: {} hits, {} misses; {} blocked)\n",
+                cache.hits(),
+                cache.misses(),
+                blocklist.blocked()
+            );
+            return;
+        }
+        match resp.questions.first() {
+            Some(question) => println!(
+                "{question}: {}, {} answers",
+                resp.header.rcode,
+                resp.answers.len()
+            ),
+            None => println!("{}", resp.header.rcode),
         }
-    } else {
-        resp.header.rcode = RCode::Formerr;
     }
+}
+
+// ORIGIN@IP[:PORT], with 53 the port to use by default
+fn parse_secondary(s: &str) -> Result<(DnsName, SocketAddr), String> {
+    let (origin, primary) = s
+        .split_once('@')
+        .ok_or("expected ORIGIN@IP[:PORT], as in example.com@192.0.2.1")?;
+    let origin = DnsName::new(origin).map_err(|e| format!("{origin:?}: {e}"))?;
+    Ok((origin, parse_addr(primary)?))
+}
 
-    resp.header.qdcount = resp.questions.len() as u16;
-    resp.header.ancount = resp.answers.len() as u16;
-    resp.header.nscount = resp.authorities.len() as u16;
-    resp.header.arcount = resp.resources.len() as u16;
+// IP[:PORT], with 53 the port to use by default
+fn parse_addr(s: &str) -> Result<SocketAddr, String> {
+    s.parse()
+        .or_else(|_| s.parse().map(|ip: IpAddr| SocketAddr::new(ip, 53)))
+        .map_err(|_| format!("{s:?} isn't an IP address with an optional port"))
+}
 
-    let mut resp_buf = [0u8; PACKET_SIZE];
-    resp.to_bytes(&mut resp_buf);
+fn load_zone(path: &PathBuf) -> io::Result<Zone> {
+    let text = fs::read_to_string(path)?;
+    Zone::parse(&text, "").map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
+}
 
-    socket.send_to(&resp_buf, src_addr).map(|_| resp)
+fn load_hosts<R: Resolver>(inner: R, path: &PathBuf) -> io::Result<Hosts<R>> {
+    let text = fs::read_to_string(path)?;
+    Hosts::new(inner, &text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    })
 }
 
 fn main() -> io::Result<()> {
-    let socket = UdpSocket::bind(("0.0.0.0", 2053))?;
+    let args = Arc::new(Args::parse());
+    let resolver: Box<dyn Resolver + Send + Sync> = if args.upstream.is_empty() {
+        Box::new(Recursive)
+    } else {
+        let timeout = Duration::from_secs(args.timeout);
+        let upstreams = args
+            .upstream
+            .iter()
+            .map(|u| u.clone().with_timeout(timeout));
+        let failover = Arc::new(Failover::new(upstreams.collect()).with_fan_out(args.fan_out));
+        let (prober, interval) = (failover.clone(), Duration::from_secs(args.probe_interval));
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                prober.probe();
+            }
+        });
+        Box::new(failover)
+    };
+    let cache = Arc::new(Cache::new(resolver));
+    let zones = args.zone.iter().map(load_zone).collect::<io::Result<_>>()?;
+    let fallback: Box<dyn Resolver + Send + Sync> = match (args.dnssec, &args.trust_anchor) {
+        (false, _) => Box::new(cache.clone()),
+        (true, None) => Box::new(Validator::new(cache.clone())),
+        (true, Some(path)) => Box::new(Validator::with_anchors(cache.clone(), &load_zone(path)?)?),
+    };
+    let blocklist = Blocklist::new(fallback, args.blocklist.clone())?.block_with(args.block_with);
+    let blocklist = Arc::new(blocklist);
+    if !args.blocklist.is_empty() {
+        let (reloader, interval) = (
+            blocklist.clone(),
+            Duration::from_secs(args.blocklist_reload),
+        );
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if let Err(e) = reloader.reload() {
+                    eprintln!("Could not read the blocklists again: {e}");
+                }
+            }
+        });
+    }
+    let hosts = match &args.hosts {
+        Some(path) => load_hosts(blocklist.clone(), path)?,
+        None => Hosts::new(blocklist.clone(), "").expect("no hosts to get wrong"),
+    };
+    let authority = Authority::new(zones, hosts)?
+        .allow_updates(args.allow_update.clone())
+        .notify_secondaries(args.notify.clone())
+        .with_primaries(args.secondary.clone());
+    let resolver = Arc::new(authority);
+
+    for (origin, primary) in args.secondary.clone() {
+        let authority = resolver.clone();
+        let mut wait = authority.refresh(&origin, primary);
+        thread::spawn(move || {
+            loop {
+                authority.wait_for_change(&origin, wait);
+                wait = authority.refresh(&origin, primary);
+            }
+        });
+    }
+
+    let socket = UdpSocket::bind((args.host, args.port))?;
+    let listener = TcpListener::bind((args.host, args.port))?;
 
-    loop {
-        match handle_query(&socket) {
-            Ok(resp) => println!("Sent back {resp:#?}\n"),
-            Err(e) => eprintln!("An error occurred: {e}"),
+    let tcp_resolver = resolver.clone();
+    let (tcp_args, tcp_cache, tcp_blocklist) = (args.clone(), cache.clone(), blocklist.clone());
+    thread::spawn(move || {
+        // a clone for each connection being answered on, let go of when it's
+        // done with
+        let open = Arc::new(());
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Could not accept a connection: {e}");
+                    continue;
+                }
+            };
+            if Arc::strong_count(&open) > tcp_args.tcp_connections.get() {
+                eprintln!(
+                    "Closing a connection, as {} are open already",
+                    tcp_args.tcp_connections
+                );
+                continue;
+            }
+            let slot = open.clone();
+            let resolver = tcp_resolver.clone();
+            let (args, cache, blocklist) =
+                (tcp_args.clone(), tcp_cache.clone(), tcp_blocklist.clone());
+            thread::spawn(move || {
+                let log = |resp: &DnsPacket| args.log(resp, &cache, &blocklist);
+                if let Err(e) = handle_connection(stream, &*resolver, log) {
+                    eprintln!("An error occurred: {e}");
+                }
+                drop(slot);
+            });
+        }
+    });
+
+    // each worker answers one query at a time, and this thread is one of them
+    let workers = args.workers.get();
+    let serve = move |socket: UdpSocket| {
+        loop {
+            match handle_datagram(&socket, &*resolver) {
+                Ok(resp) => args.log(&resp, &cache, &blocklist),
+                Err(e) => eprintln!("An error occurred: {e}"),
+            }
         }
+    };
+    for _ in 1..workers {
+        let socket = socket.try_clone()?;
+        let serve = serve.clone();
+        thread::spawn(move || serve(socket));
     }
+    serve(socket)
 }
@@ -0,0 +1,270 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{DnsName, DnsPacket, DnsQuestion, DnsRecord, QueryType, RCode, RData, Resolver};
+
+// how long blocked answers may be kept, short so that a name taken off a list
+// is soon answered again
+const BLOCKED_TTL: u32 = 60;
+
+// names in hosts files that are the machine's own, not ones to block
+const LOCAL_NAMES: [&str; 5] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+];
+
+// How to answer for a blocked name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BlockWith {
+    // as if there were no such name
+    #[default]
+    Nxdomain,
+    // with 0.0.0.0 or ::, and no records of the other types
+    Unspecified,
+}
+
+// As taken on the command line: nxdomain or null.
+impl FromStr for BlockWith {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "nxdomain" => Ok(BlockWith::Nxdomain),
+            "null" => Ok(BlockWith::Unspecified),
+            _ => Err(format!("{s:?} isn't nxdomain or null")),
+        }
+    }
+}
+
+impl fmt::Display for BlockWith {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockWith::Nxdomain => f.write_str("nxdomain"),
+            BlockWith::Unspecified => f.write_str("null"),
+        }
+    }
+}
+
+// Answers for the names in its lists, and those below them, itself, the way
+// `block_with` says, and asks `inner` about the rest. The lists are in hosts
+// format, with the names after an address, or a name to a line, and are read
+// again on `reload`.
+#[derive(Debug)]
+pub struct Blocklist<R> {
+    inner: R,
+    paths: Vec<PathBuf>,
+    names: RwLock<HashSet<DnsName>>,
+    block_with: BlockWith,
+    blocked: AtomicUsize,
+}
+
+impl<R: Resolver> Blocklist<R> {
+    pub fn new(inner: R, paths: Vec<PathBuf>) -> io::Result<Self> {
+        let blocklist = Self {
+            inner,
+            paths,
+            names: RwLock::default(),
+            block_with: BlockWith::default(),
+            blocked: AtomicUsize::new(0),
+        };
+        blocklist.reload()?;
+        Ok(blocklist)
+    }
+
+    pub fn block_with(mut self, block_with: BlockWith) -> Self {
+        self.block_with = block_with;
+        self
+    }
+
+    // how many questions were answered as blocked
+    pub fn blocked(&self) -> usize {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    // how many names are blocked, not counting those below them
+    pub fn len(&self) -> usize {
+        self.names.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Reads the lists again, keeping the names from before if any of them
+    // can't be read.
+    pub fn reload(&self) -> io::Result<()> {
+        let mut names = HashSet::new();
+        for path in &self.paths {
+            let text = fs::read_to_string(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+            names.extend(parse(&text));
+        }
+        *self.names.write().unwrap() = names;
+        Ok(())
+    }
+
+    fn is_blocked(&self, name: &DnsName) -> bool {
+        let names = self.names.read().unwrap();
+        let mut name = Some(name.clone());
+        while let Some(n) = name {
+            if n.is_root() {
+                break;
+            }
+            if names.contains(&n) {
+                return true;
+            }
+            name = n.parent();
+        }
+        false
+    }
+}
+
+// The names in a list, skipping the machine's own and those that can't be
+// names.
+fn parse(text: &str) -> impl Iterator<Item = DnsName> + '_ {
+    text.lines()
+        .map(|line| line.split_once('#').map_or(line, |(line, _)| line))
+        .flat_map(|line| {
+            let mut words = line.split_whitespace().peekable();
+            // the address in hosts format
+            if words.peek().is_some_and(|w| w.parse::<IpAddr>().is_ok()) {
+                words.next();
+            }
+            words
+        })
+        .filter(|word| !LOCAL_NAMES.iter().any(|l| l.eq_ignore_ascii_case(word)))
+        .filter_map(|word| DnsName::new(word).ok())
+        .filter(|name| !name.is_root())
+}
+
+fn blocked_answer(question: &DnsQuestion, block_with: BlockWith) -> DnsPacket {
+    let mut resp = DnsPacket::new_empty();
+    let rdata = match (block_with, question.r#type) {
+        (BlockWith::Nxdomain, _) => {
+            resp.header.rcode = RCode::Nxdomain;
+            return resp;
+        }
+        (BlockWith::Unspecified, QueryType::A) => RData::A {
+            ip: Ipv4Addr::UNSPECIFIED,
+        },
+        (BlockWith::Unspecified, QueryType::AAAA) => RData::AAAA {
+            ip: Ipv6Addr::UNSPECIFIED,
+        },
+        // no records of the type
+        (BlockWith::Unspecified, _) => return resp,
+    };
+    resp.answers.push(DnsRecord {
+        domain: question.name.clone(),
+        r#type: question.r#type,
+        class: 1,
+        ttl: BLOCKED_TTL,
+        rdata,
+    });
+    resp
+}
+
+impl<R: Resolver> Resolver for Blocklist<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        if !self.is_blocked(&question.name) {
+            return self.inner.resolve(question);
+        }
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        Ok(blocked_answer(question, self.block_with))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::MockResolver;
+    use std::env;
+
+    fn question(name: &str, qtype: QueryType) -> DnsQuestion {
+        DnsQuestion::new(name.parse().unwrap(), qtype)
+    }
+
+    #[test]
+    fn lists() {
+        let text = "\
+# hosts format
+0.0.0.0 ads.example.com tracker.example.net  # two to a line
+127.0.0.1 localhost
+::1 ip6-localhost
+
+# a name to a line
+Telemetry.example.org
+not..a.name
+";
+        let names: Vec<_> = parse(text).map(|n| n.to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "ads.example.com",
+                "tracker.example.net",
+                "Telemetry.example.org"
+            ]
+        );
+    }
+
+    #[test]
+    fn blocked_names() {
+        let path = env::temp_dir().join(format!("blocklist-{}", std::process::id()));
+        fs::write(&path, "0.0.0.0 ads.example.com\n").unwrap();
+        let inner = MockResolver::default().with_answer(
+            "www.example.com",
+            QueryType::A,
+            DnsPacket::new_empty(),
+        );
+        let blocklist = Blocklist::new(inner, vec![path.clone()]).unwrap();
+        assert_eq!(blocklist.len(), 1);
+
+        let resp = blocklist
+            .resolve(&question("x.ADS.example.com", QueryType::A))
+            .unwrap();
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+        assert_eq!(blocklist.blocked(), 1);
+        assert!(
+            blocklist
+                .resolve(&question("www.example.com", QueryType::A))
+                .is_ok()
+        );
+        assert_eq!((blocklist.blocked(), blocklist.inner.asked()), (1, 1));
+
+        let blocklist = blocklist.block_with(BlockWith::Unspecified);
+        let resp = blocklist
+            .resolve(&question("ads.example.com", QueryType::AAAA))
+            .unwrap();
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert_eq!(
+            resp.answers[0].rdata,
+            RData::AAAA {
+                ip: Ipv6Addr::UNSPECIFIED
+            }
+        );
+        let resp = blocklist
+            .resolve(&question("ads.example.com", QueryType::MX))
+            .unwrap();
+        assert!(resp.answers.is_empty());
+
+        // taken off the list
+        fs::write(&path, "www.example.com\n").unwrap();
+        blocklist.reload().unwrap();
+        assert!(!blocklist.is_blocked(&"ads.example.com".parse().unwrap()));
+        assert!(blocklist.is_blocked(&"www.example.com".parse().unwrap()));
+        // kept when the list is gone
+        fs::remove_file(&path).unwrap();
+        assert!(blocklist.reload().is_err());
+        assert_eq!(blocklist.len(), 1);
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{DnsPacket, DnsQuestion, QueryType, RCode, RData, Resolver};
+
+// past this many answers, the expired ones are cleared out to make room, and
+// new ones aren't kept if that doesn't free any
+const MAX_ENTRIES: usize = 10_000;
+
+// the name, lowercased since names are compared without regard to case, the
+// type and the class asked for
+type Key = (String, QueryType, u16);
+
+#[derive(Debug)]
+struct Entry {
+    answer: DnsPacket,
+    stored: Instant,
+    expires: Instant,
+}
+
+// Keeps the answers `inner` gives for as long as their TTLs allow, answering
+// the same questions from memory in the meantime.
+#[derive(Debug)]
+pub struct Cache<R> {
+    inner: R,
+    entries: Mutex<HashMap<Key, Entry>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl<R: Resolver> Cache<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            entries: Mutex::default(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    // how many questions were answered from memory
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    // how many questions had to be passed on
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn get(&self, key: &Key, now: Instant) -> Option<DnsPacket> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        if entry.expires <= now {
+            entries.remove(key);
+            return None;
+        }
+
+        // counting down for as long as it has been kept
+        let elapsed = (now - entry.stored)
+            .as_secs()
+            .try_into()
+            .unwrap_or(u32::MAX);
+        let mut answer = entry.answer.clone();
+        for rec in answer
+            .answers
+            .iter_mut()
+            .chain(&mut answer.authorities)
+            .chain(&mut answer.resources)
+            .filter(|rec| rec.r#type != QueryType::OPT)
+        {
+            rec.ttl = rec.ttl.saturating_sub(elapsed);
+        }
+        Some(answer)
+    }
+
+    fn put(&self, key: Key, answer: &DnsPacket, now: Instant) {
+        let Some(ttl) = ttl(answer) else {
+            return;
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                answer: answer.clone(),
+                stored: now,
+                expires: now + ttl,
+            },
+        );
+    }
+}
+
+// How long `answer` may be kept for, if at all.
+fn ttl(answer: &DnsPacket) -> Option<Duration> {
+    if answer.header.tc {
+        return None;
+    }
+    let ttl = match answer.header.rcode {
+        // no longer than any of its records
+        RCode::Noerror if !answer.answers.is_empty() => answer
+            .answers
+            .iter()
+            .chain(&answer.authorities)
+            .chain(&answer.resources)
+            .filter(|rec| rec.r#type != QueryType::OPT)
+            .map(|rec| rec.ttl)
+            .min()?,
+        // NXDOMAIN, or NODATA for a name without records of the type asked
+        // for
+        RCode::Nxdomain | RCode::Noerror => negative_ttl(answer)?,
+        _ => return None,
+    };
+    (ttl > 0).then(|| Duration::from_secs(ttl.into()))
+}
+
+// Negative answers are kept for as long as the SOA that comes with them says
+// (RFC 2308), and not at all without one.
+fn negative_ttl(answer: &DnsPacket) -> Option<u32> {
+    answer.authorities.iter().find_map(|rec| match rec.rdata {
+        RData::SOA { minimum, .. } => Some(rec.ttl.min(minimum)),
+        _ => None,
+    })
+}
+
+impl<R: Resolver> Resolver for Cache<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let key = (
+            question.name.to_ascii_lowercase(),
+            question.r#type,
+            question.class,
+        );
+        let now = Instant::now();
+        if let Some(answer) = self.get(&key, now) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(answer);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let answer = self.inner.resolve(question)?;
+        self.put(key, &answer, now);
+        Ok(answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{DnsRecord, MockResolver, query};
+    use std::net::Ipv4Addr;
+
+    fn answer(name: &str, ttl: u32) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.ancount = 1;
+        resp.answers.push(DnsRecord {
+            domain: name.parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl,
+            rdata: RData::A {
+                ip: Ipv4Addr::LOCALHOST,
+            },
+        });
+        resp
+    }
+
+    fn question(name: &str, qtype: QueryType) -> DnsQuestion {
+        query(name, qtype).unwrap().questions.remove(0)
+    }
+
+    #[test]
+    fn answers_are_kept_until_they_expire() {
+        let cache = Cache::new(
+            MockResolver::default()
+                .with_answer("example.com", QueryType::A, answer("example.com", 60))
+                .with_answer("zero.example.com", QueryType::A, answer("zero", 0)),
+        );
+        let ques = question("example.com", QueryType::A);
+
+        assert_eq!(cache.resolve(&ques).unwrap().answers[0].ttl, 60);
+        let resp = cache
+            .resolve(&question("EXAMPLE.com", QueryType::A))
+            .unwrap();
+        assert_eq!(resp.answers[0].domain, "example.com");
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(cache.inner.asked(), 1);
+
+        let key = ("example.com".to_string(), QueryType::A, 1);
+        let ago = Instant::now() - Duration::from_secs(10);
+        cache.entries.lock().unwrap().get_mut(&key).unwrap().stored = ago;
+        assert_eq!(cache.resolve(&ques).unwrap().answers[0].ttl, 50);
+
+        cache.entries.lock().unwrap().get_mut(&key).unwrap().expires = Instant::now();
+        cache.resolve(&ques).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+        assert_eq!(cache.inner.asked(), 2);
+
+        // neither answers that mustn't be kept nor failures are
+        for _ in 0..2 {
+            cache
+                .resolve(&question("zero.example.com", QueryType::A))
+                .unwrap();
+            assert!(
+                cache
+                    .resolve(&question("example.com", QueryType::MX))
+                    .is_err()
+            );
+        }
+        assert_eq!(cache.inner.asked(), 6);
+    }
+
+    #[test]
+    fn edns_isnt_a_record_to_age() {
+        // the OPT record's TTL field carries the extended rcode and the DO
+        // bit, so it's neither a TTL to keep the answer by nor one to count
+        // down, with the DO bit set or not
+        let mut without = answer("example.com", 60);
+        without.set_edns(4096, false);
+        let mut with = answer("example.com", 60);
+        with.set_edns(4096, true);
+        let cache = Cache::new(
+            MockResolver::default()
+                .with_answer("example.com", QueryType::A, without)
+                .with_answer("example.com", QueryType::AAAA, with),
+        );
+
+        for qtype in [QueryType::A, QueryType::AAAA] {
+            let ques = question("example.com", qtype);
+            cache.resolve(&ques).unwrap();
+            let key = ("example.com".to_string(), qtype, 1);
+            let ago = Instant::now() - Duration::from_secs(10);
+            cache.entries.lock().unwrap().get_mut(&key).unwrap().stored = ago;
+            let resp = cache.resolve(&ques).unwrap();
+            assert_eq!(resp.answers[0].ttl, 50);
+            assert_eq!(resp.dnssec_ok(), qtype == QueryType::AAAA);
+        }
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+    }
+
+    fn negative(rcode: RCode, soa: Option<(u32, u32)>) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.rcode = rcode;
+        if let Some((ttl, minimum)) = soa {
+            resp.header.nscount = 1;
+            resp.authorities.push(DnsRecord {
+                domain: "example.com".parse().unwrap(),
+                r#type: QueryType::SOA,
+                class: 1,
+                ttl,
+                rdata: RData::SOA {
+                    mname: "ns.example.com".parse().unwrap(),
+                    rname: "hostmaster.example.com".parse().unwrap(),
+                    serial: 1,
+                    refresh: 3600,
+                    retry: 600,
+                    expire: 86400,
+                    minimum,
+                },
+            });
+        }
+        resp
+    }
+
+    #[test]
+    fn negative_answers_are_kept_as_long_as_their_soa_says() {
+        let cache = Cache::new(
+            MockResolver::default()
+                .with_answer(
+                    "nowhere.example.com",
+                    QueryType::A,
+                    negative(RCode::Nxdomain, Some((3600, 300))),
+                )
+                .with_answer(
+                    "example.com",
+                    QueryType::AAAA,
+                    negative(RCode::Noerror, Some((60, 300))),
+                )
+                .with_answer(
+                    "elsewhere.example.com",
+                    QueryType::A,
+                    negative(RCode::Nxdomain, None),
+                ),
+        );
+        for _ in 0..2 {
+            let resp = cache
+                .resolve(&question("nowhere.example.com", QueryType::A))
+                .unwrap();
+            assert_eq!(resp.header.rcode, RCode::Nxdomain);
+            cache
+                .resolve(&question("example.com", QueryType::AAAA))
+                .unwrap();
+            cache
+                .resolve(&question("elsewhere.example.com", QueryType::A))
+                .unwrap();
+        }
+        assert_eq!(cache.inner.asked(), 4);
+
+        let entries = cache.entries.lock().unwrap();
+        let ttl = |name: &str, qtype| {
+            let entry = &entries[&(name.to_string(), qtype, 1)];
+            entry.expires - entry.stored
+        };
+        assert_eq!(ttl("nowhere.example.com", QueryType::A).as_secs(), 300);
+        assert_eq!(ttl("example.com", QueryType::AAAA).as_secs(), 60);
+    }
+}
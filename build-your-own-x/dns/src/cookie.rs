@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+
+use ring::hmac;
+use ring::rand::SystemRandom;
+
+use crate::{DnsPacket, RCode};
+
+// DNS cookies (RFC 7873): each client sends a server a cookie of its own,
+// which the server sends back, so that answers without it can be told to be
+// spoofed. The server sends one of its own too, made from the client's and
+// the client's address, for the client to send back in turn.
+
+// what cookies are made with, different each time the process starts
+static SECRET: LazyLock<hmac::Key> = LazyLock::new(|| {
+    hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+        .expect("no randomness for a cookie secret")
+});
+
+// the cookies servers have sent, to send them back
+static SERVER_COOKIES: LazyLock<Mutex<HashMap<IpAddr, Vec<u8>>>> = LazyLock::new(Default::default);
+
+// BADCOOKIE, for a server that wants its cookie back before it answers: 23,
+// the top bits of which are in the OPT record's TTL
+const BADCOOKIE_LOW: RCode = RCode::Yxrrset;
+const BADCOOKIE_HIGH: u32 = 1;
+
+fn mac(parts: &[&[u8]]) -> Vec<u8> {
+    let mut ctx = hmac::Context::with_key(&SECRET);
+    for part in parts {
+        ctx.update(part);
+    }
+    ctx.sign().as_ref()[..8].to_vec()
+}
+
+fn ip_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+// The same for each server, and different for each of them, so that
+// servers can't tell it's the same client.
+fn client_cookie(server: IpAddr) -> Vec<u8> {
+    mac(&[b"client", &ip_bytes(server)])
+}
+
+// Sends `server` our cookie, and its own when it has sent one.
+pub(crate) fn add(query: &mut DnsPacket, server: IpAddr) {
+    let cookies = SERVER_COOKIES.lock().unwrap();
+    let server_cookie = cookies.get(&server).map_or(&[][..], Vec::as_slice);
+    query.set_cookie(&client_cookie(server), server_cookie);
+}
+
+// Fails for an answer from `server` with a cookie other than the one it was
+// sent, and keeps the one it sends of its own.
+pub(crate) fn check(resp: &DnsPacket, server: IpAddr) -> io::Result<()> {
+    // from servers that don't do cookies
+    let Some((client, server_cookie)) = resp.cookie() else {
+        return Ok(());
+    };
+    if client != client_cookie(server) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("answer from {server} with someone else's cookie"),
+        ));
+    }
+    if !server_cookie.is_empty() {
+        SERVER_COOKIES
+            .lock()
+            .unwrap()
+            .insert(server, server_cookie.to_vec());
+    }
+    Ok(())
+}
+
+pub(crate) fn is_bad(resp: &DnsPacket) -> bool {
+    resp.header.rcode == BADCOOKIE_LOW
+        && resp
+            .edns()
+            .is_some_and(|opt| opt.ttl >> 24 == BADCOOKIE_HIGH)
+}
+
+// What to answer `client` with, for a query with `client_cookie` in it.
+pub(crate) fn server_cookie(client_cookie: &[u8], client: IpAddr) -> Vec<u8> {
+    mac(&[b"server", client_cookie, &ip_bytes(client)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{QueryType, query};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn cookies() {
+        let server = IpAddr::from(Ipv4Addr::new(192, 0, 2, 53));
+        let mut req = query("example.com", QueryType::A).unwrap();
+        add(&mut req, server);
+        let req = DnsPacket::from_bytes(&req.to_vec().unwrap()).unwrap();
+        let (client, none_yet) = req.cookie().unwrap();
+        assert_eq!(client.len(), 8);
+        assert!(none_yet.is_empty());
+        // someone else's
+        let mut other = query("example.com", QueryType::A).unwrap();
+        add(&mut other, Ipv4Addr::new(192, 0, 2, 54).into());
+        assert_ne!(other.cookie().unwrap().0, client);
+
+        // the server's, sent back from then on
+        let mut resp = req.clone();
+        let from_server = server_cookie(client, Ipv4Addr::LOCALHOST.into());
+        resp.set_cookie(client, &from_server);
+        check(&resp, server).unwrap();
+        let mut again = query("example.com", QueryType::A).unwrap();
+        add(&mut again, server);
+        assert_eq!(again.cookie(), Some((client, &from_server[..])));
+
+        // a spoofed answer
+        resp.set_cookie(&[0; 8], &[]);
+        let e = check(&resp, server).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // a cookie that's too short to be one
+        let mut req_buf = req.to_vec().unwrap();
+        let len = req_buf.len();
+        // the record's length and the option's, before the 8 bytes
+        req_buf[len - 13] -= 1;
+        req_buf[len - 9] -= 1;
+        req_buf.pop();
+        assert!(DnsPacket::from_bytes(&req_buf).is_err());
+    }
+}
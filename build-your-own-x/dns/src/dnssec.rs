@@ -0,0 +1,1089 @@
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::digest::{self, SHA1_FOR_LEGACY_USE_ONLY, SHA256, SHA384};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+use crate::encoding::Base32Hex;
+use crate::transfer::is_newer;
+use crate::{
+    DnsName, DnsPacket, DnsQuestion, DnsRecord, PacketBufWriter, QueryType, RCode, RData, Resolver,
+    ToBytes, Zone, query,
+};
+
+// the root's key-signing keys, KSK-2017 and KSK-2024, as IANA publishes them
+const ROOT_ANCHORS: &str = "
+$TTL 172800
+.  IN  DS  20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D
+.  IN  DS  38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16
+";
+
+// in a DNSKEY's flags, for keys that sign the zone's records
+const ZONE_KEY: u16 = 1 << 8;
+// in an NSEC3's flags, for delegations without a DS that it may skip over
+const OPT_OUT: u8 = 1;
+// NSEC3 records hashed more often than this are taken to prove nothing, and
+// what they'd prove as insecure, as RFC 9276 lets validators have it: no zone
+// should make us hash every name thousands of times
+const MAX_ITERATIONS: u16 = 100;
+
+// Checks the answers `inner` gives against their signatures (RFC 4035),
+// following the chain of trust down from the DS records in `anchors`. Those
+// that check out come back with the AD bit set, and those from signed zones
+// that don't, or that come without signatures at all, don't come back.
+#[derive(Debug)]
+pub struct Validator<R> {
+    inner: R,
+    anchors: Vec<DnsRecord>,
+}
+
+// The keys of a zone found to be signed.
+struct Keys {
+    zone: String,
+    dnskeys: Vec<DnsRecord>,
+}
+
+// What a name without a DS turns out to be.
+enum NoDs {
+    // in the same zone as the name above it
+    NotACut,
+    // the apex of a zone that isn't signed
+    Unsigned,
+}
+
+impl<R: Resolver> Validator<R> {
+    // Trusting the root's keys.
+    pub fn new(inner: R) -> Self {
+        let anchors = Zone::parse(ROOT_ANCHORS, "").unwrap();
+        Self::with_anchors(inner, &anchors).unwrap()
+    }
+
+    // Trusting the keys of the DS records in `anchors` instead, of the root
+    // or of any other zone.
+    pub fn with_anchors(inner: R, anchors: &Zone) -> io::Result<Self> {
+        let anchors: Vec<DnsRecord> = anchors.records().to_vec();
+        if anchors.is_empty() || anchors.iter().any(|r| r.r#type != QueryType::DS) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "trust anchors have to be DS records",
+            ));
+        }
+        Ok(Self { inner, anchors })
+    }
+
+    fn ask(&self, name: &str, qtype: QueryType) -> Result<DnsPacket, String> {
+        let ask = || self.inner.resolve(&query(name, qtype)?.questions.remove(0));
+        ask().map_err(|e| format!("could not ask for {name} {qtype:?}: {e}"))
+    }
+
+    // Whether the answer checks out all the way, or is from zones that
+    // aren't signed, or the reason it's bogus.
+    fn check(&self, question: &DnsQuestion, resp: &DnsPacket) -> Result<bool, String> {
+        if !matches!(resp.header.rcode, RCode::Noerror | RCode::Nxdomain) {
+            return Ok(false);
+        }
+        let now = now();
+        let mut secure = true;
+        for (rrset, sigs) in rrsets(&resp.answers) {
+            secure &= self.check_rrset(&rrset, &sigs, now)?;
+        }
+
+        // where the CNAMEs lead, for what's said not to be there
+        let mut name = question.name.as_str();
+        for _ in 0..resp.answers.len() {
+            let cname = resp.answers.iter().find_map(|r| match &r.rdata {
+                RData::CNAME { host } if r.domain.eq_ignore_ascii_case(name) => Some(host),
+                _ => None,
+            });
+            match cname {
+                Some(host) if question.r#type != QueryType::CNAME => name = host,
+                _ => break,
+            }
+        }
+        let answered = resp
+            .answers
+            .iter()
+            .any(|r| r.domain.eq_ignore_ascii_case(name) && r.r#type == question.r#type);
+        if !answered {
+            let nxdomain = resp.header.rcode == RCode::Nxdomain;
+            secure &= self.check_denial(name, question.r#type, nxdomain, resp, now)?;
+        }
+        Ok(secure)
+    }
+
+    // Whether the records are signed by their zone's keys, or from a zone
+    // that isn't signed.
+    fn check_rrset(
+        &self,
+        rrset: &[DnsRecord],
+        sigs: &[&DnsRecord],
+        now: u32,
+    ) -> Result<bool, String> {
+        let owner = &rrset[0].domain;
+        let signer = sigs.iter().find_map(|sig| match &sig.rdata {
+            RData::RRSIG { signer, .. } if owner.is_within(signer) => Some(signer),
+            _ => None,
+        });
+        let Some(signer) = signer else {
+            return match self.keys_for(owner, now)? {
+                None => Ok(false),
+                Some(keys) => Err(format!(
+                    "no signatures for {owner} {:?} in signed zone {:?}",
+                    rrset[0].r#type, keys.zone
+                )),
+            };
+        };
+        match self.keys_for(signer, now)? {
+            None => Ok(false),
+            Some(keys) if !keys.zone.eq_ignore_ascii_case(signer) => {
+                Err(format!("{signer:?} signed {owner}, but isn't a zone"))
+            }
+            Some(keys) => verify(rrset, sigs, &keys, now).map(|()| true),
+        }
+    }
+
+    // The keys of the zone `name` is in, found from the closest anchor above
+    // it by the DS records of each zone below that, or None for names in
+    // zones that aren't signed, or that no anchor is above.
+    fn keys_for(&self, name: &str, now: u32) -> Result<Option<Keys>, String> {
+        // from the root down
+        let mut ancestors = vec![name];
+        let mut rest = name;
+        while !rest.is_empty() {
+            rest = rest.split_once('.').map_or("", |(_, parent)| parent);
+            ancestors.push(rest);
+        }
+
+        let mut keys: Option<Keys> = None;
+        for zone in ancestors.into_iter().rev() {
+            let anchored: Vec<&DnsRecord> = self
+                .anchors
+                .iter()
+                .filter(|r| r.domain.eq_ignore_ascii_case(zone))
+                .collect();
+            if !anchored.is_empty() {
+                keys = self.dnskeys(zone, &anchored, now)?;
+                continue;
+            }
+            let Some(parent) = &keys else {
+                continue;
+            };
+
+            let resp = self.ask(zone, QueryType::DS)?;
+            let ds = rrsets(&resp.answers)
+                .into_iter()
+                .find(|(rrset, _)| is_at(&rrset[0], zone, QueryType::DS));
+            if let Some((ds, sigs)) = ds {
+                verify(&ds, &sigs, parent, now)?;
+                keys = self.dnskeys(zone, &ds.iter().collect::<Vec<_>>(), now)?;
+                continue;
+            }
+            match no_ds(parent, zone, &resp, now)? {
+                NoDs::NotACut => {}
+                NoDs::Unsigned => return Ok(None),
+            }
+        }
+        Ok(keys)
+    }
+
+    // The keys of `zone`, checked against the DS records for it, or None
+    // when none of those are of algorithms we know.
+    fn dnskeys(&self, zone: &str, ds: &[&DnsRecord], now: u32) -> Result<Option<Keys>, String> {
+        let ds: Vec<_> = ds
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::DS {
+                    key_tag,
+                    algorithm,
+                    digest_type,
+                    digest,
+                } if is_supported(*algorithm) && digester(*digest_type).is_some() => {
+                    Some((*key_tag, *algorithm, *digest_type, &digest.0))
+                }
+                _ => None,
+            })
+            .collect();
+        if ds.is_empty() {
+            return Ok(None);
+        }
+
+        let resp = self.ask(zone, QueryType::DNSKEY)?;
+        let Some((dnskeys, sigs)) = rrsets(&resp.answers)
+            .into_iter()
+            .find(|(rrset, _)| is_at(&rrset[0], zone, QueryType::DNSKEY))
+        else {
+            return Err(format!("no DNSKEY records for {zone:?}"));
+        };
+        // those the parent vouches for, which sign the rest
+        let entry_points: Vec<DnsRecord> = dnskeys
+            .iter()
+            .filter(|key| {
+                let rdata = rdata_bytes(key);
+                ds.iter().any(|&(tag, algorithm, digest_type, digest)| {
+                    key_tag(&rdata) == tag
+                        && matches!(key.rdata, RData::DNSKEY { algorithm: a, .. } if a == algorithm)
+                        && ds_digest(digest_type, zone, &rdata).as_ref() == Some(digest)
+                })
+            })
+            .cloned()
+            .collect();
+        let entry = Keys {
+            zone: zone.to_string(),
+            dnskeys: entry_points,
+        };
+        if entry.dnskeys.is_empty() {
+            return Err(format!("no DNSKEY of {zone:?} matches its DS records"));
+        }
+        verify(&dnskeys, &sigs, &entry, now)?;
+
+        let dnskeys = dnskeys
+            .into_iter()
+            .filter(|key| matches!(key.rdata, RData::DNSKEY { flags, .. } if flags & ZONE_KEY != 0))
+            .collect();
+        Ok(Some(Keys {
+            zone: zone.to_string(),
+            dnskeys,
+        }))
+    }
+
+    // Whether the NSEC or NSEC3 records in `resp` prove there's no `qtype`
+    // at `name`, or no `name` at all.
+    fn check_denial(
+        &self,
+        name: &str,
+        qtype: QueryType,
+        nxdomain: bool,
+        resp: &DnsPacket,
+        now: u32,
+    ) -> Result<bool, String> {
+        let Some(keys) = self.keys_for(name, now)? else {
+            return Ok(false);
+        };
+        let mut proofs = vec![];
+        for (rrset, sigs) in rrsets(&resp.authorities) {
+            if !self.check_rrset(&rrset, &sigs, now)? {
+                continue;
+            }
+            proofs.extend(
+                rrset
+                    .into_iter()
+                    .filter(|r| matches!(r.r#type, QueryType::NSEC | QueryType::NSEC3)),
+            );
+        }
+        if too_costly(&proofs) {
+            return Ok(false);
+        }
+        let lacks =
+            |types: &[QueryType]| !types.contains(&qtype) && !types.contains(&QueryType::CNAME);
+        // nothing at the name, and no wildcard it could have been made from
+        // at its closest encloser (RFC 4035 section 5.4)
+        let proven = if nxdomain {
+            let by_nsec = proofs.iter().any(|r| {
+                nsec_covers(r, name)
+                    && nsec_encloser(r, name).is_some_and(|encloser| no_wildcard(&proofs, encloser))
+            });
+            by_nsec
+                || nsec3_encloser(&proofs, name, &keys.zone)
+                    .is_some_and(|(encloser, _)| no_wildcard(&proofs, encloser))
+        } else {
+            proofs.iter().any(|r| match &r.rdata {
+                RData::NSEC { types, .. } => r.domain.eq_ignore_ascii_case(name) && lacks(types),
+                RData::NSEC3 { types, .. } => nsec3_matches(r, name) && lacks(types),
+                _ => false,
+            })
+        };
+        if proven {
+            Ok(true)
+        } else {
+            Err(format!("no proof there's no {name} {qtype:?}"))
+        }
+    }
+}
+
+impl<R: Resolver> Resolver for Validator<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let mut resp = self.inner.resolve(question)?;
+        match self.check(question, &resp) {
+            Ok(secure) => {
+                resp.header.ad = secure;
+                Ok(resp)
+            }
+            Err(e) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "bogus answer for {} {:?}: {e}",
+                    question.name, question.r#type
+                ),
+            )),
+        }
+    }
+}
+
+// How the parent's `resp` to a DS query for `name` shows there's none,
+// checked against the parent's keys.
+fn no_ds(parent: &Keys, name: &str, resp: &DnsPacket, now: u32) -> Result<NoDs, String> {
+    let mut proofs = vec![];
+    for (rrset, sigs) in rrsets(&resp.authorities) {
+        if matches!(rrset[0].r#type, QueryType::NSEC | QueryType::NSEC3) {
+            verify(&rrset, &sigs, parent, now)?;
+            proofs.extend(rrset);
+        }
+    }
+    if too_costly(&proofs) {
+        return Ok(NoDs::Unsigned);
+    }
+    let at_cut = |types: &[QueryType]| {
+        if types.contains(&QueryType::DS) {
+            return Err(format!("DS for {name:?} said to be there and not"));
+        }
+        let cut = types.contains(&QueryType::NS) && !types.contains(&QueryType::SOA);
+        Ok(if cut { NoDs::Unsigned } else { NoDs::NotACut })
+    };
+    for proof in &proofs {
+        match &proof.rdata {
+            RData::NSEC { types, .. } if proof.domain.eq_ignore_ascii_case(name) => {
+                return at_cut(types);
+            }
+            RData::NSEC3 { types, .. } if nsec3_matches(proof, name) => return at_cut(types),
+            _ => {}
+        }
+    }
+    // names that aren't there at all aren't cuts either, unless they're
+    // skipped over as insecure delegations (RFC 5155)
+    if proofs.iter().any(|r| nsec_covers(r, name)) {
+        return Ok(NoDs::NotACut);
+    }
+    if let Some((_, opt_out)) = nsec3_encloser(&proofs, name, &parent.zone) {
+        return Ok(if opt_out {
+            NoDs::Unsigned
+        } else {
+            NoDs::NotACut
+        });
+    }
+    Err(format!("no proof there's no DS for {name:?}"))
+}
+
+// The records grouped by name and type, each with the signatures over them.
+fn rrsets(records: &[DnsRecord]) -> Vec<(Vec<DnsRecord>, Vec<&DnsRecord>)> {
+    let mut rrsets: Vec<(Vec<DnsRecord>, Vec<&DnsRecord>)> = vec![];
+    for rec in records.iter().filter(|r| r.r#type != QueryType::RRSIG) {
+        match rrsets
+            .iter_mut()
+            .find(|(rrset, _)| is_at(&rrset[0], &rec.domain, rec.r#type))
+        {
+            Some((rrset, _)) => rrset.push(rec.clone()),
+            None => rrsets.push((vec![rec.clone()], vec![])),
+        }
+    }
+    for (rrset, sigs) in &mut rrsets {
+        sigs.extend(records.iter().filter(|r| match r.rdata {
+            RData::RRSIG { type_covered, .. } => {
+                r.domain.eq_ignore_ascii_case(&rrset[0].domain) && type_covered == rrset[0].r#type
+            }
+            _ => false,
+        }));
+    }
+    rrsets
+}
+
+fn is_at(rec: &DnsRecord, name: &str, r#type: QueryType) -> bool {
+    rec.r#type == r#type && rec.domain.eq_ignore_ascii_case(name)
+}
+
+// Whether one of `sigs` by one of `keys` is good for `rrset` now.
+fn verify(rrset: &[DnsRecord], sigs: &[&DnsRecord], keys: &Keys, now: u32) -> Result<(), String> {
+    for sig in sigs {
+        let RData::RRSIG {
+            algorithm,
+            expiration,
+            inception,
+            key_tag: tag,
+            signer,
+            signature,
+            ..
+        } = &sig.rdata
+        else {
+            continue;
+        };
+        if !signer.eq_ignore_ascii_case(&keys.zone)
+            || is_newer(*inception, now)
+            || is_newer(now, *expiration)
+        {
+            continue;
+        }
+        let data = signed_data(sig, rrset);
+        for key in &keys.dnskeys {
+            let RData::DNSKEY {
+                algorithm: key_algorithm,
+                public_key,
+                ..
+            } = &key.rdata
+            else {
+                continue;
+            };
+            if key_algorithm == algorithm
+                && key_tag(&rdata_bytes(key)) == *tag
+                && verify_signature(*algorithm, &public_key.0, &data, &signature.0)
+            {
+                return Ok(());
+            }
+        }
+    }
+    Err(format!(
+        "no good signature for {} {:?} by {:?}",
+        rrset[0].domain, rrset[0].r#type, keys.zone
+    ))
+}
+
+// What `sig` is a signature over: its own data but the signature, then the
+// records in canonical form and order (RFC 4034).
+fn signed_data(sig: &DnsRecord, rrset: &[DnsRecord]) -> Vec<u8> {
+    let RData::RRSIG {
+        labels: signed_labels,
+        original_ttl,
+        ..
+    } = sig.rdata
+    else {
+        return vec![];
+    };
+    let mut data = rdata_bytes(sig);
+    let RData::RRSIG { signature, .. } = &sig.rdata else {
+        unreachable!();
+    };
+    data.truncate(data.len() - signature.0.len());
+
+    // the wildcard the records were made from, if they were
+    let owner = &rrset[0].domain;
+    let owner_labels: Vec<&str> = owner.labels().collect();
+    let signed_labels = signed_labels as usize;
+    let owner = if signed_labels < owner_labels.len() {
+        let rest = owner_labels[owner_labels.len() - signed_labels..].join(".");
+        let wildcard = if rest.is_empty() {
+            "*".to_string()
+        } else {
+            format!("*.{rest}")
+        };
+        // no longer than the name it stands in for
+        DnsName::new(&wildcard).unwrap_or_else(|_| owner.clone())
+    } else {
+        owner.clone()
+    };
+
+    let prefix = owner.to_canonical_wire().len() + 10;
+    let mut rrs: Vec<Vec<u8>> = rrset
+        .iter()
+        .map(|rec| {
+            let rec = DnsRecord {
+                domain: owner.clone(),
+                ttl: original_ttl,
+                ..rec.clone()
+            };
+            wire(&rec)
+        })
+        .collect();
+    rrs.sort_by(|a, b| a[prefix..].cmp(&b[prefix..]));
+    rrs.dedup();
+    data.extend(rrs.concat());
+    data
+}
+
+// The record in canonical form: without compression, and its names in
+// lowercase, but for those in NSEC records (RFC 6840).
+fn wire(rec: &DnsRecord) -> Vec<u8> {
+    let mut rec = rec.clone();
+    rec.domain.make_ascii_lowercase();
+    match &mut rec.rdata {
+        RData::NS { host }
+        | RData::CNAME { host }
+        | RData::PTR { host }
+        | RData::MX { host, .. } => host.make_ascii_lowercase(),
+        RData::SOA { mname, rname, .. } => {
+            mname.make_ascii_lowercase();
+            rname.make_ascii_lowercase();
+        }
+        RData::SRV { target, .. } => target.make_ascii_lowercase(),
+        RData::NAPTR { replacement, .. } => replacement.make_ascii_lowercase(),
+        RData::RRSIG { signer, .. } => signer.make_ascii_lowercase(),
+        _ => {}
+    }
+    let mut writer = PacketBufWriter::canonical();
+    // only fails for records that couldn't have been read in the first place
+    if rec.to_bytes(&mut writer).is_err() {
+        return vec![];
+    }
+    writer.buf
+}
+
+fn rdata_bytes(rec: &DnsRecord) -> Vec<u8> {
+    let wire = wire(rec);
+    let prefix = rec.domain.to_canonical_wire().len() + 10;
+    wire.get(prefix..).unwrap_or_default().to_vec()
+}
+
+// Whether `nsec` shows there's nothing at `name`, between the name it's at
+// and the next one, or after the last of the zone's names.
+fn nsec_covers(nsec: &DnsRecord, name: &str) -> bool {
+    let RData::NSEC { next, .. } = &nsec.rdata else {
+        return false;
+    };
+    let Ok(name) = DnsName::new(name) else {
+        return false;
+    };
+    let after = nsec.domain < name;
+    let before = name < *next;
+    if nsec.domain < *next {
+        after && before
+    } else {
+        // the last one wraps around to the apex
+        after || before
+    }
+}
+
+// The closest encloser of `name` that `nsec`, covering it, shows: the
+// nearest name above it that the names either side of it are at or below.
+fn nsec_encloser<'a>(nsec: &DnsRecord, name: &'a str) -> Option<&'a str> {
+    let RData::NSEC { next, .. } = &nsec.rdata else {
+        return None;
+    };
+    let mut encloser = name;
+    loop {
+        encloser = encloser.split_once('.').map_or("", |(_, parent)| parent);
+        // every name is within the root, so this ends there at the latest
+        let ancestor = DnsName::new(encloser).ok()?;
+        if nsec.domain.is_within(&ancestor) || next.is_within(&ancestor) {
+            return Some(encloser);
+        }
+    }
+}
+
+// Whether `proofs` show there's no wildcard at `encloser` for a name below
+// it to have been made from.
+fn no_wildcard(proofs: &[DnsRecord], encloser: &str) -> bool {
+    let wildcard = if encloser.is_empty() {
+        "*".to_string()
+    } else {
+        format!("*.{encloser}")
+    };
+    // no room for one below a name this long
+    if DnsName::new(&wildcard).is_err() {
+        return true;
+    }
+    proofs
+        .iter()
+        .any(|r| nsec_covers(r, &wildcard) || nsec3_covers(r, &wildcard).is_some())
+}
+
+// The hash of `name` as NSEC3 records have it: SHA-1 over and over, with
+// the salt each time (RFC 5155).
+fn nsec3_hash(name: &DnsName, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let mut input = name.to_canonical_wire();
+    let mut hash = vec![];
+    for _ in 0..=iterations {
+        input.extend(salt);
+        hash = digest::digest(&SHA1_FOR_LEGACY_USE_ONLY, &input)
+            .as_ref()
+            .to_vec();
+        input = hash.clone();
+    }
+    hash
+}
+
+// The hash of `name`, by `nsec3`'s parameters, and the hashes `nsec3` is at
+// and leads to, all in base32hex. None for those hashed too often to bother.
+fn nsec3_hashes(nsec3: &DnsRecord, name: &str) -> Option<(String, String, String, bool)> {
+    let RData::NSEC3 {
+        hash_algorithm: 1,
+        flags,
+        iterations,
+        salt,
+        next_hashed,
+        ..
+    } = &nsec3.rdata
+    else {
+        return None;
+    };
+    if *iterations > MAX_ITERATIONS {
+        return None;
+    }
+    let name = DnsName::new(name).ok()?;
+    let hash = format!("{:?}", Base32Hex(nsec3_hash(&name, &salt.0, *iterations)));
+    let owner = nsec3.domain.labels().next()?.to_ascii_uppercase();
+    let next = format!("{next_hashed:?}");
+    Some((hash, owner, next, flags & OPT_OUT != 0))
+}
+
+fn nsec3_matches(nsec3: &DnsRecord, name: &str) -> bool {
+    nsec3_hashes(nsec3, name).is_some_and(|(hash, owner, _, _)| hash == owner)
+}
+
+// Whether `nsec3` shows there's nothing at `name`, and whether it may skip
+// over insecure delegations.
+fn nsec3_covers(nsec3: &DnsRecord, name: &str) -> Option<bool> {
+    let (hash, owner, next, opt_out) = nsec3_hashes(nsec3, name)?;
+    let covered = if owner < next {
+        owner < hash && hash < next
+    } else {
+        owner < hash || hash < next
+    };
+    covered.then_some(opt_out)
+}
+
+// Whether `proofs` show there's nothing at `name` in `zone`: the closest
+// name above it that is there, and nothing at the next one down towards it
+// (RFC 5155). Returns the closest encloser, and whether the proof relies on
+// opt-out.
+fn nsec3_encloser<'a>(proofs: &[DnsRecord], name: &'a str, zone: &str) -> Option<(&'a str, bool)> {
+    let mut next_closer = name;
+    while !next_closer.eq_ignore_ascii_case(zone) {
+        let encloser = next_closer.split_once('.').map_or("", |(_, parent)| parent);
+        if proofs.iter().any(|r| nsec3_matches(r, encloser)) {
+            let opt_out = proofs.iter().find_map(|r| nsec3_covers(r, next_closer))?;
+            return Some((encloser, opt_out));
+        }
+        next_closer = encloser;
+    }
+    None
+}
+
+// Whether any of `proofs` is an NSEC3 record hashed too often to check.
+fn too_costly(proofs: &[DnsRecord]) -> bool {
+    proofs
+        .iter()
+        .any(|r| matches!(r.rdata, RData::NSEC3 { iterations, .. } if iterations > MAX_ITERATIONS))
+}
+
+// The key tag of a DNSKEY, from its data (RFC 4034 appendix B).
+fn key_tag(rdata: &[u8]) -> u16 {
+    let mut acc: u32 = 0;
+    for (i, &b) in rdata.iter().enumerate() {
+        acc += if i % 2 == 0 {
+            u32::from(b) << 8
+        } else {
+            u32::from(b)
+        };
+    }
+    acc += acc >> 16;
+    acc as u16
+}
+
+fn digester(digest_type: u8) -> Option<&'static digest::Algorithm> {
+    match digest_type {
+        1 => Some(&SHA1_FOR_LEGACY_USE_ONLY),
+        2 => Some(&SHA256),
+        4 => Some(&SHA384),
+        _ => None,
+    }
+}
+
+// What a DS record for the DNSKEY with data `rdata` at `owner` has as its
+// digest.
+fn ds_digest(digest_type: u8, owner: &str, rdata: &[u8]) -> Option<Vec<u8>> {
+    let mut input = DnsName::new(owner).ok()?.to_canonical_wire();
+    input.extend(rdata);
+    Some(
+        digest::digest(digester(digest_type)?, &input)
+            .as_ref()
+            .to_vec(),
+    )
+}
+
+fn is_supported(algorithm: u8) -> bool {
+    matches!(algorithm, 5 | 7 | 8 | 10 | 13 | 14 | 15)
+}
+
+fn verify_signature(algorithm: u8, key: &[u8], data: &[u8], sig: &[u8]) -> bool {
+    let ecdsa = |alg: &'static signature::EcdsaVerificationAlgorithm| {
+        // the point without the byte that says it's uncompressed
+        let mut point = vec![4];
+        point.extend(key);
+        UnparsedPublicKey::new(alg, point).verify(data, sig).is_ok()
+    };
+    match algorithm {
+        5 | 7 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            key,
+            data,
+            sig,
+        ),
+        8 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+            key,
+            data,
+            sig,
+        ),
+        10 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+            key,
+            data,
+            sig,
+        ),
+        13 => ecdsa(&signature::ECDSA_P256_SHA256_FIXED),
+        14 => ecdsa(&signature::ECDSA_P384_SHA384_FIXED),
+        15 => UnparsedPublicKey::new(&signature::ED25519, key)
+            .verify(data, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+// RSA keys are the exponent's length, in one byte or three when it's
+// longer than 255, then the exponent and the modulus (RFC 3110).
+fn verify_rsa(
+    params: &'static signature::RsaParameters,
+    key: &[u8],
+    data: &[u8],
+    sig: &[u8],
+) -> bool {
+    let (e_len, rest) = match key {
+        [0, hi, lo, rest @ ..] => (usize::from(u16::from_be_bytes([*hi, *lo])), rest),
+        [len, rest @ ..] => (usize::from(*len), rest),
+        [] => return false,
+    };
+    if rest.len() <= e_len {
+        return false;
+    }
+    let (e, n) = rest.split_at(e_len);
+    RsaPublicKeyComponents { n, e }
+        .verify(params, data, sig)
+        .is_ok()
+}
+
+fn now() -> u32 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // as RRSIGs count, wrapping around
+    secs as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::MockResolver;
+    use crate::encoding::{Base64, Hex};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::net::Ipv4Addr;
+
+    // A zone's one key, signing all of its records, and the DS for it.
+    struct Signer {
+        zone: String,
+        pair: Ed25519KeyPair,
+        dnskey: DnsRecord,
+    }
+
+    impl Signer {
+        fn new(zone: &str) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+            let dnskey = record(
+                zone,
+                RData::DNSKEY {
+                    flags: ZONE_KEY | 1,
+                    protocol: 3,
+                    algorithm: 15,
+                    public_key: Base64(pair.public_key().as_ref().to_vec()),
+                },
+            );
+            Self {
+                zone: zone.to_string(),
+                pair,
+                dnskey,
+            }
+        }
+
+        fn ds(&self) -> DnsRecord {
+            let rdata = rdata_bytes(&self.dnskey);
+            record(
+                &self.zone,
+                RData::DS {
+                    key_tag: key_tag(&rdata),
+                    algorithm: 15,
+                    digest_type: 2,
+                    digest: Hex(ds_digest(2, &self.zone, &rdata).unwrap()),
+                },
+            )
+        }
+
+        // The records with a signature over them after them.
+        fn sign(&self, rrset: Vec<DnsRecord>) -> Vec<DnsRecord> {
+            let now = now();
+            let mut sig = record(
+                &rrset[0].domain,
+                RData::RRSIG {
+                    type_covered: rrset[0].r#type,
+                    algorithm: 15,
+                    labels: rrset[0].domain.labels().count() as u8,
+                    original_ttl: 300,
+                    expiration: now.wrapping_add(3600),
+                    inception: now.wrapping_sub(3600),
+                    key_tag: key_tag(&rdata_bytes(&self.dnskey)),
+                    signer: self.zone.parse().unwrap(),
+                    signature: Base64(vec![]),
+                },
+            );
+            let signed = self.pair.sign(&signed_data(&sig, &rrset));
+            let RData::RRSIG { signature, .. } = &mut sig.rdata else {
+                unreachable!();
+            };
+            signature.0 = signed.as_ref().to_vec();
+            let mut records = rrset;
+            records.push(sig);
+            records
+        }
+
+        fn dnskeys(&self) -> DnsPacket {
+            answer(self.sign(vec![self.dnskey.clone()]), vec![])
+        }
+    }
+
+    fn record(name: &str, rdata: RData) -> DnsRecord {
+        let r#type = match rdata {
+            RData::A { .. } => QueryType::A,
+            RData::DNSKEY { .. } => QueryType::DNSKEY,
+            RData::DS { .. } => QueryType::DS,
+            RData::NSEC { .. } => QueryType::NSEC,
+            RData::NSEC3 { .. } => QueryType::NSEC3,
+            RData::RRSIG { .. } => QueryType::RRSIG,
+            _ => unreachable!(),
+        };
+        DnsRecord {
+            domain: name.parse().unwrap(),
+            r#type,
+            class: 1,
+            ttl: 300,
+            rdata,
+        }
+    }
+
+    fn a(name: &str, last: u8) -> DnsRecord {
+        record(
+            name,
+            RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, last),
+            },
+        )
+    }
+
+    fn nsec(name: &str, next: &str, types: &[QueryType]) -> DnsRecord {
+        record(
+            name,
+            RData::NSEC {
+                next: next.parse().unwrap(),
+                types: types.to_vec(),
+            },
+        )
+    }
+
+    // The only NSEC3 record of example.com, at its apex and leading back to
+    // it, so covering every other name.
+    fn nsec3(iterations: u16) -> DnsRecord {
+        let apex = DnsName::new("example.com").unwrap();
+        let hash = nsec3_hash(&apex, &[0xab], iterations);
+        record(
+            &format!("{:?}.example.com", Base32Hex(hash.clone())),
+            RData::NSEC3 {
+                hash_algorithm: 1,
+                flags: 0,
+                iterations,
+                salt: Hex(vec![0xab]),
+                next_hashed: Base32Hex(hash),
+                types: vec![QueryType::SOA, QueryType::RRSIG, QueryType::DNSKEY],
+            },
+        )
+    }
+
+    fn answer(answers: Vec<DnsRecord>, authorities: Vec<DnsRecord>) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.qr = true;
+        resp.header.ancount = answers.len() as u16;
+        resp.header.nscount = authorities.len() as u16;
+        resp.answers = answers;
+        resp.authorities = authorities;
+        resp
+    }
+
+    fn question(name: &str) -> DnsQuestion {
+        query(name, QueryType::A).unwrap().questions.remove(0)
+    }
+
+    // com, trusted as an anchor, with example.com signed below it and
+    // insecure.com not.
+    fn validator() -> Validator<MockResolver> {
+        let com = Signer::new("com");
+        let example = Signer::new("example.com");
+        let in_zone = [QueryType::A, QueryType::RRSIG, QueryType::NSEC];
+
+        let mut tampered = example.sign(vec![a("tampered.example.com", 1)]);
+        tampered[0] = a("tampered.example.com", 66);
+        let mut nxdomain = answer(
+            vec![],
+            example.sign(vec![nsec("example.com", "tampered.example.com", &[])]),
+        );
+        nxdomain.header.rcode = RCode::Nxdomain;
+        // nothing between m and o, but nothing said about *.example.com
+        let mut unproven = answer(
+            vec![],
+            example.sign(vec![nsec("m.example.com", "o.example.com", &in_zone)]),
+        );
+        unproven.header.rcode = RCode::Nxdomain;
+        let hashed = |iterations| {
+            let mut resp = answer(vec![], example.sign(vec![nsec3(iterations)]));
+            resp.header.rcode = RCode::Nxdomain;
+            resp
+        };
+
+        let mock = MockResolver::default()
+            .with_answer("com", QueryType::DNSKEY, com.dnskeys())
+            .with_answer("example.com", QueryType::DNSKEY, example.dnskeys())
+            .with_answer(
+                "example.com",
+                QueryType::DS,
+                answer(com.sign(vec![example.ds()]), vec![]),
+            )
+            .with_answer(
+                "www.example.com",
+                QueryType::A,
+                answer(example.sign(vec![a("www.example.com", 1)]), vec![]),
+            )
+            .with_answer(
+                "tampered.example.com",
+                QueryType::A,
+                answer(tampered, vec![]),
+            )
+            .with_answer(
+                "stripped.example.com",
+                QueryType::A,
+                answer(vec![a("stripped.example.com", 1)], vec![]),
+            )
+            .with_answer(
+                "stripped.example.com",
+                QueryType::DS,
+                answer(
+                    vec![],
+                    example.sign(vec![nsec(
+                        "stripped.example.com",
+                        "tampered.example.com",
+                        &in_zone,
+                    )]),
+                ),
+            )
+            .with_answer("nowhere.example.com", QueryType::A, nxdomain.clone())
+            .with_answer("nowhere.example.com", QueryType::DS, nxdomain)
+            .with_answer("nowild.example.com", QueryType::A, unproven.clone())
+            .with_answer("nowild.example.com", QueryType::DS, unproven)
+            .with_answer("hashed.example.com", QueryType::A, hashed(1))
+            .with_answer("hashed.example.com", QueryType::DS, hashed(1))
+            .with_answer(
+                "costly.example.com",
+                QueryType::A,
+                hashed(MAX_ITERATIONS + 1),
+            )
+            .with_answer(
+                "costly.example.com",
+                QueryType::DS,
+                hashed(MAX_ITERATIONS + 1),
+            )
+            .with_answer(
+                "insecure.com",
+                QueryType::DS,
+                answer(
+                    vec![],
+                    com.sign(vec![nsec(
+                        "insecure.com",
+                        "zzz.com",
+                        &[QueryType::NS, QueryType::RRSIG, QueryType::NSEC],
+                    )]),
+                ),
+            )
+            .with_answer(
+                "www.insecure.com",
+                QueryType::A,
+                answer(vec![a("www.insecure.com", 1)], vec![]),
+            );
+        let anchors = Zone::new("com".parse().unwrap(), vec![com.ds()]);
+        Validator::with_anchors(mock, &anchors).unwrap()
+    }
+
+    #[test]
+    fn chains_of_trust() {
+        let validator = validator();
+
+        let resp = validator.resolve(&question("www.example.com")).unwrap();
+        assert!(resp.header.ad);
+        assert_eq!(resp.answers.len(), 2);
+
+        // proven not to be there
+        let resp = validator.resolve(&question("nowhere.example.com")).unwrap();
+        assert!(resp.header.ad);
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+
+        let resp = validator.resolve(&question("hashed.example.com")).unwrap();
+        assert!(resp.header.ad);
+        // too costly to check, so taken as insecure
+        let resp = validator.resolve(&question("costly.example.com")).unwrap();
+        assert!(!resp.header.ad);
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+
+        // below a delegation proven not to be signed
+        let resp = validator.resolve(&question("www.insecure.com")).unwrap();
+        assert!(!resp.header.ad);
+
+        for bogus in [
+            "tampered.example.com",
+            "stripped.example.com",
+            // a wildcard might have answered it
+            "nowild.example.com",
+        ] {
+            let e = validator.resolve(&question(bogus)).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{bogus}: {e}");
+        }
+        let e = validator
+            .resolve(&question("nowild.example.com"))
+            .unwrap_err();
+        assert!(e.to_string().contains("no proof"), "{e}");
+    }
+
+    #[test]
+    fn key_tags() {
+        // the root's KSK-2024, whose tag is 38696
+        let key = "AwEAAa96jeuknZlaeSrvyAJj6ZHv28hhOKkx3rLGXVaC6rXTsDc449/cidltpkyGwCJNnOAlFNKF2jBosZBU5eeHspaQWOmOElZsjICMQMC3aeHbGiShvZsx4wMYSjH8e7Vrhbu6irwCzVBApESjbUdpWWmEnhathWu1jo+siFUiRAAxm9qyJNg/wOZqqzL/dL/q8PkcRU5oUKEpUge71M3ej2/7CPqpdVwuMoTvoB+ZOT4YeGyxMvHmbrxlFzGOHOijtzN+u1TQNatX2XBuzZNQ1K+s2CXkPIZo7s6JgZyvaBevYtxPvYLw4z9mR7K2vaF18UYH9Z9GNUUeayffKC73PYc=";
+        let mut bits = 0u32;
+        let mut held = 0;
+        let mut public_key = vec![];
+        for c in key.bytes().filter(|&c| c != b'=') {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                _ => 63,
+            };
+            bits = (bits << 6) | u32::from(value);
+            held += 6;
+            if held >= 8 {
+                held -= 8;
+                public_key.push((bits >> held) as u8);
+            }
+        }
+        let dnskey = record(
+            "",
+            RData::DNSKEY {
+                flags: 257,
+                protocol: 3,
+                algorithm: 8,
+                public_key: Base64(public_key),
+            },
+        );
+        let rdata = rdata_bytes(&dnskey);
+        assert_eq!(key_tag(&rdata), 38696);
+
+        // and its digest is the one trusted
+        let anchors = Zone::parse(ROOT_ANCHORS, "").unwrap();
+        let RData::DS { digest, .. } = &anchors.records()[1].rdata else {
+            panic!("not a DS");
+        };
+        assert_eq!(ds_digest(2, "", &rdata).as_ref(), Some(&digest.0));
+    }
+}
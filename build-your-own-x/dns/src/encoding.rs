@@ -0,0 +1,111 @@
+use std::fmt;
+
+// Binary fields of records, shown the way zone files write them rather than
+// as lists of numbers.
+
+// keys and signatures
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub(crate) struct Base64(pub Vec<u8>);
+
+// digests and salts
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub(crate) struct Hex(pub Vec<u8>);
+
+// hashed owner names (RFC 4648, with the "extended hex" alphabet so that they
+// sort the same as the hashes do)
+#[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub(crate) struct Base32Hex(pub Vec<u8>);
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE32HEX: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+// Writes `bytes` `bits` at a time as characters of `alphabet`, padded with
+// '=' to a whole number of `group` characters.
+fn encode(
+    f: &mut fmt::Formatter<'_>,
+    bytes: &[u8],
+    alphabet: &[u8],
+    bits: u32,
+    group: usize,
+) -> fmt::Result {
+    let mask = (1 << bits) - 1;
+    let mut acc = 0u32;
+    let mut held = 0;
+    let mut written = 0usize;
+    for &byte in bytes {
+        acc = (acc << 8) | u32::from(byte);
+        held += 8;
+        while held >= bits {
+            held -= bits;
+            write!(f, "{}", alphabet[((acc >> held) & mask) as usize] as char)?;
+            written += 1;
+        }
+    }
+    if held > 0 {
+        write!(
+            f,
+            "{}",
+            alphabet[((acc << (bits - held)) & mask) as usize] as char
+        )?;
+        written += 1;
+    }
+    while !written.is_multiple_of(group) {
+        f.write_str("=")?;
+        written += 1;
+    }
+    Ok(())
+}
+
+impl fmt::Debug for Base64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        encode(f, &self.0, BASE64, 6, 4)
+    }
+}
+
+impl fmt::Debug for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // a lone "-" for none at all, as for NSEC3 salts
+        if self.0.is_empty() {
+            return f.write_str("-");
+        }
+        self.0.iter().try_for_each(|b| write!(f, "{b:02X}"))
+    }
+}
+
+impl fmt::Debug for Base32Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // unpadded, as NSEC3 records have it
+        encode(f, &self.0, BASE32HEX, 5, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        // the test vectors from RFC 4648
+        for (bytes, base64, base32hex) in [
+            ("", "", ""),
+            ("f", "Zg==", "CO"),
+            ("fo", "Zm8=", "CPNG"),
+            ("foo", "Zm9v", "CPNMU"),
+            ("foob", "Zm9vYg==", "CPNMUOG"),
+            ("fooba", "Zm9vYmE=", "CPNMUOJ1"),
+            ("foobar", "Zm9vYmFy", "CPNMUOJ1E8"),
+        ] {
+            let bytes = bytes.as_bytes().to_vec();
+            assert_eq!(format!("{:?}", Base64(bytes.clone())), base64);
+            assert_eq!(format!("{:?}", Base32Hex(bytes)), base32hex);
+        }
+        assert_eq!(format!("{:?}", Hex(vec![0xAA, 0xBB, 0x0C])), "AABB0C");
+        assert_eq!(format!("{:?}", Hex(vec![])), "-");
+    }
+}
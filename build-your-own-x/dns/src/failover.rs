@@ -0,0 +1,356 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+use crate::{DnsName, DnsPacket, DnsQuestion, QueryType, RCode, Resolver};
+
+// after this many failures in a row, a resolver is left alone until a probe
+// finds it answering again
+const MAX_FAILURES: usize = 3;
+// how many threads racing to answer may be running at once, those left behind
+// by earlier races included, past which groups are asked in turn instead
+const MAX_RACERS: usize = 32;
+
+#[derive(Debug)]
+struct Member<R> {
+    resolver: R,
+    // in a row, timeouts and SERVFAILs alike
+    failures: AtomicUsize,
+}
+
+impl<R> Member<R> {
+    fn is_healthy(&self) -> bool {
+        self.failures.load(Ordering::Relaxed) < MAX_FAILURES
+    }
+}
+
+// Asks the first of its resolvers that's healthy, moving on to the next when
+// it fails or answers SERVFAIL. Those that do that too often in a row are
+// passed over until `probe` finds them answering again, unless they all have,
+// when they're all asked in turn anyway.
+#[derive(Debug)]
+pub struct Failover<R> {
+    members: Vec<Arc<Member<R>>>,
+    // how many are asked at once
+    fan_out: usize,
+    // the threads asking for races that are still running
+    racers: Arc<AtomicUsize>,
+}
+
+impl<R: Resolver + fmt::Display + Send + Sync + 'static> Failover<R> {
+    pub fn new(resolvers: Vec<R>) -> Self {
+        let members = resolvers
+            .into_iter()
+            .map(|resolver| {
+                Arc::new(Member {
+                    resolver,
+                    failures: AtomicUsize::new(0),
+                })
+            })
+            .collect();
+        Self {
+            members,
+            fan_out: 1,
+            racers: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Asks `fan_out` of them at once, each on a thread of its own, taking the
+    // first answer and leaving the others to finish unheard, for upstreams
+    // whose latency varies. The next `fan_out` are asked when none of them
+    // answers. While too many of those left behind are still running, they're
+    // asked in turn rather than piling up more threads.
+    pub fn with_fan_out(mut self, fan_out: usize) -> Self {
+        self.fan_out = fan_out.max(1);
+        self
+    }
+
+    // the resolvers that are being passed over
+    pub fn unhealthy(&self) -> impl Iterator<Item = &R> {
+        self.members
+            .iter()
+            .filter(|m| !m.is_healthy())
+            .map(|m| &m.resolver)
+    }
+
+    // Asks each of the unhealthy resolvers for the root's NS records, putting
+    // those that answer back in use.
+    pub fn probe(&self) {
+        let question = DnsQuestion::new(DnsName::root(), QueryType::NS);
+        for member in self.members.iter().filter(|m| !m.is_healthy()) {
+            if ask(member, &question).is_ok() {
+                eprintln!("{} is answering again", member.resolver);
+            }
+        }
+    }
+}
+
+// Asks `member`, keeping count of how it went.
+fn ask<R: Resolver + fmt::Display>(
+    member: &Member<R>,
+    question: &DnsQuestion,
+) -> io::Result<DnsPacket> {
+    let answered = member.resolver.resolve(question).and_then(|resp| {
+        if resp.header.rcode == RCode::Servfail {
+            return Err(io::Error::other("SERVFAIL"));
+        }
+        Ok(resp)
+    });
+    match answered {
+        Ok(_) => member.failures.store(0, Ordering::Relaxed),
+        Err(_) => {
+            if member.failures.fetch_add(1, Ordering::Relaxed) + 1 == MAX_FAILURES {
+                eprintln!(
+                    "{} failed {MAX_FAILURES} times in a row, passing it over",
+                    member.resolver
+                );
+            }
+        }
+    }
+    answered
+}
+
+// Asks each of `members` in turn, answering with the first of them to answer.
+fn in_turn<R: Resolver + fmt::Display>(
+    members: &[&Arc<Member<R>>],
+    question: &DnsQuestion,
+) -> io::Result<DnsPacket> {
+    let mut last = io::Error::other("nobody to ask");
+    for member in members {
+        match ask(member, question) {
+            Ok(resp) => return Ok(resp),
+            Err(e) => {
+                eprintln!("Could not ask {}: {e}", member.resolver);
+                last = e;
+            }
+        }
+    }
+    Err(last)
+}
+
+// Asks all of `members` at once, answering with the first of them to answer,
+// unless there's just the one or `racers` has no room for them.
+fn race<R: Resolver + fmt::Display + Send + Sync + 'static>(
+    members: &[&Arc<Member<R>>],
+    question: &DnsQuestion,
+    racers: &Arc<AtomicUsize>,
+) -> io::Result<DnsPacket> {
+    let n = members.len();
+    if n < 2 {
+        return in_turn(members, question);
+    }
+    let room = racers.fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+        (running + n <= MAX_RACERS).then_some(running + n)
+    });
+    if room.is_err() {
+        eprintln!("Too many earlier queries still running, asking in turn");
+        return in_turn(members, question);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    for &member in members {
+        let (member, question, tx) = (member.clone(), question.clone(), tx.clone());
+        let racers = racers.clone();
+        thread::spawn(move || {
+            let answered = ask(&member, &question);
+            racers.fetch_sub(1, Ordering::AcqRel);
+            // nobody is listening once another has answered
+            let _ = tx.send((member, answered));
+        });
+    }
+    drop(tx);
+
+    let mut last = io::Error::other("nobody to ask");
+    for (member, answered) in rx {
+        match answered {
+            Ok(resp) => return Ok(resp),
+            Err(e) => {
+                eprintln!("Could not ask {}: {e}", member.resolver);
+                last = e;
+            }
+        }
+    }
+    Err(last)
+}
+
+impl<R: Resolver + fmt::Display + Send + Sync + 'static> Resolver for Failover<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let healthy: Vec<_> = self.members.iter().filter(|m| m.is_healthy()).collect();
+        let members = if healthy.is_empty() {
+            self.members.iter().collect()
+        } else {
+            healthy
+        };
+        let mut last = io::Error::other("nobody to ask");
+        for group in members.chunks(self.fan_out) {
+            match race(group, question, &self.racers) {
+                Ok(resp) => return Ok(resp),
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::MockResolver;
+    use std::time::{Duration, Instant};
+
+    // taking as long as it says to answer
+    struct Named(&'static str, MockResolver, Duration);
+
+    impl Resolver for Named {
+        fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+            let answered = self.1.resolve(question);
+            thread::sleep(self.2);
+            answered
+        }
+    }
+
+    impl fmt::Display for Named {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    fn answer(rcode: RCode) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.rcode = rcode;
+        resp
+    }
+
+    #[test]
+    fn failover() {
+        let question = DnsQuestion::new("example.com".parse().unwrap(), QueryType::A);
+        let root = DnsQuestion::new(DnsName::root(), QueryType::NS);
+        let failover = Failover::new(vec![
+            // answers nothing but probes
+            Named(
+                "dead",
+                MockResolver::default().with_answer("", QueryType::NS, answer(RCode::Noerror)),
+                Duration::ZERO,
+            ),
+            Named(
+                "broken",
+                MockResolver::default().with_answer(
+                    "example.com",
+                    QueryType::A,
+                    answer(RCode::Servfail),
+                ),
+                Duration::ZERO,
+            ),
+            Named(
+                "working",
+                MockResolver::default().with_answer(
+                    "example.com",
+                    QueryType::A,
+                    answer(RCode::Noerror),
+                ),
+                Duration::ZERO,
+            ),
+        ]);
+        let asked = |i: usize| failover.members[i].resolver.1.asked();
+
+        for _ in 0..MAX_FAILURES {
+            let resp = failover.resolve(&question).unwrap();
+            assert_eq!(resp.header.rcode, RCode::Noerror);
+        }
+        let unhealthy: Vec<_> = failover.unhealthy().map(|r| r.0).collect();
+        assert_eq!(unhealthy, ["dead", "broken"]);
+        assert_eq!((asked(0), asked(1), asked(2)), (3, 3, 3));
+        // passed over from then on
+        failover.resolve(&question).unwrap();
+        assert_eq!((asked(0), asked(1), asked(2)), (3, 3, 4));
+
+        // the first answers probes, the second doesn't
+        failover.probe();
+        let unhealthy: Vec<_> = failover.unhealthy().map(|r| r.0).collect();
+        assert_eq!(unhealthy, ["broken"]);
+        assert!(failover.resolve(&root).is_ok());
+        assert_eq!(asked(0), 5);
+
+        // asked anyway when none are healthy
+        let failover = Failover::new(vec![Named("dead", MockResolver::default(), Duration::ZERO)]);
+        for _ in 0..=MAX_FAILURES {
+            assert!(failover.resolve(&question).is_err());
+        }
+        assert_eq!(failover.members[0].resolver.1.asked(), MAX_FAILURES + 1);
+    }
+
+    #[test]
+    fn fan_out() {
+        let question = DnsQuestion::new("example.com".parse().unwrap(), QueryType::A);
+        let named = |name, delay| {
+            let mock = MockResolver::default().with_answer(
+                "example.com",
+                QueryType::A,
+                answer(RCode::Noerror),
+            );
+            Named(name, mock, Duration::from_millis(delay))
+        };
+        let failover = Failover::new(vec![
+            named("slow", 2000),
+            named("fast", 0),
+            named("spare", 0),
+        ])
+        .with_fan_out(2);
+
+        let start = Instant::now();
+        failover.resolve(&question).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let asked: Vec<_> = failover
+            .members
+            .iter()
+            .map(|m| m.resolver.1.asked())
+            .collect();
+        assert_eq!(asked, [1, 1, 0]);
+
+        // on to the next two when neither answers
+        let failover = Failover::new(vec![
+            Named("dead", MockResolver::default(), Duration::ZERO),
+            Named("dead too", MockResolver::default(), Duration::ZERO),
+            named("working", 0),
+        ])
+        .with_fan_out(2);
+        failover.resolve(&question).unwrap();
+        assert_eq!(failover.members[2].resolver.1.asked(), 1);
+    }
+
+    #[test]
+    fn fan_out_is_bounded() {
+        let question = DnsQuestion::new("example.com".parse().unwrap(), QueryType::A);
+        let named = |name, delay| {
+            let mock = MockResolver::default().with_answer(
+                "example.com",
+                QueryType::A,
+                answer(RCode::Noerror),
+            );
+            Named(name, mock, Duration::from_millis(delay))
+        };
+        let failover = Failover::new(vec![named("slow", 200), named("fast", 0)]).with_fan_out(2);
+
+        // with the losers of earlier races still running, asked in turn
+        failover.racers.store(MAX_RACERS - 1, Ordering::Relaxed);
+        let start = Instant::now();
+        failover.resolve(&question).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        let asked: Vec<_> = failover
+            .members
+            .iter()
+            .map(|m| m.resolver.1.asked())
+            .collect();
+        assert_eq!(asked, [1, 0]);
+
+        // and raced again once they're done, without leaving any behind
+        failover.racers.store(0, Ordering::Relaxed);
+        failover.resolve(&question).unwrap();
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(failover.racers.load(Ordering::Relaxed), 0);
+        assert_eq!(failover.members[1].resolver.1.asked(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+
+use crate::{DnsName, DnsPacket, DnsQuestion, DnsRecord, QueryType, RData, Resolver};
+
+// how long answers for names of our own may be kept
+const LOCAL_TTL: u32 = 60;
+
+// the most CNAMEs followed from one name to the next
+const MAX_CNAMES: usize = 8;
+
+// Answers for the names it has records for itself, before asking `inner`
+// about anything, so that names can be pinned to addresses of our choosing.
+// Those records are given as in /etc/hosts, an address and then its names,
+// or as a name, CNAME and where it leads:
+//
+//     192.168.1.10  nas.lab  nas
+//     fd00::10      nas.lab
+//     files.lab     CNAME    nas.lab
+//
+// Names it has records for, but not of the type asked for, have none of
+// that type.
+#[derive(Debug)]
+pub struct Hosts<R> {
+    inner: R,
+    records: HashMap<DnsName, Vec<DnsRecord>>,
+}
+
+impl<R: Resolver> Hosts<R> {
+    pub fn new(inner: R, text: &str) -> Result<Self, String> {
+        let mut records: HashMap<DnsName, Vec<DnsRecord>> = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line);
+            let err = |e: String| format!("line {}: {e}", i + 1);
+            for record in parse_line(line).map_err(err)? {
+                let at = records.entry(record.domain.clone()).or_default();
+                let cname = |r: &DnsRecord| r.r#type == QueryType::CNAME;
+                if at.iter().any(cname) || (cname(&record) && !at.is_empty()) {
+                    return Err(err(format!(
+                        "{} has a CNAME and other records",
+                        record.domain
+                    )));
+                }
+                at.push(record);
+            }
+        }
+        Ok(Self { inner, records })
+    }
+
+    // how many names it has records for
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+fn record(name: &str, r#type: QueryType, rdata: RData) -> Result<DnsRecord, String> {
+    let domain = DnsName::new(name).map_err(|e| format!("{name:?} isn't a name: {e}"))?;
+    Ok(DnsRecord {
+        domain,
+        r#type,
+        class: 1,
+        ttl: LOCAL_TTL,
+        rdata,
+    })
+}
+
+fn parse_line(line: &str) -> Result<Vec<DnsRecord>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words[..] {
+        [] => Ok(vec![]),
+        [name, cname, host] if cname.eq_ignore_ascii_case("CNAME") => {
+            let host = DnsName::new(host).map_err(|e| format!("{host:?} isn't a name: {e}"))?;
+            Ok(vec![record(name, QueryType::CNAME, RData::CNAME { host })?])
+        }
+        [addr, ref names @ ..] => {
+            let ip: IpAddr = addr
+                .parse()
+                .map_err(|_| format!("expected an address or NAME CNAME NAME, not {line:?}"))?;
+            if names.is_empty() {
+                return Err(format!("no names for {ip}"));
+            }
+            let (r#type, rdata) = match ip {
+                IpAddr::V4(ip) => (QueryType::A, RData::A { ip }),
+                IpAddr::V6(ip) => (QueryType::AAAA, RData::AAAA { ip }),
+            };
+            names
+                .iter()
+                .map(|name| record(name, r#type, rdata.clone()))
+                .collect()
+        }
+    }
+}
+
+impl<R: Resolver> Resolver for Hosts<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let Some(mut records) = self.records.get(&question.name) else {
+            return self.inner.resolve(question);
+        };
+
+        let mut resp = DnsPacket::new_empty();
+        for _ in 0..MAX_CNAMES {
+            let matching = records.iter().filter(|r| r.r#type == question.r#type);
+            let before = resp.answers.len();
+            resp.answers.extend(matching.cloned());
+            if resp.answers.len() > before {
+                break;
+            }
+
+            let cname = records.iter().find_map(|r| match &r.rdata {
+                RData::CNAME { host } => Some((r, host)),
+                _ => None,
+            });
+            let Some((cname, host)) = cname else {
+                break;
+            };
+            resp.answers.push(cname.clone());
+            match self.records.get(host) {
+                Some(theirs) => records = theirs,
+                // where it leads is for `inner` to answer for
+                None => {
+                    let mut there = self
+                        .inner
+                        .resolve(&DnsQuestion::new(host.clone(), question.r#type))?;
+                    resp.header.rcode = there.header.rcode;
+                    resp.answers.append(&mut there.answers);
+                    break;
+                }
+            }
+        }
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{MockResolver, RCode};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn question(name: &str, qtype: QueryType) -> DnsQuestion {
+        DnsQuestion::new(name.parse().unwrap(), qtype)
+    }
+
+    #[test]
+    fn pinned_names() {
+        let text = "\
+192.168.1.10  nas.lab  NAS  # two names
+fd00::10      nas.lab
+files.lab     CNAME    nas.lab
+www.lab       cname    www.example.com
+";
+        let mut www = DnsPacket::new_empty();
+        www.answers.push(
+            record(
+                "www.example.com",
+                QueryType::A,
+                RData::A {
+                    ip: Ipv4Addr::LOCALHOST,
+                },
+            )
+            .unwrap(),
+        );
+        let inner = MockResolver::default().with_answer("www.example.com", QueryType::A, www);
+        let hosts = Hosts::new(inner, text).unwrap();
+        assert_eq!(hosts.len(), 4);
+
+        let resp = hosts.resolve(&question("nas", QueryType::A)).unwrap();
+        assert_eq!(
+            resp.answers[0].rdata,
+            RData::A {
+                ip: Ipv4Addr::new(192, 168, 1, 10)
+            }
+        );
+        let resp = hosts
+            .resolve(&question("files.lab", QueryType::AAAA))
+            .unwrap();
+        let answers: Vec<_> = resp.answers.iter().map(|r| r.rdata.clone()).collect();
+        assert_eq!(
+            answers,
+            [
+                RData::CNAME {
+                    host: "nas.lab".parse().unwrap()
+                },
+                RData::AAAA {
+                    ip: Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0x10)
+                }
+            ]
+        );
+        // none of the type
+        let resp = hosts.resolve(&question("nas.lab", QueryType::MX)).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert!(resp.answers.is_empty());
+        assert_eq!(hosts.inner.asked(), 0);
+
+        // from elsewhere
+        let resp = hosts.resolve(&question("www.lab", QueryType::A)).unwrap();
+        assert_eq!(resp.answers.len(), 2);
+        assert!(hosts.resolve(&question("other.lab", QueryType::A)).is_err());
+        assert_eq!(hosts.inner.asked(), 2);
+    }
+
+    #[test]
+    fn bad_lines() {
+        let inner = || MockResolver::default();
+        assert!(Hosts::new(inner(), "192.168.1.10\n").is_err());
+        assert!(Hosts::new(inner(), "nas.lab 192.168.1.10\n").is_err());
+        assert!(Hosts::new(inner(), "192.168.1.10 nas..lab\n").is_err());
+        let e = Hosts::new(inner(), "\n192.168.1.10 a.lab\na.lab CNAME b.lab\n").unwrap_err();
+        assert_eq!(e, "line 3: a.lab has a CNAME and other records");
+    }
+}
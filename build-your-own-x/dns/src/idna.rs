@@ -0,0 +1,201 @@
+// Internationalized names (RFC 5890): labels with more than ASCII in them go
+// on the wire as "xn--" and the Punycode of the label (RFC 3492), their ACE
+// form.
+
+const ACE_PREFIX: &str = "xn--";
+
+const BASE: u32 = 36;
+const TMIN: u32 = 1;
+const TMAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 128;
+
+// The label as it goes on the wire, or None for one too long to encode. Only
+// its non-ASCII letters are put in lowercase, as DNS doesn't do that for
+// them, which leaves the rest the case they were asked in.
+pub(crate) fn to_ascii(label: &str) -> Option<String> {
+    if label.is_ascii() {
+        return Some(label.to_string());
+    }
+    let chars: Vec<char> = label
+        .chars()
+        .flat_map(|c| {
+            if c.is_ascii() {
+                vec![c]
+            } else {
+                c.to_lowercase().collect()
+            }
+        })
+        .collect();
+    Some(format!("{ACE_PREFIX}{}", encode(&chars)?))
+}
+
+// The label in Unicode, or None for one that isn't in ACE form.
+pub(crate) fn to_unicode(label: &str) -> Option<String> {
+    let prefix = label.get(..ACE_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(ACE_PREFIX) {
+        return None;
+    }
+    decode(&label[ACE_PREFIX.len()..])
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    if k <= bias {
+        TMIN
+    } else if k >= bias + TMAX {
+        TMAX
+    } else {
+        k - bias
+    }
+}
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > ((BASE - TMIN) * TMAX) / 2 {
+        delta /= BASE - TMIN;
+        k += BASE;
+    }
+    k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+fn digit(d: u32) -> char {
+    match d {
+        0..=25 => (b'a' + d as u8) as char,
+        _ => (b'0' + (d - 26) as u8) as char,
+    }
+}
+
+fn value(c: u8) -> Option<u32> {
+    match c {
+        b'a'..=b'z' => Some(u32::from(c - b'a')),
+        b'A'..=b'Z' => Some(u32::from(c - b'A')),
+        b'0'..=b'9' => Some(u32::from(c - b'0') + 26),
+        _ => None,
+    }
+}
+
+// The ASCII characters as they are, then where each of the others goes,
+// in order of code point.
+fn encode(input: &[char]) -> Option<String> {
+    let mut output: String = input.iter().filter(|c| c.is_ascii()).collect();
+    let basic = output.len() as u32;
+    let mut handled = basic;
+    if basic > 0 {
+        output.push('-');
+    }
+
+    let mut n = INITIAL_N;
+    let mut delta: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    while (handled as usize) < input.len() {
+        let m = input.iter().map(|&c| c as u32).filter(|&c| c >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+        for &c in input {
+            let c = c as u32;
+            if c < n {
+                delta = delta.checked_add(1)?;
+            }
+            if c == n {
+                let mut q = delta;
+                let mut k = BASE;
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+                    output.push(digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+                output.push(digit(q));
+                bias = adapt(delta, handled + 1, handled == basic);
+                delta = 0;
+                handled += 1;
+            }
+        }
+        delta = delta.checked_add(1)?;
+        n += 1;
+    }
+    Some(output)
+}
+
+fn decode(input: &str) -> Option<String> {
+    let (basic, rest) = match input.rfind('-') {
+        Some(i) => (&input[..i], &input[i + 1..]),
+        None => ("", input),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output: Vec<char> = basic.chars().collect();
+
+    let mut n = INITIAL_N;
+    let mut i: u32 = 0;
+    let mut bias = INITIAL_BIAS;
+    let mut digits = rest.bytes().peekable();
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut w: u32 = 1;
+        let mut k = BASE;
+        loop {
+            let d = value(digits.next()?)?;
+            i = i.checked_add(d.checked_mul(w)?)?;
+            let t = threshold(k, bias);
+            if d < t {
+                break;
+            }
+            w = w.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+        let len = output.len() as u32 + 1;
+        bias = adapt(i - old_i, len, old_i == 0);
+        n = n.checked_add(i / len)?;
+        i %= len;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{DnsPacket, QueryType, query};
+
+    #[test]
+    fn punycode() {
+        for (unicode, ace) in [
+            ("bücher", "xn--bcher-kva"),
+            ("münchen", "xn--mnchen-3ya"),
+            // from RFC 3492
+            ("他们为什么不说中文", "xn--ihqwcrb4cv8a8dqg056pqjye"),
+            ("3年B組金八先生", "xn--3B-ww4c5e180e575a65lsy2b"),
+        ] {
+            assert_eq!(to_ascii(unicode).unwrap(), ace);
+            assert_eq!(to_unicode(ace).unwrap(), unicode);
+        }
+        // letters beyond ASCII put in lowercase, as DNS won't
+        assert_eq!(to_ascii("BÜCHER").unwrap(), "xn--BCHER-kva");
+        assert_eq!(to_ascii("example").unwrap(), "example");
+        assert_eq!(to_unicode("example"), None);
+        assert_eq!(to_unicode("xn--!"), None);
+    }
+
+    #[test]
+    fn names_on_the_wire() {
+        let req = query("bücher.example", QueryType::A)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let ace = DnsPacket::from_bytes(&req).unwrap();
+        assert_eq!(ace.questions[0].name, "xn--bcher-kva.example");
+        let unicode = DnsPacket::from_bytes_unicode(&req).unwrap();
+        assert_eq!(unicode.questions[0].name, "bücher.example");
+    }
+}
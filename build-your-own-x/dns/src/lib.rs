@@ -1,68 +1,225 @@
 #![allow(unused)]
 
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
 use std::io;
-#[cfg(test)]
-use std::io::Read;
-use std::net::{Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use encoding::{Base32Hex, Base64, Hex};
+use ring::rand::{SecureRandom, SystemRandom};
+
+mod async_resolver;
+mod authority;
+mod blocklist;
+mod cache;
+mod cookie;
+mod dnssec;
+mod encoding;
+mod failover;
+mod hosts;
+mod idna;
+mod name;
+mod notify;
+#[cfg(feature = "doq")]
+mod quic;
+mod resolv_conf;
+mod resolver;
+mod server;
+mod tls;
+mod transfer;
+mod update;
+mod upstream;
+mod zone;
+
+pub use async_resolver::AsyncResolver;
+pub use authority::Authority;
+pub use blocklist::{BlockWith, Blocklist};
+pub use cache::Cache;
+pub use dnssec::Validator;
+pub use failover::Failover;
+pub use hosts::Hosts;
+pub use name::DnsName;
+pub use notify::notify;
+#[cfg(feature = "doq")]
+pub use quic::QuicUpstream;
+pub use resolv_conf::{ResolvConf, lookup_system};
+pub use resolver::{MockResolver, Recursive, Resolver};
+pub use server::{handle_connection, handle_datagram};
+pub use tls::TlsUpstream;
+pub use transfer::{axfr, ixfr};
+pub use update::{Change, Network, Prerequisite, Update};
+pub use upstream::Upstream;
+pub use zone::{Zone, ZoneError};
 
 pub const PACKET_SIZE: usize = 512;
+// how big a UDP answer to ask for with EDNS, as big as fits in a packet on
+// most paths without fragmenting
+const EDNS_PAYLOAD_SIZE: usize = 1232;
+// in the OPT record's TTL, asking for DNSSEC records along with the answer
+// (RFC 3225)
+const DNSSEC_OK: u32 = 1 << 15;
+// over TCP, where each message is preceded by its length as a u16
+pub const MAX_MESSAGE_SIZE: usize = u16::MAX as usize;
 const MAX_NAME_JUMPS: u8 = 10;
+const CLASS_IN: u16 = 1;
+// the classes that stand for whole RRsets or names in UPDATE messages
+// (RFC 2136)
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+// how long to wait for an answer, and for a connection or a write to go
+// through on the way
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+// the most times the wait for an answer is doubled, for 16 times as long
+const MAX_BACKOFF: usize = 4;
+// how many referrals to follow before giving up on ever getting an answer
+const MAX_REFERRALS: usize = 16;
+// how many nameservers without glue may be looked up one within the other
+const MAX_NS_DEPTH: u8 = 4;
 
 const A_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(198, 41, 0, 4);
 const B_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(170, 247, 170, 2);
 const C_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 33, 4, 12);
 const D_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(199, 7, 91, 13);
+const E_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 203, 230, 10);
+const F_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 5, 5, 241);
+const G_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 112, 36, 4);
+const H_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(198, 97, 190, 53);
+const I_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 36, 148, 17);
+const J_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(192, 58, 128, 30);
+const K_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(193, 0, 14, 129);
+const L_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(199, 7, 83, 42);
+const M_ROOT_SERVER_NET: Ipv4Addr = Ipv4Addr::new(202, 12, 27, 33);
+// where resolving starts, each tried in turn until one answers
+const ROOT_HINTS: [Ipv4Addr; 13] = [
+    A_ROOT_SERVER_NET,
+    B_ROOT_SERVER_NET,
+    C_ROOT_SERVER_NET,
+    D_ROOT_SERVER_NET,
+    E_ROOT_SERVER_NET,
+    F_ROOT_SERVER_NET,
+    G_ROOT_SERVER_NET,
+    H_ROOT_SERVER_NET,
+    I_ROOT_SERVER_NET,
+    J_ROOT_SERVER_NET,
+    K_ROOT_SERVER_NET,
+    L_ROOT_SERVER_NET,
+    M_ROOT_SERVER_NET,
+];
+
+#[non_exhaustive]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DnsError {
+    // the packet ended in the middle of a field
+    Truncated,
+    // the packet doesn't fit in the space it's being written for
+    BufferFull,
+    // a label longer than 63 bytes, or a length byte with its reserved bits set
+    BadLabelLength(usize),
+    // a name with more compression pointers than `MAX_NAME_JUMPS`
+    TooManyJumps,
+    // a name that takes up more than 255 bytes
+    NameTooLong(usize),
+    // a character-string longer than 255 bytes
+    StringTooLong(usize),
+    // a record whose data doesn't take up the length it says it does
+    BadRdataLength(u16),
+    // more bytes than any message can take up
+    MessageTooLong(usize),
+}
+
+impl DnsError {
+    // the response code to answer a query with when handling it failed this way
+    pub fn rcode(&self) -> RCode {
+        match self {
+            DnsError::Truncated
+            | DnsError::BadLabelLength(_)
+            | DnsError::TooManyJumps
+            | DnsError::NameTooLong(_)
+            | DnsError::BadRdataLength(_)
+            | DnsError::MessageTooLong(_) => RCode::Formerr,
+            DnsError::BufferFull | DnsError::StringTooLong(_) => RCode::Servfail,
+        }
+    }
+}
 
-fn is_authoritative_for(qname: &str, domain: &str) -> bool {
-    qname == domain || qname.ends_with(&format!(".{domain}"))
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsError::Truncated => write!(f, "packet ended unexpectedly"),
+            DnsError::BufferFull => write!(f, "packet is too big to send"),
+            DnsError::BadLabelLength(len) => write!(f, "bad label length {len}"),
+            DnsError::TooManyJumps => {
+                write!(f, "name has more than {MAX_NAME_JUMPS} compression jumps")
+            }
+            DnsError::NameTooLong(len) => write!(f, "name of {len} bytes"),
+            DnsError::StringTooLong(len) => write!(f, "character-string of {len} bytes"),
+            DnsError::BadRdataLength(len) => write!(f, "record data isn't {len} bytes long"),
+            DnsError::MessageTooLong(len) => write!(f, "message of {len} bytes"),
+        }
+    }
 }
 
-fn wire_name_len(name: &str) -> u16 {
-    // sum of each label's length + 1 byte per label for the length prefix
-    // + 1 for the terminator
-    (name.split('.').map(|l| l.len() + 1).sum::<usize>() + 1) as u16
+impl std::error::Error for DnsError {}
+
+impl From<DnsError> for io::Error {
+    fn from(e: DnsError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
 }
 
 #[derive(Debug)]
 struct PacketBufReader<'a> {
     buf: &'a [u8],
     pos: usize,
+    // whether to read internationalized names in Unicode rather than in
+    // their ACE form, as they are on the wire
+    unicode: bool,
 }
 
 impl<'a> PacketBufReader<'a> {
     fn new(buf: &'a [u8]) -> Self {
-        let n = buf.len();
-        assert!(0 < n && n <= PACKET_SIZE);
-        Self { buf, pos: 0 }
+        Self {
+            buf,
+            pos: 0,
+            unicode: false,
+        }
     }
 
-    fn read_u8(&mut self) -> Option<u8> {
-        let byte = *self.buf.get(self.pos)?;
+    fn read_u8(&mut self) -> Result<u8, DnsError> {
+        let byte = *self.buf.get(self.pos).ok_or(DnsError::Truncated)?;
         self.pos += 1;
-        Some(byte)
+        Ok(byte)
     }
 
-    fn read_u16(&mut self) -> Option<u16> {
+    fn read_u16(&mut self) -> Result<u16, DnsError> {
         let hi = self.read_u8()? as u16;
         let lo = self.read_u8()? as u16;
-        Some(hi << 8 | lo)
+        Ok(hi << 8 | lo)
     }
 
-    fn read_u32(&mut self) -> Option<u32> {
+    fn read_u32(&mut self) -> Result<u32, DnsError> {
         let hi = self.read_u16()? as u32;
         let lo = self.read_u16()? as u32;
-        Some(hi << 16 | lo)
+        Ok(hi << 16 | lo)
     }
 
-    fn read_u128(&mut self) -> Option<u128> {
+    fn read_u128(&mut self) -> Result<u128, DnsError> {
         let a = self.read_u32()? as u128;
         let b = self.read_u32()? as u128;
         let c = self.read_u32()? as u128;
         let d = self.read_u32()? as u128;
-        Some(a << 96 | b << 64 | c << 32 | d)
+        Ok(a << 96 | b << 64 | c << 32 | d)
     }
 
-    fn read_name(&mut self) -> Option<String> {
+    fn read_dns_name(&mut self) -> Result<DnsName, DnsError> {
+        DnsName::new(&self.read_name()?)
+    }
+
+    fn read_name(&mut self) -> Result<String, DnsError> {
         let mut name = String::new();
         let mut jumps = 0;
         let mut jumped = false;
@@ -74,7 +231,7 @@ impl<'a> PacketBufReader<'a> {
             if byte & 0xC0 == 0xC0 {
                 // to prevent an infinite loop by a malicious packet
                 if jumps >= MAX_NAME_JUMPS {
-                    return None;
+                    return Err(DnsError::TooManyJumps);
                 }
                 jumps += 1;
 
@@ -94,12 +251,23 @@ impl<'a> PacketBufReader<'a> {
                 break;
             }
 
+            // 0x40 and 0x80 are reserved label types
+            if byte & 0xC0 != 0 {
+                return Err(DnsError::BadLabelLength(byte as usize));
+            }
+
             if !name.is_empty() {
                 name.push('.');
             }
 
-            for _ in 0..byte {
-                name.push(self.read_u8()? as char);
+            let label: String = self
+                .read_bytes(byte as usize)?
+                .iter()
+                .map(|&b| b as char)
+                .collect();
+            match idna::to_unicode(&label) {
+                Some(unicode) if self.unicode => name += &unicode,
+                _ => name += &label,
             }
         }
 
@@ -107,7 +275,121 @@ impl<'a> PacketBufReader<'a> {
             self.pos = saved_pos;
         }
 
-        Some(name)
+        Ok(name)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DnsError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(DnsError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // whatever is left of a record's data of `len` bytes that ends at `end`
+    fn read_rest(&mut self, end: usize, len: u16) -> Result<Vec<u8>, DnsError> {
+        let rest = end
+            .checked_sub(self.pos)
+            .ok_or(DnsError::BadRdataLength(len))?;
+        Ok(self.read_bytes(rest)?.to_vec())
+    }
+
+    // a <character-string>: a length byte followed by that many bytes, which
+    // needn't be text, and are kept as they were so that they pass on as is
+    fn read_string(&mut self) -> Result<Vec<u8>, DnsError> {
+        let len = self.read_u8()? as usize;
+        Ok(self.read_bytes(len)?.to_vec())
+    }
+
+    // the SvcParams of an SVCB or HTTPS record, up to `end`
+    fn read_svc_params(&mut self, end: usize) -> Result<Vec<SvcParam>, DnsError> {
+        let mut params = vec![];
+        while self.pos < end {
+            let key = self.read_u16()?;
+            let len = self.read_u16()?;
+            let value_end = self.pos + len as usize;
+            let param = match key {
+                1 => {
+                    let mut protocols = vec![];
+                    while self.pos < value_end {
+                        protocols.push(self.read_string()?);
+                    }
+                    SvcParam::Alpn(protocols)
+                }
+                3 => SvcParam::Port(self.read_u16()?),
+                4 => {
+                    let mut ips = vec![];
+                    while self.pos < value_end {
+                        ips.push(Ipv4Addr::from_bits(self.read_u32()?));
+                    }
+                    SvcParam::Ipv4Hint(ips)
+                }
+                6 => {
+                    let mut ips = vec![];
+                    while self.pos < value_end {
+                        ips.push(Ipv6Addr::from_bits(self.read_u128()?));
+                    }
+                    SvcParam::Ipv6Hint(ips)
+                }
+                _ => SvcParam::Other {
+                    key,
+                    value: self.read_bytes(len as usize)?.to_vec(),
+                },
+            };
+            if self.pos != value_end {
+                return Err(DnsError::BadRdataLength(len));
+            }
+            params.push(param);
+        }
+        Ok(params)
+    }
+
+    // the options of an OPT record, up to `end`
+    fn read_edns_options(&mut self, end: usize) -> Result<Vec<EdnsOption>, DnsError> {
+        let mut options = vec![];
+        while self.pos < end {
+            let code = self.read_u16()?;
+            let len = self.read_u16()?;
+            let data = self.read_bytes(len as usize)?;
+            let option = match code {
+                // the client's 8 bytes, then the server's 8 to 32 if it has
+                // sent it any
+                10 if len == 8 || (16..=40).contains(&len) => EdnsOption::Cookie {
+                    client: Hex(data[..8].to_vec()),
+                    server: Hex(data[8..].to_vec()),
+                },
+                10 => return Err(DnsError::BadRdataLength(len)),
+                _ => EdnsOption::Other {
+                    code,
+                    data: data.to_vec(),
+                },
+            };
+            options.push(option);
+        }
+        Ok(options)
+    }
+
+    // the types in an NSEC or NSEC3 record's bitmap, up to `end`: in windows
+    // of 256 types, each a bitmap of those in the window that are there
+    fn read_type_bitmap(&mut self, end: usize) -> Result<Vec<QueryType>, DnsError> {
+        let mut types = vec![];
+        while self.pos < end {
+            let window = self.read_u8()?;
+            let len = self.read_u8()?;
+            if !(1..=32).contains(&len) {
+                return Err(DnsError::BadRdataLength(len.into()));
+            }
+            for (i, byte) in self.read_bytes(len as usize)?.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        let num = u16::from(window) << 8 | (i as u16) << 3 | bit;
+                        types.push(QueryType::from(num));
+                    }
+                }
+            }
+        }
+        Ok(types)
     }
 
     #[cfg(test)]
@@ -117,74 +399,209 @@ impl<'a> PacketBufReader<'a> {
 }
 
 trait FromBytes: Sized {
-    fn from_bytes(reader: &mut PacketBufReader) -> Option<Self>;
+    fn from_bytes(reader: &mut PacketBufReader) -> Result<Self, DnsError>;
 }
 
 #[derive(Debug)]
-struct PacketBufWriter<'a> {
-    buf: &'a mut [u8],
-    pos: usize,
+struct PacketBufWriter {
+    buf: Vec<u8>,
+    // how big the packet may grow
+    max: usize,
+    // where each name written so far, and each of its suffixes, starts
+    names: HashMap<String, u16>,
+    // whether names may point back at those, which they mustn't in the
+    // canonical form signatures are made over (RFC 4034)
+    compress: bool,
 }
 
-impl<'a> PacketBufWriter<'a> {
-    fn new(buf: &'a mut [u8]) -> Self {
-        let n = buf.len();
-        assert!(0 < n && n <= PACKET_SIZE);
-        Self { buf, pos: 0 }
+impl PacketBufWriter {
+    fn new(max: usize) -> Self {
+        assert!(0 < max && max <= MAX_MESSAGE_SIZE);
+        Self {
+            buf: vec![],
+            max,
+            names: HashMap::new(),
+            compress: true,
+        }
     }
 
-    fn write_u8(&mut self, val: u8) -> Option<()> {
-        if self.pos >= self.buf.len() {
-            return None;
+    fn canonical() -> Self {
+        Self {
+            compress: false,
+            ..Self::new(MAX_MESSAGE_SIZE)
         }
-        self.buf[self.pos] = val;
-        self.pos += 1;
-        Some(())
     }
 
-    fn write_u16(&mut self, val: u16) -> Option<()> {
+    fn pos(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn write_u8(&mut self, val: u8) -> Result<(), DnsError> {
+        if self.buf.len() >= self.max {
+            return Err(DnsError::BufferFull);
+        }
+        self.buf.push(val);
+        Ok(())
+    }
+
+    // Forgets everything written from `pos` on.
+    fn truncate(&mut self, pos: usize) {
+        self.buf.truncate(pos);
+        self.names.retain(|_, &mut at| (at as usize) < pos);
+    }
+
+    fn write_u16(&mut self, val: u16) -> Result<(), DnsError> {
         self.write_u8((val >> 8) as u8)?;
         self.write_u8((val & 0xFF) as u8)?;
-        Some(())
+        Ok(())
     }
 
-    fn write_u32(&mut self, val: u32) -> Option<()> {
+    fn write_u32(&mut self, val: u32) -> Result<(), DnsError> {
         self.write_u8((val >> 24) as u8)?;
         self.write_u8((val >> 16 & 0xFF) as u8)?;
         self.write_u8((val >> 8 & 0xFF) as u8)?;
         self.write_u8((val & 0xFF) as u8)?;
-        Some(())
+        Ok(())
     }
 
-    fn write_u128(&mut self, val: u128) -> Option<()> {
+    fn write_u128(&mut self, val: u128) -> Result<(), DnsError> {
         self.write_u32((val >> 96) as u32)?;
         self.write_u32((val >> 64) as u32)?;
         self.write_u32((val >> 32) as u32)?;
         self.write_u32(val as u32)?;
-        Some(())
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), DnsError> {
+        for b in bytes {
+            self.write_u8(*b)?;
+        }
+        Ok(())
+    }
+
+    // Overwrites what was written at `pos`.
+    fn set_u16(&mut self, pos: usize, val: u16) {
+        self.buf[pos..pos + 2].copy_from_slice(&val.to_be_bytes());
+    }
+
+    // Points back at where the rest of the name was written before, once
+    // there is such a suffix.
+    fn write_name(&mut self, name: &str) -> Result<(), DnsError> {
+        self.write_labels(name, true)
     }
 
-    fn write_name(&mut self, name: &str) -> Option<()> {
-        for label in name.split('.') {
-            let len = label.len();
-            if len > 63 {
-                return None;
+    // For the names in record data that other servers may not expect to
+    // find pointers in: any but those of the types from RFC 1035.
+    fn write_name_uncompressed(&mut self, name: &str) -> Result<(), DnsError> {
+        self.write_labels(name, false)
+    }
+
+    fn write_labels(&mut self, name: &str, compress: bool) -> Result<(), DnsError> {
+        // the names in record data are strings that may not be names at all
+        let name = DnsName::new(name)?;
+        let mut rest = name.as_str();
+        for label in name.labels() {
+            if compress
+                && self.compress
+                && let Some(&pos) = self.names.get(rest)
+            {
+                return self.write_u16(0xC000 | pos);
             }
-            self.write_u8(len as u8)?;
-            for b in label.as_bytes() {
-                self.write_u8(*b)?;
+            // a pointer only has 14 bits for the position
+            if self.pos() < 0x4000 {
+                self.names.insert(rest.to_string(), self.pos() as u16);
             }
+
+            let ace = idna::to_ascii(label).ok_or(DnsError::BadLabelLength(label.len()))?;
+            self.write_u8(ace.len() as u8)?;
+            self.write_bytes(ace.as_bytes())?;
+            rest = rest.get(label.len() + 1..).unwrap_or_default();
         }
         self.write_u8(0)?;
-        Some(())
+        Ok(())
+    }
+
+    fn write_string(&mut self, s: &[u8]) -> Result<(), DnsError> {
+        let len = s.len();
+        if len > 255 {
+            return Err(DnsError::StringTooLong(len));
+        }
+        self.write_u8(len as u8)?;
+        self.write_bytes(s)?;
+        Ok(())
+    }
+
+    fn write_type_bitmap(&mut self, types: &[QueryType]) -> Result<(), DnsError> {
+        let mut nums: Vec<u16> = types.iter().map(|&t| u16::from(t)).collect();
+        nums.sort_unstable();
+        nums.dedup();
+        for window in nums.chunk_by(|a, b| a >> 8 == b >> 8) {
+            let mut bitmap = [0u8; 32];
+            for num in window {
+                let low = num & 0xFF;
+                bitmap[low as usize / 8] |= 0x80 >> (low % 8);
+            }
+            let len = window[window.len() - 1] as usize % 256 / 8 + 1;
+            self.write_u8((window[0] >> 8) as u8)?;
+            self.write_u8(len as u8)?;
+            self.write_bytes(&bitmap[..len])?;
+        }
+        Ok(())
+    }
+
+    fn write_edns_options(&mut self, options: &[EdnsOption]) -> Result<(), DnsError> {
+        for option in options {
+            match option {
+                EdnsOption::Cookie { client, server } => {
+                    self.write_u16(10)?;
+                    self.write_u16((client.0.len() + server.0.len()) as u16)?;
+                    self.write_bytes(&client.0)?;
+                    self.write_bytes(&server.0)?;
+                }
+                EdnsOption::Other { code, data } => {
+                    self.write_u16(*code)?;
+                    self.write_u16(data.len() as u16)?;
+                    self.write_bytes(data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_svc_params(&mut self, params: &[SvcParam]) -> Result<(), DnsError> {
+        for param in params {
+            self.write_u16(param.key())?;
+            self.write_u16(param.len())?;
+            match param {
+                SvcParam::Alpn(protocols) => {
+                    for protocol in protocols {
+                        self.write_string(protocol)?;
+                    }
+                }
+                SvcParam::Port(port) => self.write_u16(*port)?,
+                SvcParam::Ipv4Hint(ips) => {
+                    for ip in ips {
+                        self.write_u32(ip.to_bits())?;
+                    }
+                }
+                SvcParam::Ipv6Hint(ips) => {
+                    for ip in ips {
+                        self.write_u128(ip.to_bits())?;
+                    }
+                }
+                SvcParam::Other { value, .. } => self.write_bytes(value)?,
+            }
+        }
+        Ok(())
     }
 }
 
 trait ToBytes {
-    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Option<()>;
+    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Result<(), DnsError>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DnsPacket {
     pub header: DnsHeader,
     pub questions: Vec<DnsQuestion>,
@@ -220,9 +637,47 @@ impl DnsPacket {
         }
     }
 
-    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
-        let mut reader = PacketBufReader::new(buf);
+    // A query for `name`, to build on as in
+    // `DnsPacket::query("example.com", QueryType::A)?.recursion_desired(true)`,
+    // with an ID of its own unless given one.
+    pub fn query(name: &str, qtype: QueryType) -> Result<Self, DnsError> {
+        let packet = Self::new_empty().id(random_id());
+        Ok(packet.add_question(DnsQuestion::new(DnsName::new(name)?, qtype)))
+    }
+
+    pub fn id(mut self, id: u16) -> Self {
+        self.header.id = id;
+        self
+    }
+
+    pub fn recursion_desired(mut self, rd: bool) -> Self {
+        self.header.rd = rd;
+        self
+    }
+
+    pub fn add_question(mut self, question: DnsQuestion) -> Self {
+        self.questions.push(question);
+        self.header.qdcount = self.questions.len() as u16;
+        self
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, DnsError> {
+        Self::read(PacketBufReader::new(buf))
+    }
+
+    // The same, but with internationalized names in Unicode, as in
+    // "bücher.example" rather than "xn--bcher-kva.example".
+    pub fn from_bytes_unicode(buf: &[u8]) -> Result<Self, DnsError> {
+        Self::read(PacketBufReader {
+            unicode: true,
+            ..PacketBufReader::new(buf)
+        })
+    }
 
+    fn read(mut reader: PacketBufReader) -> Result<Self, DnsError> {
+        if reader.buf.len() > MAX_MESSAGE_SIZE {
+            return Err(DnsError::MessageTooLong(reader.buf.len()));
+        }
         let header = DnsHeader::from_bytes(&mut reader)?;
 
         let mut questions = Vec::with_capacity(header.qdcount as usize);
@@ -245,7 +700,7 @@ impl DnsPacket {
             resources.push(DnsRecord::from_bytes(&mut reader)?);
         }
 
-        Some(DnsPacket {
+        Ok(DnsPacket {
             header,
             questions,
             answers,
@@ -254,70 +709,277 @@ impl DnsPacket {
         })
     }
 
-    pub fn to_bytes(&self, buf: &mut [u8]) -> Option<()> {
+    // The OPT record, for a packet that uses EDNS.
+    pub fn edns(&self) -> Option<&DnsRecord> {
+        self.resources.iter().find(|r| r.r#type == QueryType::OPT)
+    }
+
+    // Uses EDNS, taking UDP answers of up to `payload` bytes, and asking for
+    // DNSSEC records with them or not.
+    pub fn set_edns(&mut self, payload: u16, dnssec_ok: bool) {
+        self.resources.retain(|r| r.r#type != QueryType::OPT);
+        self.resources.push(DnsRecord {
+            domain: DnsName::root(),
+            r#type: QueryType::OPT,
+            // its class and TTL are put to other uses
+            class: payload,
+            ttl: if dnssec_ok { DNSSEC_OK } else { 0 },
+            rdata: RData::OPT { options: vec![] },
+        });
+        self.header.arcount = self.resources.len() as u16;
+    }
+
+    pub fn dnssec_ok(&self) -> bool {
+        self.edns().is_some_and(|opt| opt.ttl & DNSSEC_OK != 0)
+    }
+
+    // The client's cookie and the server's, empty when there isn't one yet.
+    fn cookie(&self) -> Option<(&[u8], &[u8])> {
+        let RData::OPT { options } = &self.edns()?.rdata else {
+            return None;
+        };
+        options.iter().find_map(|option| match option {
+            EdnsOption::Cookie { client, server } => Some((&client.0[..], &server.0[..])),
+            EdnsOption::Other { .. } => None,
+        })
+    }
+
+    // Sends cookies along, in a packet that uses EDNS.
+    fn set_cookie(&mut self, client: &[u8], server: &[u8]) {
+        let opt = self
+            .resources
+            .iter_mut()
+            .find(|r| r.r#type == QueryType::OPT);
+        let Some(RData::OPT { options }) = opt.map(|opt| &mut opt.rdata) else {
+            return;
+        };
+        options.retain(|option| !matches!(option, EdnsOption::Cookie { .. }));
+        options.push(EdnsOption::Cookie {
+            client: Hex(client.to_vec()),
+            server: Hex(server.to_vec()),
+        });
+    }
+
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<(), DnsError> {
         assert_eq!(buf.len(), PACKET_SIZE);
 
-        let mut temp = [0u8; PACKET_SIZE]; // to keep buf untouched on midway failures
-        let mut writer = PacketBufWriter::new(&mut temp);
+        // to keep buf untouched on midway failures
+        let bytes = self.write(PACKET_SIZE, false)?;
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        buf[bytes.len()..].fill(0);
+
+        Ok(())
+    }
+
+    // The whole packet, however big, as sent over TCP.
+    pub fn to_vec(&self) -> Result<Vec<u8>, DnsError> {
+        self.write(MAX_MESSAGE_SIZE, false)
+    }
+
+    // As many of the records as fit in `max` bytes, e.g. a UDP payload. When
+    // some have to be left out the TC bit is set, telling the client to ask
+    // again over TCP for the rest.
+    pub fn to_vec_truncated(&self, max: usize) -> Result<Vec<u8>, DnsError> {
+        self.write(max, true)
+    }
+
+    fn write(&self, max: usize, truncate: bool) -> Result<Vec<u8>, DnsError> {
+        let mut writer = PacketBufWriter::new(max);
 
         self.header.to_bytes(&mut writer)?;
+        // the counts of what's there, whatever the header says, after the id
+        // and flags
+        let counts = [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.resources.len(),
+        ];
+        for (i, count) in counts.into_iter().enumerate() {
+            let count = u16::try_from(count).map_err(|_| DnsError::BufferFull)?;
+            writer.set_u16(4 + 2 * i, count);
+        }
 
         for q in &self.questions {
             q.to_bytes(&mut writer)?;
         }
-        for ans in &self.answers {
-            ans.to_bytes(&mut writer)?;
-        }
-        for auth in &self.authorities {
-            auth.to_bytes(&mut writer)?;
-        }
-        for r in &self.resources {
-            r.to_bytes(&mut writer)?;
-        }
 
-        buf.copy_from_slice(&temp);
+        let sections = [&self.answers, &self.authorities, &self.resources];
+        for (i, records) in sections.into_iter().enumerate() {
+            for (written, r) in records.iter().enumerate() {
+                let pos = writer.pos();
+                match r.to_bytes(&mut writer) {
+                    Err(DnsError::BufferFull) if truncate => {
+                        writer.truncate(pos);
+                        // the counts of this section and those after it,
+                        // which follow the id, flags and question count
+                        writer.set_u16(6 + 2 * i, written as u16);
+                        for j in i + 1..sections.len() {
+                            writer.set_u16(6 + 2 * j, 0);
+                        }
+                        // the TC bit
+                        writer.buf[2] |= 0x02;
+                        return Ok(writer.buf);
+                    }
+                    res => res?,
+                }
+            }
+        }
 
-        Some(())
+        Ok(writer.buf)
     }
 
     #[cfg(test)]
-    fn from_reader<R: Read>(mut reader: R) -> Option<Self> {
+    fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
         let mut buf = Vec::new();
-        reader.read_to_end(&mut buf).ok()?;
-        Self::from_bytes(&buf)
+        reader.read_to_end(&mut buf)?;
+        Ok(Self::from_bytes(&buf)?)
     }
 
-    fn get_random_a(&self) -> Option<Ipv4Addr> {
+    // Where the CNAME among the answers for `name` points, if there is one.
+    fn get_cname(&self, name: &str) -> Option<&str> {
         self.answers.iter().find_map(|r| match &r.rdata {
-            RData::A { ip } => Some(*ip),
+            RData::CNAME { host } if r.domain.eq_ignore_ascii_case(name) => Some(host.as_str()),
             _ => None,
         })
     }
 
-    fn get_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+    fn get_a(&self) -> Vec<Ipv4Addr> {
+        self.answers
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::A { ip } => Some(*ip),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The targets of the SRV answers with their ports, in the order to try
+    // them: by priority, and the heaviest first among equals.
+    pub fn get_srv_targets(&self) -> Vec<(&str, u16)> {
+        let mut srvs: Vec<_> = self
+            .answers
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::SRV {
+                    priority,
+                    weight,
+                    port,
+                    target,
+                } => Some((*priority, Reverse(*weight), target.as_str(), *port)),
+                _ => None,
+            })
+            .collect();
+        srvs.sort_by_key(|&(priority, weight, ..)| (priority, weight));
+        srvs.into_iter()
+            .map(|(_, _, target, port)| (target, port))
+            .collect()
+    }
+
+    fn get_ns<'a>(
+        &'a self,
+        qname: &'a DnsName,
+    ) -> impl Iterator<Item = (&'a DnsName, &'a str)> + 'a {
         self.authorities.iter().filter_map(move |r| match &r.rdata {
-            RData::NS { host } if is_authoritative_for(qname, &r.domain) => {
-                Some((r.domain.as_str(), host.as_str()))
-            }
+            RData::NS { host } if qname.is_within(&r.domain) => Some((&r.domain, host.as_str())),
             _ => None,
         })
     }
 
-    fn get_resolved_ns(&self, qname: &str) -> Option<Ipv4Addr> {
-        self.get_ns(qname).find_map(|(_, ns_host)| {
-            self.resources.iter().find_map(|r| match &r.rdata {
-                RData::A { ip } if r.domain == ns_host => Some(*ip),
+    // The addresses the additional section gives for the nameservers `hosts`.
+    fn get_glue(&self, hosts: &[&str]) -> Vec<Ipv4Addr> {
+        self.resources
+            .iter()
+            .filter_map(|r| match &r.rdata {
+                RData::A { ip } if hosts.iter().any(|h| h.eq_ignore_ascii_case(&r.domain)) => {
+                    Some(*ip)
+                }
                 _ => None,
             })
-        })
+            .collect()
     }
+}
+
+// The way dig prints it: the header, then each section that has records in
+// it, with records as lines of a zone file.
+impl fmt::Display for DnsPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let h = &self.header;
+        let opcode = match h.opcode {
+            0 => "QUERY".to_string(),
+            notify::OPCODE_NOTIFY => "NOTIFY".to_string(),
+            update::OPCODE_UPDATE => "UPDATE".to_string(),
+            other => other.to_string(),
+        };
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {opcode}, status: {}, id: {}",
+            h.rcode, h.id
+        )?;
+        let flags = [
+            ("qr", h.qr),
+            ("aa", h.aa),
+            ("tc", h.tc),
+            ("rd", h.rd),
+            ("ra", h.ra),
+            ("ad", h.ad),
+            ("cd", h.cd),
+        ];
+        let flags: Vec<_> = flags.iter().filter(|f| f.1).map(|f| f.0).collect();
+        writeln!(
+            f,
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            flags.join(" "),
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.resources.len()
+        )?;
+
+        if let Some(opt) = self.edns() {
+            writeln!(f, "\n;; OPT PSEUDOSECTION:")?;
+            let dnssec_ok = if self.dnssec_ok() { " do" } else { "" };
+            writeln!(
+                f,
+                "; EDNS: version: 0, flags:{dnssec_ok}; udp: {}",
+                opt.class
+            )?;
+            if !matches!(&opt.rdata, RData::OPT { options } if options.is_empty()) {
+                writeln!(f, "; {}", opt.rdata)?;
+            }
+        }
 
-    fn get_unresolved_ns<'a>(&'a self, qname: &'a str) -> Option<&'a str> {
-        self.get_ns(qname).map(|(_, host)| host).next()
+        if !self.questions.is_empty() {
+            writeln!(f, "\n;; QUESTION SECTION:")?;
+            for q in &self.questions {
+                writeln!(f, ";{q}")?;
+            }
+        }
+        // the OPT record isn't an additional record as such
+        let additional: Vec<_> = self
+            .resources
+            .iter()
+            .filter(|r| r.r#type != QueryType::OPT)
+            .collect();
+        for (title, records) in [
+            ("ANSWER", self.answers.iter().collect()),
+            ("AUTHORITY", self.authorities.iter().collect()),
+            ("ADDITIONAL", additional),
+        ] {
+            if records.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n;; {title} SECTION:")?;
+            for record in records {
+                writeln!(f, "{record}")?;
+            }
+        }
+        Ok(())
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RCode {
     Noerror = 0,
     Formerr = 1,
@@ -325,6 +987,23 @@ pub enum RCode {
     Nxdomain = 3,
     Notimp = 4,
     Refused = 5,
+    // for UPDATE (RFC 2136): a name that mustn't exist does
+    Yxdomain = 6,
+    // an RRset that mustn't exist does
+    Yxrrset = 7,
+    // an RRset that must exist doesn't
+    Nxrrset = 8,
+    // the server isn't authoritative for the zone
+    Notauth = 9,
+    // a name outside the zone
+    Notzone = 10,
+}
+
+// as in "NXDOMAIN"
+impl fmt::Display for RCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{self:?}").to_uppercase())
+    }
 }
 
 impl RCode {
@@ -335,12 +1014,18 @@ impl RCode {
             3 => RCode::Nxdomain,
             4 => RCode::Notimp,
             5 => RCode::Refused,
+            6 => RCode::Yxdomain,
+            7 => RCode::Yxrrset,
+            8 => RCode::Nxrrset,
+            9 => RCode::Notauth,
+            10 => RCode::Notzone,
             _ => RCode::Noerror,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DnsHeader {
     pub id: u16,
 
@@ -377,7 +1062,7 @@ pub struct DnsHeader {
 }
 
 impl FromBytes for DnsHeader {
-    fn from_bytes(reader: &mut PacketBufReader) -> Option<Self> {
+    fn from_bytes(reader: &mut PacketBufReader) -> Result<Self, DnsError> {
         let id = reader.read_u16()?;
 
         let byte = reader.read_u8()?;
@@ -399,7 +1084,7 @@ impl FromBytes for DnsHeader {
         let nscount = reader.read_u16()?;
         let arcount = reader.read_u16()?;
 
-        Some(DnsHeader {
+        Ok(DnsHeader {
             id,
             qr,
             opcode,
@@ -420,7 +1105,7 @@ impl FromBytes for DnsHeader {
 }
 
 impl ToBytes for DnsHeader {
-    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Option<()> {
+    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Result<(), DnsError> {
         writer.write_u16(self.id)?;
 
         let byte: u8 = (self.qr as u8) << 7
@@ -442,19 +1127,41 @@ impl ToBytes for DnsHeader {
         writer.write_u16(self.nscount)?;
         writer.write_u16(self.arcount)?;
 
-        Some(())
+        Ok(())
     }
 }
 
 #[non_exhaustive]
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QueryType {
     A,
     NS,
     CNAME,
+    SOA,
+    PTR,
     MX,
+    TXT,
     AAAA,
+    SRV,
+    NAPTR,
+    SVCB,
+    HTTPS,
+    // the pseudo-record EDNS puts its options in (RFC 6891)
+    OPT,
+    // for DNSSEC (RFC 4034, RFC 5155)
+    DS,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    // only ever asked for, for a zone's changes since a serial (RFC 1995)
+    IXFR,
+    // and for the whole of it (RFC 5936)
+    AXFR,
+    // any other type, by its number
+    Unknown(u16),
 }
 
 impl From<u16> for QueryType {
@@ -463,9 +1170,24 @@ impl From<u16> for QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
-            _ => unimplemented!(),
+            33 => QueryType::SRV,
+            35 => QueryType::NAPTR,
+            64 => QueryType::SVCB,
+            65 => QueryType::HTTPS,
+            41 => QueryType::OPT,
+            43 => QueryType::DS,
+            46 => QueryType::RRSIG,
+            47 => QueryType::NSEC,
+            48 => QueryType::DNSKEY,
+            50 => QueryType::NSEC3,
+            251 => QueryType::IXFR,
+            252 => QueryType::AXFR,
+            _ => QueryType::Unknown(value),
         }
     }
 }
@@ -476,26 +1198,100 @@ impl From<QueryType> for u16 {
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::NAPTR => 35,
+            QueryType::SVCB => 64,
+            QueryType::HTTPS => 65,
+            QueryType::OPT => 41,
+            QueryType::DS => 43,
+            QueryType::RRSIG => 46,
+            QueryType::NSEC => 47,
+            QueryType::DNSKEY => 48,
+            QueryType::NSEC3 => 50,
+            QueryType::IXFR => 251,
+            QueryType::AXFR => 252,
+            QueryType::Unknown(num) => num,
         }
     }
 }
 
-#[derive(Debug)]
+// As written in zone files: its mnemonic, or TYPE followed by its number
+// (RFC 3597).
+impl FromStr for QueryType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let qtype = match s.to_ascii_uppercase().as_str() {
+            "A" => QueryType::A,
+            "NS" => QueryType::NS,
+            "CNAME" => QueryType::CNAME,
+            "SOA" => QueryType::SOA,
+            "PTR" => QueryType::PTR,
+            "MX" => QueryType::MX,
+            "TXT" => QueryType::TXT,
+            "AAAA" => QueryType::AAAA,
+            "SRV" => QueryType::SRV,
+            "NAPTR" => QueryType::NAPTR,
+            "SVCB" => QueryType::SVCB,
+            "HTTPS" => QueryType::HTTPS,
+            "OPT" => QueryType::OPT,
+            "DS" => QueryType::DS,
+            "RRSIG" => QueryType::RRSIG,
+            "NSEC" => QueryType::NSEC,
+            "DNSKEY" => QueryType::DNSKEY,
+            "NSEC3" => QueryType::NSEC3,
+            "IXFR" => QueryType::IXFR,
+            "AXFR" => QueryType::AXFR,
+            other => match other.strip_prefix("TYPE").map(str::parse::<u16>) {
+                Some(Ok(num)) => QueryType::from(num),
+                _ => return Err(format!("unknown type {s:?}")),
+            },
+        };
+        Ok(qtype)
+    }
+}
+
+// the same way FromStr reads it
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryType::Unknown(num) => write!(f, "TYPE{num}"),
+            known => fmt::Debug::fmt(known, f),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DnsQuestion {
-    pub name: String,
+    pub name: DnsName,
     pub r#type: QueryType,
     class: u16,
 }
 
+impl DnsQuestion {
+    // in the Internet class, as questions nearly always are
+    pub fn new(name: DnsName, r#type: QueryType) -> Self {
+        Self {
+            name,
+            r#type,
+            class: 1,
+        }
+    }
+}
+
 impl FromBytes for DnsQuestion {
-    fn from_bytes(reader: &mut PacketBufReader) -> Option<Self> {
-        let name = reader.read_name()?;
+    fn from_bytes(reader: &mut PacketBufReader) -> Result<Self, DnsError> {
+        let name = reader.read_dns_name()?;
         let r#type = QueryType::from(reader.read_u16()?);
         let class = reader.read_u16()?;
 
-        Some(DnsQuestion {
+        Ok(DnsQuestion {
             name,
             r#type,
             class,
@@ -503,18 +1299,28 @@ impl FromBytes for DnsQuestion {
     }
 }
 
+// as in "example.com. IN A"
+impl fmt::Display for DnsQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}. ", self.name)?;
+        write_class(f, self.class)?;
+        write!(f, " {}", self.r#type)
+    }
+}
+
 impl ToBytes for DnsQuestion {
-    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Option<()> {
+    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Result<(), DnsError> {
         writer.write_name(&self.name)?;
         writer.write_u16(self.r#type.into())?;
         writer.write_u16(self.class)?;
-        Some(())
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DnsRecord {
-    domain: String,
+    domain: DnsName,
     r#type: QueryType,
     class: u16,
     ttl: u32,
@@ -523,143 +1329,1248 @@ pub struct DnsRecord {
 
 #[non_exhaustive]
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum RData {
-    A { ip: Ipv4Addr },
-    NS { host: String },
-    CNAME { host: String },
-    MX { priority: u16, host: String },
-    AAAA { ip: Ipv6Addr },
+    A {
+        ip: Ipv4Addr,
+    },
+    NS {
+        host: DnsName,
+    },
+    CNAME {
+        host: DnsName,
+    },
+    SOA {
+        // the zone's primary nameserver
+        mname: DnsName,
+        // the mailbox of whoever is responsible for the zone, with the @ as a dot
+        rname: DnsName,
+        serial: u32,
+        // how long secondaries wait between checking for changes, in seconds
+        refresh: u32,
+        // and between retrying a failed check
+        retry: u32,
+        // and before they stop answering for the zone when checks keep failing
+        expire: u32,
+        // how long to cache NXDOMAIN answers for
+        minimum: u32,
+    },
+    PTR {
+        host: DnsName,
+    },
+    MX {
+        priority: u16,
+        host: DnsName,
+    },
+    // one or more character-strings, of bytes that are usually but not always
+    // text
+    TXT {
+        strings: Vec<Vec<u8>>,
+    },
+    AAAA {
+        ip: Ipv6Addr,
+    },
+    SRV {
+        // lower is tried first
+        priority: u16,
+        // how often to pick this target among those of equal priority
+        weight: u16,
+        port: u16,
+        target: DnsName,
+    },
+    NAPTR {
+        // lower is used first, and preference breaks ties
+        order: u16,
+        preference: u16,
+        // e.g. "U" when `regexp` gives the final URI
+        flags: Vec<u8>,
+        // e.g. "E2U+sip"
+        services: Vec<u8>,
+        // a substitution to apply to the queried name, or empty
+        regexp: Vec<u8>,
+        // the next name to look up when `regexp` is empty, or the root
+        replacement: DnsName,
+    },
+    // HTTPS records are SVCB records for https, so they are read the same way
+    SVCB {
+        // 0 for an alias of `target`, otherwise lower is tried first
+        priority: u16,
+        // the root to mean the record's own name
+        target: DnsName,
+        params: Vec<SvcParam>,
+    },
+    // a signature over the records of a type at a name (RFC 4034)
+    RRSIG {
+        type_covered: QueryType,
+        algorithm: u8,
+        // how many labels the name has, not counting a wildcard's *
+        labels: u8,
+        // the TTL the records have where they come from
+        original_ttl: u32,
+        // when it's good until and from, in seconds since the epoch
+        expiration: u32,
+        inception: u32,
+        // which of the signer's keys it was made with
+        key_tag: u16,
+        // the zone whose key it was
+        signer: DnsName,
+        signature: Base64,
+    },
+    // a public key the zone's records are signed with
+    DNSKEY {
+        // 256 for a zone key, and 257 for one that's also a secure entry point
+        flags: u16,
+        // always 3
+        protocol: u8,
+        algorithm: u8,
+        public_key: Base64,
+    },
+    // the digest of a key of the zone below, kept by the one above it
+    DS {
+        key_tag: u16,
+        algorithm: u8,
+        digest_type: u8,
+        digest: Hex,
+    },
+    // the name after this one in the zone, in canonical order, to prove
+    // there's nothing between them, and the types this one has
+    NSEC {
+        next: DnsName,
+        types: Vec<QueryType>,
+    },
+    // the same, for hashes of the names rather than the names (RFC 5155)
+    NSEC3 {
+        hash_algorithm: u8,
+        // 1 when delegations without a DS may be skipped
+        flags: u8,
+        iterations: u16,
+        salt: Hex,
+        next_hashed: Base32Hex,
+        types: Vec<QueryType>,
+    },
+    // the EDNS options of a message's OPT record, which isn't a record as
+    // such (RFC 6891)
+    OPT {
+        options: Vec<EdnsOption>,
+    },
+    // the data of a record of a type we don't know, to be passed on as is
+    Unknown {
+        bytes: Vec<u8>,
+    },
 }
 
-impl FromBytes for DnsRecord {
-    fn from_bytes(reader: &mut PacketBufReader) -> Option<Self> {
-        let domain = reader.read_name()?;
-        let r#type = QueryType::from(reader.read_u16()?);
-        let class = reader.read_u16()?;
-        let ttl = reader.read_u32()?;
-        let _len = reader.read_u16()?;
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum EdnsOption {
+    // to tell answers from the server asked apart from spoofed ones
+    // (RFC 7873)
+    Cookie { client: Hex, server: Hex },
+    // kept as is so that it can be passed on
+    Other { code: u16, data: Vec<u8> },
+}
 
-        let rdata = match r#type {
-            QueryType::A => RData::A {
-                ip: Ipv4Addr::from_bits(reader.read_u32()?),
-            },
-            QueryType::NS => RData::NS {
-                host: reader.read_name()?,
-            },
-            QueryType::CNAME => RData::CNAME {
-                host: reader.read_name()?,
-            },
-            QueryType::MX => RData::MX {
-                priority: reader.read_u16()?,
-                host: reader.read_name()?,
-            },
-            QueryType::AAAA => RData::AAAA {
-                ip: Ipv6Addr::from_bits(reader.read_u128()?),
-            },
-        };
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum SvcParam {
+    // the protocols the service speaks, as ALPN IDs like "h2" or "h3"
+    Alpn(Vec<Vec<u8>>),
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    // kept as is so that it can be passed on
+    Other { key: u16, value: Vec<u8> },
+}
 
-        Some(DnsRecord {
-            domain,
-            r#type,
-            class,
-            ttl,
-            rdata,
-        })
+impl SvcParam {
+    fn key(&self) -> u16 {
+        match self {
+            SvcParam::Alpn(_) => 1,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Other { key, .. } => *key,
+        }
+    }
+
+    // of the value
+    fn len(&self) -> u16 {
+        match self {
+            SvcParam::Alpn(protocols) => protocols.iter().map(|p| 1 + p.len() as u16).sum(),
+            SvcParam::Port(_) => 2,
+            SvcParam::Ipv4Hint(ips) => 4 * ips.len() as u16,
+            SvcParam::Ipv6Hint(ips) => 16 * ips.len() as u16,
+            SvcParam::Other { value, .. } => value.len() as u16,
+        }
     }
 }
 
-impl ToBytes for DnsRecord {
-    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Option<()> {
-        writer.write_name(&self.domain)?;
-        writer.write_u16(self.r#type.into())?;
-        writer.write_u16(self.class)?;
-        writer.write_u32(self.ttl)?;
+impl DnsRecord {
+    pub fn domain(&self) -> &DnsName {
+        &self.domain
+    }
 
-        let rdlen = match &self.rdata {
-            RData::A { .. } => 4,     // Ipv4addr
-            RData::AAAA { .. } => 16, // Ipv6addr
-            RData::NS { host } | RData::CNAME { host } => wire_name_len(host),
-            RData::MX { host, .. } => 2 + wire_name_len(host), // priority + host
-        };
-        writer.write_u16(rdlen)?;
+    pub fn r#type(&self) -> QueryType {
+        self.r#type
+    }
 
-        match &self.rdata {
-            RData::A { ip } => writer.write_u32(ip.to_bits())?,
-            RData::NS { host } | RData::CNAME { host } => writer.write_name(host)?,
-            RData::MX { priority, host } => {
-                writer.write_u16(*priority)?;
-                writer.write_name(host)?;
-            }
-            RData::AAAA { ip } => writer.write_u128(ip.to_bits())?,
-        }
+    pub fn class(&self) -> u16 {
+        self.class
+    }
 
-        Some(())
+    pub fn ttl(&self) -> u32 {
+        self.ttl
     }
-}
 
-pub fn recursive_lookup(name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
-    let mut ns_ip = C_ROOT_SERVER_NET;
+    // Its data as zone files write it, as in "10 mx.example.com." for an MX
+    // record.
+    pub fn data(&self) -> String {
+        self.rdata.to_string()
+    }
 
-    loop {
-        println!("Attempting lookup of {qtype:?} {name:?} with ns {ns_ip}");
+    // The same record, whatever its TTL.
+    pub(crate) fn is_same(&self, other: &DnsRecord) -> bool {
+        self.domain.eq_ignore_ascii_case(&other.domain)
+            && self.r#type == other.r#type
+            && self.class == other.class
+            && self.rdata == other.rdata
+    }
+}
 
-        let resp = lookup(name, qtype, (ns_ip, 53))?;
+// as a line of a zone file, as in "example.com. 300 IN A 192.0.2.1"
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}. {} ", self.domain, self.ttl)?;
+        write_class(f, self.class)?;
+        write!(f, " {} {}", self.r#type, self.rdata)
+    }
+}
 
-        // return the packet as-is so the caller propagates the correct rcode
-        if (!resp.answers.is_empty() && resp.header.rcode == RCode::Noerror)
-            || resp.header.rcode == RCode::Nxdomain
-        {
-            return Ok(resp);
-        }
+fn write_class(f: &mut fmt::Formatter<'_>, class: u16) -> fmt::Result {
+    match class {
+        CLASS_IN => f.write_str("IN"),
+        CLASS_NONE => f.write_str("NONE"),
+        CLASS_ANY => f.write_str("ANY"),
+        class => write!(f, "CLASS{class}"),
+    }
+}
 
-        // fast path
-        if let Some(ip) = resp.get_resolved_ns(name) {
-            ns_ip = ip;
-            continue;
-        }
-
-        // slow path
-        match resp.get_unresolved_ns(name) {
-            Some(ns_host) => match recursive_lookup(ns_host, QueryType::A)?.get_random_a() {
-                Some(ip) => ns_ip = ip,
-                None => {
-                    return Err(io::Error::other(
-                        "nameserver hostname did not resolve to an A record",
-                    ));
+// Names are written absolute, with their final dot, which makes the root ".".
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A { ip } => write!(f, "{ip}"),
+            RData::AAAA { ip } => write!(f, "{ip}"),
+            RData::NS { host } | RData::CNAME { host } | RData::PTR { host } => {
+                write!(f, "{host}.")
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{mname}. {rname}. {serial} {refresh} {retry} {expire} {minimum}"
+            ),
+            RData::MX { priority, host } => write!(f, "{priority} {host}."),
+            RData::TXT { strings } => {
+                for (i, s) in strings.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write_quoted(f, s)?;
                 }
-            },
-            None => {
-                return Err(io::Error::other(
-                    "no authoritative nameserver to delegate to",
-                ));
+                Ok(())
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{priority} {weight} {port} {target}."),
+            RData::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                write!(f, "{order} {preference} ")?;
+                for s in [flags, services, regexp] {
+                    write_quoted(f, s)?;
+                    f.write_str(" ")?;
+                }
+                write!(f, "{replacement}.")
+            }
+            RData::SVCB {
+                priority,
+                target,
+                params,
+            } => {
+                write!(f, "{priority} {target}.")?;
+                for param in params {
+                    f.write_str(" ")?;
+                    match param {
+                        SvcParam::Alpn(protocols) => {
+                            f.write_str("alpn=")?;
+                            for (i, protocol) in protocols.iter().enumerate() {
+                                if i > 0 {
+                                    f.write_str(",")?;
+                                }
+                                // commas within one are escaped, as they
+                                // separate them
+                                for (j, part) in protocol.split(|&b| b == b',').enumerate() {
+                                    if j > 0 {
+                                        f.write_str("\\,")?;
+                                    }
+                                    write_escaped(f, part)?;
+                                }
+                            }
+                        }
+                        SvcParam::Port(port) => write!(f, "port={port}")?,
+                        SvcParam::Ipv4Hint(ips) => write!(
+                            f,
+                            "ipv4hint={}",
+                            ips.iter()
+                                .map(Ipv4Addr::to_string)
+                                .collect::<Vec<_>>()
+                                .join(",")
+                        )?,
+                        SvcParam::Ipv6Hint(ips) => write!(
+                            f,
+                            "ipv6hint={}",
+                            ips.iter()
+                                .map(Ipv6Addr::to_string)
+                                .collect::<Vec<_>>()
+                                .join(",")
+                        )?,
+                        SvcParam::Other { key, value } => {
+                            write!(f, "key{key}=")?;
+                            write_quoted(f, value)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            RData::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => write!(
+                f,
+                "{type_covered} {algorithm} {labels} {original_ttl} {} {} {key_tag} {signer}. {signature:?}",
+                timestamp(*expiration),
+                timestamp(*inception),
+            ),
+            RData::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => write!(f, "{flags} {protocol} {algorithm} {public_key:?}"),
+            RData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => write!(f, "{key_tag} {algorithm} {digest_type} {digest:?}"),
+            RData::NSEC { next, types } => {
+                write!(f, "{next}.")?;
+                types.iter().try_for_each(|t| write!(f, " {t}"))
+            }
+            RData::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => {
+                write!(
+                    f,
+                    "{hash_algorithm} {flags} {iterations} {salt:?} {next_hashed:?}"
+                )?;
+                types.iter().try_for_each(|t| write!(f, " {t}"))
+            }
+            RData::OPT { options } => {
+                for (i, option) in options.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    match option {
+                        EdnsOption::Cookie { client, server } => {
+                            f.write_str("COOKIE ")?;
+                            write_hex(f, &client.0)?;
+                            write_hex(f, &server.0)?;
+                        }
+                        EdnsOption::Other { code, data } => {
+                            write!(f, "OPT{code} ")?;
+                            write_hex(f, data)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            // as in RFC 3597
+            RData::Unknown { bytes } => {
+                write!(f, "\\# {}", bytes.len())?;
+                if !bytes.is_empty() {
+                    f.write_str(" ")?;
+                }
+                write_hex(f, bytes)
             }
         }
     }
 }
 
-fn lookup(name: &str, qtype: QueryType, server_addr: impl ToSocketAddrs) -> io::Result<DnsPacket> {
-    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|b| write!(f, "{b:02x}"))
+}
+
+// A <character-string> in quotes, see `write_escaped`.
+fn write_quoted(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    f.write_str("\"")?;
+    write_escaped(f, bytes)?;
+    f.write_str("\"")
+}
+
+// A <character-string> with quotes and backslashes escaped by a backslash,
+// and other than printable ASCII as \DDD.
+fn write_escaped(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+            0x20..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{b:03}")?,
+        }
+    }
+    Ok(())
+}
+
+// seconds since the epoch as YYYYMMDDHHmmSS, in UTC, as RRSIG records have
+// their times written
+fn timestamp(secs: u32) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // from the days since 0000-03-01, when leap days come at the end of the
+    // year
+    let days = days + 719_468;
+    let (era, day) = (days / 146_097, days % 146_097);
+    let year_of_era = (day - day / 1460 + day / 36524 - day / 146_096) / 365;
+    let day_of_year = day - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * month + 2) / 5 + 1;
+    let (year, month) = match month {
+        0..=9 => (era * 400 + year_of_era, month + 3),
+        _ => (era * 400 + year_of_era + 1, month - 9),
+    };
+    format!(
+        "{year:04}{month:02}{day_of_month:02}{:02}{:02}{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+impl FromBytes for DnsRecord {
+    fn from_bytes(reader: &mut PacketBufReader) -> Result<Self, DnsError> {
+        let domain = reader.read_dns_name()?;
+        let r#type = QueryType::from(reader.read_u16()?);
+        let class = reader.read_u16()?;
+        let ttl = reader.read_u32()?;
+        let len = reader.read_u16()?;
+        // where the data ends, for reading none of the next record's as ours
+        let end = reader.pos + len as usize;
+        if end > reader.buf.len() {
+            return Err(DnsError::Truncated);
+        }
+
+        // no data at all, in UPDATE messages, for whole RRsets rather than
+        // particular records
+        if len == 0 && (class == CLASS_NONE || class == CLASS_ANY) && r#type != QueryType::OPT {
+            return Ok(DnsRecord {
+                domain,
+                r#type,
+                class,
+                ttl,
+                rdata: RData::Unknown { bytes: vec![] },
+            });
+        }
+
+        let rdata = match r#type {
+            QueryType::A => RData::A {
+                ip: Ipv4Addr::from_bits(reader.read_u32()?),
+            },
+            QueryType::NS => RData::NS {
+                host: reader.read_dns_name()?,
+            },
+            QueryType::CNAME => RData::CNAME {
+                host: reader.read_dns_name()?,
+            },
+            QueryType::SOA => RData::SOA {
+                mname: reader.read_dns_name()?,
+                rname: reader.read_dns_name()?,
+                serial: reader.read_u32()?,
+                refresh: reader.read_u32()?,
+                retry: reader.read_u32()?,
+                expire: reader.read_u32()?,
+                minimum: reader.read_u32()?,
+            },
+            QueryType::PTR => RData::PTR {
+                host: reader.read_dns_name()?,
+            },
+            QueryType::MX => RData::MX {
+                priority: reader.read_u16()?,
+                host: reader.read_dns_name()?,
+            },
+            QueryType::TXT => {
+                let mut strings = vec![];
+                while reader.pos < end {
+                    strings.push(reader.read_string()?);
+                }
+                RData::TXT { strings }
+            }
+            QueryType::AAAA => RData::AAAA {
+                ip: Ipv6Addr::from_bits(reader.read_u128()?),
+            },
+            QueryType::SRV => RData::SRV {
+                priority: reader.read_u16()?,
+                weight: reader.read_u16()?,
+                port: reader.read_u16()?,
+                target: reader.read_dns_name()?,
+            },
+            QueryType::NAPTR => RData::NAPTR {
+                order: reader.read_u16()?,
+                preference: reader.read_u16()?,
+                flags: reader.read_string()?,
+                services: reader.read_string()?,
+                regexp: reader.read_string()?,
+                replacement: reader.read_dns_name()?,
+            },
+            QueryType::SVCB | QueryType::HTTPS => RData::SVCB {
+                priority: reader.read_u16()?,
+                target: reader.read_dns_name()?,
+                params: reader.read_svc_params(end)?,
+            },
+            QueryType::RRSIG => RData::RRSIG {
+                type_covered: QueryType::from(reader.read_u16()?),
+                algorithm: reader.read_u8()?,
+                labels: reader.read_u8()?,
+                original_ttl: reader.read_u32()?,
+                expiration: reader.read_u32()?,
+                inception: reader.read_u32()?,
+                key_tag: reader.read_u16()?,
+                signer: reader.read_dns_name()?,
+                signature: Base64(reader.read_rest(end, len)?),
+            },
+            QueryType::DNSKEY => RData::DNSKEY {
+                flags: reader.read_u16()?,
+                protocol: reader.read_u8()?,
+                algorithm: reader.read_u8()?,
+                public_key: Base64(reader.read_rest(end, len)?),
+            },
+            QueryType::DS => RData::DS {
+                key_tag: reader.read_u16()?,
+                algorithm: reader.read_u8()?,
+                digest_type: reader.read_u8()?,
+                digest: Hex(reader.read_rest(end, len)?),
+            },
+            QueryType::NSEC => {
+                let next = reader.read_dns_name()?;
+                let types = reader.read_type_bitmap(end)?;
+                RData::NSEC { next, types }
+            }
+            QueryType::NSEC3 => {
+                let hash_algorithm = reader.read_u8()?;
+                let flags = reader.read_u8()?;
+                let iterations = reader.read_u16()?;
+                let salt_len = reader.read_u8()?;
+                let salt = Hex(reader.read_bytes(salt_len as usize)?.to_vec());
+                let hash_len = reader.read_u8()?;
+                let next_hashed = Base32Hex(reader.read_bytes(hash_len as usize)?.to_vec());
+                let types = reader.read_type_bitmap(end)?;
+                RData::NSEC3 {
+                    hash_algorithm,
+                    flags,
+                    iterations,
+                    salt,
+                    next_hashed,
+                    types,
+                }
+            }
+            QueryType::OPT => RData::OPT {
+                options: reader.read_edns_options(end)?,
+            },
+            // never the type of a record, but nothing to choke on either
+            QueryType::IXFR | QueryType::AXFR | QueryType::Unknown(_) => RData::Unknown {
+                bytes: reader.read_bytes(len as usize)?.to_vec(),
+            },
+        };
+        if reader.pos != end {
+            return Err(DnsError::BadRdataLength(len));
+        }
+
+        Ok(DnsRecord {
+            domain,
+            r#type,
+            class,
+            ttl,
+            rdata,
+        })
+    }
+}
+
+impl ToBytes for DnsRecord {
+    fn to_bytes(&self, writer: &mut PacketBufWriter) -> Result<(), DnsError> {
+        writer.write_name(&self.domain)?;
+        writer.write_u16(self.r#type.into())?;
+        writer.write_u16(self.class)?;
+        writer.write_u32(self.ttl)?;
+
+        // filled in once the data is written, as how long its names end up
+        // depends on what was written before
+        let rdlen_pos = writer.pos();
+        writer.write_u16(0)?;
+
+        match &self.rdata {
+            RData::A { ip } => writer.write_u32(ip.to_bits())?,
+            RData::NS { host } | RData::CNAME { host } | RData::PTR { host } => {
+                writer.write_name(host)?
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                writer.write_name(mname)?;
+                writer.write_name(rname)?;
+                writer.write_u32(*serial)?;
+                writer.write_u32(*refresh)?;
+                writer.write_u32(*retry)?;
+                writer.write_u32(*expire)?;
+                writer.write_u32(*minimum)?;
+            }
+            RData::MX { priority, host } => {
+                writer.write_u16(*priority)?;
+                writer.write_name(host)?;
+            }
+            RData::TXT { strings } => {
+                for s in strings {
+                    writer.write_string(s)?;
+                }
+            }
+            RData::AAAA { ip } => writer.write_u128(ip.to_bits())?,
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                writer.write_u16(*priority)?;
+                writer.write_u16(*weight)?;
+                writer.write_u16(*port)?;
+                writer.write_name_uncompressed(target)?;
+            }
+            RData::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                writer.write_u16(*order)?;
+                writer.write_u16(*preference)?;
+                writer.write_string(flags)?;
+                writer.write_string(services)?;
+                writer.write_string(regexp)?;
+                writer.write_name_uncompressed(replacement)?;
+            }
+            RData::SVCB {
+                priority,
+                target,
+                params,
+            } => {
+                writer.write_u16(*priority)?;
+                writer.write_name_uncompressed(target)?;
+                writer.write_svc_params(params)?;
+            }
+            RData::RRSIG {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer,
+                signature,
+            } => {
+                writer.write_u16((*type_covered).into())?;
+                writer.write_u8(*algorithm)?;
+                writer.write_u8(*labels)?;
+                writer.write_u32(*original_ttl)?;
+                writer.write_u32(*expiration)?;
+                writer.write_u32(*inception)?;
+                writer.write_u16(*key_tag)?;
+                writer.write_name_uncompressed(signer)?;
+                writer.write_bytes(&signature.0)?;
+            }
+            RData::DNSKEY {
+                flags,
+                protocol,
+                algorithm,
+                public_key,
+            } => {
+                writer.write_u16(*flags)?;
+                writer.write_u8(*protocol)?;
+                writer.write_u8(*algorithm)?;
+                writer.write_bytes(&public_key.0)?;
+            }
+            RData::DS {
+                key_tag,
+                algorithm,
+                digest_type,
+                digest,
+            } => {
+                writer.write_u16(*key_tag)?;
+                writer.write_u8(*algorithm)?;
+                writer.write_u8(*digest_type)?;
+                writer.write_bytes(&digest.0)?;
+            }
+            RData::NSEC { next, types } => {
+                writer.write_name_uncompressed(next)?;
+                writer.write_type_bitmap(types)?;
+            }
+            RData::NSEC3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed,
+                types,
+            } => {
+                writer.write_u8(*hash_algorithm)?;
+                writer.write_u8(*flags)?;
+                writer.write_u16(*iterations)?;
+                writer.write_u8(salt.0.len() as u8)?;
+                writer.write_bytes(&salt.0)?;
+                writer.write_u8(next_hashed.0.len() as u8)?;
+                writer.write_bytes(&next_hashed.0)?;
+                writer.write_type_bitmap(types)?;
+            }
+            RData::OPT { options } => writer.write_edns_options(options)?,
+            RData::Unknown { bytes } => writer.write_bytes(bytes)?,
+        }
+
+        let rdlen = writer.pos() - rdlen_pos - 2;
+        writer.set_u16(rdlen_pos, rdlen as u16);
+
+        Ok(())
+    }
+}
+
+pub fn recursive_lookup(name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+    let resp = resolve_from(name, qtype, &ROOT_HINTS, 53, 0)?;
+    let max = LookupConfig::default().max_cnames;
+    chase_cnames(resp, name, qtype, max, |target| {
+        resolve_from(target, qtype, &ROOT_HINTS, 53, 0)
+    })
+}
+
+// Follows the referrals for `name` down from the `roots`, with every server
+// listening on `port`. `depth` is how many lookups of nameservers without glue
+// this is within.
+fn resolve_from(
+    name: &str,
+    qtype: QueryType,
+    roots: &[Ipv4Addr],
+    port: u16,
+    depth: u8,
+) -> io::Result<DnsPacket> {
+    let qname = DnsName::new(name)?;
+    let mut servers = roots.to_vec();
+    // what the servers being asked have been delegated
+    let mut zone = DnsName::root();
+
+    for _ in 0..MAX_REFERRALS {
+        println!("Attempting lookup of {qtype:?} {name:?} with ns {servers:?}");
+
+        let resp = ask(name, qtype, &servers, port)?;
 
-    let mut query = DnsPacket::new_empty();
-    query.header.id = 6666;
-    query.header.rd = true;
-    query.header.qdcount = 1;
-    query.questions.push(DnsQuestion {
-        name: name.to_string(),
-        r#type: qtype,
-        class: 1,
-    });
+        // return the packet as-is so the caller propagates the correct rcode
+        if !resp.answers.is_empty() || resp.header.rcode != RCode::Noerror {
+            return Ok(resp);
+        }
+
+        // only a zone closer to the name than the last one is progress
+        let referral: Vec<(&DnsName, &str)> = resp
+            .get_ns(&qname)
+            .filter(|(domain, _)| domain.labels().count() > zone.labels().count())
+            .collect();
+        let deepest = referral
+            .iter()
+            .map(|(domain, _)| *domain)
+            .max_by_key(|domain| domain.labels().count());
+        let Some(domain) = deepest else {
+            // the name has no records of that type, which the zone's SOA
+            // comes along to say
+            if resp.header.aa || resp.authorities.iter().any(|r| r.r#type == QueryType::SOA) {
+                return Ok(resp);
+            }
+            return Err(io::Error::other(
+                "no authoritative nameserver to delegate to",
+            ));
+        };
+        let hosts: Vec<&str> = referral
+            .iter()
+            .filter(|(d, _)| *d == domain)
+            .map(|(_, host)| *host)
+            .collect();
+
+        // fast path
+        let glue = resp.get_glue(&hosts);
+        servers = if glue.is_empty() {
+            // slow path
+            resolve_ns(&hosts, roots, port, depth)?
+        } else {
+            glue
+        };
+        zone = domain.clone();
+    }
+
+    Err(io::Error::other("too many referrals"))
+}
+
+// Looks up the addresses of nameservers that came without glue, until one of
+// them has some.
+fn resolve_ns(
+    hosts: &[&str],
+    roots: &[Ipv4Addr],
+    port: u16,
+    depth: u8,
+) -> io::Result<Vec<Ipv4Addr>> {
+    if depth >= MAX_NS_DEPTH {
+        return Err(io::Error::other(
+            "too many nameservers without glue one within the other",
+        ));
+    }
+    for host in hosts {
+        match resolve_from(host, QueryType::A, roots, port, depth + 1) {
+            Ok(resp) if !resp.get_a().is_empty() => return Ok(resp.get_a()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Could not look up nameserver {host}: {e}"),
+        }
+    }
+    Err(io::Error::other(
+        "nameserver hostname did not resolve to an A record",
+    ))
+}
+
+// Asks each of `servers` in turn, for what it knows itself, until one of them
+// answers.
+fn ask(name: &str, qtype: QueryType, servers: &[Ipv4Addr], port: u16) -> io::Result<DnsPacket> {
+    let config = LookupConfig {
+        recursion_desired: false,
+        // left to whoever goes looking for where they lead
+        max_cnames: 0,
+        // each of the others asked instead
+        retries: 0,
+        ..LookupConfig::default()
+    };
+    let mut last_err = io::Error::other("no nameservers to ask");
+    for ip in servers {
+        match lookup(name, qtype, (*ip, port), &config) {
+            Ok(resp)
+                if matches!(
+                    resp.header.rcode,
+                    RCode::Servfail | RCode::Notimp | RCode::Refused
+                ) =>
+            {
+                last_err = io::Error::other(format!("{ip} answered {:?}", resp.header.rcode));
+            }
+            Ok(resp) => return Ok(resp),
+            Err(e) => last_err = e,
+        }
+    }
+    Err(last_err)
+}
+
+// The hostnames `ip` points back to.
+pub fn lookup_reverse(ip: IpAddr) -> io::Result<Vec<DnsName>> {
+    let resp = recursive_lookup(&reverse_name(ip), QueryType::PTR)?;
+    Ok(resp
+        .answers
+        .into_iter()
+        .filter_map(|r| match r.rdata {
+            RData::PTR { host } => Some(host),
+            _ => None,
+        })
+        .collect())
+}
+
+// The name under in-addr.arpa or ip6.arpa that PTR records for `ip` live at:
+// its bytes, or for IPv6 its nibbles, least significant first.
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, d] = ip.octets();
+            format!("{d}.{c}.{b}.{a}.in-addr.arpa")
+        }
+        IpAddr::V6(ip) => {
+            let mut name = String::new();
+            for byte in ip.octets().iter().rev() {
+                name += &format!("{:x}.{:x}.", byte & 0xf, byte >> 4);
+            }
+            name + "ip6.arpa"
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Transport {
+    Udp,
+    // for answers too big for a datagram
+    Tcp,
+}
+
+#[derive(Debug, Clone)]
+pub struct LookupConfig {
+    pub transport: Transport,
+    // whether to ask again over TCP when an answer over UDP comes back
+    // truncated, rather than make do with what fit
+    pub tcp_fallback: bool,
+    // whether to ask the server to go and find the answer, rather than answer
+    // from what it knows itself
+    pub recursion_desired: bool,
+    // how many CNAMEs to follow from the name asked about to the records
+    // asked for, with 0 leaving them for the caller to follow
+    pub max_cnames: usize,
+    // how long to wait for each answer, the first time
+    pub timeout: Duration,
+    // how many more times to ask, each time the next of the servers, when
+    // one doesn't answer
+    pub retries: usize,
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::Udp,
+            tcp_fallback: true,
+            recursion_desired: true,
+            max_cnames: 8,
+            timeout: READ_TIMEOUT,
+            retries: 2,
+        }
+    }
+}
+
+// Reads a message sent over TCP, where each is preceded by its length.
+pub fn read_tcp_message(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+pub fn write_tcp_message(stream: &mut impl Write, msg: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(msg.len()).map_err(|_| DnsError::BufferFull)?;
+    // in one write, so as not to send the length in a segment of its own
+    let mut framed = len.to_be_bytes().to_vec();
+    framed.extend_from_slice(msg);
+    stream.write_all(&framed)
+}
+
+// A recursive query for `name`, or an error for a name that can't be one.
+fn query(name: &str, qtype: QueryType) -> Result<DnsPacket, DnsError> {
+    let mut query = DnsPacket::query(name, qtype)?.recursion_desired(true);
+    // with the signatures, for whoever wants to check them
+    query.set_edns(EDNS_PAYLOAD_SIZE as u16, true);
+    Ok(query)
+}
+
+pub fn lookup(
+    name: &str,
+    qtype: QueryType,
+    server_addr: impl ToSocketAddrs,
+    config: &LookupConfig,
+) -> io::Result<DnsPacket> {
+    // resolved once, as the query may have to be sent again, or others after
+    // it
+    let server_addrs: Vec<SocketAddr> = server_addr.to_socket_addrs()?.collect();
+
+    let resp = lookup_at(name, qtype, &server_addrs, config)?;
+    chase_cnames(resp, name, qtype, config.max_cnames, |target| {
+        lookup_at(target, qtype, &server_addrs, config)
+    })
+}
 
-    let mut req_buf = [0u8; PACKET_SIZE];
-    query.to_bytes(&mut req_buf).unwrap();
+fn lookup_at(
+    name: &str,
+    qtype: QueryType,
+    server_addrs: &[SocketAddr],
+    config: &LookupConfig,
+) -> io::Result<DnsPacket> {
+    let original = DnsName::new(name)?;
+    let ask = |transport, server: SocketAddr, timeout| {
+        let asked = randomize_case(name);
+        let mut query = query(&asked, qtype)?;
+        query.header.rd = config.recursion_desired;
+        cookie::add(&mut query, server.ip());
+        let mut resp = exchange(&query.to_vec()?, server, transport, timeout)?;
+        cookie::check(&resp, server.ip())?;
+        restore_case(&mut resp, &asked, &original);
+        Ok::<_, io::Error>(resp)
+    };
+
+    // each retry goes to the next of the servers, waiting twice as long as
+    // before each time it has been through all of them
+    let mut answered = Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "no address to send to",
+    ));
+    for (attempt, &server) in server_addrs
+        .iter()
+        .cycle()
+        .take(config.retries + 1)
+        .enumerate()
+    {
+        let round = (attempt / server_addrs.len()).min(MAX_BACKOFF);
+        let timeout = config.timeout * (1 << round);
+        answered = ask(config.transport, server, timeout).map(|resp| (resp, server));
+        if answered.is_ok() {
+            break;
+        }
+    }
+    let (mut resp, server) = answered?;
+
+    // for a server that won't answer without its own cookie, which it has
+    // now sent, and over TCP if that isn't enough
+    if cookie::is_bad(&resp) {
+        resp = ask(config.transport, server, config.timeout)?;
+        if cookie::is_bad(&resp) {
+            return ask(Transport::Tcp, server, config.timeout);
+        }
+    }
+    if resp.header.tc && config.transport == Transport::Udp && config.tcp_fallback {
+        return ask(Transport::Tcp, server, config.timeout);
+    }
+    Ok(resp)
+}
 
-    socket.send_to(&req_buf, server_addr)?;
+// `name` with each letter in upper or lower case at random, for answers to
+// have to match to be taken: a spoofer has to guess which as well as the ID
+// (the "0x20" bits of each letter).
+fn randomize_case(name: &str) -> String {
+    let mut random = vec![0u8; name.len()];
+    // with the name as it is should there be no randomness to be had
+    if SystemRandom::new().fill(&mut random).is_err() {
+        return name.to_string();
+    }
+    name.chars()
+        .zip(random)
+        .map(|(c, r)| {
+            if r & 1 == 0 {
+                c.to_ascii_lowercase()
+            } else {
+                c.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
 
-    let mut res_buf = [0u8; PACKET_SIZE];
-    socket.recv_from(&mut res_buf)?;
+// Puts the names in an answer to a question asked as `asked` back as they
+// were before their case was randomized. Answers that don't echo it exactly
+// aren't taken in the first place.
+fn restore_case(resp: &mut DnsPacket, asked: &str, name: &DnsName) {
+    for q in &mut resp.questions {
+        q.name = name.clone();
+    }
+    let records = resp
+        .answers
+        .iter_mut()
+        .chain(&mut resp.authorities)
+        .chain(&mut resp.resources);
+    for rec in records.filter(|r| r.domain.as_str() == asked) {
+        rec.domain = name.clone();
+    }
+}
+
+// Follows the CNAMEs in `resp` from `name`, asking `ask` where they lead
+// wherever the answers stop short of that, and adds what it says to `resp`.
+// Fails on a chain that goes round in circles or is longer than `max`, with
+// 0 leaving the CNAMEs for the caller to follow.
+fn chase_cnames(
+    mut resp: DnsPacket,
+    name: &str,
+    qtype: QueryType,
+    max: usize,
+    mut ask: impl FnMut(&str) -> io::Result<DnsPacket>,
+) -> io::Result<DnsPacket> {
+    if max == 0 || qtype == QueryType::CNAME {
+        return Ok(resp);
+    }
+
+    let mut seen: Vec<String> = vec![];
+    let mut current = name.to_string();
+    loop {
+        while let Some(host) = resp.get_cname(&current) {
+            seen.push(current.to_ascii_lowercase());
+            if seen.contains(&host.to_ascii_lowercase()) {
+                return Err(io::Error::other(format!("CNAME loop at {host}")));
+            }
+            if seen.len() > max {
+                return Err(io::Error::other(format!(
+                    "more than {max} CNAMEs from {name}"
+                )));
+            }
+            current = host.to_string();
+        }
+
+        let answered = resp
+            .answers
+            .iter()
+            .any(|r| r.r#type == qtype && r.domain.eq_ignore_ascii_case(&current));
+        if seen.is_empty() || answered || resp.header.rcode != RCode::Noerror {
+            return Ok(resp);
+        }
 
-    Ok(DnsPacket::from_bytes(&res_buf).unwrap())
+        let next = ask(&current)?;
+        let goes_on = next.get_cname(&current).is_some();
+        resp.header.rcode = next.header.rcode;
+        resp.answers.extend(next.answers);
+        resp.authorities = next.authorities;
+        resp.resources = next.resources;
+        resp.header.ancount = resp.answers.len() as u16;
+        resp.header.nscount = resp.authorities.len() as u16;
+        resp.header.arcount = resp.resources.len() as u16;
+        if !goes_on {
+            return Ok(resp);
+        }
+    }
+}
+
+// An ID for a query that can't be guessed, for answers to have to match.
+pub(crate) fn random_id() -> u16 {
+    let mut id = [0u8; 2];
+    // an ID all the same should there be no randomness to be had
+    let _ = SystemRandom::new().fill(&mut id);
+    u16::from_be_bytes(id)
+}
+
+// Whether `resp` is the answer to `req`: the same ID, and the question
+// echoed as it was asked, but for errors about queries that couldn't be
+// read.
+pub(crate) fn is_answer_to(req: &DnsPacket, resp: &DnsPacket) -> bool {
+    // names in the case they were asked in, which equality doesn't see
+    let same = |a: &DnsQuestion, b: &DnsQuestion| a == b && a.name.as_str() == b.name.as_str();
+    let echoed = (resp.questions.len() == req.questions.len()
+        && resp
+            .questions
+            .iter()
+            .zip(&req.questions)
+            .all(|(a, b)| same(a, b)))
+        || (resp.questions.is_empty() && resp.header.rcode != RCode::Noerror);
+    resp.header.qr && resp.header.id == req.header.id && echoed
+}
+
+// what reads that run out of time fail with, which is WouldBlock on Unix
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+// Sends the query in `req_buf` and waits up to `timeout` for the answer.
+fn exchange(
+    req_buf: &[u8],
+    server_addr: SocketAddr,
+    transport: Transport,
+    timeout: Duration,
+) -> io::Result<DnsPacket> {
+    let req = DnsPacket::from_bytes(req_buf)?;
+    match transport {
+        Transport::Udp => {
+            // a socket of the same family, or the query can't be sent
+            let local: SocketAddr = match server_addr {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.send_to(req_buf, server_addr)?;
+
+            // anything else that turns up, whether from elsewhere or
+            // spoofed, is passed over for the real answer, if it comes in
+            // time
+            let deadline = Instant::now() + timeout;
+            let mut res_buf = [0u8; EDNS_PAYLOAD_SIZE];
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no answer from {server_addr}"),
+                    ));
+                }
+                socket.set_read_timeout(Some(left))?;
+                let (len, src_addr) = match socket.recv_from(&mut res_buf) {
+                    Ok(received) => received,
+                    // out of time
+                    Err(e) if is_timeout(&e) => continue,
+                    Err(e) => return Err(e),
+                };
+                if src_addr != server_addr {
+                    continue;
+                }
+                match DnsPacket::from_bytes(&res_buf[..len]) {
+                    Ok(resp) if is_answer_to(&req, &resp) => return Ok(resp),
+                    _ => continue,
+                }
+            }
+        }
+        Transport::Tcp => {
+            let mut stream = TcpStream::connect_timeout(&server_addr, timeout)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            write_tcp_message(&mut stream, req_buf)?;
+
+            let resp_buf = read_tcp_message(&mut stream).map_err(|e| {
+                if is_timeout(&e) {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no answer from {server_addr}"),
+                    )
+                } else {
+                    e
+                }
+            })?;
+            let resp = DnsPacket::from_bytes(&resp_buf)?;
+            if !is_answer_to(&req, &resp) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "answer to some other query",
+                ));
+            }
+            Ok(resp)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -668,6 +2579,8 @@ mod tests {
 
     use std::fs::File;
     use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::thread;
 
     #[test]
     fn from_raw_bytes() {
@@ -749,12 +2662,1175 @@ mod tests {
         assert!(packet.resources.is_empty());
     }
 
+    #[test]
+    fn malformed_packets() {
+        let header = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+
+        // a question that stops partway through its name
+        let mut bytes = header.to_vec();
+        bytes.extend([6, b'g', b'o']);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::Truncated
+        );
+
+        // a name that points back at itself
+        let mut bytes = header.to_vec();
+        bytes.extend([0xC0, 12, 0, 1, 0, 1]);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::TooManyJumps
+        );
+
+        // a label length with the reserved 0x40 bit set
+        let mut bytes = header.to_vec();
+        bytes.extend([0x41, b'a', 0, 0, 1, 0, 1]);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::BadLabelLength(0x41)
+        );
+    }
+
+    // writes a packet answering with `rdata` and reads it back
+    fn roundtrip(r#type: QueryType, rdata: RData) -> RData {
+        let mut packet = DnsPacket::new_empty();
+        packet.header.ancount = 1;
+        packet.answers.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type,
+            class: 1,
+            ttl: 300,
+            rdata,
+        });
+        let mut buf = [0u8; PACKET_SIZE];
+        packet.to_bytes(&mut buf).unwrap();
+        DnsPacket::from_bytes(&buf).unwrap().answers.remove(0).rdata
+    }
+
+    #[test]
+    fn txt_roundtrip() {
+        let txt = || RData::TXT {
+            strings: vec![
+                b"v=spf1 -all".to_vec(),
+                vec![],
+                "ünïcödé".as_bytes().to_vec(),
+                // not UTF-8, and passed on unchanged all the same
+                vec![0xFF, 0xFE, b'x'],
+            ],
+        };
+        assert_eq!(roundtrip(QueryType::TXT, txt()), txt());
+
+        // the longest a string can be, even when not UTF-8
+        for string in [b"x".repeat(255), vec![0xFF; 255]] {
+            let txt = || RData::TXT {
+                strings: vec![string.clone()],
+            };
+            assert_eq!(roundtrip(QueryType::TXT, txt()), txt());
+        }
+    }
+
+    #[test]
+    fn soa_roundtrip() {
+        let soa = || RData::SOA {
+            mname: "ns1.example.com".parse().unwrap(),
+            rname: "hostmaster.example.com".parse().unwrap(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 300,
+        };
+        assert_eq!(roundtrip(QueryType::SOA, soa()), soa());
+    }
+
+    #[test]
+    fn srv_roundtrip_and_order() {
+        let srv = |priority, weight, target: &str| RData::SRV {
+            priority,
+            weight,
+            port: 5060,
+            target: target.parse().unwrap(),
+        };
+        assert_eq!(
+            roundtrip(QueryType::SRV, srv(10, 60, "sip.example.com")),
+            srv(10, 60, "sip.example.com")
+        );
+
+        let mut packet = DnsPacket::new_empty();
+        for rdata in [
+            srv(20, 0, "backup.example.com"),
+            srv(10, 20, "light.example.com"),
+            srv(10, 80, "heavy.example.com"),
+        ] {
+            packet.answers.push(DnsRecord {
+                domain: "_sip._tcp.example.com".parse().unwrap(),
+                r#type: QueryType::SRV,
+                class: 1,
+                ttl: 300,
+                rdata,
+            });
+        }
+        packet.answers.push(DnsRecord {
+            domain: "_sip._tcp.example.com".parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 300,
+            rdata: RData::A {
+                ip: Ipv4Addr::LOCALHOST,
+            },
+        });
+        assert_eq!(
+            packet.get_srv_targets(),
+            [
+                ("heavy.example.com", 5060),
+                ("light.example.com", 5060),
+                ("backup.example.com", 5060),
+            ]
+        );
+    }
+
+    #[test]
+    fn ptr_roundtrip_and_reverse_names() {
+        let ptr = || RData::PTR {
+            host: "dns.google".parse().unwrap(),
+        };
+        assert_eq!(roundtrip(QueryType::PTR, ptr()), ptr());
+
+        assert_eq!(
+            reverse_name(IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4))),
+            "4.4.8.8.in-addr.arpa"
+        );
+        assert_eq!(
+            reverse_name(IpAddr::V6("2001:db8::567:89ab".parse().unwrap())),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    // hands out what it reads from a byte at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn tcp_framing() {
+        let mut stream = vec![];
+        write_tcp_message(&mut stream, b"first").unwrap();
+        write_tcp_message(&mut stream, &[7; 300]).unwrap();
+        assert_eq!(&stream[..7], b"\x00\x05first");
+        assert_eq!(&stream[7..9], [1, 44]);
+
+        let mut reader = Trickle(&stream);
+        assert_eq!(read_tcp_message(&mut reader).unwrap(), b"first");
+        assert_eq!(read_tcp_message(&mut reader).unwrap(), [7; 300]);
+        let e = read_tcp_message(&mut reader).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        // the length promises more than there is
+        let e = read_tcp_message(&mut Trickle(&[0, 3, 1, 2])).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // an answer to `req` too big to fit in a datagram
+    fn big_answer(req: &DnsPacket) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.id = req.header.id;
+        resp.questions = req.questions.clone();
+        resp.header.qdcount = 1;
+        resp.header.qr = true;
+        resp.header.ancount = 10;
+        for i in 0..10 {
+            resp.answers.push(DnsRecord {
+                domain: req.questions[0].name.clone(),
+                r#type: QueryType::TXT,
+                class: 1,
+                ttl: 300,
+                rdata: RData::TXT {
+                    strings: vec![i.to_string().repeat(100).into_bytes()],
+                },
+            });
+        }
+        resp
+    }
+
+    // Answers `connections` connections on `listener` with `big_answer`.
+    fn serve_tcp(listener: TcpListener, connections: usize) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                let req = DnsPacket::from_bytes(&read_tcp_message(&mut stream).unwrap()).unwrap();
+                let resp = big_answer(&req).to_vec().unwrap();
+                write_tcp_message(&mut stream, &resp).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn lookup_over_tcp() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = serve_tcp(listener, 1);
+
+        let config = LookupConfig {
+            transport: Transport::Tcp,
+            ..LookupConfig::default()
+        };
+        let resp = lookup("example.com", QueryType::TXT, addr, &config).unwrap();
+        server.join().unwrap();
+        assert_eq!(resp.questions[0].name, "example.com");
+        assert_eq!(resp.answers.len(), 10);
+        assert_eq!(
+            resp.answers[9].rdata,
+            RData::TXT {
+                strings: vec![b"9".repeat(100)]
+            }
+        );
+    }
+
+    #[test]
+    fn truncated_answers_are_asked_for_again_over_tcp() {
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let tcp_server = serve_tcp(TcpListener::bind(addr).unwrap(), 1);
+        let udp_server = thread::spawn(move || {
+            for _ in 0..2 {
+                let mut buf = [0u8; PACKET_SIZE];
+                let (len, src_addr) = socket.recv_from(&mut buf).unwrap();
+                let req = DnsPacket::from_bytes(&buf[..len]).unwrap();
+                let resp = big_answer(&req).to_vec_truncated(PACKET_SIZE).unwrap();
+                socket.send_to(&resp, src_addr).unwrap();
+            }
+        });
+
+        let resp = lookup(
+            "example.com",
+            QueryType::TXT,
+            addr,
+            &LookupConfig::default(),
+        )
+        .unwrap();
+        assert!(!resp.header.tc);
+        assert_eq!(resp.answers.len(), 10);
+        tcp_server.join().unwrap();
+
+        let config = LookupConfig {
+            tcp_fallback: false,
+            ..LookupConfig::default()
+        };
+        let resp = lookup("example.com", QueryType::TXT, addr, &config).unwrap();
+        assert!(resp.header.tc);
+        assert_eq!(resp.answers.len(), 4);
+        udp_server.join().unwrap();
+    }
+
+    // Answers queries on `socket` with whatever `answer` makes of them.
+    fn serve_udp(socket: UdpSocket, answer: impl Fn(&DnsPacket) -> DnsPacket + Send + 'static) {
+        thread::spawn(move || {
+            let mut buf = [0u8; PACKET_SIZE];
+            loop {
+                let (len, src_addr) = socket.recv_from(&mut buf).unwrap();
+                let mut req = DnsPacket::from_bytes(&buf[..len]).unwrap();
+                let asked = req.questions.clone();
+                // whatever case the names are asked in
+                for ques in &mut req.questions {
+                    ques.name.make_ascii_lowercase();
+                }
+                let mut resp = answer(&req);
+                resp.header.id = req.header.id;
+                resp.questions = asked;
+                resp.header.qdcount = resp.questions.len() as u16;
+                resp.header.qr = true;
+                resp.header.ancount = resp.answers.len() as u16;
+                resp.header.nscount = resp.authorities.len() as u16;
+                resp.header.arcount = resp.resources.len() as u16;
+                socket.send_to(&resp.to_vec().unwrap(), src_addr).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn names_are_asked_in_random_case() {
+        let name = "www.some-long-name-with-lots-of-letters.example.com";
+        let asked: Vec<String> = (0..4).map(|_| randomize_case(name)).collect();
+        assert!(asked.iter().all(|a| a.eq_ignore_ascii_case(name)));
+        assert!(asked.iter().any(|a| a != name));
+
+        // and answered in the same case, put back as it was
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        serve_udp(socket, |req| {
+            let ip = Ipv4Addr::new(10, 0, 0, 1);
+            let mut resp = DnsPacket::new_empty();
+            resp.answers.push(record(
+                &req.questions[0].name,
+                QueryType::A,
+                RData::A { ip },
+            ));
+            resp
+        });
+        let resp = lookup(name, QueryType::A, addr, &LookupConfig::default()).unwrap();
+        assert_eq!(resp.questions[0].name, name);
+        assert_eq!(resp.answers[0].domain, name);
+    }
+
+    #[test]
+    fn servers_are_asked_over_ipv6() {
+        let socket = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        serve_udp(socket, |req| {
+            let ip = Ipv6Addr::LOCALHOST;
+            let mut resp = DnsPacket::new_empty();
+            let owner = &req.questions[0].name;
+            resp.answers
+                .push(record(owner, QueryType::AAAA, RData::AAAA { ip }));
+            resp
+        });
+        let resp = lookup(
+            "example.com",
+            QueryType::AAAA,
+            addr,
+            &LookupConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.answers.len(), 1);
+    }
+
+    #[test]
+    fn servers_that_dont_answer_are_asked_again_then_passed_over() {
+        // bound, and never read from
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let servers = [silent.local_addr().unwrap(), socket.local_addr().unwrap()];
+        serve_udp(socket, |_| DnsPacket::new_empty());
+
+        let config = LookupConfig {
+            timeout: Duration::from_millis(100),
+            retries: 0,
+            ..LookupConfig::default()
+        };
+        let e = lookup("example.com", QueryType::A, &servers[..], &config).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+
+        let config = LookupConfig {
+            retries: 1,
+            ..config
+        };
+        let start = Instant::now();
+        assert!(lookup("example.com", QueryType::A, &servers[..], &config).is_ok());
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_secs(1));
+
+        // twice as long the second time round
+        let config = LookupConfig {
+            retries: 2,
+            ..config
+        };
+        let start = Instant::now();
+        let servers = [servers[0]];
+        assert!(lookup("example.com", QueryType::A, &servers[..], &config).is_err());
+        assert!(start.elapsed() >= Duration::from_millis(700));
+    }
+
+    #[test]
+    fn only_real_answers_are_taken() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let spoofer = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; EDNS_PAYLOAD_SIZE];
+            let (len, src_addr) = socket.recv_from(&mut buf).unwrap();
+            let req = DnsPacket::from_bytes(&buf[..len]).unwrap();
+            let answer = |last| {
+                let mut resp = req.clone();
+                resp.header.qr = true;
+                let ip = Ipv4Addr::new(10, 0, 0, last);
+                let owner = &req.questions[0].name;
+                resp.answers
+                    .push(record(owner, QueryType::A, RData::A { ip }));
+                resp.header.ancount = 1;
+                resp
+            };
+
+            let mut spoofed = vec![];
+            // from somewhere else
+            spoofer
+                .send_to(&answer(66).to_vec().unwrap(), src_addr)
+                .unwrap();
+            let mut resp = answer(66);
+            resp.header.id = resp.header.id.wrapping_add(1);
+            spoofed.push(resp);
+            let mut resp = answer(66);
+            resp.header.qr = false;
+            spoofed.push(resp);
+            let mut resp = answer(66);
+            resp.questions[0].name.make_ascii_lowercase();
+            spoofed.push(resp);
+            let mut resp = answer(66);
+            resp.questions[0].r#type = QueryType::AAAA;
+            spoofed.push(resp);
+            for resp in spoofed.into_iter().chain([answer(1)]) {
+                socket.send_to(&resp.to_vec().unwrap(), src_addr).unwrap();
+            }
+        });
+
+        let name = "www.some-long-name-with-lots-of-letters.example.com";
+        let resp = lookup(name, QueryType::A, addr, &LookupConfig::default()).unwrap();
+        assert_eq!(resp.get_a(), [Ipv4Addr::new(10, 0, 0, 1)]);
+    }
+
+    fn record(domain: &str, r#type: QueryType, rdata: RData) -> DnsRecord {
+        DnsRecord {
+            domain: domain.parse().unwrap(),
+            r#type,
+            class: 1,
+            ttl: 300,
+            rdata,
+        }
+    }
+
+    fn referral(zone: &str, ns: &str, glue: Option<Ipv4Addr>) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        let host = ns.parse().unwrap();
+        resp.authorities
+            .push(record(zone, QueryType::NS, RData::NS { host }));
+        if let Some(ip) = glue {
+            resp.resources
+                .push(record(ns, QueryType::A, RData::A { ip }));
+        }
+        resp
+    }
+
+    fn soa(zone: &str) -> DnsRecord {
+        let rdata = RData::SOA {
+            mname: format!("ns1.{zone}").parse().unwrap(),
+            rname: format!("hostmaster.{zone}").parse().unwrap(),
+            serial: 1,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: 300,
+        };
+        record(zone, QueryType::SOA, rdata)
+    }
+
+    #[test]
+    fn recursive_lookups_follow_referrals() {
+        let root = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = root.local_addr().unwrap().port();
+        let com = Ipv4Addr::new(127, 0, 0, 2);
+        let org = Ipv4Addr::new(127, 0, 0, 3);
+
+        serve_udp(root, move |req| {
+            // asked only for what the server knows itself
+            assert!(!req.header.rd);
+            let ques = &req.questions[0];
+            if ques.name.is_within(&"example.com".parse().unwrap()) {
+                referral("example.com", "ns1.example.com", Some(com))
+            } else if ques.name.is_within(&"example.org".parse().unwrap()) {
+                // only to be found by looking the nameserver up
+                referral("example.org", "ns2.example.com", None)
+            } else {
+                // back to itself, which is no closer
+                referral("net", "a.root-servers.net", Some(Ipv4Addr::LOCALHOST))
+            }
+        });
+        serve_udp(UdpSocket::bind((com, port)).unwrap(), move |req| {
+            let ques = &req.questions[0];
+            let mut resp = DnsPacket::new_empty();
+            resp.header.aa = true;
+            match (ques.name.as_str(), ques.r#type) {
+                ("ns2.example.com", QueryType::A) => {
+                    let rdata = RData::A { ip: org };
+                    resp.answers.push(record(&ques.name, QueryType::A, rdata));
+                }
+                ("www.example.com", QueryType::A) => {
+                    let ip = Ipv4Addr::new(10, 0, 0, 1);
+                    let rdata = RData::A { ip };
+                    resp.answers.push(record(&ques.name, QueryType::A, rdata));
+                }
+                ("www.example.com", _) => resp.authorities.push(soa("example.com")),
+                _ => {
+                    resp.header.rcode = RCode::Nxdomain;
+                    resp.authorities.push(soa("example.com"));
+                }
+            }
+            resp
+        });
+        serve_udp(UdpSocket::bind((org, port)).unwrap(), move |req| {
+            let ques = &req.questions[0];
+            let mut resp = DnsPacket::new_empty();
+            resp.header.aa = true;
+            let ip = Ipv4Addr::new(10, 0, 0, 2);
+            resp.answers
+                .push(record(&ques.name, QueryType::A, RData::A { ip }));
+            resp
+        });
+
+        // a root that won't help, to move on from
+        let refusing = Ipv4Addr::new(127, 0, 0, 4);
+        serve_udp(UdpSocket::bind((refusing, port)).unwrap(), |_| {
+            let mut resp = DnsPacket::new_empty();
+            resp.header.rcode = RCode::Refused;
+            resp
+        });
+
+        let roots = [refusing, Ipv4Addr::LOCALHOST];
+        let resolve = |name, qtype| resolve_from(name, qtype, &roots, port, 0);
+
+        let resp = resolve("www.example.com", QueryType::A).unwrap();
+        assert_eq!(resp.get_a(), [Ipv4Addr::new(10, 0, 0, 1)]);
+        let resp = resolve("www.example.org", QueryType::A).unwrap();
+        assert_eq!(resp.get_a(), [Ipv4Addr::new(10, 0, 0, 2)]);
+
+        let resp = resolve("www.example.com", QueryType::MX).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert!(resp.answers.is_empty());
+        let resp = resolve("nowhere.example.com", QueryType::A).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+
+        assert!(resolve("example.net", QueryType::A).is_err());
+    }
+
+    #[test]
+    fn cname_chains_are_followed() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        serve_udp(socket, |req| {
+            let ques = &req.questions[0];
+            let mut resp = DnsPacket::new_empty();
+            let cname = |host: &str| {
+                let rdata = RData::CNAME {
+                    host: host.parse().unwrap(),
+                };
+                record(&ques.name, QueryType::CNAME, rdata)
+            };
+            let next = match ques.name.as_str() {
+                "a.example.com" => "b.example.com".to_string(),
+                "b.example.com" => "c.example.com".to_string(),
+                "loop1.example.com" => "loop2.example.com".to_string(),
+                "loop2.example.com" => "LOOP1.example.com".to_string(),
+                long => {
+                    let n: u8 = long[1..long.find('.').unwrap()].parse().unwrap_or(0);
+                    format!("n{}.example.com", n + 1)
+                }
+            };
+            if ques.name == "c.example.com" || ques.name == "n9.example.com" {
+                let ip = Ipv4Addr::new(10, 0, 0, 3);
+                resp.answers
+                    .push(record(&ques.name, QueryType::A, RData::A { ip }));
+            } else {
+                resp.answers.push(cname(&next));
+            }
+            resp
+        });
+
+        let config = LookupConfig::default();
+        let resp = lookup("a.example.com", QueryType::A, addr, &config).unwrap();
+        let chain: Vec<_> = resp.answers.iter().map(|r| r.domain.as_str()).collect();
+        assert_eq!(chain, ["a.example.com", "b.example.com", "c.example.com"]);
+        assert_eq!(resp.get_a(), [Ipv4Addr::new(10, 0, 0, 3)]);
+        assert_eq!(resp.header.ancount, 3);
+
+        assert!(lookup("loop1.example.com", QueryType::A, addr, &config).is_err());
+        // n0 to n9 takes 9 of them
+        assert!(lookup("n0.example.com", QueryType::A, addr, &config).is_err());
+        assert!(lookup("n1.example.com", QueryType::A, addr, &config).is_ok());
+
+        let config = LookupConfig {
+            max_cnames: 0,
+            ..LookupConfig::default()
+        };
+        let resp = lookup("a.example.com", QueryType::A, addr, &config).unwrap();
+        assert_eq!(resp.get_cname("a.example.com"), Some("b.example.com"));
+        assert_eq!(resp.answers.len(), 1);
+    }
+
+    #[test]
+    fn big_packets() {
+        let mut packet = DnsPacket::new_empty();
+        packet.header.ancount = 10;
+        packet.header.arcount = 1;
+        for _ in 0..10 {
+            packet.answers.push(DnsRecord {
+                domain: "example.com".parse().unwrap(),
+                r#type: QueryType::TXT,
+                class: 1,
+                ttl: 300,
+                rdata: RData::TXT {
+                    strings: vec![b"x".repeat(100)],
+                },
+            });
+        }
+        packet.resources.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 300,
+            rdata: RData::A {
+                ip: Ipv4Addr::LOCALHOST,
+            },
+        });
+
+        let mut buf = [0u8; PACKET_SIZE];
+        assert_eq!(packet.to_bytes(&mut buf), Err(DnsError::BufferFull));
+
+        let bytes = packet.to_vec().unwrap();
+        assert!(bytes.len() > PACKET_SIZE);
+        let read = DnsPacket::from_bytes(&bytes).unwrap();
+        assert!(!read.header.tc);
+        assert_eq!((read.answers.len(), read.resources.len()), (10, 1));
+
+        // the header takes 12 bytes, the first answer 124 and with its name
+        // compressed each one after 113
+        let bytes = packet.to_vec_truncated(PACKET_SIZE).unwrap();
+        assert!(bytes.len() <= PACKET_SIZE);
+        let read = DnsPacket::from_bytes(&bytes).unwrap();
+        assert!(read.header.tc);
+        assert_eq!((read.header.ancount, read.header.arcount), (4, 0));
+        assert_eq!((read.answers.len(), read.resources.len()), (4, 0));
+    }
+
+    #[test]
+    fn queries() {
+        let packet = DnsPacket::query("example.com", QueryType::A)
+            .unwrap()
+            .id(6666)
+            .recursion_desired(true)
+            .add_question(DnsQuestion::new(
+                "example.com".parse().unwrap(),
+                QueryType::AAAA,
+            ));
+        assert_eq!(packet.header.id, 6666);
+        assert!(packet.header.rd && !packet.header.qr);
+        assert_eq!(packet.header.qdcount, 2);
+
+        let read = DnsPacket::from_bytes(&packet.to_vec().unwrap()).unwrap();
+        assert_eq!(read.header.id, 6666);
+        assert!(read.header.rd);
+        assert_eq!(read.questions, packet.questions);
+        assert_eq!(read.questions[1].r#type, QueryType::AAAA);
+        assert!(read.resources.is_empty());
+
+        assert_eq!(
+            DnsPacket::query("www..example.com", QueryType::A).unwrap_err(),
+            DnsError::BadLabelLength(0)
+        );
+    }
+
+    #[test]
+    fn counts_follow_the_records() {
+        let mut packet = DnsPacket::query("example.com", QueryType::A).unwrap();
+        packet.questions.push(packet.questions[0].clone());
+        packet.questions[1].r#type = QueryType::AAAA;
+        packet.answers.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 300,
+            rdata: RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, 1),
+            },
+        });
+        // counts that have nothing to do with the records
+        packet.header.qdcount = 1;
+        packet.header.ancount = 0;
+        packet.header.nscount = 3;
+        packet.set_edns(1232, false);
+        packet.header.arcount = 0;
+
+        let read = DnsPacket::from_bytes(&packet.to_vec().unwrap()).unwrap();
+        let counts = |p: &DnsPacket| {
+            let h = &p.header;
+            (h.qdcount, h.ancount, h.nscount, h.arcount)
+        };
+        assert_eq!(counts(&read), (2, 1, 0, 1));
+        assert_eq!(read.questions, packet.questions);
+        assert_eq!(read.answers, packet.answers);
+        assert_eq!(read.resources, packet.resources);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn packets_as_json() {
+        let mut packet = DnsPacket::query("example.com", QueryType::MX).unwrap();
+        packet.answers.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::MX,
+            class: 1,
+            ttl: 300,
+            rdata: RData::MX {
+                priority: 10,
+                host: "mx.example.com".parse().unwrap(),
+            },
+        });
+        packet.set_edns(1232, true);
+
+        let json = serde_json::to_string(&packet).unwrap();
+        let read: DnsPacket = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.questions, packet.questions);
+        assert_eq!(read.answers, packet.answers);
+        assert_eq!(read.resources, packet.resources);
+        assert_eq!(read.to_vec().unwrap(), packet.to_vec().unwrap());
+
+        // names are checked on the way in
+        let bad = json.replace("\"example.com\"", "\"www..example.com\"");
+        assert!(serde_json::from_str::<DnsPacket>(&bad).is_err());
+    }
+
+    #[test]
+    fn records_as_text() {
+        let record = |r#type, rdata| DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type,
+            class: 1,
+            ttl: 300,
+            rdata,
+        };
+        let a = record(
+            QueryType::A,
+            RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, 1),
+            },
+        );
+        assert_eq!(a.to_string(), "example.com. 300 IN A 192.0.2.1");
+        assert_eq!(a.data(), "192.0.2.1");
+
+        for (r#type, rdata, text) in [
+            (
+                QueryType::MX,
+                RData::MX {
+                    priority: 10,
+                    host: "mx.example.com".parse().unwrap(),
+                },
+                "10 mx.example.com.",
+            ),
+            (
+                QueryType::TXT,
+                RData::TXT {
+                    strings: vec![b"say \"hi\"".to_vec(), vec![7, 0xFF]],
+                },
+                r#""say \"hi\"" "\007\255""#,
+            ),
+            (
+                QueryType::NS,
+                RData::NS {
+                    host: DnsName::root(),
+                },
+                ".",
+            ),
+            (
+                QueryType::RRSIG,
+                RData::RRSIG {
+                    type_covered: QueryType::A,
+                    algorithm: 13,
+                    labels: 2,
+                    original_ttl: 3600,
+                    expiration: 1_700_000_000,
+                    inception: 951_782_400,
+                    key_tag: 12345,
+                    signer: "example.com".parse().unwrap(),
+                    signature: Base64(b"foobar".to_vec()),
+                },
+                "A 13 2 3600 20231114221320 20000229000000 12345 example.com. Zm9vYmFy",
+            ),
+            (
+                QueryType::NSEC,
+                RData::NSEC {
+                    next: "www.example.com".parse().unwrap(),
+                    types: vec![QueryType::A, QueryType::Unknown(999)],
+                },
+                "www.example.com. A TYPE999",
+            ),
+            (
+                QueryType::Unknown(731),
+                RData::Unknown {
+                    bytes: vec![0x0a, 0, 0, 1],
+                },
+                "\\# 4 0a000001",
+            ),
+        ] {
+            assert_eq!(record(r#type, rdata).data(), text);
+        }
+    }
+
+    #[test]
+    fn packets_as_text() {
+        let mut packet = DnsPacket::query("example.com", QueryType::A)
+            .unwrap()
+            .id(1234)
+            .recursion_desired(true);
+        packet.header.qr = true;
+        packet.header.rcode = RCode::Nxdomain;
+        packet.authorities.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::NS,
+            class: 1,
+            ttl: 300,
+            rdata: RData::NS {
+                host: "ns.example.com".parse().unwrap(),
+            },
+        });
+        packet.set_edns(1232, true);
+        assert_eq!(
+            packet.to_string(),
+            ";; ->>HEADER<<- opcode: QUERY, status: NXDOMAIN, id: 1234
+;; flags: qr rd; QUERY: 1, ANSWER: 0, AUTHORITY: 1, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags: do; udp: 1232
+
+;; QUESTION SECTION:
+;example.com. IN A
+
+;; AUTHORITY SECTION:
+example.com. 300 IN NS ns.example.com.
+"
+        );
+    }
+
+    #[test]
+    fn names_are_compressed() {
+        let mut packet = DnsPacket::query("example.com", QueryType::MX).unwrap();
+        packet.header.ancount = 2;
+        for host in ["mx1.example.com", "mx2.example.com"] {
+            packet.answers.push(DnsRecord {
+                domain: "example.com".parse().unwrap(),
+                r#type: QueryType::MX,
+                class: 1,
+                ttl: 300,
+                rdata: RData::MX {
+                    priority: 10,
+                    host: host.parse().unwrap(),
+                },
+            });
+        }
+        let mut buf = [0u8; PACKET_SIZE];
+        packet.to_bytes(&mut buf).unwrap();
+
+        // the question's name is written out in full after the header
+        assert_eq!(&buf[12..25], b"\x07example\x03com\x00");
+        // the first answer's name points back to it, and so does the rest of
+        // its host after the label of its own
+        assert_eq!(&buf[29..31], [0xC0, 12]);
+        assert_eq!(&buf[39..49], [0, 8, 0, 10, 3, b'm', b'x', b'1', 0xC0, 12]);
+        // the second's host ends where the first's does
+        assert_eq!(&buf[59..69], [0, 8, 0, 10, 3, b'm', b'x', b'2', 0xC0, 12]);
+        assert!(buf[69..].iter().all(|&b| b == 0));
+
+        let read = DnsPacket::from_bytes(&buf).unwrap();
+        assert_eq!(read.questions[0].name, "example.com");
+        for (read, written) in read.answers.iter().zip(&packet.answers) {
+            assert_eq!(read.domain, written.domain);
+            assert_eq!(read.rdata, written.rdata);
+        }
+    }
+
+    #[test]
+    fn unknown_types_pass_through() {
+        // 99 (SPF), asked about and answered
+        let mut bytes = vec![0, 1, 1, 0, 0, 1, 0, 1, 0, 0, 0, 0];
+        bytes.extend([1, b'a', 0, 0, 99, 0, 1]);
+        bytes.extend([
+            0xC0, 12, 0, 99, 0, 1, 0, 0, 0, 60, 0, 4, 3, b'a', b'b', b'c',
+        ]);
+        let packet = DnsPacket::from_bytes(&bytes).unwrap();
+        assert_eq!(packet.questions[0].r#type, QueryType::Unknown(99));
+        assert_eq!(packet.answers[0].r#type, QueryType::Unknown(99));
+        assert_eq!(
+            packet.answers[0].rdata,
+            RData::Unknown {
+                bytes: vec![3, b'a', b'b', b'c']
+            }
+        );
+
+        let unknown = || RData::Unknown {
+            bytes: vec![0, 0xFF, 7],
+        };
+        assert_eq!(roundtrip(QueryType::Unknown(65280), unknown()), unknown());
+    }
+
+    #[test]
+    fn naptr_roundtrip() {
+        let naptr = || RData::NAPTR {
+            order: 100,
+            preference: 10,
+            flags: b"u".to_vec(),
+            services: b"E2U+sip".to_vec(),
+            regexp: b"!^.*$!sip:info@example.com!".to_vec(),
+            replacement: DnsName::root(),
+        };
+        assert_eq!(roundtrip(QueryType::NAPTR, naptr()), naptr());
+
+        let naptr = || RData::NAPTR {
+            order: 100,
+            preference: 50,
+            flags: b"s".to_vec(),
+            services: b"SIP+D2U".to_vec(),
+            regexp: vec![],
+            replacement: "_sip._udp.example.com".parse().unwrap(),
+        };
+        assert_eq!(roundtrip(QueryType::NAPTR, naptr()), naptr());
+    }
+
+    #[test]
+    fn svcb_roundtrip() {
+        let https = || RData::SVCB {
+            priority: 1,
+            target: DnsName::root(),
+            params: vec![
+                SvcParam::Alpn(vec![b"h2".to_vec(), b"h3".to_vec()]),
+                SvcParam::Port(8443),
+                SvcParam::Ipv4Hint(vec![Ipv4Addr::new(192, 0, 2, 1)]),
+                SvcParam::Ipv6Hint(vec![Ipv6Addr::LOCALHOST, Ipv6Addr::UNSPECIFIED]),
+                SvcParam::Other {
+                    key: 5,
+                    value: vec![1, 2, 3],
+                },
+            ],
+        };
+        assert_eq!(roundtrip(QueryType::HTTPS, https()), https());
+
+        let alias = || RData::SVCB {
+            priority: 0,
+            target: "svc.example.net".parse().unwrap(),
+            params: vec![],
+        };
+        assert_eq!(roundtrip(QueryType::SVCB, alias()), alias());
+
+        // a port param 3 bytes long
+        let mut bytes = vec![0, 1, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        bytes.extend([1, b'a', 0, 0, 65, 0, 1, 0, 0, 0, 60, 0, 10]);
+        bytes.extend([0, 1, 0, 0, 3, 0, 3, 0x20, 0xFB, 0]);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::BadRdataLength(3)
+        );
+    }
+
+    #[test]
+    fn rdata_lengths() {
+        // an A record for "a", with `len` given as its length and `data`
+        let packet = |len: u8, data: &[u8]| {
+            let mut bytes = vec![0, 1, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+            bytes.extend([1, b'a', 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, len]);
+            bytes.extend(data);
+            DnsPacket::from_bytes(&bytes)
+        };
+        assert!(packet(4, &[192, 0, 2, 1]).is_ok());
+        // too short for an address, which would take the next record's bytes
+        assert_eq!(
+            packet(2, &[192, 0, 2, 1]).unwrap_err(),
+            DnsError::BadRdataLength(2)
+        );
+        // longer than the address
+        assert_eq!(
+            packet(6, &[192, 0, 2, 1, 0, 0]).unwrap_err(),
+            DnsError::BadRdataLength(6)
+        );
+        // longer than the packet
+        assert_eq!(packet(8, &[192, 0, 2, 1]).unwrap_err(), DnsError::Truncated);
+
+        // a CNAME whose name runs on past its length
+        let mut bytes = vec![0, 1, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        bytes.extend([1, b'a', 0, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2]);
+        bytes.extend([1, b'b', 0]);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::BadRdataLength(2)
+        );
+    }
+
+    #[test]
+    fn dnssec_roundtrip() {
+        let rrsig = || RData::RRSIG {
+            type_covered: QueryType::A,
+            algorithm: 13,
+            labels: 2,
+            original_ttl: 3600,
+            expiration: 1_700_000_000,
+            inception: 1_690_000_000,
+            key_tag: 12345,
+            signer: "example.com".parse().unwrap(),
+            signature: Base64(vec![0xDE, 0xAD, 0xBE, 0xEF]),
+        };
+        assert_eq!(roundtrip(QueryType::RRSIG, rrsig()), rrsig());
+        let dnskey = || RData::DNSKEY {
+            flags: 257,
+            protocol: 3,
+            algorithm: 13,
+            public_key: Base64(vec![1, 2, 3, 4, 5]),
+        };
+        assert_eq!(roundtrip(QueryType::DNSKEY, dnskey()), dnskey());
+        let ds = || RData::DS {
+            key_tag: 12345,
+            algorithm: 13,
+            digest_type: 2,
+            digest: Hex(vec![0xAB; 32]),
+        };
+        assert_eq!(roundtrip(QueryType::DS, ds()), ds());
+        let nsec = || RData::NSEC {
+            next: "www.example.com".parse().unwrap(),
+            types: vec![
+                QueryType::A,
+                QueryType::NS,
+                QueryType::SOA,
+                QueryType::RRSIG,
+                QueryType::NSEC,
+                QueryType::DNSKEY,
+                QueryType::Unknown(1234),
+            ],
+        };
+        assert_eq!(roundtrip(QueryType::NSEC, nsec()), nsec());
+        let nsec3 = || RData::NSEC3 {
+            hash_algorithm: 1,
+            flags: 0,
+            iterations: 0,
+            salt: Hex(vec![]),
+            next_hashed: Base32Hex(vec![0x55; 20]),
+            types: vec![QueryType::A, QueryType::RRSIG],
+        };
+        assert_eq!(roundtrip(QueryType::NSEC3, nsec3()), nsec3());
+
+        // the bitmap from the example in RFC 4034
+        let mut writer = PacketBufWriter::new(PACKET_SIZE);
+        writer
+            .write_type_bitmap(&[
+                QueryType::A,
+                QueryType::MX,
+                QueryType::RRSIG,
+                QueryType::NSEC,
+                QueryType::Unknown(1234),
+            ])
+            .unwrap();
+        let mut expected = vec![0, 6, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 4, 27];
+        expected.extend([0; 26]);
+        expected.push(0x20);
+        assert_eq!(writer.buf[..writer.pos()], expected);
+
+        let debug = format!("{:?}", nsec3());
+        assert!(debug.contains("salt: -"), "{debug}");
+        assert!(debug.contains("next_hashed: ALAL"), "{debug}");
+        assert!(format!("{:?}", dnskey()).contains("public_key: AQIDBAU="));
+    }
+
+    #[test]
+    fn txt_errors() {
+        let mut buf = [0u8; PACKET_SIZE];
+        let mut packet = DnsPacket::new_empty();
+        packet.answers.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::TXT,
+            class: 1,
+            ttl: 300,
+            rdata: RData::TXT {
+                strings: vec![b"x".repeat(256)],
+            },
+        });
+        assert_eq!(
+            packet.to_bytes(&mut buf).unwrap_err(),
+            DnsError::StringTooLong(256)
+        );
+
+        // RDLENGTH 3 but a string of 3 bytes after its length byte
+        let mut bytes = vec![0, 1, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        bytes.extend([
+            1, b'a', 0, 0, 16, 0, 1, 0, 0, 0, 60, 0, 3, 3, b'a', b'b', b'c',
+        ]);
+        assert_eq!(
+            DnsPacket::from_bytes(&bytes).unwrap_err(),
+            DnsError::BadRdataLength(3)
+        );
+    }
+
+    #[test]
+    fn overlong_messages() {
+        let mut buf = query("example.com", QueryType::A)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        buf.resize(MAX_MESSAGE_SIZE + 1, 0);
+        assert_eq!(
+            DnsPacket::from_bytes(&buf).unwrap_err(),
+            DnsError::MessageTooLong(MAX_MESSAGE_SIZE + 1)
+        );
+    }
+
+    #[test]
+    fn unwritable_packets() {
+        // names that can't be written can't be made at all
+        assert_eq!(
+            DnsName::new(&format!("{}.com", "a".repeat(64))).unwrap_err(),
+            DnsError::BadLabelLength(64)
+        );
+
+        let mut buf = [0u8; PACKET_SIZE];
+        // three names that can't be compressed into each other
+        let mut packet = DnsPacket::new_empty();
+        for c in ["a", "b", "c"] {
+            let label = c.repeat(63);
+            packet.questions.push(DnsQuestion {
+                name: [&label[..]; 3].join(".").parse().unwrap(),
+                r#type: QueryType::A,
+                class: 1,
+            });
+        }
+        assert_eq!(packet.to_bytes(&mut buf).unwrap_err(), DnsError::BufferFull);
+        // untouched on failure
+        assert!(buf.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn unwritable_names() {
+        let long = vec!["a".repeat(63); 4].join(".");
+        // never make it into a record to be written
+        for (host, err) in [
+            ("www..example.com", DnsError::BadLabelLength(0)),
+            (".example.com", DnsError::BadLabelLength(0)),
+            (&long[..], DnsError::NameTooLong(257)),
+        ] {
+            assert_eq!(host.parse::<DnsName>().unwrap_err(), err, "{host}");
+        }
+
+        // 255 bytes exactly, and a final dot, are fine
+        let mut packet = DnsPacket::new_empty();
+        packet.header.ancount = 1;
+        packet.answers.push(DnsRecord {
+            domain: "example.com".parse().unwrap(),
+            r#type: QueryType::CNAME,
+            class: 1,
+            ttl: 300,
+            rdata: RData::CNAME {
+                host: format!("{}.", &long[2..]).parse().unwrap(),
+            },
+        });
+        let packet = DnsPacket::from_bytes(&packet.to_vec().unwrap()).unwrap();
+        assert_eq!(
+            packet.answers[0].rdata,
+            RData::CNAME {
+                host: long[2..].parse().unwrap()
+            }
+        );
+    }
+
     #[test]
     #[ignore]
     fn stub_resolver() {
-        let response = lookup("google.com", QueryType::A, ("8.8.8.8", 53)).unwrap();
+        let response = lookup(
+            "google.com",
+            QueryType::A,
+            ("8.8.8.8", 53),
+            &LookupConfig::default(),
+        )
+        .unwrap();
 
-        assert_eq!(response.header.id, 6666);
         assert!(response.header.qr);
         assert_eq!(response.header.opcode, 0);
         assert!(response.header.rd);
@@ -780,7 +3856,13 @@ mod tests {
     #[test]
     #[ignore]
     fn lookup_yahoo_a() {
-        let response = lookup("www.yahoo.com", QueryType::A, ("8.8.8.8", 53)).unwrap();
+        let response = lookup(
+            "www.yahoo.com",
+            QueryType::A,
+            ("8.8.8.8", 53),
+            &LookupConfig::default(),
+        )
+        .unwrap();
 
         assert_eq!(response.header.rcode, RCode::Noerror);
         assert!(response.header.ancount >= 2);
@@ -801,7 +3883,13 @@ mod tests {
     #[test]
     #[ignore]
     fn lookup_yahoo_mx() {
-        let response = lookup("yahoo.com", QueryType::MX, ("8.8.8.8", 53)).unwrap();
+        let response = lookup(
+            "yahoo.com",
+            QueryType::MX,
+            ("8.8.8.8", 53),
+            &LookupConfig::default(),
+        )
+        .unwrap();
 
         assert_eq!(response.header.rcode, RCode::Noerror);
         assert!(response.header.ancount >= 1);
@@ -821,7 +3909,13 @@ mod tests {
     #[test]
     #[ignore]
     fn lookup_google_aaaa() {
-        let response = lookup("google.com", QueryType::AAAA, ("8.8.8.8", 53)).unwrap();
+        let response = lookup(
+            "google.com",
+            QueryType::AAAA,
+            ("8.8.8.8", 53),
+            &LookupConfig::default(),
+        )
+        .unwrap();
 
         assert_eq!(response.header.rcode, RCode::Noerror);
         assert!(response.header.ancount >= 1);
@@ -836,4 +3930,37 @@ mod tests {
 
         println!("{:#?}", response);
     }
+
+    #[test]
+    #[ignore]
+    fn lookup_reverse_google() {
+        let hosts = lookup_reverse(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))).unwrap();
+        assert_eq!(hosts, ["dns.google"]);
+    }
+
+    #[test]
+    #[ignore]
+    fn lookup_nxdomain_soa() {
+        let response = lookup(
+            "nonexistent.google.com",
+            QueryType::A,
+            ("8.8.8.8", 53),
+            &LookupConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(response.header.rcode, RCode::Nxdomain);
+        assert!(response.answers.is_empty());
+
+        let rec = &response.authorities[0];
+        assert_eq!(rec.domain, "google.com");
+        assert_eq!(rec.r#type, QueryType::SOA);
+        let RData::SOA { mname, minimum, .. } = &rec.rdata else {
+            panic!("expected SOA record");
+        };
+        assert!(mname.ends_with("google.com"));
+        assert!(*minimum > 0);
+
+        println!("{:#?}", response);
+    }
 }
@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::{DnsError, idna};
+
+// the most a name can take up on the wire, length bytes and all
+pub(crate) const MAX_NAME_LEN: usize = 255;
+
+// A domain name that can go on the wire: labels of 1 to 63 bytes, in their
+// ACE form for internationalized ones, 255 bytes in all. Names are kept as
+// given, without a final dot, but are equal whatever the case of their
+// letters, and ordered as DNSSEC orders them, from the root down (RFC 4034).
+#[derive(Clone, Default)]
+pub struct DnsName(String);
+
+impl DnsName {
+    pub fn new(name: &str) -> Result<Self, DnsError> {
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let mut len = 1;
+        for label in labels(name) {
+            let ace = idna::to_ascii(label).ok_or(DnsError::BadLabelLength(label.len()))?;
+            if ace.is_empty() || ace.len() > 63 {
+                return Err(DnsError::BadLabelLength(ace.len()));
+            }
+            len += 1 + ace.len();
+        }
+        if len > MAX_NAME_LEN {
+            return Err(DnsError::NameTooLong(len));
+        }
+        Ok(Self(name.to_string()))
+    }
+
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn make_ascii_lowercase(&mut self) {
+        self.0.make_ascii_lowercase();
+    }
+
+    // from the leftmost, as in "www", "example", "com"
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &str> {
+        labels(&self.0)
+    }
+
+    // The name with its leftmost label taken off, or None for the root.
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        let parent = self.0.split_once('.').map_or("", |(_, parent)| parent);
+        Some(Self(parent.to_string()))
+    }
+
+    // Whether the name is `other` or below it.
+    pub fn is_within(&self, other: &DnsName) -> bool {
+        let mut ours = self.labels().rev();
+        other
+            .labels()
+            .rev()
+            .all(|theirs| ours.next().is_some_and(|l| l.eq_ignore_ascii_case(theirs)))
+    }
+
+    // The name as DNSSEC signs and hashes it: uncompressed, its letters in
+    // lowercase (RFC 4034).
+    pub(crate) fn to_canonical_wire(&self) -> Vec<u8> {
+        let mut wire = vec![];
+        for label in self.labels() {
+            wire.push(label.len() as u8);
+            wire.extend(label.to_ascii_lowercase().bytes());
+        }
+        wire.push(0);
+        wire
+    }
+
+    // Whether `other` is right below the name, the name its parent.
+    pub fn is_parent_of(&self, other: &DnsName) -> bool {
+        other.parent().is_some_and(|parent| parent == *self)
+    }
+}
+
+// the root is the empty name, without any labels
+fn labels(name: &str) -> impl DoubleEndedIterator<Item = &str> {
+    name.split('.').filter(|_| !name.is_empty())
+}
+
+impl Deref for DnsName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for DnsName {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for DnsName {}
+
+impl PartialEq<str> for DnsName {
+    fn eq(&self, other: &str) -> bool {
+        self.0
+            .eq_ignore_ascii_case(other.strip_suffix('.').unwrap_or(other))
+    }
+}
+
+impl PartialEq<&str> for DnsName {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl Hash for DnsName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for b in self.0.bytes() {
+            state.write_u8(b.to_ascii_lowercase());
+        }
+    }
+}
+
+impl Ord for DnsName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |name: &Self| -> Vec<Vec<u8>> {
+            name.labels()
+                .rev()
+                .map(|l| l.to_ascii_lowercase().into_bytes())
+                .collect()
+        };
+        key(self).cmp(&key(other))
+    }
+}
+
+impl PartialOrd for DnsName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for DnsName {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<&str> for DnsName {
+    type Error = DnsError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::new(s)
+    }
+}
+
+impl fmt::Display for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// as the string it is
+impl fmt::Debug for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+// as the string, and checked on the way back in
+#[cfg(feature = "serde")]
+impl serde::Serialize for DnsName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DnsName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::new(&name).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(s: &str) -> DnsName {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(name("www.Example.COM."), name("www.example.com"));
+        assert_eq!(name("www.Example.COM.").as_str(), "www.Example.COM");
+        assert_eq!(name("www.example.com"), "WWW.example.com.");
+        assert!(name(".").is_root());
+        assert_eq!(name("www.example.com").labels().count(), 3);
+
+        assert_eq!(name("www.example.com").parent(), Some(name("example.com")));
+        assert_eq!(name("com").parent(), Some(DnsName::root()));
+        assert_eq!(DnsName::root().parent(), None);
+        assert!(name("example.com").is_parent_of(&name("WWW.example.com")));
+        assert!(!name("example.com").is_parent_of(&name("a.b.example.com")));
+        assert!(name("a.b.example.com").is_within(&name("EXAMPLE.com")));
+        assert!(name("example.com").is_within(&name("example.com")));
+        assert!(name("example.com").is_within(&DnsName::root()));
+        assert!(!name("badexample.com").is_within(&name("example.com")));
+        assert!(!name("com").is_within(&name("example.com")));
+
+        // the example from RFC 4034
+        let ordered = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\u{1}.z.example",
+            "*.z.example",
+            "\u{80}.z.example",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(name(pair[0]) < name(pair[1]), "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn bad_names() {
+        assert_eq!(
+            "www..example.com".parse::<DnsName>().unwrap_err(),
+            DnsError::BadLabelLength(0)
+        );
+        assert!(".example.com".parse::<DnsName>().is_err());
+        let long = "a".repeat(64);
+        assert_eq!(
+            long.parse::<DnsName>().unwrap_err(),
+            DnsError::BadLabelLength(64)
+        );
+        // 4 labels of 63 and the root take 257 bytes
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(
+            long.parse::<DnsName>().unwrap_err(),
+            DnsError::NameTooLong(257)
+        );
+        assert!(long[2..].parse::<DnsName>().is_ok());
+    }
+}
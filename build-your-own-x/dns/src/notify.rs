@@ -0,0 +1,115 @@
+use std::io;
+use std::net::SocketAddr;
+
+use crate::{QueryType, RCode, READ_TIMEOUT, Transport, Zone, exchange, is_timeout, query};
+
+pub(crate) const OPCODE_NOTIFY: u8 = 4;
+// how many times to tell a secondary before giving up on it
+const ATTEMPTS: usize = 3;
+
+// Tells `secondary` that `zone` has changed, for it to transfer the zone
+// again without waiting for its refresh interval (RFC 1996).
+pub fn notify(zone: &Zone, secondary: SocketAddr) -> io::Result<()> {
+    let mut req = query(zone.origin(), QueryType::SOA)?;
+    req.header.opcode = OPCODE_NOTIFY;
+    req.header.rd = false;
+    req.header.aa = true;
+    // what it's at now, for a secondary already there not to bother
+    if let Some(soa) = zone.soa() {
+        req.header.ancount = 1;
+        req.answers.push(soa.clone());
+    }
+    let req_buf = req.to_vec()?;
+
+    let mut attempt = 1;
+    let resp = loop {
+        match exchange(&req_buf, secondary, Transport::Udp, READ_TIMEOUT) {
+            Ok(resp) => break resp,
+            Err(e) if attempt < ATTEMPTS && is_timeout(&e) => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    match resp.header.rcode {
+        RCode::Noerror => Ok(()),
+        rcode => Err(io::Error::other(format!("secondary answered {rcode:?}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Authority, Change, MockResolver, Resolver, Update, handle_datagram};
+    use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@    SOA  ns1 hostmaster 1 3600 900 604800 300
+@    NS   ns1
+ns1  A    192.0.2.1
+";
+
+    // A secondary for example.com, of the primary at `primary`, answering
+    // on a socket of its own.
+    fn secondary(primary: IpAddr) -> (Arc<Authority<MockResolver>>, SocketAddr) {
+        let authority = Authority::new(vec![], MockResolver::default())
+            .unwrap()
+            .with_primaries(vec![("example.com".parse().unwrap(), (primary, 53).into())]);
+        let authority = Arc::new(authority);
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let serving = authority.clone();
+        thread::spawn(move || {
+            loop {
+                handle_datagram(&socket, &*serving).unwrap();
+            }
+        });
+        (authority, addr)
+    }
+
+    #[test]
+    fn secondaries_hear_of_changes() {
+        let (secondary, addr) = secondary(Ipv4Addr::LOCALHOST.into());
+        let zone = Zone::parse(ZONE, "").unwrap();
+        let primary = Authority::new(vec![zone], MockResolver::default())
+            .unwrap()
+            .allow_updates(vec!["127.0.0.1".parse().unwrap()])
+            .notify_secondaries(vec![addr]);
+        assert!(!secondary.wait_for_change(&"example.com".parse().unwrap(), Duration::ZERO));
+
+        let update = Update::new("example.com".parse().unwrap())
+            .change(Change::DeleteName("ns1.example.com".parse().unwrap()));
+        assert_eq!(
+            primary.update(&update, Ipv4Addr::LOCALHOST.into()),
+            RCode::Noerror
+        );
+        let start = Instant::now();
+        assert!(
+            secondary.wait_for_change(&"EXAMPLE.com".parse().unwrap(), Duration::from_secs(10))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // only the once
+        assert!(!secondary.wait_for_change(&"example.com".parse().unwrap(), Duration::ZERO));
+    }
+
+    #[test]
+    fn only_from_the_primary() {
+        let (secondary, addr) = secondary(Ipv4Addr::new(192, 0, 2, 1).into());
+        let zone = Zone::parse(ZONE, "").unwrap();
+        assert!(notify(&zone, addr).is_err());
+        assert!(!secondary.wait_for_change(&"example.com".parse().unwrap(), Duration::ZERO));
+
+        // nor for zones it isn't a secondary for
+        assert_eq!(
+            secondary.notify(
+                &"example.org".parse().unwrap(),
+                Ipv4Addr::new(192, 0, 2, 1).into()
+            ),
+            RCode::Notauth
+        );
+    }
+}
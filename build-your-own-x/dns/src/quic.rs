@@ -0,0 +1,274 @@
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, Connection, Endpoint};
+use rustls::RootCertStore;
+use tokio::runtime::{Builder, Runtime};
+use tokio::time;
+
+use crate::tls::{client_config, server_name, web_roots};
+use crate::{DnsPacket, MAX_MESSAGE_SIZE, QueryType, READ_TIMEOUT, is_answer_to, query};
+
+// A DNS-over-QUIC server (RFC 9250). Each query goes on a stream of its own,
+// over a connection that is kept for as long as the server does.
+#[derive(Debug)]
+pub struct QuicUpstream {
+    addr: SocketAddr,
+    // what its certificate has to be for
+    name: String,
+    // to drive the connection while a query is waiting on it
+    runtime: Runtime,
+    endpoint: Endpoint,
+    conn: Mutex<Option<Connection>>,
+}
+
+impl QuicUpstream {
+    pub fn new(addr: SocketAddr, name: &str) -> io::Result<Self> {
+        Self::with_roots(addr, name, web_roots())
+    }
+
+    fn with_roots(addr: SocketAddr, name: &str, roots: RootCertStore) -> io::Result<Self> {
+        // checked up front, where quinn would only complain on connecting
+        server_name(name)?;
+
+        let mut crypto = client_config(roots)?;
+        crypto.alpn_protocols = vec![b"doq".to_vec()];
+        let crypto = QuicClientConfig::try_from(crypto).map_err(io::Error::other)?;
+
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let mut endpoint = runtime.block_on(async { Endpoint::client(local) })?;
+        endpoint.set_default_client_config(ClientConfig::new(Arc::new(crypto)));
+
+        Ok(Self {
+            addr,
+            name: name.to_string(),
+            runtime,
+            endpoint,
+            conn: Mutex::new(None),
+        })
+    }
+
+    pub fn lookup(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        let mut query = query(name, qtype)?;
+        // the stream tells the answers apart instead
+        query.header.id = 0;
+        let req_buf = query.to_vec()?;
+
+        self.runtime.block_on(async {
+            // locked only to share it, as lookups at the same time each take
+            // a stream of their own on it
+            let open = self.conn.lock().unwrap().clone();
+            if let Some(open) = open.filter(|conn| conn.close_reason().is_none()) {
+                // the server may have dropped it without our hearing of it
+                // yet, in which case a new one is worth a try
+                if let Ok(resp) = ask(&open, &query, &req_buf).await {
+                    return Ok(resp);
+                }
+            }
+
+            let new = time::timeout(READ_TIMEOUT, self.connect())
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+            *self.conn.lock().unwrap() = Some(new.clone());
+            ask(&new, &query, &req_buf).await
+        })
+    }
+
+    async fn connect(&self) -> io::Result<Connection> {
+        let connecting = self
+            .endpoint
+            .connect(self.addr, &self.name)
+            .map_err(io::Error::other)?;
+        Ok(connecting.await?)
+    }
+}
+
+// Asks `conn` the query in `req_buf`, failing for an answer to anything but
+// `query`.
+async fn ask(conn: &Connection, query: &DnsPacket, req_buf: &[u8]) -> io::Result<DnsPacket> {
+    let resp = time::timeout(READ_TIMEOUT, exchange(conn, req_buf))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+    if !is_answer_to(query, &resp) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "answer to some other query",
+        ));
+    }
+    Ok(resp)
+}
+
+// Sends the query on a stream of its own, framed as over TCP, and reads the
+// answer back from it.
+async fn exchange(conn: &Connection, req_buf: &[u8]) -> io::Result<DnsPacket> {
+    let (mut send, mut recv) = conn.open_bi().await?;
+
+    let mut framed = (req_buf.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(req_buf);
+    send.write_all(&framed).await?;
+    // the query is all the server gets on this stream
+    send.finish()?;
+
+    let resp = recv
+        .read_to_end(2 + MAX_MESSAGE_SIZE)
+        .await
+        .map_err(io::Error::other)?;
+    match resp.split_first_chunk() {
+        Some((len, msg)) if u16::from_be_bytes(*len) as usize == msg.len() => {
+            Ok(DnsPacket::from_bytes(msg)?)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "answer doesn't match its length",
+        )),
+    }
+}
+
+impl PartialEq for QuicUpstream {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr && self.name == other.name
+    }
+}
+
+impl fmt::Display for QuicUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "quic://{}#{}", self.addr, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{DnsRecord, RData};
+    use quinn::ServerConfig;
+    use quinn::crypto::rustls::QuicServerConfig;
+    use rustls::crypto::ring;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn server_config(cert: CertificateDer<'static>, key: PrivateKeyDer<'static>) -> ServerConfig {
+        let mut crypto =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(vec![cert], key)
+                .unwrap();
+        crypto.alpn_protocols = vec![b"doq".to_vec()];
+        let crypto = QuicServerConfig::try_from(crypto).unwrap();
+        ServerConfig::with_crypto(Arc::new(crypto))
+    }
+
+    // Answers queries over QUIC on a thread of its own, sending on `accepted`
+    // for each connection it takes. Streams past the first `answers` on a
+    // connection are reset instead.
+    fn serve(config: ServerConfig, answers: usize, accepted: mpsc::Sender<()>) -> SocketAddr {
+        let (addr_tx, addr_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async move {
+                let endpoint = Endpoint::server(config, (Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+                addr_tx.send(endpoint.local_addr().unwrap()).unwrap();
+                while let Some(incoming) = endpoint.accept().await {
+                    let conn = incoming.await.unwrap();
+                    accepted.send(()).unwrap();
+                    tokio::spawn(async move {
+                        let mut answered = 0;
+                        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+                            if answered == answers {
+                                send.reset(0u32.into()).unwrap();
+                                continue;
+                            }
+                            answered += 1;
+                            let req = recv.read_to_end(MAX_MESSAGE_SIZE).await.unwrap();
+                            let req = DnsPacket::from_bytes(&req[2..]).unwrap();
+                            assert_eq!(req.header.id, 0);
+
+                            let mut resp = DnsPacket::new_empty();
+                            resp.header.qr = true;
+                            resp.questions = req.questions.clone();
+                            resp.header.ancount = 1;
+                            resp.answers.push(DnsRecord {
+                                domain: req.questions[0].name.clone(),
+                                r#type: QueryType::A,
+                                class: 1,
+                                ttl: 60,
+                                rdata: RData::A {
+                                    ip: Ipv4Addr::LOCALHOST,
+                                },
+                            });
+                            let resp = resp.to_vec().unwrap();
+                            let mut framed = (resp.len() as u16).to_be_bytes().to_vec();
+                            framed.extend(resp);
+                            send.write_all(&framed).await.unwrap();
+                            send.finish().unwrap();
+                        }
+                    });
+                }
+            });
+        });
+        addr_rx.recv().unwrap()
+    }
+
+    #[test]
+    fn queries_share_a_connection() {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+        let key = PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der());
+        let (accepted_tx, accepted) = mpsc::channel();
+        let addr = serve(
+            server_config(cert.cert.der().clone(), key.into()),
+            usize::MAX,
+            accepted_tx,
+        );
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let upstream = QuicUpstream::with_roots(addr, "localhost", roots).unwrap();
+        for name in ["a.example.com", "b.example.com"] {
+            let resp = upstream.lookup(name, QueryType::A).unwrap();
+            assert_eq!(resp.answers[0].domain, name);
+        }
+        assert_eq!(accepted.try_iter().count(), 1);
+        // and at the same time
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| upstream.lookup("example.com", QueryType::A).unwrap());
+            }
+        });
+        assert_eq!(accepted.try_iter().count(), 0);
+
+        // a certificate for another name
+        let upstream = QuicUpstream::new(addr, "example.com").unwrap();
+        assert!(upstream.lookup("example.com", QueryType::A).is_err());
+    }
+    #[test]
+    fn retries_on_a_new_connection() {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+        let key = PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der());
+        let (accepted_tx, accepted) = mpsc::channel();
+        let addr = serve(
+            server_config(cert.cert.der().clone(), key.into()),
+            1,
+            accepted_tx,
+        );
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let upstream = QuicUpstream::with_roots(addr, "localhost", roots).unwrap();
+        for name in ["a.example.com", "b.example.com"] {
+            let resp = upstream.lookup(name, QueryType::A).unwrap();
+            assert_eq!(resp.answers[0].domain, name);
+        }
+        assert_eq!(accepted.try_iter().count(), 2);
+    }
+}
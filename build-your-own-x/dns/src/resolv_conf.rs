@@ -0,0 +1,230 @@
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use crate::{DnsName, DnsPacket, LookupConfig, QueryType, RCode, lookup};
+
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+// as many nameservers as the C library takes, the rest being left out
+const MAX_NAMESERVERS: usize = 3;
+
+// the most the C library takes for each of the options
+const MAX_NDOTS: u8 = 15;
+const MAX_TIMEOUT: u64 = 30;
+const MAX_ATTEMPTS: u8 = 5;
+
+// What /etc/resolv.conf says about how to look names up, read as the C
+// library reads it: lines it doesn't know of are passed over, and so are
+// values it can't make out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvConf {
+    // the servers to ask, in order, on port 53
+    pub nameservers: Vec<IpAddr>,
+    // the domains to look names with fewer than `ndots` dots up within
+    pub search: Vec<DnsName>,
+    pub ndots: u8,
+    // how long to wait for each nameserver
+    pub timeout: Duration,
+    // how many times to go through the nameservers before giving up
+    pub attempts: u8,
+}
+
+// what the C library does without a resolv.conf, asking a server on the
+// machine itself
+impl Default for ResolvConf {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            search: vec![],
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+        }
+    }
+}
+
+impl ResolvConf {
+    pub fn parse(text: &str) -> Self {
+        let mut conf = Self {
+            nameservers: vec![],
+            ..Self::default()
+        };
+        for line in text.lines() {
+            // comments start with either
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    let ip = words.next().and_then(|ip| ip.parse().ok());
+                    if let Some(ip) = ip.filter(|_| conf.nameservers.len() < MAX_NAMESERVERS) {
+                        conf.nameservers.push(ip);
+                    }
+                }
+                // whichever of them comes last
+                Some("domain") => {
+                    conf.search = words
+                        .next()
+                        .and_then(|d| d.parse().ok())
+                        .into_iter()
+                        .collect();
+                }
+                Some("search") => conf.search = words.filter_map(|d| d.parse().ok()).collect(),
+                Some("options") => {
+                    for option in words {
+                        conf.set_option(option);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if conf.nameservers.is_empty() {
+            conf.nameservers = Self::default().nameservers;
+        }
+        conf
+    }
+
+    // What the machine's /etc/resolv.conf says, or what's done without one
+    // when there's none.
+    pub fn system() -> io::Result<Self> {
+        match fs::read_to_string(RESOLV_CONF) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn set_option(&mut self, option: &str) {
+        let Some((name, value)) = option.split_once(':') else {
+            return;
+        };
+        let Ok(value) = value.parse::<u64>() else {
+            return;
+        };
+        let capped = |max: u64| value.min(max);
+        match name {
+            "ndots" => self.ndots = capped(MAX_NDOTS.into()) as u8,
+            "timeout" => self.timeout = Duration::from_secs(capped(MAX_TIMEOUT).max(1)),
+            "attempts" => self.attempts = capped(MAX_ATTEMPTS.into()).max(1) as u8,
+            _ => {}
+        }
+    }
+
+    // The names to try for `name`, in order, as the C library tries them:
+    // those with fewer than `ndots` dots within each of the search domains
+    // before they're tried as they are, and the others the other way round.
+    // Names with a final dot are tried as they are and no other way.
+    pub fn candidates(&self, name: &str) -> Vec<String> {
+        if name.ends_with('.') {
+            return vec![name.to_string()];
+        }
+        let within = self.search.iter().map(|domain| format!("{name}.{domain}"));
+        let dots = name.matches('.').count();
+        if dots < usize::from(self.ndots) {
+            within.chain([name.to_string()]).collect()
+        } else {
+            [name.to_string()].into_iter().chain(within).collect()
+        }
+    }
+
+    // Tries each of the names `candidates` gives for `name` until one has
+    // records of the type asked for, answering with the first of them that
+    // has none of that type when none do, as that's still a name that's
+    // there, or else with how the last went.
+    pub fn lookup(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        let mut nodata = None;
+        let mut last = Err(io::Error::other("no names to try"));
+        for candidate in self.candidates(name) {
+            match self.lookup_as_is(&candidate, qtype) {
+                Ok(resp) if resp.header.rcode == RCode::Noerror && !resp.answers.is_empty() => {
+                    return Ok(resp);
+                }
+                Ok(resp) if resp.header.rcode == RCode::Noerror => {
+                    nodata.get_or_insert(resp);
+                }
+                answered => last = answered,
+            }
+        }
+        nodata.map_or(last, Ok)
+    }
+
+    // Asks each of the nameservers in turn, as many times over as `attempts`
+    // says, until one answers.
+    fn lookup_as_is(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        let servers: Vec<SocketAddr> = self
+            .nameservers
+            .iter()
+            .map(|&ip| SocketAddr::new(ip, 53))
+            .collect();
+        let config = LookupConfig {
+            timeout: self.timeout,
+            retries: (usize::from(self.attempts) * servers.len()).saturating_sub(1),
+            ..LookupConfig::default()
+        };
+        lookup(name, qtype, &servers[..], &config)
+    }
+}
+
+// Looks `name` up the way the machine's /etc/resolv.conf says to.
+pub fn lookup_system(name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+    ResolvConf::system()?.lookup(name, qtype)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolv_conf() {
+        let conf = ResolvConf::parse(
+            "\
+# from the DHCP server
+domain lab.example
+search corp.example example.com ; the last of them wins
+nameserver 192.0.2.1
+nameserver 2001:db8::1 # and its other address
+nameserver not-an-address
+nameserver 192.0.2.2
+nameserver 192.0.2.3
+options ndots:2 timeout:60 attempts:x rotate
+",
+        );
+        let ips: Vec<_> = conf.nameservers.iter().map(IpAddr::to_string).collect();
+        assert_eq!(ips, ["192.0.2.1", "2001:db8::1", "192.0.2.2"]);
+        let search: Vec<_> = conf.search.iter().map(DnsName::to_string).collect();
+        assert_eq!(search, ["corp.example", "example.com"]);
+        assert_eq!(conf.ndots, 2);
+        assert_eq!(conf.timeout, Duration::from_secs(30));
+        assert_eq!(conf.attempts, 2);
+
+        assert_eq!(
+            ResolvConf::parse("search example.com\n").nameservers,
+            [IpAddr::V4(Ipv4Addr::LOCALHOST)]
+        );
+        assert_eq!(ResolvConf::parse(""), ResolvConf::default());
+    }
+
+    #[test]
+    fn search_domains() {
+        let conf = ResolvConf::parse("search corp.example example.com\noptions ndots:2\n");
+        assert_eq!(
+            conf.candidates("www"),
+            ["www.corp.example", "www.example.com", "www"]
+        );
+        assert_eq!(
+            conf.candidates("www.lab"),
+            ["www.lab.corp.example", "www.lab.example.com", "www.lab"]
+        );
+        assert_eq!(
+            conf.candidates("a.b.example"),
+            [
+                "a.b.example",
+                "a.b.example.corp.example",
+                "a.b.example.example.com"
+            ]
+        );
+        assert_eq!(conf.candidates("www."), ["www."]);
+        assert_eq!(ResolvConf::default().candidates("www"), ["www"]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    DnsName, DnsPacket, DnsQuestion, QueryType, RCode, Update, Upstream, Zone, recursive_lookup,
+};
+
+// Where the server gets its answers from.
+pub trait Resolver {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket>;
+
+    // Makes the changes in `update`, sent from `src`, to a zone of its own,
+    // answering with how that went. Only those with zones of their own can.
+    fn update(&self, _update: &Update, _src: IpAddr) -> RCode {
+        RCode::Notimp
+    }
+
+    // Hears from the primary of `zone`, at `src`, that it changed. Only
+    // secondaries can.
+    fn notify(&self, _zone: &DnsName, _src: IpAddr) -> RCode {
+        RCode::Notimp
+    }
+
+    // The whole of `zone`, for a secondary at `src` to transfer, or the
+    // rcode to answer it with instead. Only those with zones of their own
+    // can.
+    fn transfer(&self, _zone: &DnsName, _src: IpAddr) -> Result<Zone, RCode> {
+        Err(RCode::Notimp)
+    }
+}
+
+impl<R: Resolver + ?Sized> Resolver for Box<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        (**self).resolve(question)
+    }
+
+    fn update(&self, update: &Update, src: IpAddr) -> RCode {
+        (**self).update(update, src)
+    }
+
+    fn notify(&self, zone: &DnsName, src: IpAddr) -> RCode {
+        (**self).notify(zone, src)
+    }
+
+    fn transfer(&self, zone: &DnsName, src: IpAddr) -> Result<Zone, RCode> {
+        (**self).transfer(zone, src)
+    }
+}
+
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        (**self).resolve(question)
+    }
+
+    fn update(&self, update: &Update, src: IpAddr) -> RCode {
+        (**self).update(update, src)
+    }
+
+    fn notify(&self, zone: &DnsName, src: IpAddr) -> RCode {
+        (**self).notify(zone, src)
+    }
+
+    fn transfer(&self, zone: &DnsName, src: IpAddr) -> Result<Zone, RCode> {
+        (**self).transfer(zone, src)
+    }
+}
+
+// Follows the referrals down from the root servers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Recursive;
+
+// Answers found elsewhere aren't ours to vouch for, whatever the server they
+// came from could.
+impl Resolver for Recursive {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let mut resp = recursive_lookup(&question.name, question.r#type)?;
+        resp.header.aa = false;
+        resp.header.ad = false;
+        Ok(resp)
+    }
+}
+
+impl Resolver for Upstream {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let mut resp = self.lookup(&question.name, question.r#type)?;
+        resp.header.aa = false;
+        resp.header.ad = false;
+        Ok(resp)
+    }
+}
+
+// Asks each in turn until one answers.
+impl<R: Resolver + fmt::Display> Resolver for Vec<R> {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        let Some((last, rest)) = self.split_last() else {
+            return Err(io::Error::other("nobody to ask"));
+        };
+        for resolver in rest {
+            match resolver.resolve(question) {
+                Ok(resp) => return Ok(resp),
+                Err(e) => eprintln!("Could not ask {resolver}: {e}"),
+            }
+        }
+        last.resolve(question)
+    }
+}
+
+// Answers from a table filled in beforehand, without going anywhere, and
+// fails for any question it has no answer to.
+#[derive(Debug, Default)]
+pub struct MockResolver {
+    answers: HashMap<(DnsName, QueryType), DnsPacket>,
+    // how many questions it has been asked
+    asked: AtomicUsize,
+}
+
+impl MockResolver {
+    pub fn with_answer(mut self, name: &str, qtype: QueryType, answer: DnsPacket) -> Self {
+        let name = DnsName::new(name).expect("an answer for a name that can't be one");
+        self.answers.insert((name, qtype), answer);
+        self
+    }
+
+    pub fn asked(&self) -> usize {
+        self.asked.load(Ordering::Relaxed)
+    }
+}
+
+impl Resolver for MockResolver {
+    fn resolve(&self, question: &DnsQuestion) -> io::Result<DnsPacket> {
+        self.asked.fetch_add(1, Ordering::Relaxed);
+        let key = (question.name.clone(), question.r#type);
+        self.answers.get(&key).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no answer for {} {:?}", question.name, question.r#type),
+            )
+        })
+    }
+}
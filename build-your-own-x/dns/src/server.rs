@@ -0,0 +1,387 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::time::Duration;
+
+use crate::cookie;
+use crate::notify::OPCODE_NOTIFY;
+use crate::transfer::{is_newer, serial_of};
+use crate::update::OPCODE_UPDATE;
+use crate::{
+    DnsPacket, DnsRecord, MAX_MESSAGE_SIZE, PACKET_SIZE, QueryType, RCode, Resolver, Update,
+    read_tcp_message, write_tcp_message,
+};
+
+// how long a connection may go without a query before it's closed, so that
+// idle clients don't hold on to it (RFC 7766)
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Answers the query in `req_buf`, in at most `max` bytes.
+fn handle_query<R: Resolver + ?Sized>(
+    req_buf: &[u8],
+    src_addr: SocketAddr,
+    max: usize,
+    resolver: &R,
+) -> io::Result<(DnsPacket, Vec<u8>)> {
+    let mut resp = DnsPacket::new_empty();
+    // read by hand so that even a query that fails to parse gets an answer
+    // the client can match up
+    if let [hi, lo, ..] = *req_buf {
+        resp.header.id = u16::from_be_bytes([hi, lo]);
+    }
+    resp.header.qr = true;
+    resp.header.rd = true;
+    resp.header.ra = true;
+
+    match DnsPacket::from_bytes(req_buf) {
+        Ok(req) if req.header.opcode == OPCODE_UPDATE => {
+            resp.header.opcode = OPCODE_UPDATE;
+            resp.header.rd = false;
+            resp.header.ra = false;
+            resp.header.rcode = match Update::from_packet(&req) {
+                Ok(update) => resolver.update(&update, src_addr.ip()),
+                Err(rcode) => rcode,
+            };
+            // the zone section, and nothing else
+            resp.questions = req.questions;
+        }
+        Ok(req) if req.header.opcode == OPCODE_NOTIFY => {
+            resp.header.opcode = OPCODE_NOTIFY;
+            resp.header.rd = false;
+            resp.header.ra = false;
+            resp.header.aa = true;
+            resp.header.rcode = match req.questions.as_slice() {
+                [zone] if zone.r#type == QueryType::SOA => {
+                    resolver.notify(&zone.name, src_addr.ip())
+                }
+                _ => RCode::Formerr,
+            };
+            resp.questions = req.questions;
+        }
+        Ok(req) if req.questions.is_empty() => resp.header.rcode = RCode::Formerr,
+        // only over TCP, which `handle_connection` sees to
+        Ok(req) if is_transfer(&req) => {
+            resp.header.rcode = RCode::Refused;
+            resp.questions = req.questions;
+        }
+        Ok(mut req) => {
+            let ques = req.questions.remove(0);
+            // println!("Received query: {ques:?}");
+
+            if let Ok(result) = resolver.resolve(&ques) {
+                // DNSSEC records only for those that ask for them, whether
+                // with the DO bit or by their type (RFC 3225)
+                let wanted = |rec: &DnsRecord| {
+                    req.dnssec_ok() || rec.r#type == ques.r#type || !is_dnssec(rec.r#type)
+                };
+                resp.header.rcode = result.header.rcode;
+                resp.header.aa = result.header.aa;
+                // and said to be checked only to those that can tell what
+                // that means (RFC 6840)
+                resp.header.ad = result.header.ad && (req.dnssec_ok() || req.header.ad);
+
+                for rec in result.answers.into_iter().filter(wanted) {
+                    //println!("Answer: {:?}", rec);
+                    resp.answers.push(rec);
+                }
+                for rec in result.authorities.into_iter().filter(wanted) {
+                    //println!("Authority: {:?}", rec);
+                    resp.authorities.push(rec);
+                }
+                // the OPT record is between each client and server, and
+                // ours is added below
+                let resources = result.resources.into_iter().filter(wanted);
+                for rec in resources.filter(|r| r.r#type != QueryType::OPT) {
+                    //println!("Resource: {:?}", rec);
+                    resp.resources.push(rec);
+                }
+                resp.questions.push(ques);
+            } else {
+                resp.header.rcode = RCode::Servfail;
+            }
+            if req.edns().is_some() {
+                resp.set_edns(PACKET_SIZE as u16, req.dnssec_ok());
+            }
+            // the client's cookie back, with ours for it to send next time
+            if let Some((client, _)) = req.cookie() {
+                resp.set_cookie(client, &cookie::server_cookie(client, src_addr.ip()));
+            }
+        }
+        Err(e) => {
+            eprintln!("Malformed query from {src_addr}: {e}");
+            resp.header.rcode = e.rcode();
+        }
+    }
+
+    resp.header.qdcount = resp.questions.len() as u16;
+    resp.header.ancount = resp.answers.len() as u16;
+    resp.header.nscount = resp.authorities.len() as u16;
+    resp.header.arcount = resp.resources.len() as u16;
+
+    let resp_buf = match resp.to_vec_truncated(max) {
+        Ok(buf) => buf,
+        Err(e) => {
+            eprintln!("Could not write the response: {e}");
+            resp.header.rcode = e.rcode();
+            resp.answers.clear();
+            resp.authorities.clear();
+            resp.resources.clear();
+            resp.header.ancount = 0;
+            resp.header.nscount = 0;
+            resp.header.arcount = 0;
+            resp.to_vec_truncated(max)?
+        }
+    };
+
+    Ok((resp, resp_buf))
+}
+
+fn is_dnssec(r#type: QueryType) -> bool {
+    matches!(
+        r#type,
+        QueryType::RRSIG | QueryType::NSEC | QueryType::NSEC3
+    )
+}
+
+fn is_transfer(req: &DnsPacket) -> bool {
+    req.header.opcode == 0
+        && matches!(
+            req.questions.as_slice(),
+            [q] if matches!(q.r#type, QueryType::AXFR | QueryType::IXFR)
+        )
+}
+
+// Sends the whole of the zone `req` asks for, over as many messages as it
+// takes, starting and ending with its SOA (RFC 5936). IXFR is answered the
+// same way, as RFC 1995 allows, or with the SOA alone for a secondary that's
+// up to date already. Returns everything that was sent, as one message.
+fn send_transfer<R: Resolver + ?Sized>(
+    stream: &mut TcpStream,
+    req: &DnsPacket,
+    src_addr: SocketAddr,
+    resolver: &R,
+) -> io::Result<DnsPacket> {
+    let ques = &req.questions[0];
+    let mut resp = DnsPacket::new_empty();
+    resp.header.id = req.header.id;
+    resp.header.qr = true;
+    resp.questions = req.questions.clone();
+
+    let zone = resolver
+        .transfer(&ques.name, src_addr.ip())
+        .and_then(|zone| {
+            zone.soa()
+                .cloned()
+                .map(|soa| (zone, soa))
+                .ok_or(RCode::Servfail)
+        });
+    let (zone, soa) = match zone {
+        Ok(zone) => zone,
+        Err(rcode) => {
+            resp.header.rcode = rcode;
+            write_tcp_message(stream, &resp.to_vec()?)?;
+            return Ok(resp);
+        }
+    };
+    resp.header.aa = true;
+
+    let ours = serial_of(&soa);
+    let up_to_date = ques.r#type == QueryType::IXFR
+        && req
+            .authorities
+            .iter()
+            .filter_map(serial_of)
+            .any(|theirs| ours.is_some_and(|ours| !is_newer(ours, theirs)));
+    let mut records = vec![soa.clone()];
+    if !up_to_date {
+        let rest = zone.records().iter().filter(|r| !r.is_same(&soa));
+        records.extend(rest.cloned());
+        records.push(soa);
+    }
+
+    // as many records to a message as fit
+    let mut message = resp.clone();
+    for rec in &records {
+        message.answers.push(rec.clone());
+        if message.answers.len() > 1 && message.to_vec().is_err() {
+            let rec = message.answers.pop().unwrap();
+            write_tcp_message(stream, &message.to_vec()?)?;
+            message.answers = vec![rec];
+        }
+    }
+    write_tcp_message(stream, &message.to_vec()?)?;
+
+    resp.answers = records;
+    Ok(resp)
+}
+
+// Answers the next query to arrive on `socket`.
+pub fn handle_datagram<R: Resolver + ?Sized>(
+    socket: &UdpSocket,
+    resolver: &R,
+) -> io::Result<DnsPacket> {
+    let mut req_buf = [0u8; PACKET_SIZE];
+    let (len, src_addr) = socket.recv_from(&mut req_buf)?;
+
+    let (resp, resp_buf) = handle_query(&req_buf[..len], src_addr, PACKET_SIZE, resolver)?;
+    socket.send_to(&resp_buf, src_addr).map(|_| resp)
+}
+
+// Answers queries on `stream` until the client hangs up, or asks nothing for
+// too long, handing each answer to `answered` once it's sent.
+pub fn handle_connection<R: Resolver + ?Sized>(
+    mut stream: TcpStream,
+    resolver: &R,
+    mut answered: impl FnMut(&DnsPacket),
+) -> io::Result<()> {
+    let src_addr = stream.peer_addr()?;
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+
+    loop {
+        let req_buf = match read_tcp_message(&mut stream) {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // zone transfers, which take more than one message
+        let req = DnsPacket::from_bytes(&req_buf).ok();
+        if let Some(req) = req.filter(is_transfer) {
+            let resp = send_transfer(&mut stream, &req, src_addr, resolver)?;
+            answered(&resp);
+            continue;
+        }
+
+        let (resp, resp_buf) = handle_query(&req_buf, src_addr, MAX_MESSAGE_SIZE, resolver)?;
+        write_tcp_message(&mut stream, &resp_buf)?;
+        answered(&resp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{MockResolver, RData, query};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    fn answer(name: &str, n: u8) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        for i in 0..n {
+            resp.answers.push(DnsRecord {
+                domain: name.parse().unwrap(),
+                r#type: QueryType::A,
+                class: 1,
+                ttl: 60,
+                rdata: RData::A {
+                    ip: Ipv4Addr::new(10, 0, 0, i),
+                },
+            });
+        }
+        resp.header.ancount = n as u16;
+        resp
+    }
+
+    #[test]
+    fn answers_come_from_the_resolver() {
+        let src_addr = (Ipv4Addr::LOCALHOST, 5353).into();
+        let mut nxdomain = DnsPacket::new_empty();
+        nxdomain.header.rcode = RCode::Nxdomain;
+        let resolver = MockResolver::default()
+            .with_answer("example.com", QueryType::A, answer("example.com", 2))
+            .with_answer("nowhere.example.com", QueryType::A, nxdomain);
+
+        let mut req = query("example.com", QueryType::A).unwrap();
+        req.header.id = 4242;
+        let (resp, resp_buf) =
+            handle_query(&req.to_vec().unwrap(), src_addr, PACKET_SIZE, &resolver).unwrap();
+        assert_eq!(DnsPacket::from_bytes(&resp_buf).unwrap().header.id, 4242);
+        assert_eq!(resp.header.rcode, RCode::Noerror);
+        assert!(resp.header.qr);
+        assert_eq!(resp.questions[0].name, "example.com");
+        assert_eq!(resp.answers.len(), 2);
+
+        let req = query("nowhere.example.com", QueryType::A)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let (resp, _) = handle_query(&req, src_addr, PACKET_SIZE, &resolver).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Nxdomain);
+
+        // nothing the resolver knows about
+        let req = query("example.com", QueryType::MX)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let (resp, _) = handle_query(&req, src_addr, PACKET_SIZE, &resolver).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Servfail);
+        assert_eq!(resolver.asked(), 3);
+
+        // no question to ask it
+        let mut req = query("example.com", QueryType::A).unwrap();
+        req.questions.clear();
+        req.header.qdcount = 0;
+        let (resp, _) =
+            handle_query(&req.to_vec().unwrap(), src_addr, PACKET_SIZE, &resolver).unwrap();
+        assert_eq!(resp.header.rcode, RCode::Formerr);
+        assert_eq!(resolver.asked(), 3);
+
+        // an update, for a resolver without zones to make it to
+        let req = Update::new("example.com".parse().unwrap())
+            .to_packet()
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let (resp, _) = handle_query(&req, src_addr, PACKET_SIZE, &resolver).unwrap();
+        assert_eq!(resp.header.opcode, OPCODE_UPDATE);
+        assert_eq!(resp.header.rcode, RCode::Notimp);
+        assert_eq!(resp.questions[0].r#type, QueryType::SOA);
+        assert_eq!(resolver.asked(), 3);
+
+        // the client's cookie back, with one of ours
+        let mut req = query("example.com", QueryType::A).unwrap();
+        req.set_cookie(&[1; 8], &[]);
+        let (resp, _) =
+            handle_query(&req.to_vec().unwrap(), src_addr, PACKET_SIZE, &resolver).unwrap();
+        let (client, server) = resp.cookie().unwrap();
+        assert_eq!(client, [1; 8]);
+        assert_eq!(server, cookie::server_cookie(&[1; 8], src_addr.ip()));
+    }
+
+    #[test]
+    fn only_tcp_gets_big_answers_whole() {
+        let name = "big.example.com";
+        let resolver = MockResolver::default().with_answer(name, QueryType::A, answer(name, 50));
+
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let req = query(name, QueryType::A).unwrap().to_vec().unwrap();
+        client.send_to(&req, socket.local_addr().unwrap()).unwrap();
+        handle_datagram(&socket, &resolver).unwrap();
+        let mut buf = [0u8; PACKET_SIZE];
+        let len = client.recv(&mut buf).unwrap();
+        let resp = DnsPacket::from_bytes(&buf[..len]).unwrap();
+        assert!(resp.header.tc);
+        assert!(resp.answers.len() < 50);
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        let server = thread::spawn(move || handle_connection(conn, &resolver, |_| {}));
+        write_tcp_message(&mut stream, &req).unwrap();
+        let resp = DnsPacket::from_bytes(&read_tcp_message(&mut stream).unwrap()).unwrap();
+        assert!(!resp.header.tc);
+        assert_eq!(resp.answers.len(), 50);
+
+        drop(stream);
+        server.join().unwrap().unwrap();
+    }
+}
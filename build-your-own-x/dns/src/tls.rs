@@ -0,0 +1,222 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rustls::crypto::ring;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::{
+    DnsPacket, QueryType, READ_TIMEOUT, is_answer_to, query, read_tcp_message, write_tcp_message,
+};
+
+// how long a connection may go unused before the next query opens another
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A DNS-over-TLS server (RFC 7858). The connection to it is kept open for the
+// queries that follow, until it has been idle for a while.
+#[derive(Debug)]
+pub struct TlsUpstream {
+    addr: SocketAddr,
+    // what its certificate has to be for
+    name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+    idle_timeout: Duration,
+    conn: Mutex<Option<Connection>>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+    last_used: Instant,
+}
+
+impl TlsUpstream {
+    pub fn new(addr: SocketAddr, name: &str) -> io::Result<Self> {
+        Self::with_roots(addr, name, web_roots())
+    }
+
+    fn with_roots(addr: SocketAddr, name: &str, roots: RootCertStore) -> io::Result<Self> {
+        Ok(Self {
+            addr,
+            name: server_name(name)?,
+            config: Arc::new(client_config(roots)?),
+            idle_timeout: IDLE_TIMEOUT,
+            conn: Mutex::new(None),
+        })
+    }
+
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    pub fn lookup(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        let req = query(name, qtype)?;
+        // locked only to take it, so that lookups at the same time go over
+        // connections of their own rather than wait for it
+        let idle = self
+            .conn
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|conn| conn.last_used.elapsed() < self.idle_timeout);
+        if let Some(mut idle) = idle {
+            // the server may have closed it since, in which case a new one
+            // is worth a try
+            if let Ok(resp) = idle.exchange(&req) {
+                *self.conn.lock().unwrap() = Some(idle);
+                return Ok(resp);
+            }
+        }
+
+        let mut new = self.connect()?;
+        let resp = new.exchange(&req)?;
+        *self.conn.lock().unwrap() = Some(new);
+        Ok(resp)
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let tcp = TcpStream::connect_timeout(&self.addr, READ_TIMEOUT)?;
+        tcp.set_read_timeout(Some(READ_TIMEOUT))?;
+        let tls = ClientConnection::new(self.config.clone(), self.name.clone())
+            .map_err(io::Error::other)?;
+        Ok(Connection {
+            stream: StreamOwned::new(tls, tcp),
+            last_used: Instant::now(),
+        })
+    }
+}
+
+pub(crate) fn server_name(name: &str) -> io::Result<ServerName<'static>> {
+    ServerName::try_from(name.to_string())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+pub(crate) fn web_roots() -> RootCertStore {
+    RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    }
+}
+
+pub(crate) fn client_config(roots: RootCertStore) -> io::Result<ClientConfig> {
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(config)
+}
+
+impl Connection {
+    // Fails for an answer to anything but `req`, after which the connection
+    // is out of step and no good for more.
+    fn exchange(&mut self, req: &DnsPacket) -> io::Result<DnsPacket> {
+        write_tcp_message(&mut self.stream, &req.to_vec()?)?;
+        self.stream.flush()?;
+        let resp = DnsPacket::from_bytes(&read_tcp_message(&mut self.stream)?)?;
+        if !is_answer_to(req, &resp) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "answer to some other query",
+            ));
+        }
+        self.last_used = Instant::now();
+        Ok(resp)
+    }
+}
+
+impl PartialEq for TlsUpstream {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr && self.name == other.name
+    }
+}
+
+impl fmt::Display for TlsUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tls://{}#{}", self.addr, self.name.to_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{DnsRecord, RData};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+
+    // Answers queries over TLS as localhost, sending on `accepted` for each
+    // connection it takes.
+    fn serve(listener: TcpListener, config: Arc<ServerConfig>, accepted: mpsc::Sender<()>) {
+        for tcp in listener.incoming() {
+            let tls = ServerConnection::new(config.clone()).unwrap();
+            let mut stream = StreamOwned::new(tls, tcp.unwrap());
+            accepted.send(()).unwrap();
+            thread::spawn(move || {
+                while let Ok(req) = read_tcp_message(&mut stream) {
+                    let req = DnsPacket::from_bytes(&req).unwrap();
+                    let mut resp = DnsPacket::new_empty();
+                    resp.header.id = req.header.id;
+                    resp.header.qr = true;
+                    resp.questions = req.questions.clone();
+                    resp.header.ancount = 1;
+                    resp.answers.push(DnsRecord {
+                        domain: req.questions[0].name.clone(),
+                        r#type: QueryType::A,
+                        class: 1,
+                        ttl: 60,
+                        rdata: RData::A {
+                            ip: Ipv4Addr::LOCALHOST,
+                        },
+                    });
+                    write_tcp_message(&mut stream, &resp.to_vec().unwrap()).unwrap();
+                    stream.flush().unwrap();
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn reuses_connections_until_idle() {
+        let cert = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+        let key = PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der());
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.cert.der().clone()], PrivateKeyDer::Pkcs8(key))
+            .unwrap();
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (accepted_tx, accepted) = mpsc::channel();
+        thread::spawn(move || serve(listener, Arc::new(config), accepted_tx));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let upstream = TlsUpstream::with_roots(addr, "localhost", roots.clone()).unwrap();
+        for name in ["a.example.com", "b.example.com"] {
+            let resp = upstream.lookup(name, QueryType::A).unwrap();
+            assert_eq!(resp.answers[0].domain, name);
+        }
+        assert_eq!(accepted.try_iter().count(), 1);
+
+        let upstream = TlsUpstream::with_roots(addr, "localhost", roots)
+            .unwrap()
+            .with_idle_timeout(Duration::ZERO);
+        for _ in 0..2 {
+            upstream.lookup("example.com", QueryType::A).unwrap();
+        }
+        assert_eq!(accepted.try_iter().count(), 2);
+
+        // a certificate for another name
+        let upstream = TlsUpstream::new(addr, "example.com").unwrap();
+        assert!(upstream.lookup("example.com", QueryType::A).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+
+use crate::{
+    DnsName, DnsPacket, DnsRecord, QueryType, RCode, RData, READ_TIMEOUT, Zone, query,
+    read_tcp_message, write_tcp_message,
+};
+
+// Pulls the whole of the zone at `origin` from `primary` (RFC 5936).
+pub fn axfr(origin: &DnsName, primary: SocketAddr) -> io::Result<Zone> {
+    let mut req = query(origin, QueryType::AXFR)?;
+    req.header.rd = false;
+    let mut records = transfer(&req, primary, None)?;
+    // the SOA it ends with, as it started
+    records.pop();
+    Ok(Zone::new(origin.clone(), records))
+}
+
+// Pulls what changed in `zone` since its serial from `primary` (RFC 1995),
+// or the whole of it from a primary that can't say, with None when there's
+// nothing newer.
+pub fn ixfr(zone: &Zone, primary: SocketAddr) -> io::Result<Option<Zone>> {
+    let (Some(soa), Some(serial)) = (zone.soa(), zone.serial()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no SOA to say what the zone is at",
+        ));
+    };
+    let mut req = query(zone.origin(), QueryType::IXFR)?;
+    req.header.rd = false;
+    req.header.nscount = 1;
+    req.authorities.push(soa.clone());
+
+    let mut records = match transfer(&req, primary, Some(serial)) {
+        Ok(records) => records,
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            let full = axfr(zone.origin(), primary)?;
+            let newer = full.serial().is_some_and(|new| is_newer(new, serial));
+            return Ok(newer.then_some(full));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let latest = serial_of(&records[0]).unwrap_or(serial);
+    if !is_newer(latest, serial) {
+        return Ok(None);
+    }
+    if !is_incremental(&records) {
+        records.pop();
+        return Ok(Some(Zone::new(zone.origin().clone(), records)));
+    }
+    apply(zone, records).map(Some)
+}
+
+// Sends `req` and reads the records that come back, over as many messages as
+// it takes, up to the SOA they end with. `serial` is what the zone is at for
+// IXFR, and None for AXFR.
+fn transfer(
+    req: &DnsPacket,
+    primary: SocketAddr,
+    serial: Option<u32>,
+) -> io::Result<Vec<DnsRecord>> {
+    let mut stream = TcpStream::connect_timeout(&primary, READ_TIMEOUT)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    write_tcp_message(&mut stream, &req.to_vec()?)?;
+
+    let mut records = vec![];
+    loop {
+        let resp = DnsPacket::from_bytes(&read_tcp_message(&mut stream)?)?;
+        match resp.header.rcode {
+            RCode::Noerror => {}
+            // for those that don't do IXFR, to be asked for AXFR instead
+            rcode @ (RCode::Formerr | RCode::Notimp | RCode::Refused) if serial.is_some() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("primary answered {rcode:?}"),
+                ));
+            }
+            rcode => return Err(io::Error::other(format!("primary answered {rcode:?}"))),
+        }
+        if resp.answers.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "primary answered without any records",
+            ));
+        }
+        records.extend(resp.answers);
+
+        let Some(latest) = serial_of(&records[0]) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "transfer doesn't start with an SOA",
+            ));
+        };
+        // a single SOA, for a zone that's up to date
+        if serial.is_some_and(|serial| !is_newer(latest, serial)) {
+            return Ok(records);
+        }
+        // the SOA comes again at the end, and for IXFR also before the
+        // records added in the last change
+        let times = records
+            .iter()
+            .filter(|r| serial_of(r) == Some(latest))
+            .count();
+        let incremental = serial.is_some() && is_incremental(&records);
+        if times >= if incremental { 3 } else { 2 } {
+            return Ok(records);
+        }
+    }
+}
+
+// Whether the records are changes rather than the whole zone: those have an
+// older SOA right after the first.
+fn is_incremental(records: &[DnsRecord]) -> bool {
+    match (records.first().and_then(serial_of), records.get(1)) {
+        (Some(latest), Some(second)) => serial_of(second).is_some_and(|s| s != latest),
+        _ => false,
+    }
+}
+
+// Applies the changes in `records` to `zone`, each of them the old SOA and
+// the records that went, then the new SOA and those that came.
+fn apply(zone: &Zone, records: Vec<DnsRecord>) -> io::Result<Zone> {
+    let latest = serial_of(&records[0]);
+    let mut current = zone.records().to_vec();
+    let mut serial = zone.serial();
+    let mut deleting = false;
+
+    // between the SOAs it starts and ends with
+    for rec in &records[1..records.len() - 1] {
+        if let Some(soa_serial) = serial_of(rec) {
+            deleting = !deleting;
+            if deleting && Some(soa_serial) != serial {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("changes from serial {soa_serial} for a zone at {serial:?}"),
+                ));
+            }
+            serial = Some(soa_serial);
+        }
+        current.retain(|r| !r.is_same(rec));
+        if !deleting {
+            current.push(rec.clone());
+        }
+    }
+
+    if serial != latest {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "changes stop short of the latest serial",
+        ));
+    }
+    Ok(Zone::new(zone.origin().clone(), current))
+}
+
+pub(crate) fn serial_of(rec: &DnsRecord) -> Option<u32> {
+    match rec.rdata {
+        RData::SOA { serial, .. } => Some(serial),
+        _ => None,
+    }
+}
+
+// Whether serial `a` comes after `b`, counting on from where they wrap
+// around (RFC 1982).
+pub(crate) fn is_newer(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) < 1 << 31
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@    SOA  ns1 hostmaster 1 3600 900 604800 300
+@    NS   ns1
+ns1  A    192.0.2.1
+www  A    192.0.2.2
+";
+
+    fn soa(serial: u32) -> DnsRecord {
+        let zone = Zone::parse(ZONE, "").unwrap();
+        let mut soa = zone.soa().unwrap().clone();
+        let RData::SOA { serial: s, .. } = &mut soa.rdata else {
+            unreachable!();
+        };
+        *s = serial;
+        soa
+    }
+
+    fn a(name: &str, last: u8) -> DnsRecord {
+        DnsRecord {
+            domain: name.parse().unwrap(),
+            r#type: QueryType::A,
+            class: 1,
+            ttl: 3600,
+            rdata: RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, last),
+            },
+        }
+    }
+
+    fn message(answers: Vec<DnsRecord>) -> DnsPacket {
+        let mut resp = DnsPacket::new_empty();
+        resp.header.qr = true;
+        resp.header.ancount = answers.len() as u16;
+        resp.answers = answers;
+        resp
+    }
+
+    // Answers each connection with the messages `answer` makes of what's
+    // asked on it.
+    fn serve(answer: impl Fn(&DnsPacket) -> Vec<DnsPacket> + Send + 'static) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let req = DnsPacket::from_bytes(&read_tcp_message(&mut stream).unwrap()).unwrap();
+                for mut resp in answer(&req) {
+                    resp.header.id = req.header.id;
+                    write_tcp_message(&mut stream, &resp.to_vec().unwrap()).unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn transfers() {
+        let primary = serve(|req| {
+            let full = || {
+                vec![
+                    message(vec![soa(3), a("ns1.example.com", 1)]),
+                    message(vec![a("mail.example.com", 25), soa(3)]),
+                ]
+            };
+            match req.questions[0].r#type {
+                QueryType::AXFR => full(),
+                QueryType::IXFR => {
+                    let RData::SOA { serial, .. } = req.authorities[0].rdata else {
+                        panic!("IXFR without an SOA");
+                    };
+                    match serial {
+                        1 => vec![
+                            message(vec![soa(3), soa(1), a("www.example.com", 2), soa(2)]),
+                            message(vec![a("www.example.com", 80), soa(2), soa(3)]),
+                            message(vec![a("mail.example.com", 25), soa(3)]),
+                        ],
+                        // too far back to have kept the changes
+                        0 => full(),
+                        _ => vec![message(vec![soa(3)])],
+                    }
+                }
+                _ => panic!("not a transfer"),
+            }
+        });
+
+        let zone = axfr(&"example.com".parse().unwrap(), primary).unwrap();
+        assert_eq!(zone.serial(), Some(3));
+        assert_eq!(zone.records().len(), 3);
+
+        let old = Zone::parse(ZONE, "").unwrap();
+        let new = ixfr(&old, primary).unwrap().unwrap();
+        assert_eq!(new.serial(), Some(3));
+        let mut names: Vec<_> = new.records().iter().map(|r| r.domain.as_str()).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "example.com",
+                "example.com",
+                "mail.example.com",
+                "ns1.example.com",
+                "www.example.com"
+            ]
+        );
+        assert!(new.records().contains(&a("www.example.com", 80)));
+
+        assert!(ixfr(&new, primary).unwrap().is_none());
+
+        let older = Zone::new("example.com".parse().unwrap(), vec![soa(0)]);
+        assert_eq!(ixfr(&older, primary).unwrap().unwrap().records().len(), 3);
+    }
+
+    #[test]
+    fn primaries_without_ixfr() {
+        let primary = serve(|req| match req.questions[0].r#type {
+            QueryType::AXFR => vec![message(vec![soa(2), a("www.example.com", 2), soa(2)])],
+            _ => {
+                let mut resp = message(vec![]);
+                resp.header.rcode = RCode::Notimp;
+                vec![resp]
+            }
+        });
+        let old = Zone::parse(ZONE, "").unwrap();
+        let new = ixfr(&old, primary).unwrap().unwrap();
+        assert_eq!(new.serial(), Some(2));
+        assert!(ixfr(&new, primary).unwrap().is_none());
+    }
+
+    #[test]
+    fn serials_wrap_around() {
+        assert!(is_newer(2, 1));
+        assert!(!is_newer(1, 2));
+        assert!(!is_newer(1, 1));
+        assert!(is_newer(0, u32::MAX));
+        assert!(!is_newer(u32::MAX, 0));
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::{
+    CLASS_ANY, CLASS_IN, CLASS_NONE, DnsError, DnsName, DnsPacket, DnsRecord, QueryType, RCode,
+    RData, query,
+};
+
+pub(crate) const OPCODE_UPDATE: u8 = 5;
+// the type that stands for all of them, for names as a whole
+const ANY: QueryType = QueryType::Unknown(255);
+
+// What has to hold of the zone for an update to go ahead.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Prerequisite {
+    // the name has records of some type
+    NameInUse(DnsName),
+    NameNotInUse(DnsName),
+    RRsetExists(DnsName, QueryType),
+    RRsetDoesNotExist(DnsName, QueryType),
+    // the records of its name and type are exactly those required this way,
+    // whatever their TTLs
+    RRsetHas(DnsRecord),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Change {
+    Add(DnsRecord),
+    DeleteRRset(DnsName, QueryType),
+    // all of the name's records, but the SOA and NS records at the apex
+    DeleteName(DnsName),
+    // the record with the same data, whatever its TTL
+    Delete(DnsRecord),
+}
+
+// A dynamic update of a zone (RFC 2136): changes to be made all together,
+// or not at all when the prerequisites don't hold.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Update {
+    pub zone: DnsName,
+    pub prerequisites: Vec<Prerequisite>,
+    pub changes: Vec<Change>,
+}
+
+impl Update {
+    pub fn new(zone: DnsName) -> Self {
+        Self {
+            zone,
+            prerequisites: vec![],
+            changes: vec![],
+        }
+    }
+
+    pub fn require(mut self, prerequisite: Prerequisite) -> Self {
+        self.prerequisites.push(prerequisite);
+        self
+    }
+
+    pub fn change(mut self, change: Change) -> Self {
+        self.changes.push(change);
+        self
+    }
+
+    // The message for it: the zone goes in the question section, the
+    // prerequisites in the answer section and the changes in the authority
+    // section.
+    pub fn to_packet(&self) -> Result<DnsPacket, DnsError> {
+        let mut packet = query(&self.zone, QueryType::SOA)?;
+        packet.header.opcode = OPCODE_UPDATE;
+        packet.header.rd = false;
+        packet.answers = self.prerequisites.iter().map(|p| p.to_record()).collect();
+        packet.authorities = self.changes.iter().map(|c| c.to_record()).collect();
+        packet.header.ancount = packet.answers.len() as u16;
+        packet.header.nscount = packet.authorities.len() as u16;
+        Ok(packet)
+    }
+
+    // Reads an update back out of its message, with the rcode to answer
+    // with when it makes no sense.
+    pub fn from_packet(packet: &DnsPacket) -> Result<Self, RCode> {
+        let [zone] = packet.questions.as_slice() else {
+            return Err(RCode::Formerr);
+        };
+        if zone.r#type != QueryType::SOA || zone.class != CLASS_IN {
+            return Err(RCode::Formerr);
+        }
+        Ok(Self {
+            zone: zone.name.clone(),
+            prerequisites: packet
+                .answers
+                .iter()
+                .map(Prerequisite::from_record)
+                .collect::<Result<_, _>>()?,
+            changes: packet
+                .authorities
+                .iter()
+                .map(Change::from_record)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl Prerequisite {
+    fn to_record(&self) -> DnsRecord {
+        match self {
+            Self::NameInUse(name) => rrset(name, ANY, CLASS_ANY),
+            Self::NameNotInUse(name) => rrset(name, ANY, CLASS_NONE),
+            Self::RRsetExists(name, r#type) => rrset(name, *r#type, CLASS_ANY),
+            Self::RRsetDoesNotExist(name, r#type) => rrset(name, *r#type, CLASS_NONE),
+            Self::RRsetHas(rec) => DnsRecord {
+                class: CLASS_IN,
+                ttl: 0,
+                ..rec.clone()
+            },
+        }
+    }
+
+    fn from_record(rec: &DnsRecord) -> Result<Self, RCode> {
+        if rec.ttl != 0 {
+            return Err(RCode::Formerr);
+        }
+        let name = || rec.domain.clone();
+        match (rec.class, rec.r#type) {
+            (CLASS_ANY, ANY) if is_empty(rec) => Ok(Self::NameInUse(name())),
+            (CLASS_ANY, r#type) if is_empty(rec) => Ok(Self::RRsetExists(name(), r#type)),
+            (CLASS_NONE, ANY) if is_empty(rec) => Ok(Self::NameNotInUse(name())),
+            (CLASS_NONE, r#type) if is_empty(rec) => Ok(Self::RRsetDoesNotExist(name(), r#type)),
+            (CLASS_IN, r#type) if !is_meta(r#type) => Ok(Self::RRsetHas(rec.clone())),
+            _ => Err(RCode::Formerr),
+        }
+    }
+}
+
+impl Change {
+    fn to_record(&self) -> DnsRecord {
+        match self {
+            Self::Add(rec) => DnsRecord {
+                class: CLASS_IN,
+                ..rec.clone()
+            },
+            Self::DeleteRRset(name, r#type) => rrset(name, *r#type, CLASS_ANY),
+            Self::DeleteName(name) => rrset(name, ANY, CLASS_ANY),
+            Self::Delete(rec) => DnsRecord {
+                class: CLASS_NONE,
+                ttl: 0,
+                ..rec.clone()
+            },
+        }
+    }
+
+    fn from_record(rec: &DnsRecord) -> Result<Self, RCode> {
+        match (rec.class, rec.r#type) {
+            (CLASS_IN, r#type) if !is_meta(r#type) => Ok(Self::Add(rec.clone())),
+            (CLASS_ANY, ANY) if rec.ttl == 0 && is_empty(rec) => {
+                Ok(Self::DeleteName(rec.domain.clone()))
+            }
+            (CLASS_ANY, r#type) if rec.ttl == 0 && is_empty(rec) && !is_meta(r#type) => {
+                Ok(Self::DeleteRRset(rec.domain.clone(), r#type))
+            }
+            (CLASS_NONE, r#type) if rec.ttl == 0 && !is_meta(r#type) => {
+                Ok(Self::Delete(DnsRecord {
+                    class: CLASS_IN,
+                    ..rec.clone()
+                }))
+            }
+            _ => Err(RCode::Formerr),
+        }
+    }
+}
+
+// A record that stands for all of a name's records of a type, without any
+// data of its own.
+fn rrset(name: &DnsName, r#type: QueryType, class: u16) -> DnsRecord {
+    DnsRecord {
+        domain: name.clone(),
+        r#type,
+        class,
+        ttl: 0,
+        rdata: RData::Unknown { bytes: vec![] },
+    }
+}
+
+fn is_empty(rec: &DnsRecord) -> bool {
+    matches!(&rec.rdata, RData::Unknown { bytes } if bytes.is_empty())
+}
+
+// Types only ever asked about, like AXFR and ANY, that no record has.
+fn is_meta(r#type: QueryType) -> bool {
+    (128..=255).contains(&u16::from(r#type))
+}
+
+// The addresses under a prefix, as in 192.0.2.0/24, or a single address.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (net, width) = bits(self.addr);
+        let (ip, ip_width) = bits(ip.to_canonical());
+        let shift = 128 - u32::from(self.prefix);
+        width == ip_width && (net ^ ip).checked_shr(shift).unwrap_or(0) == 0
+    }
+}
+
+// The address as the top bits of a u128, and how many of them there are.
+fn bits(ip: IpAddr) -> (u128, u8) {
+    match ip {
+        IpAddr::V4(ip) => (u128::from(ip.to_bits()) << 96, 32),
+        IpAddr::V6(ip) => (ip.to_bits(), 128),
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("{addr:?} isn't an IP address"))?;
+        let width = bits(addr).1;
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|&prefix| prefix <= width)
+                .ok_or_else(|| format!("{prefix:?} isn't a prefix length up to {width}"))?,
+            None => width,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn a(name: &str, last: u8) -> DnsRecord {
+        DnsRecord {
+            domain: name.parse().unwrap(),
+            r#type: QueryType::A,
+            class: CLASS_IN,
+            ttl: 300,
+            rdata: RData::A {
+                ip: Ipv4Addr::new(192, 0, 2, last),
+            },
+        }
+    }
+
+    #[test]
+    fn updates_survive_the_wire() {
+        let update = Update::new("example.com.".parse().unwrap())
+            .require(Prerequisite::NameInUse("example.com".parse().unwrap()))
+            .require(Prerequisite::NameNotInUse(
+                "new.example.com".parse().unwrap(),
+            ))
+            .require(Prerequisite::RRsetExists(
+                "www.example.com".parse().unwrap(),
+                QueryType::A,
+            ))
+            .require(Prerequisite::RRsetDoesNotExist(
+                "www.example.com".parse().unwrap(),
+                QueryType::CNAME,
+            ))
+            .require(Prerequisite::RRsetHas(DnsRecord {
+                ttl: 0,
+                ..a("www.example.com", 2)
+            }))
+            .change(Change::Add(a("new.example.com", 3)))
+            .change(Change::DeleteRRset(
+                "old.example.com".parse().unwrap(),
+                QueryType::TXT,
+            ))
+            .change(Change::DeleteName("gone.example.com".parse().unwrap()))
+            .change(Change::Delete(a("www.example.com", 2)));
+
+        let packet = update.to_packet().unwrap();
+        assert_eq!(packet.header.opcode, OPCODE_UPDATE);
+        let packet = DnsPacket::from_bytes(&packet.to_vec().unwrap()).unwrap();
+        let mut expected = update;
+        // deletions don't carry a TTL
+        expected.changes[3] = Change::Delete(DnsRecord {
+            ttl: 0,
+            ..a("www.example.com", 2)
+        });
+        assert_eq!(Update::from_packet(&packet), Ok(expected));
+    }
+
+    #[test]
+    fn nonsense_updates() {
+        let mut packet = Update::new("example.com".parse().unwrap())
+            .to_packet()
+            .unwrap();
+        packet.questions[0].r#type = QueryType::A;
+        assert_eq!(Update::from_packet(&packet), Err(RCode::Formerr));
+
+        // a record of a type only ever asked about
+        let mut packet = Update::new("example.com".parse().unwrap())
+            .to_packet()
+            .unwrap();
+        packet
+            .authorities
+            .push(rrset(&packet.questions[0].name, QueryType::AXFR, CLASS_IN));
+        assert_eq!(Update::from_packet(&packet), Err(RCode::Formerr));
+
+        // a prerequisite with a TTL
+        let mut packet = Update::new("example.com".parse().unwrap())
+            .to_packet()
+            .unwrap();
+        packet.answers.push(DnsRecord {
+            ttl: 60,
+            ..rrset(&packet.questions[0].name, ANY, CLASS_ANY)
+        });
+        assert_eq!(Update::from_packet(&packet), Err(RCode::Formerr));
+    }
+
+    #[test]
+    fn networks() {
+        let net: Network = "192.0.2.0/24".parse().unwrap();
+        assert!(net.contains("192.0.2.200".parse().unwrap()));
+        assert!(net.contains("::ffff:192.0.2.1".parse().unwrap()));
+        assert!(!net.contains("192.0.3.1".parse().unwrap()));
+        assert!(!net.contains("2001:db8::1".parse().unwrap()));
+
+        let one: Network = "2001:db8::1".parse().unwrap();
+        assert!(one.contains("2001:db8::1".parse().unwrap()));
+        assert!(!one.contains("2001:db8::2".parse().unwrap()));
+        assert_eq!(one.to_string(), "2001:db8::1/128");
+
+        let all: Network = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(Ipv4Addr::LOCALHOST.into()));
+
+        assert!("192.0.2.0/33".parse::<Network>().is_err());
+        assert!("example.com".parse::<Network>().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+#[cfg(feature = "doq")]
+use crate::quic::QuicUpstream;
+use crate::tls::TlsUpstream;
+use crate::{
+    DnsPacket, LookupConfig, MAX_MESSAGE_SIZE, QueryType, READ_TIMEOUT, Transport, lookup, query,
+};
+
+// the media type of a DNS message in wire format, as sent over HTTPS
+const DNS_MESSAGE: &str = "application/dns-message";
+
+// shared by all DNS-over-HTTPS lookups, for their connections to be kept
+// and used again
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(READ_TIMEOUT)
+        .build()
+        .expect("no HTTP client for DNS-over-HTTPS")
+});
+
+// A server to forward queries to, and how to reach it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Upstream {
+    // with how long to wait for answers
+    Udp(SocketAddr, Duration),
+    Tcp(SocketAddr, Duration),
+    // the URL of a DNS-over-HTTPS endpoint, like
+    // https://cloudflare-dns.com/dns-query
+    Https(String),
+    // shared, along with the connection to it, between clones
+    Tls(Arc<TlsUpstream>),
+    #[cfg(feature = "doq")]
+    Quic(Arc<QuicUpstream>),
+}
+
+impl Upstream {
+    // Waits `timeout` for answers over UDP or TCP, rather than the 5 seconds
+    // it does otherwise. The other transports keep their own.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        match self {
+            Upstream::Udp(addr, _) => Upstream::Udp(addr, timeout),
+            Upstream::Tcp(addr, _) => Upstream::Tcp(addr, timeout),
+            other => other,
+        }
+    }
+
+    pub fn lookup(&self, name: &str, qtype: QueryType) -> io::Result<DnsPacket> {
+        match self {
+            Upstream::Udp(addr, timeout) | Upstream::Tcp(addr, timeout) => {
+                let config = LookupConfig {
+                    transport: match self {
+                        Upstream::Tcp(..) => Transport::Tcp,
+                        _ => Transport::Udp,
+                    },
+                    timeout: *timeout,
+                    // the next upstream is asked instead
+                    retries: 0,
+                    ..LookupConfig::default()
+                };
+                lookup(name, qtype, addr, &config)
+            }
+            Upstream::Https(url) => lookup_https(name, qtype, url),
+            Upstream::Tls(tls) => tls.lookup(name, qtype),
+            #[cfg(feature = "doq")]
+            Upstream::Quic(quic) => quic.lookup(name, qtype),
+        }
+    }
+}
+
+// Asks the DNS-over-HTTPS endpoint at `url`, POSTing the query as is
+// (RFC 8484).
+fn lookup_https(name: &str, qtype: QueryType, url: &str) -> io::Result<DnsPacket> {
+    let mut query = query(name, qtype)?;
+    // so that the same question always makes the same request, for HTTP
+    // caches to recognise
+    query.header.id = 0;
+
+    let resp = CLIENT
+        .post(url)
+        .header(CONTENT_TYPE, DNS_MESSAGE)
+        .header(ACCEPT, DNS_MESSAGE)
+        .body(query.to_vec()?)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .map_err(io::Error::other)?;
+
+    // no more than a message can take up, and a byte over to tell one
+    // that's too long
+    let mut body = vec![];
+    resp.take(MAX_MESSAGE_SIZE as u64 + 1)
+        .read_to_end(&mut body)?;
+    Ok(DnsPacket::from_bytes(&body)?)
+}
+
+// As taken on the command line: udp://IP[:PORT], tcp://IP[:PORT],
+// tls://IP[:PORT]#NAME, quic://IP[:PORT]#NAME (with the doq feature) or an
+// https:// URL, with a bare address meaning UDP.
+impl FromStr for Upstream {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s.starts_with("https://") {
+            return Ok(Upstream::Https(s.to_string()));
+        }
+
+        let (scheme, addr) = s.split_once("://").unwrap_or(("udp", s));
+        match scheme {
+            "udp" => Ok(Upstream::Udp(parse_addr(addr, 53)?, READ_TIMEOUT)),
+            "tcp" => Ok(Upstream::Tcp(parse_addr(addr, 53)?, READ_TIMEOUT)),
+            "tls" => {
+                let (addr, name) = parse_named(addr)?;
+                let tls = TlsUpstream::new(addr, name).map_err(|e| e.to_string())?;
+                Ok(Upstream::Tls(Arc::new(tls)))
+            }
+            #[cfg(feature = "doq")]
+            "quic" => {
+                let (addr, name) = parse_named(addr)?;
+                let quic = QuicUpstream::new(addr, name).map_err(|e| e.to_string())?;
+                Ok(Upstream::Quic(Arc::new(quic)))
+            }
+            _ => Err(format!(
+                "unknown transport {scheme:?}, expected udp, tcp, tls or https"
+            )),
+        }
+    }
+}
+
+// IP[:PORT]#NAME, with NAME what the server's certificate is for, and 853 the
+// port to use by default
+fn parse_named(addr: &str) -> Result<(SocketAddr, &str), String> {
+    let (addr, name) = addr.split_once('#').ok_or(
+        "expected the name the server's certificate is for after a #, \
+         as in tls://1.1.1.1#one.one.one.one",
+    )?;
+    Ok((parse_addr(addr, 853)?, name))
+}
+
+// IP[:PORT]
+fn parse_addr(addr: &str, default_port: u16) -> Result<SocketAddr, String> {
+    addr.parse()
+        .or_else(|_| {
+            addr.parse()
+                .map(|ip: IpAddr| SocketAddr::new(ip, default_port))
+        })
+        .map_err(|_| format!("{addr:?} isn't an IP address with an optional port"))
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Udp(addr, _) => write!(f, "udp://{addr}"),
+            Upstream::Tcp(addr, _) => write!(f, "tcp://{addr}"),
+            Upstream::Https(url) => f.write_str(url),
+            Upstream::Tls(tls) => tls.fmt(f),
+            #[cfg(feature = "doq")]
+            Upstream::Quic(quic) => quic.fmt(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{RCode, RData};
+
+    #[test]
+    fn parse() {
+        let udp = Upstream::Udp("8.8.8.8:53".parse().unwrap(), READ_TIMEOUT);
+        assert_eq!("8.8.8.8".parse(), Ok(udp.clone()));
+        assert_eq!("udp://8.8.8.8:53".parse(), Ok(udp));
+        let tcp: Upstream = "tcp://[::1]:5353".parse().unwrap();
+        assert_eq!(
+            tcp.with_timeout(Duration::from_secs(1)),
+            Upstream::Tcp("[::1]:5353".parse().unwrap(), Duration::from_secs(1))
+        );
+        let doh = "https://cloudflare-dns.com/dns-query";
+        assert_eq!(doh.parse(), Ok(Upstream::Https(doh.to_string())));
+
+        let dot = "tls://1.1.1.1:853#one.one.one.one";
+        let Ok(Upstream::Tls(tls)) = "tls://1.1.1.1#one.one.one.one".parse() else {
+            panic!("expected a TLS upstream");
+        };
+        assert_eq!(tls.to_string(), dot);
+
+        assert!("quic://8.8.8.8".parse::<Upstream>().is_err());
+        assert!("tcp://dns.google".parse::<Upstream>().is_err());
+        assert!("tls://1.1.1.1".parse::<Upstream>().is_err());
+
+        for s in ["udp://8.8.8.8:53", "tcp://[::1]:5353", dot, doh] {
+            assert_eq!(s.parse::<Upstream>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn lookup_cloudflare_https() {
+        let upstream: Upstream = "https://cloudflare-dns.com/dns-query".parse().unwrap();
+        let response = upstream.lookup("one.one.one.one", QueryType::A).unwrap();
+
+        assert_eq!(response.header.rcode, RCode::Noerror);
+        assert!(response.answers.iter().any(|rec| matches!(
+            rec.rdata,
+            RData::A { ip } if ip.octets() == [1, 1, 1, 1]
+        )));
+    }
+}
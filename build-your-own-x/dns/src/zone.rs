@@ -0,0 +1,660 @@
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::encoding::Hex;
+use crate::{DnsName, DnsRecord, QueryType, RData};
+
+// The records of a zone, as read from a master file (RFC 1035 section 5).
+#[derive(Debug, Clone)]
+pub struct Zone {
+    // its apex, where its SOA is
+    origin: DnsName,
+    records: Vec<DnsRecord>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZoneError {
+    // where in the file, counting from 1
+    pub line: usize,
+    pub msg: String,
+}
+
+// An entry spread over as many lines as its parentheses take.
+struct Entry {
+    line: usize,
+    // whether it starts with a blank, for the same owner as the one before
+    same_owner: bool,
+    tokens: Vec<String>,
+}
+
+impl Zone {
+    // `origin` is what relative names are relative to until a $ORIGIN says
+    // otherwise, and the zone's apex unless its SOA says otherwise.
+    pub fn parse(text: &str, origin: &str) -> Result<Self, ZoneError> {
+        // line 0, as it's given rather than read from the file
+        let apex = DnsName::new(origin).map_err(|e| ZoneError {
+            line: 0,
+            msg: format!("origin {origin:?}: {e}"),
+        })?;
+        let mut parser = Parser {
+            origin: absolute(origin).to_string(),
+            ttl: None,
+            owner: None,
+            line: 0,
+        };
+        let mut records = vec![];
+        for entry in entries(text)? {
+            parser.line = entry.line;
+            if let Some(record) = parser.entry(entry)? {
+                records.push(record);
+            }
+        }
+        let origin = match records.iter().find(|r| r.r#type == QueryType::SOA) {
+            Some(soa) => soa.domain.clone(),
+            None => apex,
+        };
+        Ok(Self { origin, records })
+    }
+
+    pub(crate) fn new(origin: DnsName, records: Vec<DnsRecord>) -> Self {
+        Self { origin, records }
+    }
+
+    pub fn origin(&self) -> &DnsName {
+        &self.origin
+    }
+
+    pub fn records(&self) -> &[DnsRecord] {
+        &self.records
+    }
+
+    pub(crate) fn soa(&self) -> Option<&DnsRecord> {
+        self.records
+            .iter()
+            .find(|r| r.r#type == QueryType::SOA && r.domain == self.origin)
+    }
+
+    // the version of the zone its SOA says it's at
+    pub fn serial(&self) -> Option<u32> {
+        match self.soa()?.rdata {
+            RData::SOA { serial, .. } => Some(serial),
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    origin: String,
+    // for records that don't give their own
+    ttl: Option<u32>,
+    // for entries that start with a blank
+    owner: Option<String>,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: impl Into<String>) -> ZoneError {
+        ZoneError {
+            line: self.line,
+            msg: msg.into(),
+        }
+    }
+
+    fn entry(&mut self, entry: Entry) -> Result<Option<DnsRecord>, ZoneError> {
+        let mut tokens = entry.tokens.into_iter();
+
+        let owner = if entry.same_owner {
+            self.owner
+                .clone()
+                .ok_or_else(|| self.error("no owner to carry over from before"))?
+        } else {
+            let first = tokens.next().unwrap_or_default();
+            match first.as_str() {
+                "$ORIGIN" => {
+                    let origin = self.one(tokens, "$ORIGIN")?;
+                    self.origin = self.name(&origin)?;
+                    return Ok(None);
+                }
+                "$TTL" => {
+                    let ttl = self.one(tokens, "$TTL")?;
+                    self.ttl = Some(self.ttl(&ttl)?);
+                    return Ok(None);
+                }
+                directive if directive.starts_with('$') => {
+                    return Err(self.error(format!("unsupported directive {directive}")));
+                }
+                owner => self.name(owner)?,
+            }
+        };
+        self.owner = Some(owner.clone());
+
+        // the TTL and class, in either order, and both optional
+        let mut ttl = None;
+        let mut r#type = None;
+        for token in tokens.by_ref() {
+            if token.eq_ignore_ascii_case("IN") {
+                continue;
+            }
+            if token.starts_with(|c: char| c.is_ascii_digit()) && ttl.is_none() {
+                ttl = Some(self.ttl(&token)?);
+                continue;
+            }
+            r#type = Some(token);
+            break;
+        }
+        let r#type = r#type.ok_or_else(|| self.error("missing the record's type"))?;
+        let r#type = QueryType::from_str(&r#type).map_err(|e| self.error(e))?;
+
+        let ttl = match ttl.or(self.ttl) {
+            Some(ttl) => ttl,
+            None => return Err(self.error("no TTL, and no $TTL to default to")),
+        };
+        // later records without one take the last one given (RFC 1035)
+        self.ttl = self.ttl.or(Some(ttl));
+
+        let rdata = self.rdata(r#type, &tokens.collect::<Vec<_>>())?;
+        let domain = DnsName::new(&owner).map_err(|e| self.error(format!("{owner:?}: {e}")))?;
+        Ok(Some(DnsRecord {
+            domain,
+            r#type,
+            class: 1,
+            ttl,
+            rdata,
+        }))
+    }
+
+    fn rdata(&self, r#type: QueryType, fields: &[String]) -> Result<RData, ZoneError> {
+        let want = |n: usize| {
+            if fields.len() == n {
+                Ok(())
+            } else {
+                Err(self.error(format!(
+                    "{type:?} records take {n} fields, not {}",
+                    fields.len(),
+                    type = r#type,
+                )))
+            }
+        };
+
+        // as in RFC 3597, for types without a text format of their own
+        if fields.first().is_some_and(|f| f == "\\#") {
+            return self.unknown(fields);
+        }
+
+        let rdata = match r#type {
+            QueryType::A => {
+                want(1)?;
+                RData::A {
+                    ip: self.parse::<Ipv4Addr>(&fields[0])?,
+                }
+            }
+            QueryType::AAAA => {
+                want(1)?;
+                RData::AAAA {
+                    ip: self.parse::<Ipv6Addr>(&fields[0])?,
+                }
+            }
+            QueryType::NS => {
+                want(1)?;
+                RData::NS {
+                    host: self.host(&fields[0])?,
+                }
+            }
+            QueryType::CNAME => {
+                want(1)?;
+                RData::CNAME {
+                    host: self.host(&fields[0])?,
+                }
+            }
+            QueryType::PTR => {
+                want(1)?;
+                RData::PTR {
+                    host: self.host(&fields[0])?,
+                }
+            }
+            QueryType::MX => {
+                want(2)?;
+                RData::MX {
+                    priority: self.parse(&fields[0])?,
+                    host: self.host(&fields[1])?,
+                }
+            }
+            QueryType::TXT => {
+                if fields.is_empty() {
+                    return Err(self.error("TXT records take at least one string"));
+                }
+                for s in fields {
+                    if s.len() > 255 {
+                        return Err(self.error(format!("string of {} bytes", s.len())));
+                    }
+                }
+                RData::TXT {
+                    strings: fields
+                        .iter()
+                        .map(|s| self.character_string(s))
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            QueryType::SOA => {
+                want(7)?;
+                RData::SOA {
+                    mname: self.host(&fields[0])?,
+                    rname: self.host(&fields[1])?,
+                    serial: self.parse(&fields[2])?,
+                    refresh: self.ttl(&fields[3])?,
+                    retry: self.ttl(&fields[4])?,
+                    expire: self.ttl(&fields[5])?,
+                    minimum: self.ttl(&fields[6])?,
+                }
+            }
+            QueryType::SRV => {
+                want(4)?;
+                RData::SRV {
+                    priority: self.parse(&fields[0])?,
+                    weight: self.parse(&fields[1])?,
+                    port: self.parse(&fields[2])?,
+                    target: self.host(&fields[3])?,
+                }
+            }
+            QueryType::NAPTR => {
+                want(6)?;
+                RData::NAPTR {
+                    order: self.parse(&fields[0])?,
+                    preference: self.parse(&fields[1])?,
+                    flags: self.character_string(&fields[2])?,
+                    services: self.character_string(&fields[3])?,
+                    regexp: self.character_string(&fields[4])?,
+                    replacement: self.host(&fields[5])?,
+                }
+            }
+            // the digest may be split up with blanks
+            QueryType::DS if fields.len() >= 4 => RData::DS {
+                key_tag: self.parse(&fields[0])?,
+                algorithm: self.parse(&fields[1])?,
+                digest_type: self.parse(&fields[2])?,
+                digest: Hex(self.hex(&fields[3..].concat())?),
+            },
+            QueryType::DS => {
+                return Err(self.error(format!(
+                    "DS records take at least 4 fields, not {}",
+                    fields.len()
+                )));
+            }
+            other => {
+                return Err(self.error(format!(
+                    "can't read {other:?} records other than as \\# and hex"
+                )));
+            }
+        };
+        Ok(rdata)
+    }
+
+    // \# LENGTH HEX...
+    fn unknown(&self, fields: &[String]) -> Result<RData, ZoneError> {
+        let len: usize = self.parse(fields.get(1).map_or("", |f| f.as_str()))?;
+        let bytes = self.hex(&fields[2.min(fields.len())..].concat())?;
+        if bytes.len() != len {
+            return Err(self.error(format!("{} bytes where {len} were said", bytes.len())));
+        }
+        Ok(RData::Unknown { bytes })
+    }
+
+    fn hex(&self, hex: &str) -> Result<Vec<u8>, ZoneError> {
+        if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(self.error(format!("{hex:?} isn't hex")));
+        }
+        Ok((0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect())
+    }
+
+    // The bytes of a <character-string>, quoted or not: `\X` stands for X,
+    // and `\DDD` for the byte whose value DDD is in decimal (RFC 1035 section
+    // 5.1).
+    fn character_string(&self, field: &str) -> Result<Vec<u8>, ZoneError> {
+        let mut bytes = vec![];
+        let mut rest = field.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            rest = tail;
+            if b != b'\\' {
+                bytes.push(b);
+                continue;
+            }
+            match rest {
+                [
+                    d1 @ b'0'..=b'9',
+                    d2 @ b'0'..=b'9',
+                    d3 @ b'0'..=b'9',
+                    tail @ ..,
+                ] => {
+                    let digits = [*d1, *d2, *d3];
+                    bytes.push(self.parse(std::str::from_utf8(&digits).unwrap())?);
+                    rest = tail;
+                }
+                [c, tail @ ..] => {
+                    bytes.push(*c);
+                    rest = tail;
+                }
+                [] => return Err(self.error(format!("{field:?} ends in a lone \\"))),
+            }
+        }
+        if bytes.len() > 255 {
+            return Err(self.error(format!("{field:?} is longer than 255 bytes")));
+        }
+        Ok(bytes)
+    }
+
+    fn one(
+        &self,
+        mut tokens: impl Iterator<Item = String>,
+        what: &str,
+    ) -> Result<String, ZoneError> {
+        match (tokens.next(), tokens.next()) {
+            (Some(token), None) => Ok(token),
+            _ => Err(self.error(format!("{what} takes exactly one argument"))),
+        }
+    }
+
+    fn parse<T: FromStr>(&self, s: &str) -> Result<T, ZoneError> {
+        s.parse()
+            .map_err(|_| self.error(format!("can't read {s:?}")))
+    }
+
+    // Relative names are relative to the origin, with @ being the origin
+    // itself.
+    fn name(&self, name: &str) -> Result<String, ZoneError> {
+        if name == "@" {
+            return Ok(self.origin.clone());
+        }
+        if let Some(absolute) = name.strip_suffix('.') {
+            return Ok(absolute.to_string());
+        }
+        if name.is_empty() || name.split('.').any(str::is_empty) {
+            return Err(self.error(format!("{name:?} has an empty label")));
+        }
+        if self.origin.is_empty() {
+            return Ok(name.to_string());
+        }
+        Ok(format!("{name}.{}", self.origin))
+    }
+
+    // A name in a record's data, as `name` makes it, that has to be one that
+    // can go on the wire.
+    fn host(&self, name: &str) -> Result<DnsName, ZoneError> {
+        let host = self.name(name)?;
+        DnsName::new(&host).map_err(|e| self.error(format!("{host:?}: {e}")))
+    }
+
+    // In seconds, or with units as in 1h30m (as BIND takes them).
+    fn ttl(&self, s: &str) -> Result<u32, ZoneError> {
+        if let Ok(secs) = s.parse() {
+            return Ok(secs);
+        }
+        let bad = || self.error(format!("{s:?} isn't a valid TTL"));
+        let mut total: u32 = 0;
+        let mut num = String::new();
+        for c in s.chars() {
+            if c.is_ascii_digit() {
+                num.push(c);
+                continue;
+            }
+            let unit = match c.to_ascii_lowercase() {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                'w' => 7 * 24 * 60 * 60,
+                _ => return Err(bad()),
+            };
+            let n: u32 = num.parse().map_err(|_| bad())?;
+            total = n
+                .checked_mul(unit)
+                .and_then(|n| total.checked_add(n))
+                .ok_or_else(bad)?;
+            num.clear();
+        }
+        if !num.is_empty() {
+            return Err(bad());
+        }
+        Ok(total)
+    }
+}
+
+// "example.com." and "example.com" alike.
+fn absolute(name: &str) -> &str {
+    name.strip_suffix('.').unwrap_or(name)
+}
+
+// Splits `text` into entries, dropping comments and joining the lines that
+// parentheses span.
+fn entries(text: &str) -> Result<Vec<Entry>, ZoneError> {
+    let mut entries = vec![];
+    let mut tokens = vec![];
+    let mut open = 0;
+    let mut start = 0;
+    let mut same_owner = false;
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |msg: &str| ZoneError {
+            line: i + 1,
+            msg: msg.to_string(),
+        };
+        if open == 0 {
+            start = i + 1;
+            same_owner = line.starts_with([' ', '\t']);
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => break,
+                '(' => open += 1,
+                ')' if open == 0 => return Err(error("unmatched )")),
+                ')' => open -= 1,
+                c if c.is_whitespace() => {}
+                '"' => {
+                    let mut token = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            // left for whoever reads the string to decode
+                            Some('\\') => {
+                                token.push('\\');
+                                token.extend(chars.next());
+                            }
+                            Some(c) => token.push(c),
+                            None => return Err(error("unterminated string")),
+                        }
+                    }
+                    tokens.push(token);
+                }
+                c => {
+                    let mut token = c.to_string();
+                    // an escaped character never ends the token
+                    if c == '\\' {
+                        token.extend(chars.next());
+                    }
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || "();\"".contains(c) {
+                            break;
+                        }
+                        token.push(c);
+                        chars.next();
+                        if c == '\\' {
+                            token.extend(chars.next());
+                        }
+                    }
+                    tokens.push(token);
+                }
+            }
+        }
+
+        if open == 0 && !tokens.is_empty() {
+            entries.push(Entry {
+                line: start,
+                same_owner,
+                tokens: std::mem::take(&mut tokens),
+            });
+        }
+    }
+
+    if open > 0 {
+        return Err(ZoneError {
+            line: start,
+            msg: "unclosed (".to_string(),
+        });
+    }
+    Ok(entries)
+}
+
+impl fmt::Display for ZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+impl std::error::Error for ZoneError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@       IN  SOA ns1 hostmaster (
+                2024010101 ; serial
+                3600       ; refresh
+                15m        ; retry
+                1w         ; expire
+                300 )      ; minimum
+        IN  NS  ns1
+        IN  NS  ns2.example.net.
+        IN  MX  10 mail
+ns1     IN  A   192.0.2.1
+www 60      A   192.0.2.2
+            AAAA 2001:db8::2
+txt     TXT "hello world" "say \"hi\"" ; and a comment
+_sip._tcp SRV 10 60 5060 sip
+$ORIGIN sub
+host    CNAME www.example.com.
+opaque  TYPE731 \# 4 0a00 0001
+"#;
+
+    #[test]
+    fn parse_zone_file() {
+        let zone = Zone::parse(EXAMPLE, "example.com").unwrap();
+        assert_eq!(zone.origin(), "example.com");
+        let records = zone.records();
+        let owners: Vec<_> = records.iter().map(|r| r.domain.as_str()).collect();
+        assert_eq!(
+            owners,
+            [
+                "example.com",
+                "example.com",
+                "example.com",
+                "example.com",
+                "ns1.example.com",
+                "www.example.com",
+                "www.example.com",
+                "txt.example.com",
+                "_sip._tcp.example.com",
+                "host.sub.example.com",
+                "opaque.sub.example.com",
+            ]
+        );
+
+        assert_eq!(
+            records[0].rdata,
+            RData::SOA {
+                mname: "ns1.example.com".parse().unwrap(),
+                rname: "hostmaster.example.com".parse().unwrap(),
+                serial: 2024010101,
+                refresh: 3600,
+                retry: 900,
+                expire: 604800,
+                minimum: 300,
+            }
+        );
+        assert_eq!(records[0].ttl, 3600);
+        assert_eq!(
+            records[2].rdata,
+            RData::NS {
+                host: "ns2.example.net".parse().unwrap()
+            }
+        );
+        assert_eq!(records[5].ttl, 60);
+        // what $TTL says rather than the TTL before
+        assert_eq!(records[6].ttl, 3600);
+        assert_eq!(records[6].r#type, QueryType::AAAA);
+        assert_eq!(
+            records[7].rdata,
+            RData::TXT {
+                strings: vec![b"hello world".to_vec(), b"say \"hi\"".to_vec()]
+            }
+        );
+        assert_eq!(
+            records[8].rdata,
+            RData::SRV {
+                priority: 10,
+                weight: 60,
+                port: 5060,
+                target: "sip.example.com".parse().unwrap()
+            }
+        );
+        assert_eq!(
+            records[10].rdata,
+            RData::Unknown {
+                bytes: vec![10, 0, 0, 1]
+            }
+        );
+    }
+
+    #[test]
+    fn zone_errors() {
+        let error = |text: &str| Zone::parse(text, "example.com").unwrap_err();
+
+        assert_eq!(error("www A 192.0.2.1").line, 1);
+        assert_eq!(error("$TTL 60\nwww A 192.0.2.300").line, 2);
+        assert_eq!(error("$TTL 60\n\nwww MX 10").line, 3);
+        assert_eq!(error("$TTL 60\n@ SOA ns1 hostmaster (\n1 2 3 4 5").line, 2);
+        assert_eq!(error("$TTL 60\n  A 192.0.2.1").line, 2);
+        assert_eq!(error("$TTL 60\nwww BOGUS x").line, 2);
+        assert_eq!(error("$TTL 60\nwww TXT \"open").line, 2);
+        assert_eq!(error("$INCLUDE other.zone").line, 1);
+        assert_eq!(error("$TTL 60\na..b A 192.0.2.1").line, 2);
+        assert_eq!(error("$TTL 60\nx TYPE99 \\# 2 00").line, 2);
+        assert_eq!(error("$TTL 60\nx TXT \"\\256\"").line, 2);
+        let long = "a".repeat(64);
+        assert_eq!(error(&format!("$TTL 60\nwww CNAME {long}.")).line, 2);
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let strings = vec![
+            b"tab\tand \"quotes\" \\ ; ( )".to_vec(),
+            vec![0, 1, 0x7f, 0x80, 0xff],
+            b"".to_vec(),
+        ];
+        let record = DnsRecord {
+            domain: "txt.example.com".parse().unwrap(),
+            r#type: QueryType::TXT,
+            class: 1,
+            ttl: 60,
+            rdata: RData::TXT { strings },
+        };
+        let zone = Zone::parse(&record.to_string(), "example.com").unwrap();
+        assert_eq!(zone.records(), [record]);
+
+        // escapes outside quotes too
+        let zone = Zone::parse("x 60 TXT a\\032b\\;c \\\"", "example.com").unwrap();
+        assert_eq!(
+            zone.records()[0].rdata,
+            RData::TXT {
+                strings: vec![b"a b;c".to_vec(), b"\"".to_vec()]
+            }
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{BoxError, Event};
+use serde_json::{json, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+// An append-only file that is moved aside to `<path>.1` once it grows past
+// `max_bytes`, shifting older ones along up to `<path>.<keep>`.
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    len: u64,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(path: &Path, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            keep,
+            file,
+            len,
+        })
+    }
+
+    // Appends `record` as one line of JSON.
+    pub(crate) fn write(&mut self, record: &Value) -> io::Result<()> {
+        let mut line = record.to_string();
+        line.push('\n');
+        if self.len > 0 && self.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match fs::rename(rotated(n), rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        *self = Self::open(&self.path, self.max_bytes, self.keep)?;
+        Ok(())
+    }
+}
+
+fn now_ms() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    since_epoch.as_millis() as u64
+}
+
+// Who connected, who was turned away and what they did, without the content of
+// their messages.
+pub(crate) struct AuditLog(Option<Mutex<RotatingFile>>);
+
+impl AuditLog {
+    pub(crate) fn disabled() -> Self {
+        Self(None)
+    }
+
+    pub(crate) fn to_file(file: RotatingFile) -> Self {
+        Self(Some(Mutex::new(file)))
+    }
+
+    // Records `event` by `addr`, with the entries of `details` (an object)
+    // alongside.
+    pub(crate) fn record(&self, event: &str, addr: SocketAddr, details: Value) {
+        let Some(file) = &self.0 else {
+            return;
+        };
+        let mut record = json!({ "time_ms": now_ms(), "event": event, "addr": addr });
+        if let (Some(record), Value::Object(details)) = (record.as_object_mut(), details) {
+            record.extend(details);
+        }
+        if let Err(e) = file.lock().unwrap().write(&record) {
+            eprintln!("could not write to the audit log: {e}");
+        }
+    }
+}
+
+// Writes every chat message, edit and deletion to `file`, for deployments that
+// must keep a transcript.
+pub(crate) async fn log_messages(
+    mut file: RotatingFile,
+    mut bcast_rx: Receiver<Event>,
+) -> Result<(), BoxError> {
+    loop {
+        let record = match bcast_rx.recv().await {
+            Ok(Event::Message { id, from, text, .. }) => {
+                json!({ "time_ms": now_ms(), "id": id, "from": from, "text": text })
+            }
+            Ok(Event::Edited { id, by, text }) => {
+                json!({ "time_ms": now_ms(), "id": id, "from": by, "text": text, "edited": true })
+            }
+            Ok(Event::Deleted { id, by }) => {
+                json!({ "time_ms": now_ms(), "id": id, "from": by, "deleted": true })
+            }
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => {
+                eprintln!("message log fell behind, dropped {n} messages");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        if let Err(e) = file.write(&record) {
+            eprintln!("could not write to the message log: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chat-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        let text = fs::read_to_string(path).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn rotates_when_full() {
+        let dir = scratch_dir("rotate");
+        let path = dir.join("audit.log");
+        // each record is 8 bytes with its newline
+        let mut file = RotatingFile::open(&path, 20, 2).unwrap();
+        for n in 0..7 {
+            file.write(&json!({ "n": n })).unwrap();
+        }
+
+        assert_eq!(lines(&path), [r#"{"n":6}"#]);
+        assert_eq!(
+            lines(&dir.join("audit.log.1")),
+            [r#"{"n":4}"#, r#"{"n":5}"#]
+        );
+        assert_eq!(
+            lines(&dir.join("audit.log.2")),
+            [r#"{"n":2}"#, r#"{"n":3}"#]
+        );
+        assert!(!dir.join("audit.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reopening_appends() {
+        let dir = scratch_dir("reopen");
+        let path = dir.join("audit.log");
+        RotatingFile::open(&path, 1024, 1)
+            .unwrap()
+            .write(&json!(1))
+            .unwrap();
+        RotatingFile::open(&path, 1024, 1)
+            .unwrap()
+            .write(&json!(2))
+            .unwrap();
+
+        assert_eq!(lines(&path), ["1", "2"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
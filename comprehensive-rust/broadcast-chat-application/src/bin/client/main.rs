@@ -0,0 +1,262 @@
+use clap::Parser;
+use futures_util::stream::StreamExt;
+use futures_util::SinkExt;
+use http::Uri;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tokio_websockets::{ClientBuilder, Connector, MaybeTlsStream, Message, WebSocketStream};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+#[derive(Parser)]
+struct Args {
+    /// Chat server to connect to; wss:// for one behind TLS
+    #[clap(long, env = "CHAT_SERVER", default_value = "ws://127.0.0.1:2000")]
+    server: Uri,
+
+    /// Your name, which rings the bell like --mention does
+    #[clap(long, env = "CHAT_USERNAME")]
+    username: Option<String>,
+
+    /// PEM file of CA certificates to trust for wss:// besides the public
+    /// ones, e.g. for a deployment with a self-signed certificate
+    #[clap(long, env = "CHAT_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+
+    /// Send the lines read from stdin, then exit instead of chatting interactively
+    #[clap(long)]
+    once: bool,
+
+    /// With --once, wait for the server to close the connection after reading
+    /// our messages before exiting
+    #[clap(long, requires = "once")]
+    wait_ack: bool,
+
+    /// Ring the terminal bell when a message mentions this word (e.g. your
+    /// name, with or without a leading @); may be repeated
+    #[clap(long = "mention")]
+    mentions: Vec<String>,
+
+    /// Also show a desktop notification for mentions
+    #[cfg(feature = "notify")]
+    #[clap(long)]
+    notify: bool,
+
+    /// Print messages line by line instead of using the full-screen interface
+    #[cfg(feature = "tui")]
+    #[clap(long)]
+    simple: bool,
+}
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Trusts the public web roots, and the certificates in `ca_cert` besides.
+fn tls_connector(ca_cert: Option<&Path>) -> io::Result<Connector> {
+    let mut roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    if let Some(path) = ca_cert {
+        for cert in CertificateDer::pem_file_iter(path).map_err(io::Error::other)? {
+            roots
+                .add(cert.map_err(io::Error::other)?)
+                .map_err(io::Error::other)?;
+        }
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Connector::Rustls(TlsConnector::from(Arc::new(config))))
+}
+
+fn mentions(text: &str, words: &[String]) -> bool {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_' && c != '-')
+        .any(|token| words.iter().any(|w| token.eq_ignore_ascii_case(w)))
+}
+
+// Splits a "[#<id>] <text>" message, or a "[#<id>] [re #<id>] <text>"
+// reply, into its ID, the ID of the message it replies to, and its text.
+fn parse_message(text: &str) -> Option<(u64, Option<u64>, &str)> {
+    let (id, text) = text.strip_prefix("[#")?.split_once("] ")?;
+    let id = id.parse().ok()?;
+    let reply = text.strip_prefix("[re #").and_then(|r| r.split_once("] "));
+    match reply.and_then(|(re, reply)| Some((re.parse().ok()?, reply))) {
+        Some((re, reply)) => Some((id, Some(re), reply)),
+        None => Some((id, None, text)),
+    }
+}
+
+// How a message from someone else is shown.
+fn show_message(id: u64, reply_to: Option<u64>, text: &str) -> String {
+    match reply_to {
+        Some(re) => format!("  ↳ #{id} re #{re}: {text}"),
+        None => format!("#{id}: {text}"),
+    }
+}
+
+// Splits a "[edit #<id>] <text>" or "[delete #<id>]" line into the ID of the
+// message changed and its new text, None when it was deleted.
+fn parse_update(text: &str) -> Option<(u64, Option<&str>)> {
+    if let Some(id) = text.strip_prefix("[delete #") {
+        return Some((id.strip_suffix(']')?.parse().ok()?, None));
+    }
+    let (id, text) = text.strip_prefix("[edit #")?.split_once("] ")?;
+    Some((id.parse().ok()?, Some(text)))
+}
+
+// How a change to a message is shown when it can't be made in place.
+fn show_update(id: u64, text: Option<&str>) -> String {
+    match text {
+        Some(text) => format!("#{id} (edited): {text}"),
+        None => format!("#{id} (deleted)"),
+    }
+}
+
+// Splits a "[sent #<id> at <ms>] <text>" echo of our own message into its
+// parts.
+fn parse_sent(text: &str) -> Option<(u64, u64, &str)> {
+    let (id, rest) = text.strip_prefix("[sent #")?.split_once(" at ")?;
+    let (ms, text) = rest.split_once("] ")?;
+    Some((id.parse().ok()?, ms.parse().ok()?, text))
+}
+
+// How an echo of our own message is shown.
+fn show_sent(id: u64, sent_at_ms: u64, text: &str) -> String {
+    let secs = sent_at_ms / 1000 % 86400;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    format!("you, #{id} at {h:02}:{m:02}:{s:02} UTC: {text}")
+}
+
+// Alerts the user if `text` mentions one of `words`, and returns whether it
+// did.
+fn alert(args: &Args, words: &[String], text: &str) -> bool {
+    if !mentions(text, words) {
+        return false;
+    }
+    // most terminals only flash or badge a window that isn't focused
+    print!("\x07");
+    #[cfg(feature = "notify")]
+    if args.notify {
+        notify(text);
+    }
+    #[cfg(not(feature = "notify"))]
+    let _ = args;
+    true
+}
+
+#[cfg(feature = "notify")]
+fn notify(text: &str) {
+    let text = text.to_string();
+    // talking to the notification daemon blocks
+    tokio::task::spawn_blocking(move || {
+        let shown = notify_rust::Notification::new()
+            .summary("You were mentioned")
+            .body(&text)
+            .show();
+        if let Err(e) = shown {
+            eprintln!("could not show a notification: {e}");
+        }
+    });
+}
+
+async fn pipe(
+    mut ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    wait_ack: bool,
+) -> Result<(), tokio_websockets::Error> {
+    let stdin = tokio::io::stdin();
+    let mut stdin = BufReader::new(stdin).lines();
+
+    while let Some(msg) = stdin.next_line().await? {
+        if !msg.is_empty() {
+            ws_stream.send(Message::text(msg)).await?;
+        }
+    }
+    ws_stream.close().await?;
+
+    if wait_ack {
+        // the server only answers our close frame once it has read everything
+        // sent before it
+        let drain = async { while let Some(Ok(_)) = ws_stream.next().await {} };
+        if tokio::time::timeout(ACK_TIMEOUT, drain).await.is_err() {
+            eprintln!("no acknowledgement from server within {ACK_TIMEOUT:?}");
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), tokio_websockets::Error> {
+    let args = Args::parse();
+    let words: Vec<_> = args
+        .mentions
+        .iter()
+        .chain(&args.username)
+        .map(|w| w.trim_start_matches('@').to_string())
+        .collect();
+
+    let connector = tls_connector(args.ca_cert.as_deref())?;
+    let (mut ws_stream, _) = ClientBuilder::from_uri(args.server.clone())
+        .connector(&connector)
+        .connect()
+        .await?;
+
+    if args.once {
+        return pipe(ws_stream, args.wait_ack).await;
+    }
+    #[cfg(feature = "tui")]
+    if !args.simple {
+        return tui::run(ws_stream, |text| alert(&args, &words, text)).await;
+    }
+
+    let stdin = tokio::io::stdin();
+    let mut stdin = BufReader::new(stdin).lines();
+
+    loop {
+        tokio::select! {
+            val = ws_stream.next() => {
+                match val {
+                    Some(Ok(msg)) => {
+                        if let Some(text) = msg.as_text() {
+                            if let Some((id, ms, sent)) = parse_sent(text) {
+                                println!("{}", show_sent(id, ms, sent));
+                                continue;
+                            }
+                            if let Some((id, edited)) = parse_update(text) {
+                                println!("{}", show_update(id, edited));
+                                continue;
+                            }
+                            alert(&args, &words, text);
+                            match parse_message(text) {
+                                Some((id, re, text)) => println!("{}", show_message(id, re, text)),
+                                None => println!("Message from server: {text}"),
+                            }
+                        };
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()), // stream ended
+                }
+            }
+
+            line = stdin.next_line() => {
+                match line {
+                    Ok(None) => {},
+                    Ok(Some(msg)) => ws_stream.send(Message::text(msg)).await?,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{parse_message, parse_sent, parse_update, show_message, show_sent, show_update};
+use futures_util::stream::StreamExt;
+use futures_util::SinkExt;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::Stylize;
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_websockets::{MaybeTlsStream, Message, WebSocketStream};
+
+// What the screen shows, kept up to date from the lines the server sends.
+#[derive(Default)]
+struct Chat {
+    // and whether each mentions us
+    messages: Vec<(String, bool)>,
+    // messages we sent that the server hasn't echoed back yet, by index
+    // into `messages`; they stay as typed if it doesn't echo
+    unconfirmed: VecDeque<usize>,
+    // where each message is in `messages`, and what it replies to, for edits
+    // and deletions to change it in place
+    by_id: HashMap<u64, (usize, Option<u64>)>,
+    online: BTreeSet<String>,
+    topic: Option<String>,
+    input: String,
+}
+
+impl Chat {
+    fn send(&mut self, msg: &str) {
+        // commands besides /reply don't send a message
+        if msg.starts_with('/') && !msg.starts_with("/reply ") {
+            return;
+        }
+        self.unconfirmed.push_back(self.messages.len());
+        self.messages.push((format!("> {msg}"), false));
+    }
+
+    fn receive(&mut self, text: &str, mentioned: bool) {
+        if let Some((id, sent_at_ms, sent)) = parse_sent(text) {
+            let shown = show_sent(id, sent_at_ms, sent);
+            let i = match self.unconfirmed.pop_front() {
+                Some(i) => {
+                    self.messages[i].0 = shown;
+                    i
+                }
+                None => {
+                    self.messages.push((shown, false));
+                    self.messages.len() - 1
+                }
+            };
+            self.by_id.insert(id, (i, None));
+            return;
+        }
+
+        if let Some((id, edited)) = parse_update(text) {
+            match (self.by_id.get(&id), edited) {
+                (Some(&(i, re)), Some(edited)) => {
+                    let shown = format!("{} (edited)", show_message(id, re, edited));
+                    self.messages[i] = (shown, mentioned);
+                }
+                (Some(&(i, _)), None) => self.messages[i] = (show_update(id, None), false),
+                // from before we joined
+                (None, _) => self.messages.push((show_update(id, edited), mentioned)),
+            }
+            return;
+        }
+
+        if let Some(online) = text.strip_prefix("* online: ") {
+            self.online = online.split(", ").map(str::to_string).collect();
+        } else if let Some(addr) = notice(text, " joined") {
+            self.online.insert(addr.to_string());
+        } else if let Some(addr) = notice(text, " left") {
+            self.online.remove(addr);
+        } else if let Some(topic) = text.strip_prefix("* topic: ") {
+            self.topic = Some(topic.to_string());
+        } else if let Some((_, topic)) = text.split_once(" changed the topic to: ") {
+            self.topic = Some(topic.to_string());
+        }
+
+        let shown = match parse_message(text) {
+            Some((id, re, text)) => {
+                self.by_id.insert(id, (self.messages.len(), re));
+                show_message(id, re, text)
+            }
+            None => text.to_string(),
+        };
+        self.messages.push((shown, mentioned));
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [rooms, main, online] = Layout::horizontal([
+            Constraint::Length(20),
+            Constraint::Fill(1),
+            Constraint::Length(24),
+        ])
+        .areas(frame.area());
+        let [messages, input] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas(main);
+
+        // the server only has the one room
+        let mut room = vec![Line::from("# chat".bold())];
+        if let Some(topic) = &self.topic {
+            room.push(Line::from(topic.as_str().italic()));
+        }
+        let rooms_pane = Paragraph::new(room)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title("Rooms"));
+        frame.render_widget(rooms_pane, rooms);
+
+        // the most recent messages that fit, leaving room for the border
+        let height = messages.height.saturating_sub(2) as usize;
+        let skip = self.messages.len().saturating_sub(height);
+        let shown = self.messages[skip..].iter().map(|(text, mentioned)| {
+            let line = Line::from(text.as_str());
+            if *mentioned {
+                line.yellow().bold()
+            } else {
+                line
+            }
+        });
+        frame.render_widget(List::new(shown).block(Block::bordered()), messages);
+
+        let input_box =
+            Paragraph::new(self.input.as_str()).block(Block::bordered().title("Message"));
+        frame.render_widget(input_box, input);
+        let typed = self.input.chars().count() as u16;
+        frame.set_cursor_position(Position::new(input.x + 1 + typed, input.y + 1));
+
+        let title = format!("Online ({})", self.online.len());
+        let online_pane =
+            List::new(self.online.iter().map(String::as_str)).block(Block::bordered().title(title));
+        frame.render_widget(online_pane, online);
+    }
+}
+
+// The subject of a "* <subject><what>" line from the server.
+fn notice<'a>(text: &'a str, what: &str) -> Option<&'a str> {
+    text.strip_prefix("* ")?.strip_suffix(what)
+}
+
+// Chats full-screen until the user quits or the server hangs up. `alert` is
+// told about every message and returns whether it mentions us.
+pub(crate) async fn run(
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    alert: impl Fn(&str) -> bool,
+) -> Result<(), tokio_websockets::Error> {
+    let (event_tx, event_rx) = mpsc::channel(16);
+    // crossterm only reads events asynchronously with its event-stream feature
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if event_tx.blocking_send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let res = chat(&mut terminal, ws_stream, event_rx, alert).await;
+    ratatui::restore();
+    res
+}
+
+async fn chat(
+    terminal: &mut DefaultTerminal,
+    mut ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut event_rx: mpsc::Receiver<Event>,
+    alert: impl Fn(&str) -> bool,
+) -> Result<(), tokio_websockets::Error> {
+    let mut chat = Chat::default();
+    loop {
+        terminal.draw(|frame| chat.draw(frame))?;
+
+        tokio::select! {
+            val = ws_stream.next() => {
+                match val {
+                    Some(Ok(msg)) => {
+                        if let Some(text) = msg.as_text() {
+                            let mentioned = parse_sent(text).is_none() && alert(text);
+                            chat.receive(text, mentioned);
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                }
+            }
+
+            Some(event) = event_rx.recv() => {
+                let Event::Key(key) = event else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(());
+                    }
+                    KeyCode::Char(c) => chat.input.push(c),
+                    KeyCode::Backspace => {
+                        chat.input.pop();
+                    }
+                    KeyCode::Enter if !chat.input.is_empty() => {
+                        let msg = std::mem::take(&mut chat.input);
+                        chat.send(&msg);
+                        ws_stream.send(Message::text(msg)).await?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_presence_and_topic() {
+        let mut chat = Chat::default();
+        chat.receive("* topic: rust", false);
+        chat.receive("* online: 127.0.0.1:1, 127.0.0.1:2", false);
+        chat.receive("* 127.0.0.1:3 joined", false);
+        chat.receive("* 127.0.0.1:1 left", false);
+        chat.receive("* 127.0.0.1:2 changed the topic to: async", false);
+        chat.receive("[#4] lunch?", false);
+        chat.receive("[#5] [re #4] agreed", true);
+
+        assert_eq!(chat.topic.as_deref(), Some("async"));
+        assert_eq!(
+            chat.online.iter().collect::<Vec<_>>(),
+            ["127.0.0.1:2", "127.0.0.1:3"]
+        );
+        assert_eq!(chat.messages.len(), 7);
+        assert_eq!(chat.messages[5], ("#4: lunch?".to_string(), false));
+        assert_eq!(chat.messages[6], ("  ↳ #5 re #4: agreed".to_string(), true));
+    }
+
+    #[test]
+    fn edits_and_deletions_change_messages_in_place() {
+        let mut chat = Chat::default();
+        chat.receive("[#4] lunch?", false);
+        chat.receive("[#5] [re #4] agreed", false);
+        chat.receive("[edit #5] agreed, at noon", false);
+        chat.receive("[delete #4]", false);
+        chat.receive("[delete #2]", false);
+
+        let shown: Vec<_> = chat
+            .messages
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                "#4 (deleted)",
+                "  ↳ #5 re #4: agreed, at noon (edited)",
+                "#2 (deleted)"
+            ]
+        );
+    }
+
+    #[test]
+    fn echoes_replace_what_was_typed() {
+        let mut chat = Chat::default();
+        chat.send("one");
+        chat.send("/topic x");
+        chat.send("two");
+        chat.receive("[sent #7 at 3723000] one", false);
+
+        let shown: Vec<_> = chat
+            .messages
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect();
+        assert_eq!(shown, ["you, #7 at 01:02:03 UTC: one", "> two"]);
+    }
+}
@@ -1,63 +1,194 @@
-use futures_util::sink::SinkExt;
-use futures_util::stream::StreamExt;
-use std::error::Error;
+use broadcast_chat_application::filter::{MaxLinks, MessageFilter, ProfanityFilter};
+use broadcast_chat_application::{BoxError, Config, Server};
+use clap::Parser;
 use std::net::SocketAddr;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::broadcast::{channel, Sender};
-use tokio_websockets::{Message, ServerBuilder, WebSocketStream};
-
-async fn handle_connection(
-    addr: SocketAddr,
-    mut ws_stream: WebSocketStream<TcpStream>,
-    bcast_tx: Sender<(SocketAddr, String)>,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut bcast_rx = bcast_tx.subscribe();
-
-    // Consider it a non-recoverable error if it couldn't be read/written from/to ws_stream
-    loop {
-        tokio::select! {
-            val = ws_stream.next() => {
-                match val {
-                    Some(Ok(msg)) => {
-                        if let Some(text) = msg.as_text() {
-                            let _ = bcast_tx.send((addr, text.to_string()));
-                        };
-                    }
-                    Some(Err(e)) => return Err(e.into()),
-                    None => return Ok(()), // stream ended
-                }
-            }
-
-            val2 = bcast_rx.recv() => {
-                match val2 {
-                    Ok(msg) => {
-                        if msg.0 != addr {
-                            ws_stream.send(Message::text(msg.1)).await?;
-                        }
-                    }
-                    Err(e) => return Err(e.into()),
-                }
-            }
-        }
-    }
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser)]
+struct Args {
+    /// Address for websocket clients; may be repeated, e.g. for IPv4 and IPv6
+    #[clap(long = "ws-addr", default_value = "127.0.0.1:2000")]
+    ws_addrs: Vec<SocketAddr>,
+
+    /// Address for line-protocol clients; may be repeated
+    #[clap(long = "line-addr", default_value = "127.0.0.1:2001")]
+    line_addrs: Vec<SocketAddr>,
+
+    /// Unix socket to serve the line protocol on, for local bots
+    #[cfg(unix)]
+    #[clap(long)]
+    unix_path: Option<PathBuf>,
+
+    /// Address for the HTTP endpoints (e.g. `POST /hooks`)
+    #[clap(long)]
+    http_addr: Option<SocketAddr>,
+
+    /// Bearer token bots must present to `POST /hooks`
+    #[clap(long, env = "CHAT_HOOK_TOKEN", hide_env_values = true)]
+    hook_token: Option<String>,
+
+    /// Token that `/mod <token>` takes to make a connection a moderator
+    #[clap(long, env = "CHAT_MODERATOR_TOKEN", hide_env_values = true)]
+    moderator_token: Option<String>,
+
+    /// URL to POST every chat message to; may be repeated
+    #[clap(long = "webhook")]
+    webhooks: Vec<String>,
+
+    /// Largest accepted message (websocket frame or line), in bytes
+    #[clap(long, default_value_t = 4096)]
+    max_message_len: usize,
+
+    /// Most lines a single websocket message may contain
+    #[clap(long, default_value_t = 20)]
+    max_lines_per_message: usize,
+
+    /// File with one word per line to mask in messages
+    #[clap(long)]
+    profanity_list: Option<PathBuf>,
+
+    /// Reject messages with more links than this
+    #[clap(long)]
+    max_links: Option<usize>,
+
+    /// Number of recent messages kept for `GET /messages`
+    #[clap(long, default_value_t = 1000)]
+    history_len: usize,
+
+    /// SQLite database to keep all messages in, instead of memory
+    #[clap(long)]
+    database: Option<PathBuf>,
+
+    /// Announce joins and leaves, and tell new clients who is online
+    #[clap(long)]
+    presence: bool,
+
+    /// Send clients their own messages back, with the ID and time the server
+    /// gave them
+    #[clap(long)]
+    echo: bool,
+
+    /// Origin whose pages may connect from a browser, besides pages from the
+    /// chat's hostnames; may be repeated, `*` allows any
+    #[clap(long = "allow-origin")]
+    allowed_origins: Vec<String>,
+
+    /// Name the chat is reached by, e.g. `chat.example`, whose pages may
+    /// connect from a browser, as the chat's own page does; may be repeated.
+    /// Pages from localhost always may
+    #[clap(long = "hostname")]
+    hostnames: Vec<String>,
+
+    /// File to record connections, auth failures, moderation and commands in
+    #[clap(long)]
+    audit_log: Option<PathBuf>,
+
+    /// File to record every chat message in
+    #[clap(long)]
+    message_log: Option<PathBuf>,
+
+    /// Size in bytes at which the audit and message logs are rotated
+    #[clap(long, default_value_t = 10 << 20)]
+    log_max_bytes: u64,
+
+    /// Number of rotated logs to keep
+    #[clap(long, default_value_t = 5)]
+    log_keep: usize,
+
+    /// Messages that may queue up for one client before it is disconnected
+    /// as too slow
+    #[clap(long, default_value_t = 64)]
+    send_queue_len: usize,
+
+    /// Connections open at once; past this, new ones wait to be accepted
+    #[clap(long, default_value_t = 1024)]
+    max_connections: usize,
+
+    /// Connections open at once from one IP address; past this, new ones are
+    /// turned away
+    #[clap(long, default_value_t = 32)]
+    max_connections_per_ip: usize,
+
+    /// Seconds a websocket client has to finish its handshake
+    #[clap(long, default_value_t = 10)]
+    handshake_timeout: u64,
+
+    /// Name this server goes by among its peers (random by default)
+    #[clap(long)]
+    server_id: Option<String>,
+
+    /// Bearer token peers must present, and that is presented to them
+    #[clap(long, env = "CHAT_FEDERATION_TOKEN", hide_env_values = true)]
+    federation_token: Option<String>,
+
+    /// Websocket URI of a server to relay messages with, e.g.
+    /// `ws://chat.example:2000/federation`; may be repeated
+    #[clap(long = "peer")]
+    peers: Vec<String>,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let (bcast_tx, _) = channel(16);
-
-    let listener = TcpListener::bind("127.0.0.1:2000").await?;
-    println!("listening on port 2000");
-
-    loop {
-        let (socket, addr) = listener.accept().await?;
-        println!("New connection from {addr:?}");
-        let bcast_tx = bcast_tx.clone();
-        tokio::spawn(async move {
-            // Wrap the raw TCP stream into a websocket.
-            let (_req, ws_stream) = ServerBuilder::new().accept(socket).await?;
-
-            handle_connection(addr, ws_stream, bcast_tx).await
-        });
+async fn main() -> Result<(), BoxError> {
+    let args = Args::parse();
+
+    let mut filters: Vec<Arc<dyn MessageFilter>> = Vec::new();
+    if let Some(path) = &args.profanity_list {
+        let words = std::fs::read_to_string(path)?;
+        filters.push(Arc::new(ProfanityFilter::new(words.lines())));
+    }
+    if let Some(max) = args.max_links {
+        filters.push(Arc::new(MaxLinks { max }));
+    }
+
+    let server = Server::start(Config {
+        ws_addrs: args.ws_addrs,
+        line_addrs: args.line_addrs,
+        #[cfg(unix)]
+        unix_path: args.unix_path.clone(),
+        http_addr: args.http_addr,
+        hook_token: args.hook_token,
+        moderator_token: args.moderator_token,
+        webhooks: args.webhooks,
+        max_message_len: args.max_message_len,
+        max_lines_per_message: args.max_lines_per_message,
+        filters,
+        history_len: args.history_len,
+        database: args.database,
+        presence: args.presence,
+        echo: args.echo,
+        send_queue_len: args.send_queue_len,
+        max_connections: args.max_connections,
+        max_connections_per_ip: args.max_connections_per_ip,
+        handshake_timeout: Duration::from_secs(args.handshake_timeout),
+        allowed_origins: args.allowed_origins,
+        hostnames: args.hostnames,
+        audit_log: args.audit_log,
+        message_log: args.message_log,
+        log_max_bytes: args.log_max_bytes,
+        log_keep: args.log_keep,
+        server_id: args
+            .server_id
+            .unwrap_or_else(|| Config::default().server_id),
+        federation_token: args.federation_token,
+        peers: args.peers,
+    })
+    .await?;
+
+    for addr in server.ws_addrs() {
+        println!("listening on {addr}");
     }
+    for addr in server.line_addrs() {
+        println!("listening for line-protocol clients on {addr}");
+    }
+    #[cfg(unix)]
+    if let Some(path) = &args.unix_path {
+        println!("listening for local clients on {}", path.display());
+    }
+    if let Some(addr) = server.http_addr() {
+        println!("listening for HTTP requests on {addr}");
+    }
+
+    server.run().await
 }
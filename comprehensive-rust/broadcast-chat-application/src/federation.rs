@@ -0,0 +1,243 @@
+use crate::outbox::{Outbox, TOO_SLOW};
+use crate::{token_matches, BoxError, Event, Hub};
+use futures_util::stream::StreamExt;
+use http::header::AUTHORIZATION;
+use http::{HeaderValue, Request, Uri};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio_websockets::{ClientBuilder, CloseCode, Message, WebSocketStream};
+
+// Peers connect to this path on the websocket listener.
+pub(crate) const PATH: &str = "/federation";
+
+const REDIAL_DELAY: Duration = Duration::from_secs(5);
+// How many relayed messages are remembered to drop the ones seen before.
+const SEEN_CAPACITY: usize = 10_000;
+
+static NEXT_LINK: AtomicU64 = AtomicU64::new(0);
+
+// Where a message relayed from a peer was first sent.
+#[derive(Clone, Debug)]
+pub(crate) struct Relay {
+    // `server_id` of the server the sender is connected to, and the ID that
+    // server gave the message
+    pub(crate) server: Arc<str>,
+    pub(crate) id: u64,
+    // the link it arrived over, so it isn't sent straight back
+    link: u64,
+}
+
+// A message as known across servers: the `server_id` of the one it was first
+// sent to, and the ID it was given there.
+type GlobalId = (Arc<str>, u64);
+
+// The most recent messages relayed to this server, and the IDs they were
+// stored under here (none if a filter rejected them).
+#[derive(Default)]
+pub(crate) struct Seen {
+    order: VecDeque<GlobalId>,
+    local: HashMap<GlobalId, Option<u64>>,
+    global: HashMap<u64, GlobalId>,
+}
+
+impl Seen {
+    fn insert(&mut self, global: GlobalId, local: Option<u64>) {
+        self.order.push_back(global.clone());
+        if let Some(local) = local {
+            self.global.insert(local, global.clone());
+        }
+        self.local.insert(global, local);
+
+        if self.order.len() > SEEN_CAPACITY {
+            let oldest = self.order.pop_front().unwrap();
+            if let Some(Some(local)) = self.local.remove(&oldest) {
+                self.global.remove(&local);
+            }
+        }
+    }
+}
+
+// How a message stored here with the ID `local` is known to peers.
+fn to_global(hub: &Hub, local: u64) -> GlobalId {
+    let seen = hub.seen.lock().unwrap();
+    match seen.global.get(&local) {
+        Some(global) => global.clone(),
+        None => (hub.config.server_id.as_str().into(), local),
+    }
+}
+
+pub(crate) fn is_authorized(hub: &Hub, req: &Request<()>) -> bool {
+    let Some(token) = &hub.config.federation_token else {
+        return false;
+    };
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| token_matches(given, token))
+}
+
+// Keeps a link to the peer at `uri` open, redialing whenever it drops.
+pub(crate) async fn dial(hub: Arc<Hub>, uri: String) -> Result<(), BoxError> {
+    let uri: Uri = uri.parse()?;
+    let auth = match &hub.config.federation_token {
+        Some(token) => Some(HeaderValue::from_str(&format!("Bearer {token}"))?),
+        None => None,
+    };
+
+    loop {
+        let bcast_rx = hub.bcast_tx.subscribe();
+        let mut builder = ClientBuilder::from_uri(uri.clone());
+        if let Some(auth) = &auth {
+            builder = builder.add_header(AUTHORIZATION, auth.clone())?;
+        }
+        match builder.connect().await {
+            Ok((ws_stream, _)) => {
+                println!("linked to peer {uri}");
+                if let Err(e) = run_link(ws_stream, bcast_rx, hub.clone()).await {
+                    eprintln!("link to peer {uri} failed: {e}");
+                }
+            }
+            Err(e) => eprintln!("could not reach peer {uri}: {e}"),
+        }
+        tokio::time::sleep(REDIAL_DELAY).await;
+    }
+}
+
+// Relays chat messages both ways over an established link, in either direction
+// of dialing. What goes out is queued like for any connection, so that a slow
+// peer is dropped rather than holding up the rest of the chat.
+pub(crate) async fn run_link<S>(
+    ws_stream: WebSocketStream<S>,
+    mut bcast_rx: Receiver<Event>,
+    hub: Arc<Hub>,
+) -> Result<(), BoxError>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let link = NEXT_LINK.fetch_add(1, Ordering::Relaxed);
+    let _counted = LinkCount::new(hub.clone());
+    let (sink, mut ws_stream) = ws_stream.split();
+    let mut outbox = Outbox::spawn(sink, hub.config.send_queue_len);
+    let too_slow = || vec![Message::close(Some(CloseCode::POLICY_VIOLATION), TOO_SLOW)];
+
+    loop {
+        tokio::select! {
+            val = ws_stream.next() => {
+                match val {
+                    Some(Ok(msg)) => {
+                        if let Some(text) = msg.as_text() {
+                            receive(&hub, link, text)?;
+                        }
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                }
+            }
+
+            val = bcast_rx.recv() => {
+                let (id, from, text, reply_to, relay) = match val {
+                    Ok(Event::Message { id, from, text, reply_to, relay, .. }) => {
+                        (id, from, text, reply_to, relay)
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(n)) => {
+                        eprintln!("federation link fell behind, dropped {n} messages");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                };
+                let (origin, id) = match &relay {
+                    Some(relay) if relay.link == link => continue,
+                    Some(relay) => (&*relay.server, relay.id),
+                    None => (&*hub.config.server_id, id),
+                };
+                let reply_to = reply_to.map(|reply_to| {
+                    let (origin, id) = to_global(&hub, reply_to);
+                    json!({ "origin": &*origin, "id": id })
+                });
+                let body = json!({
+                    "origin": origin,
+                    "id": id,
+                    "from": from,
+                    "text": text,
+                    "reply_to": reply_to,
+                });
+                outbox.push(Message::text(body.to_string()), too_slow)?;
+            }
+        }
+    }
+}
+
+fn receive(hub: &Hub, link: u64, text: &str) -> Result<(), BoxError> {
+    let body: Value = serde_json::from_str(text)?;
+    let (Some(origin), Some(id), Some(from), Some(text)) = (
+        body["origin"].as_str(),
+        body["id"].as_u64(),
+        body["from"].as_str(),
+        body["text"].as_str(),
+    ) else {
+        return Err("malformed federation message".into());
+    };
+    let from: SocketAddr = from.parse()?;
+
+    // it went all the way around a loop of peers
+    if origin == hub.config.server_id {
+        return Ok(());
+    }
+    let global: GlobalId = (origin.into(), id);
+    // held until the message is stored, so that another link delivering it
+    // at the same time drops it
+    let mut seen = hub.seen.lock().unwrap();
+    if seen.local.contains_key(&global) {
+        return Ok(());
+    }
+
+    // a reply to a message this server doesn't know is shown as a plain one
+    let reply_to = match (
+        body["reply_to"]["origin"].as_str(),
+        body["reply_to"]["id"].as_u64(),
+    ) {
+        (Some(origin), Some(id)) if origin == hub.config.server_id => Some(id),
+        (Some(origin), Some(id)) => seen.local.get(&(origin.into(), id)).copied().flatten(),
+        _ => None,
+    };
+
+    let relay = Relay {
+        server: global.0.clone(),
+        id,
+        link,
+    };
+    let local = match hub.publish_relayed(from, text, reply_to, relay) {
+        Ok(local) => local,
+        Err(reason) => {
+            eprintln!("dropped message relayed from {origin}: {reason}");
+            None
+        }
+    };
+    seen.insert(global, local);
+    Ok(())
+}
+
+// Counts a link in `Server::federation_links` while it is alive.
+struct LinkCount(Arc<Hub>);
+
+impl LinkCount {
+    fn new(hub: Arc<Hub>) -> Self {
+        hub.links.fetch_add(1, Ordering::Relaxed);
+        Self(hub)
+    }
+}
+
+impl Drop for LinkCount {
+    fn drop(&mut self) {
+        self.0.links.fetch_sub(1, Ordering::Relaxed);
+    }
+}
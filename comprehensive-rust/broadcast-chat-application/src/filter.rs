@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Modify(String),
+    // the reason is reported back to the sender
+    Reject(String),
+}
+
+/// Inspects every message before it is broadcast.
+///
+/// Filters run in the order they are configured; each one sees the text as
+/// modified by the ones before it.
+pub trait MessageFilter: Debug + Send + Sync {
+    fn filter(&self, from: SocketAddr, text: &str) -> Verdict;
+}
+
+/// Masks listed words (case-insensitively, whole words only) with asterisks.
+#[derive(Debug)]
+pub struct ProfanityFilter {
+    words: HashSet<String>,
+}
+
+impl ProfanityFilter {
+    pub fn new<I, S>(words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            words: words
+                .into_iter()
+                .map(|w| w.as_ref().trim().to_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl MessageFilter for ProfanityFilter {
+    fn filter(&self, _from: SocketAddr, text: &str) -> Verdict {
+        let mut out = String::with_capacity(text.len());
+        let mut word = String::new();
+        let mut masked = false;
+
+        let mut flush = |word: &mut String, out: &mut String| {
+            if self.words.contains(&word.to_lowercase()) {
+                out.extend(word.chars().map(|_| '*'));
+                masked = true;
+            } else {
+                out.push_str(word);
+            }
+            word.clear();
+        };
+
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut out);
+                out.push(c);
+            }
+        }
+        flush(&mut word, &mut out);
+
+        if masked {
+            Verdict::Modify(out)
+        } else {
+            Verdict::Pass
+        }
+    }
+}
+
+/// Rejects messages containing more than `max` links, a cheap spam guard.
+#[derive(Debug)]
+pub struct MaxLinks {
+    pub max: usize,
+}
+
+impl MessageFilter for MaxLinks {
+    fn filter(&self, _from: SocketAddr, text: &str) -> Verdict {
+        let links = text
+            .split_whitespace()
+            .filter(|w| w.starts_with("http://") || w.starts_with("https://"))
+            .count();
+
+        if links > self.max {
+            Verdict::Reject(format!("too many links (at most {} allowed)", self.max))
+        } else {
+            Verdict::Pass
+        }
+    }
+}
+
+// Returns the text to broadcast, or why it was rejected.
+pub(crate) fn apply(
+    filters: &[Arc<dyn MessageFilter>],
+    from: SocketAddr,
+    text: String,
+) -> Result<String, String> {
+    filters
+        .iter()
+        .try_fold(text, |text, f| match f.filter(from, &text) {
+            Verdict::Pass => Ok(text),
+            Verdict::Modify(new) => Ok(new),
+            Verdict::Reject(reason) => Err(reason),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        ([127, 0, 0, 1], 1234).into()
+    }
+
+    #[test]
+    fn profanity_is_masked() {
+        let f = ProfanityFilter::new(["darn"]);
+        assert_eq!(
+            f.filter(addr(), "Darn it, darnit... darn!"),
+            Verdict::Modify("**** it, darnit... ****!".to_string())
+        );
+        assert_eq!(f.filter(addr(), "all good"), Verdict::Pass);
+    }
+
+    #[test]
+    fn too_many_links_are_rejected() {
+        let f = MaxLinks { max: 1 };
+        assert_eq!(f.filter(addr(), "see https://a.example"), Verdict::Pass);
+        assert!(matches!(
+            f.filter(addr(), "http://a.example https://b.example"),
+            Verdict::Reject(_)
+        ));
+    }
+
+    #[test]
+    fn filters_are_chained() {
+        let filters: Vec<Arc<dyn MessageFilter>> = vec![
+            Arc::new(ProfanityFilter::new(["heck"])),
+            Arc::new(MaxLinks { max: 0 }),
+        ];
+        assert_eq!(apply(&filters, addr(), "heck".into()), Ok("****".into()));
+        assert!(apply(&filters, addr(), "heck https://x".into()).is_err());
+    }
+}
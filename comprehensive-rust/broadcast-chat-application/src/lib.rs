@@ -0,0 +1,1270 @@
+use futures_util::sink::SinkExt;
+use futures_util::stream::StreamExt;
+use http::header::ORIGIN;
+use serde_json::json;
+use socket2::{Domain, Socket, Type};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::task::JoinSet;
+use tokio_websockets::{CloseCode, Limits, Message, ServerBuilder, WebSocketStream};
+
+mod audit;
+mod federation;
+pub mod filter;
+mod limits;
+mod outbox;
+mod sanitize;
+pub mod store;
+pub mod testing;
+mod web;
+mod webhooks;
+
+use audit::{AuditLog, RotatingFile};
+use federation::{Relay, Seen};
+use filter::MessageFilter;
+use limits::ConnectionLimits;
+use outbox::{Outbox, TOO_SLOW};
+pub use sanitize::sanitize;
+use store::{ChatStore, MemoryStore};
+
+pub type BoxError = Box<dyn Error + Send + Sync>;
+
+// the longest name `/nick` takes
+const MAX_NAME_LEN: usize = 32;
+
+// What travels over the broadcast bus.
+#[derive(Clone, Debug)]
+pub(crate) enum Event {
+    Message {
+        // as stored on this server
+        id: u64,
+        from: SocketAddr,
+        text: String,
+        reply_to: Option<u64>,
+        sent_at: SystemTime,
+        // set when a peer server relayed the message
+        relay: Option<Relay>,
+    },
+    // a stored message changed by its author or a moderator
+    Edited {
+        id: u64,
+        by: SocketAddr,
+        text: String,
+    },
+    Deleted {
+        id: u64,
+        by: SocketAddr,
+    },
+    Joined(SocketAddr),
+    Left(SocketAddr),
+    Topic {
+        by: SocketAddr,
+        topic: String,
+    },
+}
+
+impl Event {
+    // The local connection that caused the event; it isn't sent back to it.
+    fn origin(&self) -> Option<SocketAddr> {
+        match self {
+            Event::Message { relay: Some(_), .. } => None,
+            Event::Message { from, .. } => Some(*from),
+            // shown to whoever made the change too, to update their view
+            Event::Edited { .. } | Event::Deleted { .. } => None,
+            Event::Joined(addr) | Event::Left(addr) => Some(*addr),
+            Event::Topic { by, .. } => Some(*by),
+        }
+    }
+
+    // The text shown to clients of the plain-text protocols. Messages start
+    // with their ID, for `/reply` and `/read` to refer to, and changes to them
+    // with the ID of the message changed.
+    fn render(&self) -> String {
+        match self {
+            Event::Message {
+                id, text, reply_to, ..
+            } => format!("[#{id}] {}", body(text, *reply_to)),
+            Event::Edited { id, text, .. } => format!("[edit #{id}] {text}"),
+            Event::Deleted { id, .. } => format!("[delete #{id}]"),
+            Event::Joined(addr) => format!("* {addr} joined"),
+            Event::Left(addr) => format!("* {addr} left"),
+            Event::Topic { by, topic } => format!("* {by} changed the topic to: {topic}"),
+        }
+    }
+
+    // How the event is shown to the connection at `addr`, if at all.
+    fn render_for(&self, addr: SocketAddr, echo: bool) -> Option<String> {
+        if self.origin() != Some(addr) {
+            return Some(self.render());
+        }
+        match self {
+            Event::Message {
+                id,
+                text,
+                reply_to,
+                sent_at,
+                ..
+            } if echo => {
+                let sent_at = sent_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                let ms = sent_at.as_millis();
+                Some(format!("[sent #{id} at {ms}] {}", body(text, *reply_to)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// A message's text, marked with the ID of the one it replies to, if any.
+fn body(text: &str, reply_to: Option<u64>) -> String {
+    match reply_to {
+        Some(id) => format!("[re #{id}] {text}"),
+        None => text.to_string(),
+    }
+}
+
+// Everything the listeners and connections share.
+pub(crate) struct Hub {
+    config: Config,
+    bcast_tx: Sender<Event>,
+    store: Mutex<Box<dyn ChatStore>>,
+    roster: Mutex<BTreeSet<SocketAddr>>,
+    topic: Mutex<Option<String>>,
+    // the name each connection goes by, once it has picked one with `/nick`;
+    // read markers are kept by name, so that they outlast the connection
+    names: Mutex<HashMap<SocketAddr, String>>,
+    // connections that have shown the moderator token with `/mod`
+    moderators: Mutex<BTreeSet<SocketAddr>>,
+    seen: Mutex<Seen>,
+    links: AtomicUsize,
+    limits: ConnectionLimits,
+    audit: AuditLog,
+    // for the browser client to connect to
+    ws_port: u16,
+}
+
+// Held for as long as a connection is open, to clear up after it when dropped:
+// its name and standing are forgotten, and it is announced as gone if it was on
+// the roster.
+struct Presence {
+    hub: Arc<Hub>,
+    addr: SocketAddr,
+    on_roster: bool,
+}
+
+impl Drop for Presence {
+    fn drop(&mut self) {
+        self.hub.names.lock().unwrap().remove(&self.addr);
+        self.hub.moderators.lock().unwrap().remove(&self.addr);
+        if self.on_roster {
+            let mut roster = self.hub.roster.lock().unwrap();
+            roster.remove(&self.addr);
+            let _ = self.hub.bcast_tx.send(Event::Left(self.addr));
+        }
+    }
+}
+
+impl Hub {
+    // Handles a line of input from `addr`, which is either a command or a chat
+    // message. Returns a notice for the sender alone, or why the input was
+    // rejected.
+    fn handle_input(&self, addr: SocketAddr, text: &str) -> Result<Option<String>, String> {
+        if let Some(arg) = command(text, "/topic") {
+            self.audit
+                .record("command", addr, json!({ "command": "topic" }));
+            return Ok(Some(self.set_topic(addr, arg)));
+        }
+        if let Some(arg) = command(text, "/nick") {
+            self.audit
+                .record("command", addr, json!({ "command": "nick" }));
+            return self.set_name(addr, arg).map(Some);
+        }
+        if let Some(arg) = command(text, "/mod") {
+            return self.moderate(addr, arg).map(Some);
+        }
+        for name in ["/ban", "/unban"] {
+            if let Some(arg) = command(text, name) {
+                let details = json!({ "command": &name[1..], "target": arg.trim() });
+                self.audit.record("command", addr, details);
+                return self.ban(addr, name, arg).map(Some);
+            }
+        }
+        for name in ["/read", "/unread", "/seen"] {
+            if let Some(arg) = command(text, name) {
+                let details = json!({ "command": &name[1..] });
+                self.audit.record("command", addr, details);
+                return self.receipts(addr, name, arg).map(Some);
+            }
+        }
+        if let Some(arg) = command(text, "/reply") {
+            self.audit
+                .record("command", addr, json!({ "command": "reply" }));
+            return self.reply(addr, arg).map(|_| None);
+        }
+        if let Some(arg) = command(text, "/edit") {
+            self.audit
+                .record("command", addr, json!({ "command": "edit" }));
+            return self.edit(addr, arg).map(|_| None);
+        }
+        if let Some(arg) = command(text, "/delete") {
+            self.audit
+                .record("command", addr, json!({ "command": "delete" }));
+            return self.delete(addr, arg).map(|_| None);
+        }
+        self.publish(addr, text).map(|_| None)
+    }
+
+    fn set_topic(&self, addr: SocketAddr, arg: &str) -> String {
+        let new_topic = sanitize(arg.trim());
+        let mut topic = self.topic.lock().unwrap();
+        if new_topic.is_empty() {
+            return match &*topic {
+                Some(topic) => format!("* topic: {topic}"),
+                None => "* no topic is set".to_string(),
+            };
+        }
+
+        *topic = Some(new_topic.clone());
+        let _ = self.bcast_tx.send(Event::Topic {
+            by: addr,
+            topic: new_topic,
+        });
+        "* topic changed".to_string()
+    }
+
+    // `/nick <name>` picks the name to keep read markers under; several
+    // connections may share one, as the same user's devices would.
+    fn set_name(&self, addr: SocketAddr, arg: &str) -> Result<String, String> {
+        let name = arg.trim();
+        let mut names = self.names.lock().unwrap();
+        if name.is_empty() {
+            return Ok(match names.get(&addr) {
+                Some(name) => format!("* you are {name}"),
+                None => "* you have no name yet".to_string(),
+            });
+        }
+        let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+        if name.len() > MAX_NAME_LEN || !name.chars().all(allowed) {
+            return Err(format!(
+                "names are up to {MAX_NAME_LEN} letters, digits, '-', '_' or '.'"
+            ));
+        }
+        if let Err(e) = self.store.lock().unwrap().add_user(name) {
+            eprintln!("could not store the user {name}: {e}");
+            return Err("the server could not store the name".to_string());
+        }
+        names.insert(addr, name.to_string());
+        Ok(format!("* you are now {name}"))
+    }
+
+    // `/mod <token>` makes the connection a moderator, if the token is the
+    // one configured.
+    fn moderate(&self, addr: SocketAddr, arg: &str) -> Result<String, String> {
+        let matches = self
+            .config
+            .moderator_token
+            .as_deref()
+            .is_some_and(|token| token_matches(arg.trim(), token));
+        if !matches {
+            let details = json!({ "command": "mod" });
+            self.audit.record("auth_failure", addr, details);
+            return Err("wrong moderator token".to_string());
+        }
+        self.audit
+            .record("command", addr, json!({ "command": "mod" }));
+        self.moderators.lock().unwrap().insert(addr);
+        Ok("* you are now a moderator".to_string())
+    }
+
+    fn is_moderator(&self, addr: SocketAddr) -> bool {
+        self.moderators.lock().unwrap().contains(&addr)
+    }
+
+    // `/ban <ip>` turns away connections and messages from an address, and
+    // `/unban <ip>` lets it back; moderators only. Without an address, both
+    // list those banned.
+    fn ban(&self, addr: SocketAddr, name: &str, arg: &str) -> Result<String, String> {
+        if !self.is_moderator(addr) {
+            return Err("only moderators may ban".to_string());
+        }
+        let failed = |e: BoxError| {
+            eprintln!("could not update the bans: {e}");
+            "the server could not update the bans".to_string()
+        };
+        let mut store = self.store.lock().unwrap();
+        let arg = arg.trim();
+        if arg.is_empty() {
+            let bans: Vec<_> = store
+                .bans()
+                .map_err(failed)?
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect();
+            if bans.is_empty() {
+                return Ok("* nobody is banned".to_string());
+            }
+            return Ok(format!("* banned: {}", bans.join(", ")));
+        }
+        let ip: IpAddr = arg.parse().map_err(|_| format!("usage: {name} <ip>"))?;
+        if name == "/ban" {
+            return Ok(match store.ban(ip).map_err(failed)? {
+                true => format!("* banned {ip}"),
+                false => format!("* {ip} was already banned"),
+            });
+        }
+        Ok(match store.unban(ip).map_err(failed)? {
+            true => format!("* unbanned {ip}"),
+            false => format!("* {ip} wasn't banned"),
+        })
+    }
+
+    // Whether `ip` is banned; a store that can't say lets it through.
+    fn is_banned(&self, ip: IpAddr) -> bool {
+        match self.store.lock().unwrap().is_banned(ip) {
+            Ok(banned) => banned,
+            Err(e) => {
+                eprintln!("could not look up the bans: {e}");
+                false
+            }
+        }
+    }
+
+    // `/read <id>` reports having read everything up to `id`, `/unread` counts
+    // the messages since, and `/seen <id>` tells who has read `id`. Reading
+    // takes a name from `/nick`, which the markers are kept under.
+    fn receipts(&self, addr: SocketAddr, name: &str, arg: &str) -> Result<String, String> {
+        let user = self.names.lock().unwrap().get(&addr).cloned();
+        let store = &mut *self.store.lock().unwrap();
+        let latest = match store.page(None, 1) {
+            Ok(page) => page.last().map_or(0, |m| m.id),
+            Err(e) => {
+                eprintln!("could not load the latest message: {e}");
+                return Err("the server could not load the messages".to_string());
+            }
+        };
+        let markers = match store.read_markers() {
+            Ok(markers) => markers,
+            Err(e) => {
+                eprintln!("could not load the read markers: {e}");
+                return Err("the server could not load the read markers".to_string());
+            }
+        };
+        let need_name = || "pick a name with /nick <name> first".to_string();
+
+        if name == "/unread" {
+            let user = user.ok_or_else(need_name)?;
+            let read = markers.get(&user).copied().unwrap_or(0);
+            return Ok(format!("* {} unread", latest.saturating_sub(read)));
+        }
+
+        let usage = || format!("usage: {name} <id>");
+        let id: u64 = arg
+            .trim()
+            .trim_start_matches('#')
+            .parse()
+            .map_err(|_| usage())?;
+        if id == 0 || id > latest {
+            return Err(format!("no message #{id}"));
+        }
+        if name == "/read" {
+            let user = user.ok_or_else(need_name)?;
+            return match store.mark_read(&user, id) {
+                Ok(marker) => Ok(format!("* read up to #{marker}")),
+                Err(e) => {
+                    eprintln!("could not mark {user} as having read #{id}: {e}");
+                    Err("the server could not store the read marker".to_string())
+                }
+            };
+        }
+        let seen_by: Vec<_> = markers
+            .iter()
+            .filter(|&(_, &marker)| marker >= id)
+            .map(|(user, _)| user.as_str())
+            .collect();
+        if seen_by.is_empty() {
+            return Ok(format!("* #{id} not seen yet"));
+        }
+        Ok(format!("* #{id} seen by: {}", seen_by.join(", ")))
+    }
+
+    // `/reply <id> <text>`, where the ID may be written as `#<id>`.
+    fn reply(&self, addr: SocketAddr, arg: &str) -> Result<(), String> {
+        let usage = || "usage: /reply <id> <text>".to_string();
+        let (id, text) = arg.trim_start().split_once(' ').ok_or_else(usage)?;
+        let id: u64 = id.trim_start_matches('#').parse().map_err(|_| usage())?;
+
+        match self.store.lock().unwrap().get(id) {
+            Ok(Some(m)) if !m.deleted => {}
+            Ok(_) => return Err(format!("no message #{id}")),
+            Err(e) => {
+                eprintln!("could not load message #{id}: {e}");
+                return Err("the server could not find the message".to_string());
+            }
+        }
+        self.publish_inner(addr, text, Some(id), None).map(|_| ())
+    }
+
+    // `/edit <id> <text>` replaces the text of a message, which is vetted as a
+    // new one would be.
+    fn edit(&self, addr: SocketAddr, arg: &str) -> Result<(), String> {
+        let usage = || "usage: /edit <id> <text>".to_string();
+        let (id, text) = arg.trim_start().split_once(' ').ok_or_else(usage)?;
+        let id: u64 = id.trim_start_matches('#').parse().map_err(|_| usage())?;
+        let text = self.vet(addr, text, false)?;
+        if text.is_empty() {
+            return Err(usage());
+        }
+
+        // hold the lock while sending so the bus order matches the store's
+        let mut store = self.store.lock().unwrap();
+        self.may_change(&**store, addr, id)?;
+        if let Err(e) = store.edit(id, &text) {
+            eprintln!("could not edit message #{id}: {e}");
+            return Err("the server could not store the edit".to_string());
+        }
+        let _ = self.bcast_tx.send(Event::Edited { id, by: addr, text });
+        Ok(())
+    }
+
+    // `/delete <id>` takes a message back, leaving a gap where it was.
+    fn delete(&self, addr: SocketAddr, arg: &str) -> Result<(), String> {
+        let usage = || "usage: /delete <id>".to_string();
+        let id = arg.trim().trim_start_matches('#');
+        let id: u64 = id.parse().map_err(|_| usage())?;
+
+        let mut store = self.store.lock().unwrap();
+        self.may_change(&**store, addr, id)?;
+        if let Err(e) = store.delete(id) {
+            eprintln!("could not delete message #{id}: {e}");
+            return Err("the server could not delete the message".to_string());
+        }
+        let _ = self.bcast_tx.send(Event::Deleted { id, by: addr });
+        Ok(())
+    }
+
+    // Whether `addr` may edit or delete message `id`: the connection that
+    // sent it may, and so may moderators.
+    fn may_change(&self, store: &dyn ChatStore, addr: SocketAddr, id: u64) -> Result<(), String> {
+        let message = match store.get(id) {
+            Ok(Some(m)) if !m.deleted => m,
+            Ok(_) => return Err(format!("no message #{id}")),
+            Err(e) => {
+                eprintln!("could not load message #{id}: {e}");
+                return Err("the server could not find the message".to_string());
+            }
+        };
+        if message.from != addr && !self.is_moderator(addr) {
+            return Err("only its author or a moderator may change a message".to_string());
+        }
+        Ok(())
+    }
+
+    // Sanitizes and filters a message from `addr`, then records and
+    // broadcasts it. Returns the reason if a filter rejected it.
+    fn publish(&self, addr: SocketAddr, text: &str) -> Result<(), String> {
+        self.publish_inner(addr, text, None, None).map(|_| ())
+    }
+
+    // Like `publish`, for a message a peer server relayed; peers are trusted
+    // no more than clients. Returns the ID the message was stored under.
+    fn publish_relayed(
+        &self,
+        from: SocketAddr,
+        text: &str,
+        reply_to: Option<u64>,
+        relay: Relay,
+    ) -> Result<Option<u64>, String> {
+        self.publish_inner(from, text, reply_to, Some(relay))
+    }
+
+    fn publish_inner(
+        &self,
+        addr: SocketAddr,
+        text: &str,
+        reply_to: Option<u64>,
+        relay: Option<Relay>,
+    ) -> Result<Option<u64>, String> {
+        let text = self.vet(addr, text, relay.is_some())?;
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        // hold the lock while sending so the bus order matches the IDs
+        let mut store = self.store.lock().unwrap();
+        let id = match store.append(addr, &text, reply_to) {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("could not store message from {addr:?}: {e}");
+                return Err("the server could not store the message".to_string());
+            }
+        };
+        let _ = self.bcast_tx.send(Event::Message {
+            id,
+            from: addr,
+            text,
+            reply_to,
+            sent_at: SystemTime::now(),
+            relay,
+        });
+        Ok(Some(id))
+    }
+
+    // Sanitizes and filters the text of a message from `addr`, returning what
+    // is left of it, which may be nothing, or why it was rejected.
+    fn vet(&self, addr: SocketAddr, text: &str, relayed: bool) -> Result<String, String> {
+        let text = sanitize(text);
+        if text.is_empty() {
+            return Ok(text);
+        }
+        // relayed messages come from addresses on other servers
+        if !relayed && self.is_banned(addr.ip()) {
+            let reason = "you are banned";
+            let details = json!({ "reason": reason });
+            self.audit.record("message_rejected", addr, details);
+            return Err(reason.to_string());
+        }
+        if sanitize::forges_marker(&text) {
+            let reason = "lines may not start with a marker the server puts there, like \"[#\"";
+            let details = json!({ "reason": reason });
+            self.audit.record("message_rejected", addr, details);
+            return Err(reason.to_string());
+        }
+        match filter::apply(&self.config.filters, addr, text.clone()) {
+            Ok(filtered) => {
+                if filtered != text {
+                    self.audit.record("message_modified", addr, json!({}));
+                }
+                Ok(filtered)
+            }
+            Err(reason) => {
+                let details = json!({ "reason": reason });
+                self.audit.record("message_rejected", addr, details);
+                Err(reason)
+            }
+        }
+    }
+
+    // Puts `addr` on the roster (when presence is enabled) and announces it to
+    // everyone else. Returns the lines to greet the new connection with.
+    fn join(self: &Arc<Self>, addr: SocketAddr) -> (Presence, Vec<String>) {
+        let mut greeting = Vec::new();
+        if let Some(topic) = &*self.topic.lock().unwrap() {
+            greeting.push(format!("* topic: {topic}"));
+        }
+
+        let mut presence = Presence {
+            hub: self.clone(),
+            addr,
+            on_roster: false,
+        };
+        if !self.config.presence {
+            return (presence, greeting);
+        }
+
+        let mut roster = self.roster.lock().unwrap();
+        roster.insert(addr);
+        let _ = self.bcast_tx.send(Event::Joined(addr));
+
+        let online: Vec<_> = roster.iter().map(|a| a.to_string()).collect();
+        greeting.push(format!("* online: {}", online.join(", ")));
+        presence.on_roster = true;
+        (presence, greeting)
+    }
+}
+
+// The argument to `name` if `text` invokes that command, e.g. "x" for
+// ("/topic x", "/topic"), but not for ("/topical", "/topic").
+fn command<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let arg = text.strip_prefix(name)?;
+    if arg.is_empty() || arg.starts_with(' ') {
+        Some(arg)
+    } else {
+        None
+    }
+}
+
+// Whether `given` is `token`, taking as long to say no however much of it
+// matches, so that a token can't be guessed a byte at a time.
+pub(crate) fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn handle_connection(
+    addr: SocketAddr,
+    ws_stream: WebSocketStream<TcpStream>,
+    mut bcast_rx: Receiver<Event>,
+    hub: Arc<Hub>,
+) -> Result<(), BoxError> {
+    let (sink, mut ws_stream) = ws_stream.split();
+    let mut outbox = Outbox::spawn(sink, hub.config.send_queue_len);
+    let too_slow = || {
+        vec![
+            Message::text(format!("error: {TOO_SLOW}, disconnecting")),
+            Message::close(Some(CloseCode::POLICY_VIOLATION), TOO_SLOW),
+        ]
+    };
+
+    let (_presence, greeting) = hub.join(addr);
+    for line in greeting {
+        outbox.push(Message::text(line), too_slow)?;
+    }
+
+    // Consider it a non-recoverable error if it couldn't be read from ws_stream
+    loop {
+        tokio::select! {
+            // oversized frames are rejected by the codec itself, see `Limits`
+            val = ws_stream.next() => {
+                match val {
+                    Some(Ok(msg)) => {
+                        if let Some(text) = msg.as_text() {
+                            if text.lines().count() > hub.config.max_lines_per_message {
+                                let reason = "too many lines in one message";
+                                let close = Message::close(Some(CloseCode::POLICY_VIOLATION), reason);
+                                outbox.evict(vec![close]);
+                                return Err(reason.into());
+                            }
+                            let reply = match hub.handle_input(addr, text) {
+                                Ok(None) => continue,
+                                Ok(Some(notice)) => notice,
+                                Err(reason) => format!("message rejected: {reason}"),
+                            };
+                            outbox.push(Message::text(reply), too_slow)?;
+                        };
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()), // stream ended
+                }
+            }
+
+            val2 = bcast_rx.recv() => {
+                match val2 {
+                    Ok(event) => {
+                        if let Some(text) = event.render_for(addr, hub.config.echo) {
+                            outbox.push(Message::text(text), too_slow)?;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        outbox.evict(too_slow());
+                        return Err(TOO_SLOW.into());
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+// Same as `handle_connection`, but speaking newline-delimited text so that
+// `nc`/telnet and simple scripts can join the chat.
+async fn handle_line_connection<S>(
+    addr: SocketAddr,
+    socket: S,
+    mut bcast_rx: Receiver<Event>,
+    hub: Arc<Hub>,
+) -> Result<(), BoxError>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let config = &hub.config;
+    let (reader, writer) = tokio::io::split(socket);
+    let sink = futures_util::sink::unfold(writer, |mut writer, line: String| async move {
+        writer.write_all(line.as_bytes()).await?;
+        Ok::<_, io::Error>(writer)
+    });
+    let mut outbox = Outbox::spawn(Box::pin(sink), config.send_queue_len);
+    let too_slow = || vec![format!("error: {TOO_SLOW}, disconnecting\n")];
+
+    let (_presence, greeting) = hub.join(addr);
+    for line in greeting {
+        outbox.push(format!("{line}\n"), too_slow)?;
+    }
+
+    let mut reader = BufReader::new(reader);
+    // kept across iterations as `read_until` may be cancelled midway by select!
+    let mut buf = Vec::new();
+
+    loop {
+        // never buffer more than one maximum-sized line (plus its newline)
+        let budget = (config.max_message_len + 1).saturating_sub(buf.len()) as u64;
+        let mut limited = (&mut reader).take(budget);
+
+        tokio::select! {
+            n = limited.read_until(b'\n', &mut buf) => {
+                if n? == 0 {
+                    return Ok(()); // stream ended
+                }
+                if buf.last() != Some(&b'\n') && buf.len() > config.max_message_len {
+                    outbox.evict(vec!["error: line too long\n".to_string()]);
+                    return Err("line too long".into());
+                }
+                // a partial line at EOF is still delivered
+                let line = std::mem::take(&mut buf);
+                let Ok(text) = String::from_utf8(line) else {
+                    outbox.evict(vec!["error: invalid UTF-8\n".to_string()]);
+                    return Err("invalid UTF-8".into());
+                };
+                // tolerate telnet-style CRLF line endings
+                let text = text.trim_end_matches('\n').trim_end_matches('\r');
+                let reply = match hub.handle_input(addr, text) {
+                    Ok(None) => continue,
+                    Ok(Some(notice)) => format!("{notice}\n"),
+                    Err(reason) => format!("error: message rejected: {reason}\n"),
+                };
+                outbox.push(reply, too_slow)?;
+            }
+
+            val = bcast_rx.recv() => {
+                match val {
+                    Ok(event) => {
+                        if let Some(text) = event.render_for(addr, hub.config.echo) {
+                            // a multi-line websocket message would otherwise be
+                            // indistinguishable from several messages
+                            let mut lines = String::new();
+                            for line in text.lines() {
+                                lines.push_str(line);
+                                lines.push('\n');
+                            }
+                            outbox.push(lines, too_slow)?;
+                        }
+                    }
+                    Err(RecvError::Lagged(_)) => {
+                        outbox.evict(too_slow());
+                        return Err(TOO_SLOW.into());
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+async fn serve_websockets(listener: TcpListener, hub: Arc<Hub>) -> Result<(), BoxError> {
+    let limits = Limits::default().max_payload_len(Some(hub.config.max_message_len));
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        println!("New connection from {addr:?}");
+        let permit = hub.limits.wait().await;
+        let slot = hub.limits.admit(permit, addr.ip());
+        // subscribe before the handshake completes so that a client never
+        // misses messages sent right after it considers itself connected
+        let bcast_rx = hub.bcast_tx.subscribe();
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            // Wrap the raw TCP stream into a websocket. The connection holds
+            // a place from the moment it is accepted, so one that never
+            // finishes the handshake must not keep it for good.
+            let builder = ServerBuilder::new().limits(limits);
+            let handshake = builder.accept(socket);
+            let (req, mut ws_stream) =
+                match tokio::time::timeout(hub.config.handshake_timeout, handshake).await {
+                    Ok(accepted) => accepted?,
+                    Err(_) => return Err("websocket handshake timed out".into()),
+                };
+
+            let _slot = match slot {
+                Ok(slot) => slot,
+                Err(refused) => {
+                    let reason = refused.to_string();
+                    let details = json!({ "reason": reason });
+                    hub.audit.record("connection_rejected", addr, details);
+                    ws_stream
+                        .send(Message::close(Some(CloseCode::POLICY_VIOLATION), &reason))
+                        .await?;
+                    return Err(reason.into());
+                }
+            };
+
+            if hub.is_banned(addr.ip()) {
+                let reason = "you are banned";
+                let details = json!({ "reason": reason });
+                hub.audit.record("connection_rejected", addr, details);
+                ws_stream
+                    .send(Message::close(Some(CloseCode::POLICY_VIOLATION), reason))
+                    .await?;
+                return Err(reason.into());
+            }
+            if !origin_allowed(&hub.config, &req) {
+                let reason = "origin not allowed";
+                let origin = req.headers().get(ORIGIN).and_then(|o| o.to_str().ok());
+                let details = json!({ "origin": origin });
+                hub.audit.record("origin_rejected", addr, details);
+                ws_stream
+                    .send(Message::close(Some(CloseCode::POLICY_VIOLATION), reason))
+                    .await?;
+                return Err(reason.into());
+            }
+            if req.uri().path() != federation::PATH {
+                let handler = handle_connection(addr, ws_stream, bcast_rx, hub.clone());
+                return audited(&hub, addr, "websocket", handler).await;
+            }
+            if !federation::is_authorized(&hub, &req) {
+                let reason = "not authorized to federate";
+                let details = json!({ "endpoint": federation::PATH });
+                hub.audit.record("auth_failure", addr, details);
+                ws_stream
+                    .send(Message::close(Some(CloseCode::POLICY_VIOLATION), reason))
+                    .await?;
+                return Err(reason.into());
+            }
+            println!("{addr:?} linked as a peer");
+            let handler = federation::run_link(ws_stream, bcast_rx, hub.clone());
+            audited(&hub, addr, "federation", handler).await
+        });
+    }
+}
+
+// Whether a browser page from the request's `Origin` may connect: pages from
+// this machine, from the hostnames the chat is reached by, and from the origins
+// listed, may. The `Host` header isn't trusted for this, as a page from any
+// site can have its name point at the chat (DNS rebinding) and send it. Clients
+// that aren't browsers don't send an `Origin` and are always let in.
+fn origin_allowed(config: &Config, req: &http::Request<()>) -> bool {
+    let Some(origin) = req.headers().get(ORIGIN) else {
+        return true;
+    };
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    if config
+        .allowed_origins
+        .iter()
+        .any(|a| a == "*" || a.eq_ignore_ascii_case(origin))
+    {
+        return true;
+    }
+
+    let Some(host) = origin
+        .parse::<http::Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_string))
+    else {
+        return false;
+    };
+    let loopback = host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback());
+    loopback
+        || config
+            .hostnames
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(&host))
+}
+
+// Runs a connection's handler between audit records of it coming and going.
+async fn audited(
+    hub: &Hub,
+    addr: SocketAddr,
+    transport: &str,
+    handler: impl Future<Output = Result<(), BoxError>>,
+) -> Result<(), BoxError> {
+    hub.audit
+        .record("connect", addr, json!({ "transport": transport }));
+    let res = handler.await;
+    let error = res.as_ref().err().map(|e| e.to_string());
+    hub.audit
+        .record("disconnect", addr, json!({ "error": error }));
+    res
+}
+
+async fn serve_lines(listener: TcpListener, hub: Arc<Hub>) -> Result<(), BoxError> {
+    const TRANSPORT: &str = "line";
+
+    loop {
+        let (mut socket, addr) = listener.accept().await?;
+        println!("New line-protocol connection from {addr:?}");
+        let permit = hub.limits.wait().await;
+        let slot = hub.limits.admit(permit, addr.ip());
+        let bcast_rx = hub.bcast_tx.subscribe();
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            let _slot = match slot {
+                Ok(slot) => slot,
+                Err(refused) => {
+                    let reason = refused.to_string();
+                    let details = json!({ "reason": reason });
+                    hub.audit.record("connection_rejected", addr, details);
+                    socket
+                        .write_all(format!("error: {reason}\n").as_bytes())
+                        .await?;
+                    return Err(reason.into());
+                }
+            };
+            if hub.is_banned(addr.ip()) {
+                let reason = "you are banned";
+                let details = json!({ "reason": reason });
+                hub.audit.record("connection_rejected", addr, details);
+                socket
+                    .write_all(format!("error: {reason}\n").as_bytes())
+                    .await?;
+                return Err(reason.into());
+            }
+            let handler = handle_line_connection(addr, socket, bcast_rx, hub.clone());
+            audited(&hub, addr, TRANSPORT, handler).await
+        });
+    }
+}
+
+// Serves the line protocol to local processes. They have no network address,
+// so each gets a made-up one from the discard-only prefix `100::/64`.
+#[cfg(unix)]
+async fn serve_unix(listener: UnixListener, hub: Arc<Hub>) -> Result<(), BoxError> {
+    use std::net::Ipv6Addr;
+    const TRANSPORT: &str = "unix";
+
+    let mut next = 0u64;
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let permit = hub.limits.wait().await;
+        let slot = hub.limits.admit_local(permit);
+        next += 1;
+        let addr = SocketAddr::from((Ipv6Addr::from(0x100_u128 << 112 | next as u128), 0));
+        println!("New local connection, known as {addr}");
+        let bcast_rx = hub.bcast_tx.subscribe();
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            let handler = handle_line_connection(addr, socket, bcast_rx, hub.clone());
+            audited(&hub, addr, TRANSPORT, handler).await
+        });
+    }
+}
+
+// Binds a Unix socket at `path`, replacing a socket left behind by a server
+// that didn't shut down cleanly, but not one a server is still listening on.
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> io::Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is still being listened on", path.display()),
+                    ))
+                }
+                // nobody is
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    std::fs::remove_file(path)?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        _ => {}
+    }
+    UnixListener::bind(path)
+}
+
+// Like `TcpListener::bind`, except that IPv6 addresses don't take IPv4
+// connections too, so that `[::]` and `0.0.0.0` can share a port.
+fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    // every address is listened on at once, e.g. `0.0.0.0:2000` and
+    // `[::]:2000`; there must be at least one of each
+    pub ws_addrs: Vec<SocketAddr>,
+    pub line_addrs: Vec<SocketAddr>,
+    // Unix socket to also serve the line protocol on, for bots on the same
+    // host
+    #[cfg(unix)]
+    pub unix_path: Option<PathBuf>,
+    // the HTTP listener is only started when an address is given
+    pub http_addr: Option<SocketAddr>,
+    // bearer token required by `POST /hooks`; hooks are disabled without one
+    pub hook_token: Option<String>,
+    // token that `/mod <token>` takes to make a connection a moderator, who may
+    // ban addresses; nobody can be one without it
+    pub moderator_token: Option<String>,
+    // URLs every chat message is POSTed to
+    pub webhooks: Vec<String>,
+    // in bytes, for websocket frames and line-protocol lines alike
+    pub max_message_len: usize,
+    pub max_lines_per_message: usize,
+    pub filters: Vec<Arc<dyn MessageFilter>>,
+    // how many recent messages `GET /messages` can page through when
+    // they are only kept in memory
+    pub history_len: usize,
+    // SQLite database to keep every message in, instead of memory
+    pub database: Option<PathBuf>,
+    // announce joins/leaves and greet new connections with who is online
+    pub presence: bool,
+    // send each message back to its sender too, prefixed with
+    // "[sent #<id> at <ms since the epoch>]" rather than "[#<id>]"
+    pub echo: bool,
+    // origins, e.g. `https://chat.example`, whose pages may connect besides
+    // those from the chat's hostnames; `*` allows any
+    pub allowed_origins: Vec<String>,
+    // names the chat is reached by, e.g. `chat.example`, whose pages on any
+    // port may connect, as the chat's own page does; those of this machine
+    // always may
+    pub hostnames: Vec<String>,
+    // where to record connections, auth failures, moderation and commands
+    pub audit_log: Option<PathBuf>,
+    // where to record every message; kept apart from the audit log so that
+    // transcripts are only kept when asked for
+    pub message_log: Option<PathBuf>,
+    // both logs are rotated past this size, keeping `log_keep` old files
+    pub log_max_bytes: u64,
+    pub log_keep: usize,
+    // how many messages may wait to be written to one connection before it is
+    // considered too slow and disconnected
+    pub send_queue_len: usize,
+    // connections open at once, past which listeners stop accepting until
+    // one closes; federation links and local connections count too
+    pub max_connections: usize,
+    // connections open at once from one IP address, past which new ones are
+    // turned away
+    pub max_connections_per_ip: usize,
+    // how long a websocket client has to finish its handshake before it is
+    // disconnected and its place given up
+    pub handshake_timeout: Duration,
+    // identifies this server to its peers; must differ between them
+    pub server_id: String,
+    // bearer token peers present, and that is presented to `peers`;
+    // nobody may link to this server without one
+    pub federation_token: Option<String>,
+    // websocket URIs of other servers to link to and relay messages with,
+    // e.g. `ws://chat.example:2000/federation`
+    pub peers: Vec<String>,
+}
+
+impl Default for Config {
+    /// Binds every listener to an OS-assigned port on the loopback interface.
+    fn default() -> Self {
+        let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+        Self {
+            ws_addrs: vec![localhost],
+            line_addrs: vec![localhost],
+            #[cfg(unix)]
+            unix_path: None,
+            http_addr: None,
+            hook_token: None,
+            moderator_token: None,
+            webhooks: Vec::new(),
+            max_message_len: 4096,
+            max_lines_per_message: 20,
+            filters: Vec::new(),
+            history_len: 1000,
+            database: None,
+            presence: false,
+            echo: false,
+            allowed_origins: Vec::new(),
+            hostnames: Vec::new(),
+            audit_log: None,
+            message_log: None,
+            log_max_bytes: 10 << 20,
+            log_keep: 5,
+            send_queue_len: 64,
+            max_connections: 1024,
+            max_connections_per_ip: 32,
+            handshake_timeout: Duration::from_secs(10),
+            server_id: format!("{:016x}", RandomState::new().build_hasher().finish()),
+            federation_token: None,
+            peers: Vec::new(),
+        }
+    }
+}
+
+/// A running chat server: websocket listeners, line-protocol listeners and
+/// optionally an HTTP listener, all feeding the same broadcast bus.
+///
+/// Dropping the server stops accepting new connections.
+pub struct Server {
+    ws_addrs: Vec<SocketAddr>,
+    line_addrs: Vec<SocketAddr>,
+    http_addr: Option<SocketAddr>,
+    hub: Arc<Hub>,
+    tasks: JoinSet<Result<(), BoxError>>,
+}
+
+impl Server {
+    pub async fn start(config: Config) -> io::Result<Self> {
+        let store: Box<dyn ChatStore> = match &config.database {
+            #[cfg(feature = "sqlite")]
+            Some(path) => Box::new(store::SqliteStore::open(path).map_err(io::Error::other)?),
+            #[cfg(not(feature = "sqlite"))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "built without the `sqlite` feature",
+                ));
+            }
+            None => Box::new(MemoryStore::new(config.history_len)),
+        };
+        Self::with_store(config, store).await
+    }
+
+    /// Like [`Server::start`], but keeping messages in the given store
+    /// regardless of `config.database`.
+    pub async fn with_store(config: Config, store: Box<dyn ChatStore>) -> io::Result<Self> {
+        if config.ws_addrs.is_empty() || config.line_addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no address to listen on",
+            ));
+        }
+        let listeners = config.ws_addrs.iter().map(|&addr| bind(addr));
+        let listeners = listeners.collect::<io::Result<Vec<_>>>()?;
+        let line_listeners = config.line_addrs.iter().map(|&addr| bind(addr));
+        let line_listeners = line_listeners.collect::<io::Result<Vec<_>>>()?;
+
+        let ws_addrs = listeners.iter().map(|l| l.local_addr());
+        let ws_addrs = ws_addrs.collect::<io::Result<Vec<_>>>()?;
+        let line_addrs = line_listeners.iter().map(|l| l.local_addr());
+        let line_addrs = line_addrs.collect::<io::Result<Vec<_>>>()?;
+
+        let audit = match &config.audit_log {
+            Some(path) => AuditLog::to_file(RotatingFile::open(
+                path,
+                config.log_max_bytes,
+                config.log_keep,
+            )?),
+            None => AuditLog::disabled(),
+        };
+        let message_log = match &config.message_log {
+            Some(path) => Some(RotatingFile::open(
+                path,
+                config.log_max_bytes,
+                config.log_keep,
+            )?),
+            None => None,
+        };
+
+        let (bcast_tx, _) = channel(16);
+        let hub = Arc::new(Hub {
+            store: Mutex::new(store),
+            roster: Mutex::new(BTreeSet::new()),
+            topic: Mutex::new(None),
+            names: Mutex::new(HashMap::new()),
+            moderators: Mutex::new(BTreeSet::new()),
+            seen: Mutex::new(Seen::default()),
+            links: AtomicUsize::new(0),
+            limits: ConnectionLimits::new(config.max_connections, config.max_connections_per_ip),
+            audit,
+            ws_port: ws_addrs[0].port(),
+            config,
+            bcast_tx,
+        });
+        let mut tasks = JoinSet::new();
+
+        let http_addr = match hub.config.http_addr {
+            Some(addr) => {
+                let http_listener = TcpListener::bind(addr).await?;
+                let http_addr = http_listener.local_addr()?;
+                tasks.spawn(web::serve(http_listener, hub.clone()));
+                Some(http_addr)
+            }
+            None => None,
+        };
+
+        if !hub.config.webhooks.is_empty() {
+            tasks.spawn(webhooks::forward(
+                hub.config.webhooks.clone(),
+                hub.bcast_tx.subscribe(),
+            ));
+        }
+
+        if let Some(file) = message_log {
+            tasks.spawn(audit::log_messages(file, hub.bcast_tx.subscribe()));
+        }
+
+        for peer in &hub.config.peers {
+            tasks.spawn(federation::dial(hub.clone(), peer.clone()));
+        }
+
+        for listener in listeners {
+            tasks.spawn(serve_websockets(listener, hub.clone()));
+        }
+        for listener in line_listeners {
+            tasks.spawn(serve_lines(listener, hub.clone()));
+        }
+        #[cfg(unix)]
+        if let Some(path) = &hub.config.unix_path {
+            tasks.spawn(serve_unix(bind_unix(path)?, hub.clone()));
+        }
+
+        Ok(Self {
+            ws_addrs,
+            line_addrs,
+            http_addr,
+            hub,
+            tasks,
+        })
+    }
+
+    pub async fn ephemeral() -> io::Result<Self> {
+        Self::start(Config::default()).await
+    }
+
+    /// The first websocket address, see [`Server::ws_addrs`].
+    pub fn ws_addr(&self) -> SocketAddr {
+        self.ws_addrs[0]
+    }
+
+    pub fn ws_addrs(&self) -> &[SocketAddr] {
+        &self.ws_addrs
+    }
+
+    /// The first line-protocol address, see [`Server::line_addrs`].
+    pub fn line_addr(&self) -> SocketAddr {
+        self.line_addrs[0]
+    }
+
+    pub fn line_addrs(&self) -> &[SocketAddr] {
+        &self.line_addrs
+    }
+
+    pub fn http_addr(&self) -> Option<SocketAddr> {
+        self.http_addr
+    }
+
+    /// Number of peer servers currently linked, whichever side dialed.
+    pub fn federation_links(&self) -> usize {
+        self.hub.links.load(Ordering::Relaxed)
+    }
+
+    /// Runs until one of the listeners fails.
+    pub async fn run(mut self) -> Result<(), BoxError> {
+        match self.tasks.join_next().await {
+            Some(res) => res?,
+            None => Ok(()),
+        }
+    }
+}
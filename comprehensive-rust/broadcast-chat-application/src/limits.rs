@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// How many connections may be open at once, in total and from one IP address.
+pub(crate) struct ConnectionLimits {
+    total: Arc<Semaphore>,
+    per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    max_per_ip: usize,
+}
+
+// Held for as long as an admitted connection is open.
+pub(crate) struct Slot {
+    _permit: OwnedSemaphorePermit,
+    ip: Option<IpAddr>,
+    per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+// A connection turned away for having too many others from its address. It
+// still holds a slot until it has been told so.
+#[derive(Debug)]
+pub(crate) struct Refused {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("too many connections from your address")
+    }
+}
+
+impl ConnectionLimits {
+    pub(crate) fn new(max: usize, max_per_ip: usize) -> Self {
+        Self {
+            total: Arc::new(Semaphore::new(max)),
+            per_ip: Arc::default(),
+            max_per_ip,
+        }
+    }
+
+    // Waits until fewer than the maximum number of connections are open. A
+    // listener waits for this after each accept, so that while the server is
+    // full new connections queue up in the backlog instead of being taken on.
+    pub(crate) async fn wait(&self) -> OwnedSemaphorePermit {
+        let total = self.total.clone();
+        total
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed")
+    }
+
+    // Counts a connection from `ip`, unless it already has as many as it may.
+    pub(crate) fn admit(&self, permit: OwnedSemaphorePermit, ip: IpAddr) -> Result<Slot, Refused> {
+        let mut per_ip = self.per_ip.lock().unwrap();
+        let count = per_ip.entry(ip).or_default();
+        if *count >= self.max_per_ip {
+            return Err(Refused { _permit: permit });
+        }
+        *count += 1;
+        Ok(Slot {
+            _permit: permit,
+            ip: Some(ip),
+            per_ip: self.per_ip.clone(),
+        })
+    }
+
+    // Local connections only count towards the total.
+    #[cfg(unix)]
+    pub(crate) fn admit_local(&self, permit: OwnedSemaphorePermit) -> Slot {
+        Slot {
+            _permit: permit,
+            ip: None,
+            per_ip: self.per_ip.clone(),
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let Some(ip) = self.ip else {
+            return;
+        };
+        let mut per_ip = self.per_ip.lock().unwrap();
+        if let Some(count) = per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_per_address_and_in_total() {
+        let limits = ConnectionLimits::new(3, 2);
+        let home: IpAddr = [127, 0, 0, 1].into();
+
+        let first = limits.admit(limits.wait().await, home).unwrap();
+        let _second = limits.admit(limits.wait().await, home).unwrap();
+        assert!(limits.admit(limits.wait().await, home).is_err());
+
+        drop(first);
+        let _third = limits.admit(limits.wait().await, home).unwrap();
+        let _away = limits
+            .admit(limits.wait().await, [10, 0, 0, 1].into())
+            .unwrap();
+        assert_eq!(limits.total.available_permits(), 0);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::BoxError;
+use futures_util::sink::{Sink, SinkExt};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+// Why connections that can't keep up are disconnected.
+pub(crate) const TOO_SLOW: &str = "not keeping up with the chat";
+
+// How long an evicted connection gets to take its last messages.
+const FAREWELL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The queue of messages waiting to be written to one connection by its own
+// writer task, so that a slow reader never holds up the rest of the chat.
+pub(crate) struct Outbox<T> {
+    tx: mpsc::Sender<T>,
+    evict: Option<oneshot::Sender<Vec<T>>>,
+}
+
+// The connection didn't keep up and was told so.
+#[derive(Debug)]
+pub(crate) struct Evicted;
+
+impl fmt::Display for Evicted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(TOO_SLOW)
+    }
+}
+
+impl Error for Evicted {}
+
+impl<T: Send + 'static> Outbox<T> {
+    // Starts the writer task, which ends once the outbox is dropped and
+    // everything queued before that was written.
+    pub(crate) fn spawn<S>(mut sink: S, capacity: usize) -> Self
+    where
+        S: Sink<T> + Send + Unpin + 'static,
+        S::Error: Into<BoxError>,
+    {
+        let (tx, mut rx) = mpsc::channel(capacity);
+        let (evict, mut evicted) = oneshot::channel::<Vec<T>>();
+
+        tokio::spawn(async move {
+            let farewell = {
+                let write = async {
+                    while let Some(item) = rx.recv().await {
+                        sink.send(item).await.map_err(Into::into)?;
+                    }
+                    Ok::<_, BoxError>(())
+                };
+                tokio::select! {
+                    // the outbox is dropped right after evicting, which also
+                    // ends `write`
+                    biased;
+                    // an `Err` only means the outbox was dropped
+                    Ok(farewell) = &mut evicted => farewell,
+                    res = write => return res,
+                }
+            };
+            // whatever is still queued is dropped, the farewell skips ahead
+            let send_all = async {
+                for item in farewell {
+                    sink.send(item).await.map_err(Into::into)?;
+                }
+                Ok::<_, BoxError>(())
+            };
+            tokio::time::timeout(FAREWELL_TIMEOUT, send_all).await?
+        });
+
+        Self {
+            tx,
+            evict: Some(evict),
+        }
+    }
+
+    // Queues `item`, or if the queue is full, evicts the connection with the
+    // `farewell` messages.
+    pub(crate) fn push(
+        &mut self,
+        item: T,
+        farewell: impl FnOnce() -> Vec<T>,
+    ) -> Result<(), Evicted> {
+        if self.tx.try_send(item).is_ok() {
+            return Ok(());
+        }
+        self.evict(farewell());
+        Err(Evicted)
+    }
+
+    pub(crate) fn evict(&mut self, farewell: Vec<T>) {
+        if let Some(evict) = self.evict.take() {
+            let _ = evict.send(farewell);
+        }
+    }
+}
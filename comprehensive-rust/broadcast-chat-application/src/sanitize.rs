@@ -0,0 +1,68 @@
+use unicode_normalization::UnicodeNormalization;
+
+// Embedding, override and isolate controls can reorder the rest of a
+// recipient's terminal line, e.g. to disguise who sent what.
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Makes client-supplied text safe to show on other clients' terminals.
+///
+/// The text is NFC-normalized, and control characters (other than newline and
+/// tab) as well as bidi overrides are removed, so escape sequences can't be
+/// smuggled to other terminals.
+pub fn sanitize(text: &str) -> String {
+    text.nfc()
+        .filter(|&c| c == '\n' || c == '\t' || !(c.is_control() || is_bidi_control(c)))
+        .collect()
+}
+
+// What the server puts before a message's text: its ID, the ID of the message
+// it replies to, the confirmation sent back to its sender, and the IDs of
+// messages edited or deleted.
+const MARKERS: [&str; 5] = ["[#", "[re #", "[sent #", "[edit #", "[delete #"];
+
+// Whether a line of `text` starts like something the server puts there, which
+// would let its sender pass it off as another message, a reply, a change to
+// one, or as the recipient's own. The line protocol shows each line of a message separately.
+pub(crate) fn forges_marker(text: &str) -> bool {
+    text.lines()
+        .any(|line| MARKERS.iter().any(|m| line.trim_start().starts_with(m)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_escape_sequences() {
+        assert_eq!(sanitize("\x1b[2Jhi\x07\r"), "[2Jhi");
+        assert_eq!(sanitize("a\u{9b}31mb"), "a31mb"); // C1 CSI
+    }
+
+    #[test]
+    fn keeps_newlines_and_tabs() {
+        assert_eq!(sanitize("a\tb\nc"), "a\tb\nc");
+    }
+
+    #[test]
+    fn strips_bidi_overrides() {
+        assert_eq!(sanitize("evil\u{202E}txt.exe"), "eviltxt.exe");
+        assert_eq!(sanitize("\u{2067}x\u{2069}"), "x");
+    }
+
+    #[test]
+    fn spots_forged_markers() {
+        assert!(forges_marker("[sent #5 at 0] hi"));
+        assert!(forges_marker("[re #1] me too"));
+        assert!(forges_marker("hi\n [#9] from someone else"));
+        assert!(forges_marker("[delete #3]"));
+        assert!(!forges_marker("see [#1] above"));
+        assert!(!forges_marker("[x] done"));
+    }
+
+    #[test]
+    fn normalizes_to_nfc() {
+        assert_eq!(sanitize("e\u{301}"), "\u{e9}");
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::SystemTime;
+
+use crate::BoxError;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[derive(Clone, Debug)]
+pub struct StoredMessage {
+    pub id: u64,
+    pub from: SocketAddr,
+    pub text: String,
+    pub sent_at: SystemTime,
+    // the message this one replies to
+    pub reply_to: Option<u64>,
+    // whether its text has been changed since it was sent
+    pub edited: bool,
+    // deleted messages keep their place, but not their text
+    pub deleted: bool,
+}
+
+/// Where chat messages are kept.
+///
+/// IDs are assigned by the store and must increase with every appended
+/// message.
+pub trait ChatStore: Send {
+    fn append(
+        &mut self,
+        from: SocketAddr,
+        text: &str,
+        reply_to: Option<u64>,
+    ) -> Result<u64, BoxError>;
+
+    /// Returns up to `limit` messages with an ID below `before` (or the latest
+    /// ones without it), oldest first.
+    fn page(&self, before: Option<u64>, limit: usize) -> Result<Vec<StoredMessage>, BoxError>;
+
+    fn get(&self, id: u64) -> Result<Option<StoredMessage>, BoxError> {
+        let page = self.page(Some(id.saturating_add(1)), 1)?;
+        Ok(page.into_iter().find(|m| m.id == id))
+    }
+
+    /// Replaces the text of message `id`, marking it as edited. Messages the
+    /// store doesn't have are left alone.
+    fn edit(&mut self, id: u64, text: &str) -> Result<(), BoxError>;
+
+    /// Marks message `id` as deleted and forgets its text.
+    fn delete(&mut self, id: u64) -> Result<(), BoxError>;
+
+    /// Records that `user` has read everything up to `id`, and returns how far
+    /// they have read. Markers only ever move forward, as clients may report
+    /// out of order.
+    fn mark_read(&mut self, user: &str, id: u64) -> Result<u64, BoxError>;
+
+    /// How far each user has read, by name.
+    fn read_markers(&self) -> Result<BTreeMap<String, u64>, BoxError>;
+
+    /// Remembers `name` as one a user has gone by.
+    fn add_user(&mut self, name: &str) -> Result<(), BoxError>;
+
+    /// Every name users have gone by, in order.
+    fn users(&self) -> Result<BTreeSet<String>, BoxError>;
+
+    /// Turns away connections and messages from `ip`. Returns whether it
+    /// wasn't banned already.
+    fn ban(&mut self, ip: IpAddr) -> Result<bool, BoxError>;
+
+    /// Lifts a ban. Returns whether `ip` was banned.
+    fn unban(&mut self, ip: IpAddr) -> Result<bool, BoxError>;
+
+    fn bans(&self) -> Result<BTreeSet<IpAddr>, BoxError>;
+
+    fn is_banned(&self, ip: IpAddr) -> Result<bool, BoxError> {
+        Ok(self.bans()?.contains(&ip))
+    }
+}
+
+/// Keeps the most recent `capacity` messages, with IDs starting at 1.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    next_id: u64,
+    messages: VecDeque<StoredMessage>,
+    read_markers: BTreeMap<String, u64>,
+    users: BTreeSet<String>,
+    bans: BTreeSet<IpAddr>,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_id: 1,
+            messages: VecDeque::with_capacity(capacity),
+            read_markers: BTreeMap::new(),
+            users: BTreeSet::new(),
+            bans: BTreeSet::new(),
+        }
+    }
+}
+
+impl ChatStore for MemoryStore {
+    fn append(
+        &mut self,
+        from: SocketAddr,
+        text: &str,
+        reply_to: Option<u64>,
+    ) -> Result<u64, BoxError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if self.capacity == 0 {
+            return Ok(id);
+        }
+        if self.messages.len() == self.capacity {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(StoredMessage {
+            id,
+            from,
+            text: text.to_string(),
+            sent_at: SystemTime::now(),
+            reply_to,
+            edited: false,
+            deleted: false,
+        });
+        Ok(id)
+    }
+
+    fn page(&self, before: Option<u64>, limit: usize) -> Result<Vec<StoredMessage>, BoxError> {
+        let end = match before {
+            Some(before) => self.messages.partition_point(|m| m.id < before),
+            None => self.messages.len(),
+        };
+        let start = end.saturating_sub(limit);
+        Ok(self.messages.range(start..end).cloned().collect())
+    }
+
+    fn edit(&mut self, id: u64, text: &str) -> Result<(), BoxError> {
+        if let Some(m) = self.messages.iter_mut().find(|m| m.id == id) {
+            m.text = text.to_string();
+            m.edited = true;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64) -> Result<(), BoxError> {
+        if let Some(m) = self.messages.iter_mut().find(|m| m.id == id) {
+            m.text.clear();
+            m.deleted = true;
+        }
+        Ok(())
+    }
+
+    fn mark_read(&mut self, user: &str, id: u64) -> Result<u64, BoxError> {
+        let marker = self.read_markers.entry(user.to_string()).or_default();
+        *marker = id.max(*marker);
+        Ok(*marker)
+    }
+
+    fn read_markers(&self) -> Result<BTreeMap<String, u64>, BoxError> {
+        Ok(self.read_markers.clone())
+    }
+
+    fn add_user(&mut self, name: &str) -> Result<(), BoxError> {
+        self.users.insert(name.to_string());
+        Ok(())
+    }
+
+    fn users(&self) -> Result<BTreeSet<String>, BoxError> {
+        Ok(self.users.clone())
+    }
+
+    fn ban(&mut self, ip: IpAddr) -> Result<bool, BoxError> {
+        Ok(self.bans.insert(ip))
+    }
+
+    fn unban(&mut self, ip: IpAddr) -> Result<bool, BoxError> {
+        Ok(self.bans.remove(&ip))
+    }
+
+    fn bans(&self) -> Result<BTreeSet<IpAddr>, BoxError> {
+        Ok(self.bans.clone())
+    }
+
+    fn is_banned(&self, ip: IpAddr) -> Result<bool, BoxError> {
+        Ok(self.bans.contains(&ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn fill(store: &mut dyn ChatStore, n: u64) {
+        for i in 1..=n {
+            store
+                .append(([127, 0, 0, 1], 1000).into(), &format!("msg {i}"), None)
+                .unwrap();
+        }
+    }
+
+    pub(super) fn ids(store: &dyn ChatStore, before: Option<u64>, limit: usize) -> Vec<u64> {
+        let page = store.page(before, limit).unwrap();
+        page.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn pages_backwards_from_the_latest() {
+        let mut store = MemoryStore::new(100);
+        fill(&mut store, 10);
+        assert_eq!(ids(&store, None, 3), [8, 9, 10]);
+        assert_eq!(ids(&store, Some(8), 3), [5, 6, 7]);
+        assert_eq!(ids(&store, Some(3), 3), [1, 2]);
+        assert!(ids(&store, Some(1), 3).is_empty());
+    }
+
+    #[test]
+    fn forgets_beyond_capacity() {
+        let mut store = MemoryStore::new(4);
+        fill(&mut store, 10);
+        assert_eq!(ids(&store, None, 100), [7, 8, 9, 10]);
+        assert!(ids(&store, Some(5), 100).is_empty());
+    }
+
+    pub(super) fn keeps_replies(store: &mut dyn ChatStore) {
+        let from = ([10, 0, 0, 7], 4242).into();
+        let question = store.append(from, "lunch?", None).unwrap();
+        let answer = store.append(from, "sure", Some(question)).unwrap();
+
+        assert_eq!(store.get(question).unwrap().unwrap().reply_to, None);
+        let answer = store.get(answer).unwrap().unwrap();
+        assert_eq!(answer.text, "sure");
+        assert_eq!(answer.reply_to, Some(question));
+        assert!(store.get(answer.id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn gets_messages_with_their_reply() {
+        keeps_replies(&mut MemoryStore::new(10));
+    }
+
+    pub(super) fn keeps_edits_and_deletions(store: &mut dyn ChatStore) {
+        fill(store, 3);
+        store.edit(1, "msg one").unwrap();
+        store.delete(2).unwrap();
+        // neither touches messages the store doesn't have
+        store.edit(9, "nothing").unwrap();
+        store.delete(9).unwrap();
+
+        let page = store.page(None, 10).unwrap();
+        let shown: Vec<_> = page
+            .iter()
+            .map(|m| (m.id, m.text.as_str(), m.edited, m.deleted))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                (1, "msg one", true, false),
+                (2, "", false, true),
+                (3, "msg 3", false, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn edits_and_deletes_messages() {
+        keeps_edits_and_deletions(&mut MemoryStore::new(10));
+    }
+
+    pub(super) fn keeps_read_markers(store: &mut dyn ChatStore) {
+        assert_eq!(store.mark_read("alice", 5).unwrap(), 5);
+        assert_eq!(store.mark_read("alice", 3).unwrap(), 5);
+        assert_eq!(store.mark_read("bob", 4).unwrap(), 4);
+
+        let markers = store.read_markers().unwrap();
+        let markers: Vec<_> = markers
+            .iter()
+            .map(|(user, &id)| (user.as_str(), id))
+            .collect();
+        assert_eq!(markers, [("alice", 5), ("bob", 4)]);
+    }
+
+    #[test]
+    fn read_markers_only_move_forward() {
+        keeps_read_markers(&mut MemoryStore::new(10));
+    }
+
+    pub(super) fn keeps_users_and_bans(store: &mut dyn ChatStore) {
+        store.add_user("bob").unwrap();
+        store.add_user("alice").unwrap();
+        store.add_user("bob").unwrap();
+        assert_eq!(
+            store.users().unwrap(),
+            BTreeSet::from(["alice".into(), "bob".into()])
+        );
+
+        let ip: IpAddr = [10, 0, 0, 7].into();
+        assert!(store.ban(ip).unwrap());
+        assert!(!store.ban(ip).unwrap());
+        assert!(store.is_banned(ip).unwrap());
+        assert!(!store.is_banned([10, 0, 0, 8].into()).unwrap());
+        assert_eq!(store.bans().unwrap(), BTreeSet::from([ip]));
+        assert!(store.unban(ip).unwrap());
+        assert!(!store.unban(ip).unwrap());
+        assert!(store.bans().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_users_and_bans_in_memory() {
+        keeps_users_and_bans(&mut MemoryStore::new(10));
+    }
+}
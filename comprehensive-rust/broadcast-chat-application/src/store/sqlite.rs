@@ -0,0 +1,298 @@
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{ChatStore, StoredMessage};
+use crate::BoxError;
+
+/// Keeps every message in an SQLite database.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BoxError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, BoxError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, BoxError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sender TEXT NOT NULL,
+                text TEXT NOT NULL,
+                sent_at_ms INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS read_markers (
+                user TEXT PRIMARY KEY,
+                last_read INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS users (name TEXT PRIMARY KEY);
+            CREATE TABLE IF NOT EXISTS bans (ip TEXT PRIMARY KEY)",
+        )?;
+        // added after the table itself, so older databases lack them
+        let columns = [
+            ("reply_to", "INTEGER"),
+            ("edited", "INTEGER NOT NULL DEFAULT 0"),
+            ("deleted", "INTEGER NOT NULL DEFAULT 0"),
+        ];
+        for (name, decl) in columns {
+            let exists: bool = conn.query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('messages') WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                conn.execute_batch(&format!("ALTER TABLE messages ADD COLUMN {name} {decl}"))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+}
+
+impl ChatStore for SqliteStore {
+    fn append(
+        &mut self,
+        from: SocketAddr,
+        text: &str,
+        reply_to: Option<u64>,
+    ) -> Result<u64, BoxError> {
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        self.conn.execute(
+            "INSERT INTO messages (sender, text, sent_at_ms, reply_to) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                from.to_string(),
+                text,
+                sent_at.as_millis() as i64,
+                reply_to.map(|id| id as i64)
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid() as u64)
+    }
+
+    fn page(&self, before: Option<u64>, limit: usize) -> Result<Vec<StoredMessage>, BoxError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, sender, text, sent_at_ms, reply_to, edited, deleted FROM messages
+             WHERE id < ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        // IDs past what SQLite holds are past every message
+        let before = before.map_or(i64::MAX, |id| i64::try_from(id).unwrap_or(i64::MAX));
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let rows = stmt.query_map(params![before, limit], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+                row.get::<_, bool>(5)?,
+                row.get::<_, bool>(6)?,
+            ))
+        })?;
+
+        let mut page = Vec::new();
+        for row in rows {
+            let (id, from, text, sent_at_ms, reply_to, edited, deleted) = row?;
+            page.push(StoredMessage {
+                id: id as u64,
+                from: from.parse()?,
+                text,
+                sent_at: UNIX_EPOCH + Duration::from_millis(sent_at_ms as u64),
+                reply_to: reply_to.map(|id| id as u64),
+                edited,
+                deleted,
+            });
+        }
+        page.reverse();
+        Ok(page)
+    }
+
+    fn edit(&mut self, id: u64, text: &str) -> Result<(), BoxError> {
+        self.conn.execute(
+            "UPDATE messages SET text = ?2, edited = 1 WHERE id = ?1",
+            params![i64::try_from(id)?, text],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64) -> Result<(), BoxError> {
+        self.conn.execute(
+            "UPDATE messages SET text = '', deleted = 1 WHERE id = ?1",
+            params![i64::try_from(id)?],
+        )?;
+        Ok(())
+    }
+
+    fn mark_read(&mut self, user: &str, id: u64) -> Result<u64, BoxError> {
+        let id = i64::try_from(id)?;
+        let last_read: i64 = self.conn.query_row(
+            "INSERT INTO read_markers (user, last_read) VALUES (?1, ?2)
+             ON CONFLICT (user) DO UPDATE SET last_read = MAX(last_read, excluded.last_read)
+             RETURNING last_read",
+            params![user, id],
+            |row| row.get(0),
+        )?;
+        Ok(last_read as u64)
+    }
+
+    fn read_markers(&self) -> Result<BTreeMap<String, u64>, BoxError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT user, last_read FROM read_markers")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn add_user(&mut self, name: &str) -> Result<(), BoxError> {
+        self.conn.execute(
+            "INSERT INTO users (name) VALUES (?1) ON CONFLICT DO NOTHING",
+            params![name],
+        )?;
+        Ok(())
+    }
+
+    fn users(&self) -> Result<BTreeSet<String>, BoxError> {
+        let mut stmt = self.conn.prepare("SELECT name FROM users")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn ban(&mut self, ip: IpAddr) -> Result<bool, BoxError> {
+        let added = self.conn.execute(
+            "INSERT INTO bans (ip) VALUES (?1) ON CONFLICT DO NOTHING",
+            params![ip.to_string()],
+        )?;
+        Ok(added > 0)
+    }
+
+    fn unban(&mut self, ip: IpAddr) -> Result<bool, BoxError> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM bans WHERE ip = ?1", params![ip.to_string()])?;
+        Ok(removed > 0)
+    }
+
+    fn bans(&self) -> Result<BTreeSet<IpAddr>, BoxError> {
+        let mut stmt = self.conn.prepare("SELECT ip FROM bans")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut bans = BTreeSet::new();
+        for ip in rows {
+            bans.insert(ip?.parse()?);
+        }
+        Ok(bans)
+    }
+
+    fn is_banned(&self, ip: IpAddr) -> Result<bool, BoxError> {
+        let banned = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM bans WHERE ip = ?1",
+            params![ip.to_string()],
+            |row| row.get(0),
+        )?;
+        Ok(banned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        fill, ids, keeps_edits_and_deletions, keeps_read_markers, keeps_replies,
+        keeps_users_and_bans,
+    };
+    use super::*;
+
+    #[test]
+    fn pages_backwards_from_the_latest() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        fill(&mut store, 10);
+        assert_eq!(ids(&store, None, 3), [8, 9, 10]);
+        assert_eq!(ids(&store, Some(8), 3), [5, 6, 7]);
+        assert_eq!(ids(&store, Some(3), 3), [1, 2]);
+        assert!(ids(&store, Some(1), 3).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_text_and_sender() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let from = ([10, 0, 0, 7], 4242).into();
+        let id = store.append(from, "héllo", None).unwrap();
+
+        let page = store.page(None, 10).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, id);
+        assert_eq!(page[0].from, from);
+        assert_eq!(page[0].text, "héllo");
+    }
+
+    #[test]
+    fn gets_messages_with_their_reply() {
+        keeps_replies(&mut SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn edits_and_deletes_messages() {
+        keeps_edits_and_deletions(&mut SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn read_markers_only_move_forward() {
+        keeps_read_markers(&mut SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn keeps_users_and_bans_in_the_database() {
+        keeps_users_and_bans(&mut SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn pages_from_ids_past_what_sqlite_holds() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        fill(&mut store, 3);
+        assert_eq!(ids(&store, Some(u64::MAX), 10), [1, 2, 3]);
+    }
+
+    #[test]
+    fn keeps_read_markers_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("chat-markers-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chat.db");
+        let _ = std::fs::remove_file(&path);
+
+        SqliteStore::open(&path)
+            .unwrap()
+            .mark_read("alice", 7)
+            .unwrap();
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.read_markers().unwrap().get("alice"), Some(&7));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn adds_replies_to_older_databases() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sender TEXT NOT NULL,
+                text TEXT NOT NULL,
+                sent_at_ms INTEGER NOT NULL
+            );
+            INSERT INTO messages (sender, text, sent_at_ms) VALUES ('10.0.0.7:4242', 'old', 0);",
+        )
+        .unwrap();
+        let mut store = SqliteStore::init(conn).unwrap();
+
+        let reply = store
+            .append(([10, 0, 0, 7], 4242).into(), "new", Some(1))
+            .unwrap();
+        assert_eq!(store.get(1).unwrap().unwrap().reply_to, None);
+        assert_eq!(store.get(reply).unwrap().unwrap().reply_to, Some(1));
+    }
+}
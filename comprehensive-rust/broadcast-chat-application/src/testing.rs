@@ -0,0 +1,125 @@
+//! Scripted clients for driving a [`Server`] from tests.
+
+use futures_util::sink::SinkExt;
+use futures_util::stream::StreamExt;
+use http::Uri;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+use tokio_websockets::{ClientBuilder, MaybeTlsStream, Message, WebSocketStream};
+
+use crate::{BoxError, Server};
+
+/// How long `recv` waits for a message before giving up.
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct WsClient {
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl WsClient {
+    pub async fn connect(server: &Server) -> Result<Self, BoxError> {
+        Self::connect_to(server.ws_addr()).await
+    }
+
+    pub async fn connect_to(addr: SocketAddr) -> Result<Self, BoxError> {
+        let uri: Uri = format!("ws://{addr}").parse()?;
+        let (ws_stream, _) = ClientBuilder::from_uri(uri).connect().await?;
+        Ok(Self { ws_stream })
+    }
+
+    pub async fn send(&mut self, text: &str) -> Result<(), BoxError> {
+        self.ws_stream.send(Message::text(text.to_string())).await?;
+        Ok(())
+    }
+
+    /// Waits for the next text message, or `None` if the connection was
+    /// closed or nothing arrived within [`RECV_TIMEOUT`].
+    pub async fn recv(&mut self) -> Option<String> {
+        loop {
+            let msg = timeout(RECV_TIMEOUT, self.ws_stream.next())
+                .await
+                .ok()??
+                .ok()?;
+            if let Some(text) = msg.as_text() {
+                return Some(text.to_string());
+            }
+        }
+    }
+
+    /// Returns true if no text message arrives within `dur`.
+    pub async fn is_silent_for(&mut self, dur: Duration) -> bool {
+        loop {
+            match timeout(dur, self.ws_stream.next()).await {
+                Err(_) => return true,
+                Ok(Some(Ok(msg))) if !msg.is_text() => continue,
+                Ok(_) => return false,
+            }
+        }
+    }
+
+    pub async fn close(mut self) -> Result<(), BoxError> {
+        self.ws_stream.close().await?;
+        Ok(())
+    }
+}
+
+/// Splits a "[#<id>] <text>" chat message, as clients receive it, into its
+/// parts.
+pub fn parse_message(line: &str) -> Option<(u64, &str)> {
+    let (id, text) = line.strip_prefix("[#")?.split_once("] ")?;
+    Some((id.parse().ok()?, text))
+}
+
+type BoxReader = Box<dyn AsyncRead + Send + Unpin>;
+type BoxWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+pub struct LineClient {
+    lines: Lines<BufReader<BoxReader>>,
+    writer: BoxWriter,
+}
+
+impl LineClient {
+    pub async fn connect(server: &Server) -> Result<Self, BoxError> {
+        Self::connect_to(server.line_addr()).await
+    }
+
+    pub async fn connect_to(addr: SocketAddr) -> Result<Self, BoxError> {
+        let (reader, writer) = TcpStream::connect(addr).await?.into_split();
+        Ok(Self::new(Box::new(reader), Box::new(writer)))
+    }
+
+    #[cfg(unix)]
+    pub async fn connect_unix(path: &Path) -> Result<Self, BoxError> {
+        let (reader, writer) = UnixStream::connect(path).await?.into_split();
+        Ok(Self::new(Box::new(reader), Box::new(writer)))
+    }
+
+    fn new(reader: BoxReader, writer: BoxWriter) -> Self {
+        Self {
+            lines: BufReader::new(reader).lines(),
+            writer,
+        }
+    }
+
+    pub async fn send(&mut self, text: &str) -> Result<(), BoxError> {
+        self.writer.write_all(text.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        Ok(())
+    }
+
+    /// Waits for the next line, or `None` if the connection was closed or
+    /// nothing arrived within [`RECV_TIMEOUT`].
+    pub async fn recv(&mut self) -> Option<String> {
+        timeout(RECV_TIMEOUT, self.lines.next_line())
+            .await
+            .ok()?
+            .ok()?
+    }
+}
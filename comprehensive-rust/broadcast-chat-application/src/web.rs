@@ -0,0 +1,210 @@
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::net::TcpListener;
+
+use crate::{token_matches, BoxError, Hub};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+const CLIENT_PAGE: &str = include_str!("../static/client.html");
+
+pub(crate) async fn serve(listener: TcpListener, hub: Arc<Hub>) -> Result<(), BoxError> {
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let hub = hub.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|req| handle_request(addr, req, hub.clone()));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(socket), service)
+                .await
+            {
+                eprintln!("HTTP connection from {addr:?} failed: {e}");
+            }
+        });
+    }
+}
+
+fn respond(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    respond_owned(status, body.to_string())
+}
+
+fn respond_json(body: serde_json::Value) -> Response<Full<Bytes>> {
+    let mut resp = respond_owned(StatusCode::OK, body.to_string());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+fn respond_owned(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut resp = Response::new(Full::new(Bytes::from(body)));
+    *resp.status_mut() = status;
+    resp
+}
+
+async fn handle_request(
+    addr: SocketAddr,
+    req: Request<Incoming>,
+    hub: Arc<Hub>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/") => client_page(&hub),
+        (_, "/") => respond(StatusCode::METHOD_NOT_ALLOWED, "use GET\n"),
+        (&Method::POST, "/hooks") => handle_hook(addr, req, &hub).await,
+        (_, "/hooks") => respond(StatusCode::METHOD_NOT_ALLOWED, "use POST\n"),
+        (&Method::GET, "/messages") => handle_messages(req.uri().query(), &hub),
+        (_, "/messages") => respond(StatusCode::METHOD_NOT_ALLOWED, "use GET\n"),
+        (&Method::GET, "/receipts") => handle_receipts(&hub),
+        (_, "/receipts") => respond(StatusCode::METHOD_NOT_ALLOWED, "use GET\n"),
+        (&Method::GET, "/users") => handle_users(&hub),
+        (_, "/users") => respond(StatusCode::METHOD_NOT_ALLOWED, "use GET\n"),
+        _ => respond(StatusCode::NOT_FOUND, "not found\n"),
+    };
+    Ok(resp)
+}
+
+fn client_page(hub: &Hub) -> Response<Full<Bytes>> {
+    let page = CLIENT_PAGE
+        .replace("{{WS_PORT}}", &hub.ws_port.to_string())
+        .replace("{{ECHO}}", &hub.config.echo.to_string());
+    let mut resp = respond_owned(StatusCode::OK, page);
+    resp.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    resp
+}
+
+// Injects the request body into the chat as a message from `addr`.
+async fn handle_hook(addr: SocketAddr, req: Request<Incoming>, hub: &Hub) -> Response<Full<Bytes>> {
+    let Some(token) = &hub.config.hook_token else {
+        return respond(StatusCode::NOT_FOUND, "hooks are disabled\n");
+    };
+
+    let authorized = req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|t| token_matches(t, token));
+    if !authorized {
+        let details = json!({ "endpoint": "/hooks" });
+        hub.audit.record("auth_failure", addr, details);
+        return respond(StatusCode::UNAUTHORIZED, "missing or invalid token\n");
+    }
+
+    let body = match Limited::new(req.into_body(), hub.config.max_message_len)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(_) => return respond(StatusCode::PAYLOAD_TOO_LARGE, "body too large\n"),
+    };
+    let Ok(text) = String::from_utf8(body.to_vec()) else {
+        return respond(StatusCode::BAD_REQUEST, "body must be UTF-8 text\n");
+    };
+    let text = text.trim_end();
+    if text.is_empty() {
+        return respond(StatusCode::BAD_REQUEST, "empty message\n");
+    }
+
+    match hub.publish(addr, text) {
+        Ok(()) => respond(StatusCode::ACCEPTED, ""),
+        Err(reason) => respond_owned(StatusCode::UNPROCESSABLE_ENTITY, format!("{reason}\n")),
+    }
+}
+
+// `GET /messages?before=<id>&limit=<n>` pages backwards through the history,
+// returning a JSON array ordered oldest first.
+fn handle_messages(query: Option<&str>, hub: &Hub) -> Response<Full<Bytes>> {
+    let mut before = None;
+    let mut limit = DEFAULT_PAGE_SIZE;
+
+    for param in query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+    {
+        let parsed = match param.split_once('=') {
+            Some(("before", v)) => v.parse().map(|v| before = Some(v)),
+            Some(("limit", v)) => v.parse().map(|v: usize| limit = v.min(MAX_PAGE_SIZE)),
+            _ => continue,
+        };
+        if parsed.is_err() {
+            return respond(StatusCode::BAD_REQUEST, "invalid query parameter\n");
+        }
+    }
+
+    let page = match hub.store.lock().unwrap().page(before, limit) {
+        Ok(page) => page,
+        Err(e) => {
+            eprintln!("could not load messages: {e}");
+            return respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not load messages\n",
+            );
+        }
+    };
+    let body: Vec<_> = page
+        .into_iter()
+        .map(|m| {
+            let sent_at = m.sent_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+            json!({
+                "id": m.id,
+                "from": m.from.to_string(),
+                "text": m.text,
+                "sent_at_ms": sent_at.as_millis() as u64,
+                "reply_to": m.reply_to,
+                "edited": m.edited,
+                "deleted": m.deleted,
+            })
+        })
+        .collect();
+
+    respond_json(body.into())
+}
+
+// `GET /receipts` lists how far each user has read, as a JSON array.
+fn handle_receipts(hub: &Hub) -> Response<Full<Bytes>> {
+    let markers = match hub.store.lock().unwrap().read_markers() {
+        Ok(markers) => markers,
+        Err(e) => {
+            eprintln!("could not load the read markers: {e}");
+            return respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not load the read markers\n",
+            );
+        }
+    };
+    let body: Vec<_> = markers
+        .into_iter()
+        .map(|(user, id)| json!({ "user": user, "last_read": id }))
+        .collect();
+
+    respond_json(body.into())
+}
+
+// `GET /users` lists every name users have gone by, as a JSON array.
+fn handle_users(hub: &Hub) -> Response<Full<Bytes>> {
+    let users = match hub.store.lock().unwrap().users() {
+        Ok(users) => users,
+        Err(e) => {
+            eprintln!("could not load the users: {e}");
+            return respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "could not load the users\n",
+            );
+        }
+    };
+    respond_json(users.into_iter().collect::<Vec<_>>().into())
+}
@@ -0,0 +1,72 @@
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::{BoxError, Event};
+
+// How long an endpoint gets to answer each POST.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How many messages may wait for each endpoint before more are dropped.
+const QUEUE_LEN: usize = 64;
+
+// POSTs every chat message on the bus to each of `urls` as
+// `{"from": "<addr>", "text": "<message>", "reply_to": <id or null>}`.
+pub(crate) async fn forward(
+    urls: Vec<String>,
+    mut bcast_rx: Receiver<Event>,
+) -> Result<(), BoxError> {
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+
+    // a slow or failing endpoint must not hold up the others, so each gets
+    // its own queue and task, which POSTs one message at a time
+    let queues: Vec<_> = urls
+        .into_iter()
+        .map(|url| {
+            let (tx, rx) = mpsc::channel(QUEUE_LEN);
+            tokio::spawn(post_all(client.clone(), url.clone(), rx));
+            (url, tx)
+        })
+        .collect();
+
+    loop {
+        let (addr, text, reply_to) = match bcast_rx.recv().await {
+            Ok(Event::Message {
+                from,
+                text,
+                reply_to,
+                ..
+            }) => (from, text, reply_to),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => {
+                eprintln!("webhooks fell behind, dropped {n} messages");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let body = json!({ "from": addr.to_string(), "text": text, "reply_to": reply_to });
+        for (url, tx) in &queues {
+            match tx.try_send(body.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    eprintln!("webhook {url} is falling behind, dropped a message")
+                }
+                Err(TrySendError::Closed(_)) => {}
+            }
+        }
+    }
+}
+
+// POSTs each body queued for `url` in turn, until the queue is dropped.
+async fn post_all(client: Client, url: String, mut rx: mpsc::Receiver<Value>) {
+    while let Some(body) = rx.recv().await {
+        let sent = client.post(&url).json(&body).send().await;
+        if let Err(e) = sent.and_then(|r| r.error_for_status()) {
+            eprintln!("webhook {url} failed: {e}");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use broadcast_chat_application::filter::MaxLinks;
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+fn records(path: &Path) -> Vec<Value> {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    text.lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect()
+}
+
+fn events(records: &[Value]) -> Vec<&str> {
+    records
+        .iter()
+        .map(|r| r["event"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn audit_log_records_activity_but_not_messages() {
+    let dir = std::env::temp_dir().join(format!("chat-audit-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let audit_log = dir.join("audit.log");
+    let message_log = dir.join("messages.log");
+    let _ = std::fs::remove_file(&audit_log);
+    let _ = std::fs::remove_file(&message_log);
+
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        hook_token: Some("s3cret".to_string()),
+        filters: vec![Arc::new(MaxLinks { max: 0 })],
+        audit_log: Some(audit_log.clone()),
+        message_log: Some(message_log.clone()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+
+    let mut alice = LineClient::connect(&server).await.unwrap();
+    let mut bob = WsClient::connect(&server).await.unwrap();
+    alice.send("/topic secrets").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* topic changed"));
+    alice.send("see http://spam.example").await.unwrap();
+    assert!(alice
+        .recv()
+        .await
+        .unwrap()
+        .starts_with("error: message rejected"));
+    alice.send("a secret message").await.unwrap();
+    assert!(bob
+        .recv()
+        .await
+        .unwrap()
+        .ends_with("changed the topic to: secrets"));
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] a secret message"));
+    bob.close().await.unwrap();
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/hooks", server.http_addr().unwrap()))
+        .bearer_auth("guess")
+        .body("spam")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    let mut audit = Vec::new();
+    for _ in 0..100 {
+        audit = records(&audit_log);
+        if events(&audit).len() == 6 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(
+        events(&audit),
+        [
+            "connect",
+            "connect",
+            "command",
+            "message_rejected",
+            "disconnect",
+            "auth_failure"
+        ]
+    );
+    assert_eq!(audit[0]["transport"], "line");
+    assert_eq!(audit[1]["transport"], "websocket");
+    assert_eq!(audit[4]["error"], Value::Null);
+    let text = std::fs::read_to_string(&audit_log).unwrap();
+    assert!(!text.contains("secret"), "{text}");
+
+    let messages = records(&message_log);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["text"], "a secret message");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,151 @@
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpSocket;
+use tokio::time::timeout;
+
+use broadcast_chat_application::filter::{MaxLinks, ProfanityFilter};
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+
+#[tokio::test]
+async fn broadcasts_to_everyone_but_the_sender() {
+    let server = Server::ephemeral().await.unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = WsClient::connect(&server).await.unwrap();
+    let mut carol = WsClient::connect(&server).await.unwrap();
+
+    alice.send("hi all").await.unwrap();
+
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] hi all"));
+    assert_eq!(carol.recv().await.as_deref(), Some("[#1] hi all"));
+    assert!(alice.is_silent_for(Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn line_clients_share_the_bus() {
+    let server = Server::ephemeral().await.unwrap();
+    let mut ws = WsClient::connect(&server).await.unwrap();
+    let mut line = LineClient::connect(&server).await.unwrap();
+
+    line.send("from nc\r").await.unwrap();
+    assert_eq!(ws.recv().await.as_deref(), Some("[#1] from nc"));
+
+    ws.send("two\nlines").await.unwrap();
+    assert_eq!(line.recv().await.as_deref(), Some("[#2] two"));
+    assert_eq!(line.recv().await.as_deref(), Some("lines"));
+}
+
+#[tokio::test]
+async fn every_listener_feeds_the_same_bus() {
+    let v4 = SocketAddr::from(([127, 0, 0, 1], 0));
+    let v6 = SocketAddr::from((Ipv6Addr::LOCALHOST, 0));
+    let server = Server::start(Config {
+        ws_addrs: vec![v4, v6],
+        line_addrs: vec![v4, v6],
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let [ws4, ws6] = server.ws_addrs() else {
+        panic!("expected two websocket listeners");
+    };
+    let [line4, line6] = server.line_addrs() else {
+        panic!("expected two line-protocol listeners");
+    };
+    assert!(ws6.is_ipv6() && line6.is_ipv6());
+
+    let mut alice = WsClient::connect_to(*ws4).await.unwrap();
+    let mut bob = WsClient::connect_to(*ws6).await.unwrap();
+    let mut carol = LineClient::connect_to(*line4).await.unwrap();
+    let mut dave = LineClient::connect_to(*line6).await.unwrap();
+
+    alice.send("over v4").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] over v4"));
+    assert_eq!(carol.recv().await.as_deref(), Some("[#1] over v4"));
+    assert_eq!(dave.recv().await.as_deref(), Some("[#1] over v4"));
+
+    dave.send("over v6").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("[#2] over v6"));
+}
+
+#[tokio::test]
+async fn oversized_messages_are_rejected() {
+    let server = Server::start(Config {
+        max_message_len: 16,
+        max_lines_per_message: 2,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut sender = WsClient::connect(&server).await.unwrap();
+    let mut receiver = WsClient::connect(&server).await.unwrap();
+
+    sender.send(&"x".repeat(17)).await.unwrap();
+    assert_eq!(sender.recv().await, None);
+    assert!(receiver.is_silent_for(Duration::from_millis(200)).await);
+
+    let mut sender = WsClient::connect(&server).await.unwrap();
+    sender.send("a\nb\nc").await.unwrap();
+    assert_eq!(sender.recv().await, None);
+    assert!(receiver.is_silent_for(Duration::from_millis(200)).await);
+
+    let mut line = LineClient::connect(&server).await.unwrap();
+    line.send(&"y".repeat(17)).await.unwrap();
+    assert_eq!(line.recv().await.as_deref(), Some("error: line too long"));
+    assert!(receiver.is_silent_for(Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn filters_run_before_broadcast() {
+    let server = Server::start(Config {
+        filters: vec![
+            Arc::new(ProfanityFilter::new(["darn"])),
+            Arc::new(MaxLinks { max: 0 }),
+        ],
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut sender = WsClient::connect(&server).await.unwrap();
+    let mut receiver = WsClient::connect(&server).await.unwrap();
+
+    sender.send("darn it").await.unwrap();
+    assert_eq!(receiver.recv().await.as_deref(), Some("[#1] **** it"));
+
+    sender.send("buy now https://spam.example").await.unwrap();
+    let notice = sender.recv().await.unwrap();
+    assert!(notice.starts_with("message rejected: "), "{notice}");
+    assert!(receiver.is_silent_for(Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn slow_clients_are_disconnected() {
+    let server = Server::start(Config {
+        send_queue_len: 4,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    // never reads, and has little room to buffer what it is sent
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let mut slow = socket.connect(server.line_addr()).await.unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = WsClient::connect(&server).await.unwrap();
+
+    let count = 3000;
+    let text = "x".repeat(4000);
+    for id in 1..=count {
+        alice.send(&text).await.unwrap();
+        assert_eq!(bob.recv().await, Some(format!("[#{id}] {text}")));
+    }
+
+    let mut received = Vec::new();
+    timeout(Duration::from_secs(5), slow.read_to_end(&mut received))
+        .await
+        .expect("slow client was not disconnected")
+        .unwrap();
+    assert!(received.len() < count * (text.len() + 1));
+}
@@ -0,0 +1,72 @@
+use broadcast_chat_application::testing::{parse_message, WsClient};
+use broadcast_chat_application::{Config, Server};
+use futures_util::stream::StreamExt;
+use http::header::{CONTENT_TYPE, ORIGIN};
+use http::{HeaderValue, Uri};
+use tokio_websockets::ClientBuilder;
+
+async fn server() -> Server {
+    Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        allowed_origins: vec!["https://chat.example".to_string()],
+        hostnames: vec!["chat.lan".to_string()],
+        ..Config::default()
+    })
+    .await
+    .unwrap()
+}
+
+// Whether a page from `origin` gets to chat, i.e. receives a message.
+async fn can_chat_from(server: &Server, origin: &str) -> bool {
+    let uri: Uri = format!("ws://{}", server.ws_addr()).parse().unwrap();
+    let (mut ws_stream, _) = ClientBuilder::from_uri(uri)
+        .add_header(ORIGIN, HeaderValue::from_str(origin).unwrap())
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let mut sender = WsClient::connect(server).await.unwrap();
+    sender.send("hello browser").await.unwrap();
+    match ws_stream.next().await {
+        Some(Ok(msg)) => {
+            msg.as_text().and_then(parse_message).map(|m| m.1) == Some("hello browser")
+        }
+        _ => false,
+    }
+}
+
+#[tokio::test]
+async fn serves_the_client_page() {
+    let server = server().await;
+    let resp = reqwest::get(format!("http://{}/", server.http_addr().unwrap()))
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), reqwest::StatusCode::OK);
+    assert_eq!(
+        resp.headers()[CONTENT_TYPE.as_str()],
+        "text/html; charset=utf-8"
+    );
+    let page = resp.text().await.unwrap();
+    let port = server.ws_addr().port();
+    assert!(page.contains(&format!(r#"const WS_PORT = "{port}";"#)));
+    assert!(page.contains("const ECHO = false;"));
+}
+
+#[tokio::test]
+async fn checks_the_origin_of_browser_pages() {
+    let server = server().await;
+    let http_port = server.http_addr().unwrap().port();
+
+    assert!(can_chat_from(&server, &format!("http://127.0.0.1:{http_port}")).await);
+    assert!(can_chat_from(&server, "https://chat.example").await);
+    assert!(!can_chat_from(&server, "https://evil.example").await);
+    assert!(!can_chat_from(&server, "null").await);
+
+    // the chat's own page, however it was reached
+    assert!(can_chat_from(&server, &format!("http://localhost:{http_port}")).await);
+    assert!(can_chat_from(&server, &format!("http://CHAT.lan:{http_port}")).await);
+    // but not one from a site that has only pointed its name at the chat
+    assert!(!can_chat_from(&server, &format!("http://rebound.example:{http_port}")).await);
+}
@@ -0,0 +1,62 @@
+use broadcast_chat_application::testing::{parse_message, LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Splits a "[sent #<id> at <ms>] <text>" echo into its parts.
+fn parse_echo(echo: &str) -> (u64, u64, &str) {
+    let (id, rest) = echo
+        .strip_prefix("[sent #")
+        .unwrap()
+        .split_once(" at ")
+        .unwrap();
+    let (ms, text) = rest.split_once("] ").unwrap();
+    (id.parse().unwrap(), ms.parse().unwrap(), text)
+}
+
+#[tokio::test]
+async fn senders_get_their_messages_back() {
+    let server = Server::start(Config {
+        echo: true,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    alice.send("hello").await.unwrap();
+    let echo = alice.recv().await.unwrap();
+    let (id, sent_at_ms, text) = parse_echo(&echo);
+    assert_eq!(text, "hello");
+    assert!(sent_at_ms >= before.as_millis() as u64, "{echo}");
+    // everyone else sees it as before, under the same ID
+    let line = bob.recv().await.unwrap();
+    assert_eq!(parse_message(&line), Some((id, "hello")));
+
+    bob.send(&format!("/reply {id} hi")).await.unwrap();
+    let echo = bob.recv().await.unwrap();
+    let (reply_id, _, text) = parse_echo(&echo);
+    assert_eq!(text, format!("[re #{id}] hi"));
+    let line = alice.recv().await.unwrap();
+    assert_eq!(parse_message(&line), Some((reply_id, text)));
+}
+
+#[tokio::test]
+async fn confirmations_and_replies_cannot_be_forged() {
+    let server = Server::start(Config {
+        echo: true,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = WsClient::connect(&server).await.unwrap();
+
+    for forged in ["[sent #5 at 0] hi", "[re #1] hi", "hi\n[#7] from carol"] {
+        alice.send(forged).await.unwrap();
+        let notice = alice.recv().await.unwrap();
+        assert!(notice.starts_with("message rejected: "), "{notice}");
+    }
+    assert!(bob.is_silent_for(Duration::from_millis(200)).await);
+}
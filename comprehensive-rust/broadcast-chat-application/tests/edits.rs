@@ -0,0 +1,77 @@
+use broadcast_chat_application::testing::LineClient;
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+#[tokio::test]
+async fn authors_and_moderators_change_messages() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        moderator_token: Some("sesame".to_string()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = LineClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    alice.send("lunch?").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] lunch?"));
+
+    bob.send("/edit 1 never").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("error: message rejected: only its author or a moderator may change a message")
+    );
+    alice.send("/edit 9 dinner?").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("error: message rejected: no message #9")
+    );
+
+    // everyone sees the change, its author included
+    alice.send("/edit #1 dinner?").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("[edit #1] dinner?"));
+    assert_eq!(bob.recv().await.as_deref(), Some("[edit #1] dinner?"));
+
+    bob.send("/mod sesame").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("* you are now a moderator")
+    );
+    bob.send("/delete 1").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[delete #1]"));
+    assert_eq!(alice.recv().await.as_deref(), Some("[delete #1]"));
+
+    // gone for good
+    alice.send("/reply 1 hello?").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("error: message rejected: no message #1")
+    );
+    let url = format!("http://{}/messages", server.http_addr().unwrap());
+    let page: Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+    assert_eq!(page[0]["text"], "");
+    assert_eq!(page[0]["edited"], true);
+    assert_eq!(page[0]["deleted"], true);
+}
+
+#[tokio::test]
+async fn edits_are_vetted_like_messages() {
+    let server = Server::ephemeral().await.unwrap();
+    let mut alice = LineClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    alice.send("hi").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] hi"));
+    alice.send("/edit 1 [#2] from bob").await.unwrap();
+    assert!(alice
+        .recv()
+        .await
+        .unwrap()
+        .starts_with("error: message rejected: lines may not start with a marker"));
+    alice.send("/edit 1").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("error: message rejected: usage: /edit <id> <text>")
+    );
+}
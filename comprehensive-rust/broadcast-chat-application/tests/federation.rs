@@ -0,0 +1,132 @@
+use broadcast_chat_application::store::{ChatStore, MemoryStore};
+use broadcast_chat_application::testing::{parse_message, WsClient};
+use broadcast_chat_application::{Config, Server};
+use std::time::Duration;
+
+const TOKEN: &str = "s3cret";
+
+async fn hub() -> Server {
+    Server::start(Config {
+        federation_token: Some(TOKEN.to_string()),
+        ..Config::default()
+    })
+    .await
+    .unwrap()
+}
+
+async fn peer_of(hub: &Server, links: usize, token: &str) -> Server {
+    let uri = format!("ws://{}/federation", hub.ws_addr());
+    Server::start(Config {
+        federation_token: Some(token.to_string()),
+        peers: vec![uri; links],
+        ..Config::default()
+    })
+    .await
+    .unwrap()
+}
+
+async fn wait_for_links(server: &Server, links: usize) {
+    for _ in 0..100 {
+        if server.federation_links() == links {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("expected {links} links, have {}", server.federation_links());
+}
+
+#[tokio::test]
+async fn messages_are_relayed_both_ways() {
+    let a = hub().await;
+    let b = peer_of(&a, 1, TOKEN).await;
+    wait_for_links(&a, 1).await;
+    wait_for_links(&b, 1).await;
+
+    let mut alice = WsClient::connect(&a).await.unwrap();
+    let mut bob = WsClient::connect(&b).await.unwrap();
+
+    alice.send("hello from a").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] hello from a"));
+
+    bob.send("hello from b").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("[#2] hello from b"));
+
+    // nothing bounced back to the senders
+    assert!(alice.is_silent_for(Duration::from_millis(200)).await);
+    assert!(bob.is_silent_for(Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn redundant_links_do_not_duplicate_messages() {
+    let a = hub().await;
+    let b = peer_of(&a, 2, TOKEN).await;
+    wait_for_links(&a, 2).await;
+    wait_for_links(&b, 2).await;
+
+    let mut alice = WsClient::connect(&a).await.unwrap();
+    let mut bob = WsClient::connect(&b).await.unwrap();
+
+    alice.send("only once").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] only once"));
+    assert!(bob.is_silent_for(Duration::from_millis(200)).await);
+    assert!(alice.is_silent_for(Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn replies_point_at_the_same_message_on_every_server() {
+    // so that the same message has different IDs on the two servers
+    let mut store = MemoryStore::new(100);
+    for _ in 0..5 {
+        store
+            .append(([10, 0, 0, 1], 1).into(), "old", None)
+            .unwrap();
+    }
+    let a = Server::with_store(
+        Config {
+            federation_token: Some(TOKEN.to_string()),
+            ..Config::default()
+        },
+        Box::new(store),
+    )
+    .await
+    .unwrap();
+    let b = peer_of(&a, 1, TOKEN).await;
+    wait_for_links(&a, 1).await;
+    wait_for_links(&b, 1).await;
+
+    let mut alice = WsClient::connect(&a).await.unwrap();
+    let mut bob = WsClient::connect(&b).await.unwrap();
+
+    alice.send("lunch?").await.unwrap();
+    // after the five already stored
+    let lunch_on_a = 6;
+    let line = bob.recv().await.unwrap();
+    let (lunch_on_b, text) = parse_message(&line).unwrap();
+    assert_eq!(text, "lunch?");
+    bob.send(&format!("/reply {lunch_on_b} sure"))
+        .await
+        .unwrap();
+    let line = alice.recv().await.unwrap();
+    let (sure_on_a, text) = parse_message(&line).unwrap();
+    assert_eq!(text, format!("[re #{lunch_on_a}] sure"));
+
+    alice
+        .send(&format!("/reply {sure_on_a} great"))
+        .await
+        .unwrap();
+    let line = bob.recv().await.unwrap();
+    let (_, text) = parse_message(&line).unwrap();
+    assert_eq!(text, format!("[re #{}] great", lunch_on_b + 1));
+}
+
+#[tokio::test]
+async fn peers_must_present_the_token() {
+    let a = hub().await;
+    let b = peer_of(&a, 1, "wrong").await;
+
+    let mut alice = WsClient::connect(&a).await.unwrap();
+    let mut bob = WsClient::connect(&b).await.unwrap();
+    alice.send("private").await.unwrap();
+    assert!(bob.is_silent_for(Duration::from_millis(300)).await);
+    assert_eq!(a.federation_links(), 0);
+}
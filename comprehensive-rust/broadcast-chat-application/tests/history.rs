@@ -0,0 +1,37 @@
+use broadcast_chat_application::testing::WsClient;
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+#[tokio::test]
+async fn messages_can_be_paged_over_http() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut sender = WsClient::connect(&server).await.unwrap();
+    let mut receiver = WsClient::connect(&server).await.unwrap();
+
+    for i in 1..=5 {
+        sender.send(&format!("msg {i}")).await.unwrap();
+        receiver.recv().await.unwrap();
+    }
+
+    let url = format!("http://{}/messages", server.http_addr().unwrap());
+    let get = |query: &'static str| {
+        let url = format!("{url}?{query}");
+        async move {
+            let page: Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+            page.as_array()
+                .unwrap()
+                .iter()
+                .map(|m| m["text"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(get("limit=2").await, ["msg 4", "msg 5"]);
+    assert_eq!(get("before=4&limit=2").await, ["msg 2", "msg 3"]);
+    assert_eq!(get("before=2").await, ["msg 1"]);
+}
@@ -0,0 +1,48 @@
+use broadcast_chat_application::testing::WsClient;
+use broadcast_chat_application::{Config, Server};
+use reqwest::StatusCode;
+
+async fn server_with_hooks() -> Server {
+    Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        hook_token: Some("s3cret".to_string()),
+        ..Config::default()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn hook_injects_message() {
+    let server = server_with_hooks().await;
+    let mut client = WsClient::connect(&server).await.unwrap();
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/hooks", server.http_addr().unwrap()))
+        .bearer_auth("s3cret")
+        .body("build #42 passed\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        client.recv().await.as_deref(),
+        Some("[#1] build #42 passed")
+    );
+}
+
+#[tokio::test]
+async fn hook_rejects_bad_token() {
+    let server = server_with_hooks().await;
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/hooks", server.http_addr().unwrap()))
+        .bearer_auth("guess")
+        .body("spam")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
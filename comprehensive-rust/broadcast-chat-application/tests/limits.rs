@@ -0,0 +1,76 @@
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+use futures_util::stream::StreamExt;
+use http::Uri;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_websockets::ClientBuilder;
+
+#[tokio::test]
+async fn turns_away_connections_past_the_per_address_limit() {
+    let server = Server::start(Config {
+        max_connections_per_ip: 2,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    let uri: Uri = format!("ws://{}", server.ws_addr()).parse().unwrap();
+    let (mut ws_stream, _) = ClientBuilder::from_uri(uri).connect().await.unwrap();
+    let msg = ws_stream.next().await.unwrap().unwrap();
+    let (_, reason) = msg.as_close().unwrap();
+    assert_eq!(reason, "too many connections from your address");
+
+    let mut carol = LineClient::connect(&server).await.unwrap();
+    assert_eq!(
+        carol.recv().await.as_deref(),
+        Some("error: too many connections from your address")
+    );
+    assert_eq!(carol.recv().await, None);
+
+    // leaving frees a place
+    alice.close().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut dave = WsClient::connect(&server).await.unwrap();
+    dave.send("made it").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("[#1] made it"));
+}
+
+#[tokio::test]
+async fn stops_accepting_at_the_connection_limit() {
+    let server = Server::start(Config {
+        max_connections: 1,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let alice = WsClient::connect(&server).await.unwrap();
+
+    // the handshake waits until there is room
+    let bob = tokio::spawn(WsClient::connect_to(server.ws_addr()));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!bob.is_finished());
+
+    alice.close().await.unwrap();
+    let mut bob = bob.await.unwrap().unwrap();
+    bob.send("hello").await.unwrap();
+}
+
+#[tokio::test]
+async fn gives_up_on_connections_that_never_finish_the_handshake() {
+    let server = Server::start(Config {
+        max_connections: 1,
+        handshake_timeout: Duration::from_millis(200),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    // takes the only place and then says nothing
+    let _silent = TcpStream::connect(server.ws_addr()).await.unwrap();
+
+    let bob = tokio::time::timeout(Duration::from_secs(2), WsClient::connect(&server)).await;
+    let mut bob = bob.expect("the silent connection kept its place").unwrap();
+    bob.send("hello").await.unwrap();
+}
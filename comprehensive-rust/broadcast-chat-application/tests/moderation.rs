@@ -0,0 +1,72 @@
+use broadcast_chat_application::testing::LineClient;
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+#[tokio::test]
+async fn moderators_ban_and_unban_addresses() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        moderator_token: Some("sesame".to_string()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = LineClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    alice.send("/ban 127.0.0.1").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("error: message rejected: only moderators may ban")
+    );
+    alice.send("/mod open up").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("error: message rejected: wrong moderator token")
+    );
+    alice.send("/mod sesame").await.unwrap();
+    assert_eq!(
+        alice.recv().await.as_deref(),
+        Some("* you are now a moderator")
+    );
+
+    alice.send("/ban").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* nobody is banned"));
+    alice.send("/ban 127.0.0.1").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* banned 127.0.0.1"));
+    alice.send("/ban").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* banned: 127.0.0.1"));
+
+    // silenced, and turned away when they come back
+    bob.send("hello").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("error: message rejected: you are banned")
+    );
+    let mut carol = LineClient::connect(&server).await.unwrap();
+    assert_eq!(carol.recv().await.as_deref(), Some("error: you are banned"));
+    assert_eq!(carol.recv().await, None);
+
+    alice.send("/unban 127.0.0.1").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* unbanned 127.0.0.1"));
+    bob.send("hello again").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("[#1] hello again"));
+}
+
+#[tokio::test]
+async fn names_are_remembered_as_users() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = LineClient::connect(&server).await.unwrap();
+    alice.send("/nick alice").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* you are now alice"));
+    drop(alice);
+
+    let url = format!("http://{}/users", server.http_addr().unwrap());
+    let users: Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+    assert_eq!(users, serde_json::json!(["alice"]));
+}
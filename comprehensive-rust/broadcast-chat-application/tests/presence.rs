@@ -0,0 +1,30 @@
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+
+#[tokio::test]
+async fn roster_snapshot_and_deltas() {
+    let server = Server::start(Config {
+        presence: true,
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let snapshot = alice.recv().await.unwrap();
+    assert!(snapshot.starts_with("* online: 127.0.0.1:"), "{snapshot}");
+
+    let mut bob = LineClient::connect(&server).await.unwrap();
+    let snapshot = bob.recv().await.unwrap();
+    assert_eq!(snapshot.matches("127.0.0.1:").count(), 2, "{snapshot}");
+
+    let joined = alice.recv().await.unwrap();
+    assert!(
+        joined.starts_with("* ") && joined.ends_with(" joined"),
+        "{joined}"
+    );
+
+    alice.close().await.unwrap();
+    let left = bob.recv().await.unwrap();
+    assert!(left.starts_with("* ") && left.ends_with(" left"), "{left}");
+}
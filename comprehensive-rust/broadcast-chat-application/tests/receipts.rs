@@ -0,0 +1,76 @@
+use broadcast_chat_application::testing::{parse_message, LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+#[tokio::test]
+async fn read_markers_give_unread_counts_and_seen_by() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    let mut ids = Vec::new();
+    for text in ["one", "two", "three"] {
+        alice.send(text).await.unwrap();
+        let line = bob.recv().await.unwrap();
+        let (id, received) = parse_message(&line).unwrap();
+        assert_eq!(received, text);
+        ids.push(id);
+    }
+    let [one, two, three] = ids[..] else {
+        unreachable!()
+    };
+
+    // markers are kept by name
+    bob.send("/unread").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("error: message rejected: pick a name with /nick <name> first")
+    );
+    bob.send("/nick bob smith").await.unwrap();
+    assert!(bob.recv().await.unwrap().starts_with("error: "));
+    bob.send("/nick bob").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("* you are now bob"));
+
+    bob.send("/unread").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("* 3 unread"));
+    bob.send(&format!("/read #{two}")).await.unwrap();
+    let read_up_to_two = format!("* read up to #{two}");
+    assert_eq!(bob.recv().await, Some(read_up_to_two.clone()));
+    // reports arriving out of order don't move the marker back
+    bob.send(&format!("/read {one}")).await.unwrap();
+    assert_eq!(bob.recv().await, Some(read_up_to_two));
+    bob.send("/unread").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("* 1 unread"));
+    bob.send(&format!("/read {}", three + 1)).await.unwrap();
+    assert_eq!(
+        bob.recv().await,
+        Some(format!(
+            "error: message rejected: no message #{}",
+            three + 1
+        ))
+    );
+
+    alice.send(&format!("/seen {three}")).await.unwrap();
+    assert_eq!(alice.recv().await, Some(format!("* #{three} not seen yet")));
+    alice.send(&format!("/seen {two}")).await.unwrap();
+    assert_eq!(alice.recv().await, Some(format!("* #{two} seen by: bob")));
+
+    // and outlast the connection
+    drop(bob);
+    let mut bob = LineClient::connect(&server).await.unwrap();
+    bob.send("/nick bob").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("* you are now bob"));
+    bob.send("/unread").await.unwrap();
+    assert_eq!(bob.recv().await.as_deref(), Some("* 1 unread"));
+
+    let url = format!("http://{}/receipts", server.http_addr().unwrap());
+    let receipts: Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+    assert_eq!(receipts.as_array().unwrap().len(), 1);
+    assert_eq!(receipts[0]["user"], "bob");
+    assert_eq!(receipts[0]["last_read"], two);
+}
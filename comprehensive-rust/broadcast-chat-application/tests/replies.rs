@@ -0,0 +1,43 @@
+use broadcast_chat_application::testing::{parse_message, LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+use serde_json::Value;
+
+#[tokio::test]
+async fn replies_quote_the_message_they_answer() {
+    let server = Server::start(Config {
+        http_addr: Some(([127, 0, 0, 1], 0).into()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = LineClient::connect(&server).await.unwrap();
+
+    alice.send("lunch?").await.unwrap();
+    let line = bob.recv().await.unwrap();
+    let (id, text) = parse_message(&line).unwrap();
+    assert_eq!(text, "lunch?");
+    bob.send(&format!("/reply #{id} sure")).await.unwrap();
+    let line = alice.recv().await.unwrap();
+    let (reply_id, text) = parse_message(&line).unwrap();
+    assert_eq!(text, format!("[re #{id}] sure"));
+    assert_ne!(reply_id, id);
+
+    bob.send("/reply 42 what?").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("error: message rejected: no message #42")
+    );
+    bob.send("/reply soon").await.unwrap();
+    assert_eq!(
+        bob.recv().await.as_deref(),
+        Some("error: message rejected: usage: /reply <id> <text>")
+    );
+
+    let url = format!("http://{}/messages", server.http_addr().unwrap());
+    let history: Value = reqwest::get(url).await.unwrap().json().await.unwrap();
+    assert_eq!(history[0]["reply_to"], Value::Null);
+    assert_eq!(history[1]["text"], "sure");
+    assert_eq!(history[1]["id"], reply_id);
+    assert_eq!(history[1]["reply_to"], id);
+}
@@ -0,0 +1,26 @@
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::Server;
+
+#[tokio::test]
+async fn topic_is_broadcast_and_greets_newcomers() {
+    let server = Server::ephemeral().await.unwrap();
+    let mut alice = WsClient::connect(&server).await.unwrap();
+    let mut bob = WsClient::connect(&server).await.unwrap();
+
+    alice.send("/topic").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* no topic is set"));
+
+    alice.send("/topic release planning").await.unwrap();
+    assert_eq!(alice.recv().await.as_deref(), Some("* topic changed"));
+    let notice = bob.recv().await.unwrap();
+    assert!(
+        notice.ends_with(" changed the topic to: release planning"),
+        "{notice}"
+    );
+
+    let mut carol = LineClient::connect(&server).await.unwrap();
+    assert_eq!(
+        carol.recv().await.as_deref(),
+        Some("* topic: release planning")
+    );
+}
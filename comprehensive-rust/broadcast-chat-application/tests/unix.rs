@@ -0,0 +1,46 @@
+#![cfg(unix)]
+
+use broadcast_chat_application::testing::{LineClient, WsClient};
+use broadcast_chat_application::{Config, Server};
+
+#[tokio::test]
+async fn local_clients_share_the_bus() {
+    let path = std::env::temp_dir().join(format!("chat-test-{}.sock", std::process::id()));
+    // stands in for a socket left behind by a crashed server
+    std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let server = Server::start(Config {
+        unix_path: Some(path.clone()),
+        ..Config::default()
+    })
+    .await
+    .unwrap();
+    let mut bot = LineClient::connect_unix(&path).await.unwrap();
+    let mut other_bot = LineClient::connect_unix(&path).await.unwrap();
+    let mut ws = WsClient::connect(&server).await.unwrap();
+
+    bot.send("beep").await.unwrap();
+    assert_eq!(ws.recv().await.as_deref(), Some("[#1] beep"));
+    assert_eq!(other_bot.recv().await.as_deref(), Some("[#1] beep"));
+
+    ws.send("boop").await.unwrap();
+    assert_eq!(bot.recv().await.as_deref(), Some("[#2] boop"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn leaves_a_live_socket_alone() {
+    let path = std::env::temp_dir().join(format!("chat-test-live-{}.sock", std::process::id()));
+    let _other = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let started = Server::start(Config {
+        unix_path: Some(path.clone()),
+        ..Config::default()
+    })
+    .await;
+    assert!(started.is_err());
+    assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,94 @@
+use crate::monitor::OnDeadlock;
+use crate::strategy::Kind;
+use crate::{Config, Report, Runtime, Simulation};
+use clap::ValueEnum;
+use std::fmt;
+
+/// The same dinner served once per strategy, for comparing them.
+pub struct Bench {
+    pub reports: Vec<Report>,
+}
+
+impl Bench {
+    /// Runs `config` with every strategy that can finish it: the naive one only
+    /// if deadlocks are recovered from, and only those that share sauce bowls
+    /// if there are any.
+    pub fn run(config: &Config) -> Self {
+        let kinds = Kind::value_variants().iter().copied();
+        let reports = kinds
+            .filter(|kind| {
+                !matches!(kind, Kind::Naive) || config.on_deadlock == OnDeadlock::Recover
+            })
+            .filter(|kind| kind.handles_bowls() || config.sauce_bowls == 0)
+            .map(|strategy| {
+                let config = Config {
+                    strategy,
+                    runtime: Runtime::Threads,
+                    ..config.clone()
+                };
+                Simulation::new(config).run()
+            })
+            .collect();
+        Self { reports }
+    }
+}
+
+impl fmt::Display for Bench {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<14}{:>10}{:>10}{:>12}{:>12}{:>9}",
+            "strategy", "time", "meals/s", "wait p50", "wait p99", "gave up"
+        )?;
+        for report in &self.reports {
+            let waits = report.waits();
+            let failures: usize = report.fared.iter().map(|fared| fared.failures).sum();
+            write!(
+                f,
+                "\n{:<14}{:>10}{:>10.0}{:>12}{:>12}{:>9}",
+                report.strategy.to_possible_value().unwrap().get_name(),
+                format!("{:.1?}", report.elapsed),
+                report.meals_per_sec(),
+                format!("{:.1?}", waits.percentile(50.0)),
+                format!("{:.1?}", waits.percentile(99.0)),
+                failures
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn compares_the_strategies_that_can_finish() {
+        let config = Config {
+            philosophers: 3,
+            rounds: 10,
+            eat_time: Duration::from_micros(100),
+            sauce_bowls: 1,
+            ..Config::default()
+        };
+        let bench = Bench::run(&config);
+
+        let strategies: Vec<_> = bench.reports.iter().map(|r| r.strategy).collect();
+        assert!(matches!(
+            strategies[..],
+            [
+                Kind::Ordering,
+                Kind::Waiter,
+                Kind::Actor,
+                Kind::SeatLimit,
+                Kind::Condvar,
+                Kind::Backoff
+            ]
+        ));
+        assert!(bench.reports.iter().all(|r| r.meals() == 3 * 10));
+        let table = bench.to_string();
+        assert_eq!(table.lines().count(), 1 + 6);
+        assert!(table.lines().nth(5).unwrap().starts_with("condvar "));
+    }
+}
@@ -0,0 +1,411 @@
+use clap::ValueEnum;
+use metrics::{Fairness, Fared, Histogram};
+use monitor::{Event, Monitor, OnDeadlock};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use strategy::{Kind, Layout};
+use trace::Trace;
+
+pub mod bench;
+pub mod metrics;
+pub mod monitor;
+mod rng;
+pub mod strategy;
+mod tasks;
+mod threads;
+pub mod trace;
+
+pub use rng::{Distribution, Rng};
+
+static PHILOSOPHERS: &[&str] = &["Socrates", "Hypatia", "Plato", "Aristotle", "Pythagoras"];
+
+/// Past the first five, names repeat with a number: "Socrates 2", ...
+pub fn name(i: usize) -> String {
+    let name = PHILOSOPHERS[i % PHILOSOPHERS.len()];
+    match i / PHILOSOPHERS.len() {
+        0 => name.to_string(),
+        n => format!("{name} {}", n + 1),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Runtime {
+    /// A thread per philosopher, blocking on std mutexes
+    Threads,
+    /// A tokio task per philosopher, awaiting tokio mutexes; always uses
+    /// resource ordering
+    Tokio,
+}
+
+/// How one philosopher differs from the rest of the table; whatever is left out
+/// is as for everyone else.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Appetite {
+    pub eat_time: Option<Duration>,
+    pub think_time: Option<Duration>,
+    /// How many meals they want after every thought, picking up what they need
+    /// afresh for each; a greedy philosopher wants more than the usual one
+    pub priority: Option<usize>,
+}
+
+impl Appetite {
+    /// Parses "SEAT:eat=MS,think=MS,priority=N", any of the keys left out.
+    pub fn parse(s: &str) -> Result<(usize, Self), String> {
+        let (seat, rest) = s.split_once(':').unwrap_or((s, ""));
+        let seat = seat.parse().map_err(|_| format!("not a seat: {seat}"))?;
+        let mut appetite = Self::default();
+        for setting in rest.split(',').filter(|setting| !setting.is_empty()) {
+            let Some((key, value)) = setting.split_once('=') else {
+                return Err(format!("expected key=value: {setting}"));
+            };
+            let value: u64 = value
+                .parse()
+                .map_err(|_| format!("not a number: {value}"))?;
+            match key {
+                "eat" => appetite.eat_time = Some(Duration::from_millis(value)),
+                "think" => appetite.think_time = Some(Duration::from_millis(value)),
+                "priority" if value > 0 => appetite.priority = Some(value as usize),
+                "priority" => return Err("priority must be at least 1".to_string()),
+                _ => return Err(format!("expected eat, think or priority: {key}")),
+            }
+        }
+        Ok((seat, appetite))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub philosophers: usize,
+    /// Shared round the table, philosopher `i` needing bowl `i % sauce_bowls`
+    /// as well as their chopsticks; only the threads runtime has any
+    pub sauce_bowls: usize,
+    /// How many times each philosopher thinks and eats
+    pub rounds: usize,
+    /// How long before everyone is asked to leave, whether or not they have
+    /// had all their rounds
+    pub duration: Option<Duration>,
+    /// How long meals and thoughts last on average, each drawn from
+    /// `distribution` by an `Rng` seeded with `seed`
+    pub eat_time: Duration,
+    pub think_time: Duration,
+    pub distribution: Distribution,
+    /// By seat, for philosophers unlike the rest
+    pub appetites: HashMap<usize, Appetite>,
+    pub seed: u64,
+    pub strategy: Kind,
+    pub runtime: Runtime,
+    /// How long nobody may eat before the table counts as deadlocked
+    pub watchdog: Duration,
+    pub on_deadlock: OnDeadlock,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            philosophers: 5,
+            sauce_bowls: 0,
+            rounds: 100,
+            duration: None,
+            eat_time: Duration::from_millis(10),
+            think_time: Duration::ZERO,
+            distribution: Distribution::default(),
+            appetites: HashMap::new(),
+            seed: 0,
+            strategy: Kind::Ordering,
+            runtime: Runtime::Threads,
+            watchdog: Duration::from_secs(1),
+            on_deadlock: OnDeadlock::Abort,
+        }
+    }
+}
+
+impl Config {
+    fn appetite(&self, seat: usize) -> Appetite {
+        self.appetites.get(&seat).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn eat_time(&self, seat: usize) -> Duration {
+        self.appetite(seat).eat_time.unwrap_or(self.eat_time)
+    }
+
+    pub(crate) fn think_time(&self, seat: usize) -> Duration {
+        self.appetite(seat).think_time.unwrap_or(self.think_time)
+    }
+
+    pub(crate) fn priority(&self, seat: usize) -> usize {
+        self.appetite(seat).priority.unwrap_or(1)
+    }
+}
+
+/// Asks the philosophers to leave the table once they have finished what they
+/// are eating, whatever round they are on.
+#[derive(Clone, Debug, Default)]
+pub struct Stop(Arc<AtomicBool>);
+
+impl Stop {
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A dinner waiting to be served.
+pub struct Simulation {
+    config: Config,
+    monitor: Monitor,
+    stop: Stop,
+}
+
+impl Simulation {
+    pub fn new(config: Config) -> Self {
+        let monitor = Monitor::new(config.philosophers);
+        Self {
+            config,
+            monitor,
+            stop: Stop::default(),
+        }
+    }
+
+    pub fn with_trace(mut self, trace: Trace) -> Self {
+        self.monitor = self.monitor.with_trace(trace);
+        self
+    }
+
+    /// Sends everything the philosophers do to `events` as it happens.
+    pub fn with_events(mut self, events: mpsc::Sender<Event>) -> Self {
+        self.monitor = self.monitor.with_events(events);
+        self
+    }
+
+    /// Something to end the dinner early with, from another thread or a signal
+    /// handler.
+    pub fn stopper(&self) -> Stop {
+        self.stop.clone()
+    }
+
+    /// Runs the dinner until everyone has eaten their rounds, until it is
+    /// stopped or runs out of time, or until it deadlocks.
+    pub fn run(self) -> Report {
+        let config = self.config;
+        let monitor = Arc::new(self.monitor);
+        let stop = self.stop;
+        if let Some(duration) = config.duration {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                thread::sleep(duration);
+                stop.stop();
+            });
+        }
+        let start = Instant::now();
+        let thoughts = match config.runtime {
+            Runtime::Threads => {
+                let layout = Layout {
+                    seats: config.philosophers,
+                    bowls: config.sauce_bowls,
+                };
+                let table = config.strategy.build(layout, monitor.clone(), config.seed);
+                let _watchdog =
+                    monitor::watch(monitor.clone(), config.watchdog, config.on_deadlock);
+                threads::run(&config, table, monitor.clone(), &stop)
+            }
+            Runtime::Tokio => tasks::run(&config, monitor.clone(), &stop),
+        };
+        let elapsed = start.elapsed();
+        monitor.flush();
+        Report {
+            seed: config.seed,
+            strategy: match config.runtime {
+                Runtime::Threads => config.strategy,
+                Runtime::Tokio => Kind::Ordering,
+            },
+            thoughts,
+            fared: monitor.fared(),
+            elapsed,
+            stopped: stop.is_stopped(),
+            deadlocked: monitor.deadlocked(),
+        }
+    }
+}
+
+/// How a dinner went.
+#[derive(Debug)]
+pub struct Report {
+    pub seed: u64,
+    pub strategy: Kind,
+    pub thoughts: Vec<String>,
+    /// By seat
+    pub fared: Vec<Fared>,
+    /// From the first philosopher sitting down to the last getting up
+    pub elapsed: Duration,
+    /// Whether the dinner was cut short
+    pub stopped: bool,
+    /// Whether it ended in a deadlock, with `OnDeadlock::Abort`; those stuck at
+    /// the table are left there
+    pub deadlocked: bool,
+}
+
+impl Report {
+    pub fn meals(&self) -> usize {
+        self.fared.iter().map(|fared| fared.meals).sum()
+    }
+
+    pub fn meals_per_sec(&self) -> f64 {
+        self.meals() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn fairness(&self) -> Fairness {
+        Fairness::of(&self.fared)
+    }
+
+    /// Everyone's waits for chopsticks together.
+    pub fn waits(&self) -> Histogram {
+        let mut waits = Histogram::default();
+        for fared in &self.fared {
+            waits.merge(&fared.waits);
+        }
+        waits
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, fared) in self.fared.iter().enumerate() {
+            writeln!(
+                f,
+                "{} ate {} times, went hungry for at most {:.1?} and gave up on a chopstick {} times",
+                name(i),
+                fared.meals,
+                fared.longest_hunger,
+                fared.failures
+            )?;
+        }
+        writeln!(f, "{}", self.fairness())?;
+
+        for (i, fared) in self.fared.iter().enumerate() {
+            writeln!(f, "{} waited for chopsticks: {}", name(i), fared.waits)?;
+        }
+        let strategy = self.strategy.to_possible_value().unwrap();
+        writeln!(
+            f,
+            "{} waited for chopsticks: {}",
+            strategy.get_name(),
+            self.waits()
+        )?;
+        writeln!(
+            f,
+            "{} meals in {:.1?}, {:.0} a second",
+            self.meals(),
+            self.elapsed,
+            self.meals_per_sec()
+        )?;
+        if self.stopped {
+            writeln!(f, "stopped before everyone had all their rounds")?;
+        }
+        if self.deadlocked {
+            writeln!(f, "deadlocked before everyone had all their rounds")?;
+        }
+        write!(f, "seed: {}", self.seed)
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_a_dinner() {
+        let config = Config {
+            philosophers: 3,
+            rounds: 20,
+            eat_time: Duration::from_micros(100),
+            seed: 42,
+            strategy: Kind::ChandyMisra,
+            ..Config::default()
+        };
+        let report = Simulation::new(config).run();
+
+        assert_eq!(report.thoughts.len(), 3 * 20);
+        assert!(report.fared.iter().all(|fared| fared.meals == 20));
+        assert_eq!(report.waits().count(), 3 * 20);
+        assert!(report.to_string().ends_with("seed: 42"));
+    }
+
+    #[test]
+    fn a_greedy_philosopher_eats_more() {
+        let (seat, appetite) = Appetite::parse("1:eat=0,priority=3").unwrap();
+        let config = Config {
+            philosophers: 3,
+            rounds: 10,
+            eat_time: Duration::from_micros(100),
+            appetites: HashMap::from([(seat, appetite)]),
+            ..Config::default()
+        };
+        let report = Simulation::new(config).run();
+
+        let meals: Vec<_> = report.fared.iter().map(|fared| fared.meals).collect();
+        assert_eq!(meals, [10, 30, 10]);
+        assert_eq!(report.thoughts.len(), 3 * 10);
+    }
+
+    #[test]
+    fn stops_in_time() {
+        let config = Config {
+            philosophers: 3,
+            rounds: usize::MAX,
+            duration: Some(Duration::from_millis(50)),
+            ..Config::default()
+        };
+        let report = Simulation::new(config).run();
+
+        assert!(report.stopped);
+        assert!(report.elapsed < Duration::from_secs(1));
+        assert!(report.fared.iter().all(|fared| fared.meals > 0));
+        assert!(report.to_string().contains("stopped before"));
+    }
+
+    #[test]
+    fn reports_a_deadlock() {
+        // everyone reaches for their left chopstick together and waits for
+        // their right one, however long the meals
+        let config = Config {
+            philosophers: 3,
+            rounds: usize::MAX,
+            duration: Some(Duration::from_secs(30)),
+            eat_time: Duration::from_millis(20),
+            distribution: Distribution::Fixed,
+            strategy: Kind::Naive,
+            watchdog: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let report = Simulation::new(config).run();
+
+        assert!(report.deadlocked);
+        assert!(report.to_string().contains("deadlocked before"));
+    }
+
+    #[test]
+    fn parses_appetites() {
+        assert_eq!(
+            Appetite::parse("2:think=5,priority=2"),
+            Ok((
+                2,
+                Appetite {
+                    eat_time: None,
+                    think_time: Some(Duration::from_millis(5)),
+                    priority: Some(2),
+                }
+            ))
+        );
+        assert_eq!(Appetite::parse("0"), Ok((0, Appetite::default())));
+        assert!(Appetite::parse("0:priority=0").is_err());
+        assert!(Appetite::parse("0:sleep=5").is_err());
+        assert!(Appetite::parse("Plato:eat=5").is_err());
+    }
+}
@@ -1,98 +1,226 @@
 #![allow(dead_code)]
 
-use std::sync::{mpsc, Arc, Mutex};
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use dinning_philosophers::bench::Bench;
+use dinning_philosophers::monitor::OnDeadlock;
+use dinning_philosophers::strategy::Kind;
+use dinning_philosophers::trace::Trace;
+use dinning_philosophers::{Appetite, Config, Distribution, Runtime, Simulation};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-#[derive(Debug)]
-struct Chopstick;
-
-#[derive(Debug)]
-struct Philosopher {
-    name: String,
-    left_chopstick: Arc<Mutex<Chopstick>>,
-    right_chopstick: Arc<Mutex<Chopstick>>,
-    thoughts: mpsc::Sender<String>,
+mod progress;
+#[cfg(feature = "tui")]
+mod tui;
+
+#[derive(Parser)]
+struct Args {
+    /// Number of philosophers at the table
+    #[clap(long, default_value_t = 5, value_parser = RangedU64ValueParser::<usize>::new().range(2..))]
+    philosophers: usize,
+
+    /// Number of sauce bowls shared round the table, each philosopher needing
+    /// one as well as their chopsticks
+    #[clap(long, default_value_t = 0)]
+    sauce_bowls: usize,
+
+    /// How many times each philosopher thinks and eats; 100 unless
+    /// --duration is given, in which case as many as there is time for
+    #[clap(long)]
+    rounds: Option<usize>,
+
+    /// How long the dinner may go on for, e.g. 30s or 2m, after which everyone
+    /// finishes what they are eating and leaves
+    #[clap(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    /// How long a meal takes on average, in milliseconds
+    #[clap(long, default_value_t = 10)]
+    eat_ms: u64,
+
+    /// How long a philosopher thinks between meals on average, in milliseconds
+    #[clap(long, default_value_t = 0)]
+    think_ms: u64,
+
+    /// How meal and thinking times vary around their averages: fixed for not
+    /// at all, uniform[:JITTER] for anywhere within JITTER times the average
+    /// either side of it (0.5 unless given), or exponential
+    #[clap(long, default_value = "fixed", value_parser = Distribution::parse)]
+    distribution: Distribution,
+
+    /// A philosopher unlike the rest, as SEAT:eat=MS,think=MS,priority=N with
+    /// any of the keys left out; a priority of N has them eat N times after
+    /// every thought instead of once
+    #[clap(long, value_parser = Appetite::parse)]
+    appetite: Vec<(usize, Appetite)>,
+
+    /// Seed for the meal and thinking times; random if not given
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// How the philosophers avoid deadlock
+    #[clap(long, value_enum, default_value = "ordering")]
+    strategy: Kind,
+
+    /// What the philosophers run on
+    #[clap(long, value_enum, default_value = "threads")]
+    runtime: Runtime,
+
+    /// How long nobody may eat before the table counts as deadlocked, in
+    /// milliseconds
+    #[clap(long, default_value_t = 1000)]
+    watchdog_ms: u64,
+
+    /// What to do about a deadlock
+    #[clap(long, value_enum, default_value = "abort")]
+    on_deadlock: OnDeadlock,
+
+    /// Write every think, hungry, pickup, eat and putdown event to this file,
+    /// one JSON object per line
+    #[clap(long)]
+    trace: Option<PathBuf>,
+
+    /// Serve the same dinner with every strategy and compare how long it
+    /// took, the meals per second and how long philosophers waited for
+    /// chopsticks or gave up on them
+    #[clap(long, conflicts_with_all = ["strategy", "runtime", "trace"])]
+    bench: bool,
+
+    /// Don't print everyone's meals so far every second, only the report at
+    /// the end
+    #[clap(long, short)]
+    quiet: bool,
+
+    /// Show the table live in the terminal instead of printing progress
+    #[cfg(feature = "tui")]
+    #[clap(long, conflicts_with_all = ["bench", "quiet"])]
+    tui: bool,
 }
 
-impl Philosopher {
-    fn new(
-        name: impl Into<String>,
-        left_chopstick: Arc<Mutex<Chopstick>>,
-        right_chopstick: Arc<Mutex<Chopstick>>,
-        thoughts: mpsc::Sender<String>,
-    ) -> Self {
-        Self {
-            name: name.into(),
-            left_chopstick,
-            right_chopstick,
-            thoughts,
+impl Args {
+    fn config(&self) -> Config {
+        Config {
+            philosophers: self.philosophers,
+            sauce_bowls: self.sauce_bowls,
+            rounds: match (self.rounds, self.duration) {
+                (Some(rounds), _) => rounds,
+                (None, Some(_)) => usize::MAX,
+                (None, None) => 100,
+            },
+            duration: self.duration,
+            eat_time: Duration::from_millis(self.eat_ms),
+            think_time: Duration::from_millis(self.think_ms),
+            distribution: self.distribution,
+            appetites: self.appetite.iter().copied().collect(),
+            seed: self
+                .seed
+                .unwrap_or_else(|| RandomState::new().build_hasher().finish()),
+            strategy: self.strategy,
+            runtime: self.runtime,
+            watchdog: Duration::from_millis(self.watchdog_ms),
+            on_deadlock: self.on_deadlock,
         }
     }
+}
 
-    fn think(&self) {
-        self.thoughts
-            .send(format!("Eureka! {} has a new idea!", &self.name))
-            .unwrap();
+fn main() {
+    let args = Args::parse();
+    if args.runtime == Runtime::Tokio && !matches!(args.strategy, Kind::Ordering) {
+        let msg = "only the ordering strategy runs on tokio";
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, msg)
+            .exit();
+    }
+    if args.sauce_bowls > 0 && (args.runtime == Runtime::Tokio || !args.strategy.handles_bowls()) {
+        let msg = "sauce bowls need the threads runtime and a strategy other than chandy-misra";
+        Args::command()
+            .error(ErrorKind::ArgumentConflict, msg)
+            .exit();
     }
 
-    fn eat(&self) {
-        // Pick up chopsticks...
-        let _l_ch = self.left_chopstick.lock().unwrap();
-        let _r_ch = self.right_chopstick.lock().unwrap();
-        println!("{} is eating...", &self.name);
-        thread::sleep(Duration::from_millis(10));
+    if let Some((seat, _)) = args
+        .appetite
+        .iter()
+        .find(|(seat, _)| *seat >= args.philosophers)
+    {
+        let msg = format!(
+            "there is no seat {seat} at a table of {}",
+            args.philosophers
+        );
+        Args::command()
+            .error(ErrorKind::ValueValidation, msg)
+            .exit();
     }
-}
 
-static PHILOSOPHERS: &[&str] = &["Socrates", "Hypatia", "Plato", "Aristotle", "Pythagoras"];
+    if args.bench {
+        println!("{}", Bench::run(&args.config()));
+        return;
+    }
 
-fn main() {
-    let (tx, rx) = mpsc::channel();
-    let num_of_philosophers = PHILOSOPHERS.len();
-
-    // Create chopsticks
-    let chopsticks: Vec<_> = (0..num_of_philosophers)
-        .map(|_| Arc::new(Mutex::new(Chopstick)))
-        .collect();
-
-    // Create philosophers
-    // The 'chainned' philosopher's chopsticks are inverted to prevent deadlock
-    let philosophers: Vec<_> = chopsticks
-        .windows(2)
-        .enumerate()
-        .map(|(i, w)| Philosopher::new(PHILOSOPHERS[i], w[0].clone(), w[1].clone(), tx.clone()))
-        .chain([Philosopher::new(
-            PHILOSOPHERS[num_of_philosophers - 1],
-            chopsticks[0].clone(),
-            chopsticks[num_of_philosophers - 1].clone(),
-            tx.clone(),
-        )])
-        .collect();
-
-    // Make each of them think and eat 100 times
-    for philosopher in philosophers {
-        thread::spawn(move || {
-            for _ in 0..100 {
-                philosopher.think();
-                philosopher.eat();
+    // Lay the table
+    let config = args.config();
+    let rounds = config.rounds;
+    let mut simulation = Simulation::new(config);
+    if let Some(path) = &args.trace {
+        match Trace::create(path) {
+            Ok(trace) => simulation = simulation.with_trace(trace),
+            Err(e) => {
+                let msg = format!("could not create {}: {e}", path.display());
+                Args::command().error(ErrorKind::Io, msg).exit();
             }
-        });
+        }
     }
 
-    // Output their thoughts
-    let thoughts_th = thread::spawn(move || {
-        let mut thoughts = Vec::new();
-
-        while let Ok(thought) = rx.recv() {
-            thoughts.push(thought);
+    // The first Ctrl-C lets everyone finish their meal and still reports on
+    // the dinner; a second one doesn't wait
+    let stop = simulation.stopper();
+    let handler = ctrlc::set_handler(move || {
+        if stop.is_stopped() {
+            std::process::exit(130);
         }
-
-        thoughts
+        stop.stop();
     });
+    if let Err(e) = handler {
+        eprintln!("could not handle Ctrl-C: {e}");
+    }
 
-    drop(tx);
-    let thoughts = thoughts_th.join().unwrap();
+    // Show how the dinner is going from what the philosophers do, on this
+    // thread while they eat on another
+    let report = if args.quiet {
+        simulation.run()
+    } else {
+        let (events_tx, events_rx) = mpsc::channel();
+        let simulation = simulation.with_events(events_tx);
+        #[cfg(feature = "tui")]
+        let stop = simulation.stopper();
+        thread::scope(|s| {
+            let dinner = s.spawn(|| simulation.run());
+            let done = || dinner.is_finished();
+            #[cfg(feature = "tui")]
+            if args.tui {
+                if let Err(e) = tui::run(events_rx, args.philosophers, done) {
+                    eprintln!("could not show the table: {e}");
+                }
+                // nobody is watching any more
+                stop.stop();
+                return dinner.join().unwrap();
+            }
+            progress::run(events_rx, args.philosophers, done);
+            dinner.join().unwrap()
+        })
+    };
+    if !report.stopped && !report.deadlocked {
+        assert_eq!(report.thoughts.len(), args.philosophers * rounds);
+    }
 
-    assert_eq!(thoughts.len(), num_of_philosophers * 100);
+    println!("{report}");
+    if report.deadlocked {
+        std::process::exit(1);
+    }
 }
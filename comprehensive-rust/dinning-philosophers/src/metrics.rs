@@ -0,0 +1,186 @@
+use std::fmt;
+use std::time::Duration;
+
+/// How one philosopher fared over the whole dinner.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fared {
+    pub meals: usize,
+    /// The longest they went without eating, between meals or before the first
+    pub longest_hunger: Duration,
+    /// How often they gave up waiting for a chopstick
+    pub failures: usize,
+    /// From getting hungry to holding both chopsticks
+    pub waits: Histogram,
+}
+
+/// How evenly the meals were shared out.
+#[derive(Debug, PartialEq)]
+pub struct Fairness {
+    min: usize,
+    max: usize,
+    mean: f64,
+    stddev: f64,
+}
+
+impl Fairness {
+    pub fn of(fared: &[Fared]) -> Self {
+        let meals = fared.iter().map(|f| f.meals);
+        let n = fared.len().max(1) as f64;
+        let mean = meals.clone().sum::<usize>() as f64 / n;
+        let variance = meals
+            .clone()
+            .map(|m| (m as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        Self {
+            min: meals.clone().min().unwrap_or(0),
+            max: meals.max().unwrap_or(0),
+            mean,
+            stddev: variance.sqrt(),
+        }
+    }
+}
+
+impl fmt::Display for Fairness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "meals: min {}, max {}, mean {:.1}, stddev {:.2}",
+            self.min, self.max, self.mean, self.stddev
+        )
+    }
+}
+
+/// Durations bucketed on a log scale: every power of two of microseconds is
+/// split into `SUB_BUCKETS` equal parts, so a percentile is off by at most an
+/// eighth.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: Duration,
+}
+
+impl Histogram {
+    const SUB_BUCKETS: u64 = 8;
+
+    pub fn record(&mut self, duration: Duration) {
+        let bucket = Self::bucket(duration.as_micros() as u64);
+        if self.counts.len() <= bucket {
+            self.counts.resize(bucket + 1, 0);
+        }
+        self.counts[bucket] += 1;
+        self.total += 1;
+        self.max = self.max.max(duration);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    /// The duration `p` percent of the recorded ones are at most, give or take
+    /// a bucket.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let upper = Duration::from_micros(Self::upper(bucket));
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn bucket(micros: u64) -> usize {
+        if micros < Self::SUB_BUCKETS {
+            return micros as usize;
+        }
+        let shift = micros.ilog2() - Self::SUB_BUCKETS.ilog2();
+        let sub = (micros >> shift) - Self::SUB_BUCKETS;
+        ((shift as u64 + 1) * Self::SUB_BUCKETS + sub) as usize
+    }
+
+    // The largest number of microseconds that goes in `bucket`.
+    fn upper(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < Self::SUB_BUCKETS {
+            return bucket;
+        }
+        let shift = bucket / Self::SUB_BUCKETS - 1;
+        let sub = bucket % Self::SUB_BUCKETS;
+        ((Self::SUB_BUCKETS + sub + 1) << shift) - 1
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {:.1?}, p90 {:.1?}, p99 {:.1?}, max {:.1?}",
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread_of_meals() {
+        let fared = [2, 4, 4, 4, 5, 5, 7, 9].map(|meals| Fared {
+            meals,
+            longest_hunger: Duration::ZERO,
+            failures: 0,
+            waits: Histogram::default(),
+        });
+        let fairness = Fairness::of(&fared);
+        assert_eq!(
+            fairness.to_string(),
+            "meals: min 2, max 9, mean 5.0, stddev 2.00"
+        );
+    }
+
+    #[test]
+    fn buckets_cover_every_duration_once() {
+        for micros in 0..100_000 {
+            let bucket = Histogram::bucket(micros);
+            assert!(micros <= Histogram::upper(bucket), "{micros}");
+            if bucket > 0 {
+                assert!(micros > Histogram::upper(bucket - 1), "{micros}");
+            }
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut waits = Histogram::default();
+        for ms in 1..=100 {
+            waits.record(Duration::from_millis(ms));
+        }
+        let mut more = Histogram::default();
+        more.record(Duration::from_secs(1));
+        waits.merge(&more);
+
+        let p50 = waits.percentile(50.0);
+        assert!(p50 >= Duration::from_millis(51) && p50 < Duration::from_millis(58));
+        assert_eq!(waits.percentile(100.0), Duration::from_secs(1));
+        assert_eq!(Histogram::default().percentile(50.0), Duration::ZERO);
+    }
+}
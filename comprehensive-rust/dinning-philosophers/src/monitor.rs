@@ -0,0 +1,356 @@
+use crate::metrics::{Fared, Histogram};
+use crate::name;
+use crate::strategy::Resource;
+use crate::trace::Trace;
+use clap::ValueEnum;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// What every philosopher is up to, as reported by the strategies, so that a
+// stuck table can be noticed and explained.
+pub(crate) struct Monitor {
+    seats: Vec<Mutex<Seat>>,
+    last_meal: Mutex<Instant>,
+    preempted: Vec<AtomicBool>,
+    // set by the watchdog when it gives up on the table
+    deadlocked: AtomicBool,
+    trace: Option<Trace>,
+    events: Option<mpsc::Sender<Event>>,
+}
+
+/// Something a philosopher did, passed on to whoever watches the table live.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Event {
+    pub seat: usize,
+    /// As named in the trace
+    pub what: &'static str,
+    pub resource: Option<Resource>,
+}
+
+struct Seat {
+    holding: BTreeSet<Resource>,
+    waiting_for: Option<Resource>,
+    eating: bool,
+    meals: usize,
+    failures: usize,
+    // when they last finished eating, or sat down
+    fed_at: Instant,
+    longest_hunger: Duration,
+    hungry_since: Option<Instant>,
+    waits: Histogram,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OnDeadlock {
+    /// Report what everyone was doing and end the dinner
+    Abort,
+    /// Report it and ask a philosopher to put their chopsticks down (only
+    /// the naive strategy listens)
+    Recover,
+}
+
+impl Monitor {
+    pub(crate) fn new(seats: usize) -> Self {
+        Self {
+            seats: (0..seats)
+                .map(|_| {
+                    Mutex::new(Seat {
+                        holding: BTreeSet::new(),
+                        waiting_for: None,
+                        eating: false,
+                        meals: 0,
+                        failures: 0,
+                        fed_at: Instant::now(),
+                        longest_hunger: Duration::ZERO,
+                        hungry_since: None,
+                        waits: Histogram::default(),
+                    })
+                })
+                .collect(),
+            last_meal: Mutex::new(Instant::now()),
+            preempted: (0..seats).map(|_| AtomicBool::new(false)).collect(),
+            deadlocked: AtomicBool::new(false),
+            trace: None,
+            events: None,
+        }
+    }
+
+    pub(crate) fn with_trace(mut self, trace: Trace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub(crate) fn with_events(mut self, events: mpsc::Sender<Event>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn record(&self, seat: usize, what: &'static str, resource: Option<Resource>) {
+        if let Some(trace) = &self.trace {
+            trace.record(seat, what, resource);
+        }
+        if let Some(events) = &self.events {
+            // nobody may be watching any more
+            let _ = events.send(Event {
+                seat,
+                what,
+                resource,
+            });
+        }
+    }
+
+    // Writes out what is left of the trace; the monitor itself lives on in
+    // the watchdog.
+    pub(crate) fn flush(&self) {
+        if let Some(trace) = &self.trace {
+            trace.flush();
+        }
+    }
+
+    pub(crate) fn thinking(&self, seat: usize) {
+        self.record(seat, "think", None);
+    }
+
+    // The philosopher in `seat` has decided to eat, and is about to reach for
+    // what they need.
+    pub(crate) fn hungry(&self, seat: usize) {
+        self.seats[seat].lock().unwrap().hungry_since = Some(Instant::now());
+        self.record(seat, "hungry", None);
+    }
+
+    pub(crate) fn waiting(&self, seat: usize, resource: Option<Resource>) {
+        self.seats[seat].lock().unwrap().waiting_for = resource;
+    }
+
+    pub(crate) fn picked_up(&self, seat: usize, resource: Resource) {
+        {
+            let mut seat = self.seats[seat].lock().unwrap();
+            seat.waiting_for = None;
+            seat.holding.insert(resource);
+        }
+        let event = match resource {
+            // a seat's left chopstick has the same number
+            Resource::Chopstick(c) if c == seat => "pickup-left",
+            Resource::Chopstick(_) => "pickup-right",
+            Resource::SauceBowl(_) => "pickup-bowl",
+        };
+        self.record(seat, event, Some(resource));
+    }
+
+    // Stops waiting for something without getting it.
+    pub(crate) fn gave_up(&self, seat: usize) {
+        let mut seat = self.seats[seat].lock().unwrap();
+        seat.waiting_for = None;
+        seat.failures += 1;
+    }
+
+    pub(crate) fn put_down(&self, seat: usize, resource: Resource) {
+        self.seats[seat].lock().unwrap().holding.remove(&resource);
+        self.record(seat, "putdown", Some(resource));
+    }
+
+    pub(crate) fn eating(&self, seat: usize, eating: bool) {
+        {
+            let mut seat = self.seats[seat].lock().unwrap();
+            seat.eating = eating;
+            if eating {
+                seat.meals += 1;
+                seat.longest_hunger = seat.longest_hunger.max(seat.fed_at.elapsed());
+                if let Some(since) = seat.hungry_since.take() {
+                    seat.waits.record(since.elapsed());
+                }
+            } else {
+                seat.fed_at = Instant::now();
+            }
+        }
+        if eating {
+            self.record(seat, "eat", None);
+        }
+        *self.last_meal.lock().unwrap() = Instant::now();
+    }
+
+    pub(crate) fn fared(&self) -> Vec<Fared> {
+        let seats = self.seats.iter().map(|seat| seat.lock().unwrap());
+        seats
+            .map(|seat| Fared {
+                meals: seat.meals,
+                longest_hunger: seat.longest_hunger,
+                failures: seat.failures,
+                waits: seat.waits.clone(),
+            })
+            .collect()
+    }
+
+    // Asks the philosopher in `seat` to put down what they hold and start
+    // over.
+    fn preempt(&self, seat: usize) {
+        self.preempted[seat].store(true, Ordering::Relaxed);
+    }
+
+    // Whether the philosopher in `seat` was asked to start over since the
+    // last time they checked.
+    pub(crate) fn take_preempted(&self, seat: usize) -> bool {
+        self.preempted[seat].swap(false, Ordering::Relaxed)
+    }
+
+    // Whether the watchdog found the table deadlocked and gave up on it.
+    pub(crate) fn deadlocked(&self) -> bool {
+        self.deadlocked.load(Ordering::Relaxed)
+    }
+
+    // Nobody has eaten for `interval` although somebody is waiting to.
+    fn stuck(&self, interval: Duration) -> bool {
+        if self.last_meal.lock().unwrap().elapsed() < interval {
+            return false;
+        }
+        let seats = self.seats.iter().map(|seat| seat.lock().unwrap());
+        let (mut eating, mut waiting) = (false, false);
+        for seat in seats {
+            eating |= seat.eating;
+            waiting |= seat.waiting_for.is_some();
+        }
+        waiting && !eating
+    }
+
+    fn dump(&self) -> String {
+        let mut dump = String::new();
+        for (i, seat) in self.seats.iter().enumerate() {
+            let seat = seat.lock().unwrap();
+            let holding: Vec<_> = seat.holding.iter().map(Resource::to_string).collect();
+            let holding = if holding.is_empty() {
+                "nothing".to_string()
+            } else {
+                holding.join(" and ")
+            };
+            let _ = write!(dump, "  {} holds {holding}", name(i));
+            if let Some(resource) = seat.waiting_for {
+                let _ = write!(dump, ", waiting for {resource}");
+            }
+            dump.push('\n');
+        }
+        dump
+    }
+
+    // Someone who holds something while waiting for something else.
+    fn victim(&self) -> Option<usize> {
+        self.seats.iter().position(|seat| {
+            let seat = seat.lock().unwrap();
+            seat.waiting_for.is_some() && !seat.holding.is_empty()
+        })
+    }
+}
+
+// Checks on the table in the background, acting once nobody has eaten for
+// `interval`, until dropped.
+pub(crate) struct Watchdog {
+    // dropped to stop the thread
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub(crate) fn watch(monitor: Arc<Monitor>, interval: Duration, action: OnDeadlock) -> Watchdog {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = thread::spawn(move || {
+        loop {
+            match stopped.recv_timeout(interval / 4) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            if !monitor.stuck(interval) {
+                continue;
+            }
+            eprintln!("Nobody has eaten for {interval:?}:\n{}", monitor.dump());
+            if action == OnDeadlock::Abort {
+                // the dinner ends without those stuck at the table
+                monitor.deadlocked.store(true, Ordering::Relaxed);
+                return;
+            }
+            if let Some(seat) = monitor.victim() {
+                eprintln!("Asking {} to put their chopsticks down", name(seat));
+                monitor.preempt(seat);
+            }
+            // give them a chance before stepping in again
+            *monitor.last_meal.lock().unwrap() = Instant::now();
+        }
+    });
+    Watchdog {
+        stop: Some(stop),
+        thread: Some(thread),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stuck_only_when_someone_waits_and_nobody_eats() {
+        let monitor = Monitor::new(2);
+        assert!(!monitor.stuck(Duration::ZERO));
+
+        monitor.picked_up(0, Resource::Chopstick(0));
+        monitor.waiting(0, Some(Resource::Chopstick(1)));
+        monitor.eating(1, true);
+        assert!(!monitor.stuck(Duration::ZERO));
+
+        monitor.eating(1, false);
+        assert!(monitor.stuck(Duration::ZERO));
+        assert!(!monitor.stuck(Duration::from_secs(60)));
+        assert_eq!(monitor.victim(), Some(0));
+        assert_eq!(
+            monitor.dump(),
+            "  Socrates holds chopstick 0, waiting for chopstick 1\n  Hypatia holds nothing\n"
+        );
+    }
+
+    #[test]
+    fn the_watchdog_gives_up_on_a_stuck_table() {
+        let monitor = Arc::new(Monitor::new(2));
+        monitor.waiting(0, Some(Resource::Chopstick(1)));
+        let watchdog = watch(monitor.clone(), Duration::ZERO, OnDeadlock::Abort);
+        let start = Instant::now();
+        while !monitor.deadlocked() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+        drop(watchdog);
+
+        // and stops when asked, without waiting out its interval
+        let watchdog = watch(monitor, Duration::from_secs(60), OnDeadlock::Recover);
+        let start = Instant::now();
+        drop(watchdog);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn counts_meals_and_failures() {
+        let monitor = Monitor::new(2);
+        monitor.hungry(0);
+        monitor.waiting(0, Some(Resource::Chopstick(1)));
+        monitor.gave_up(0);
+        monitor.eating(0, true);
+        monitor.eating(0, false);
+        monitor.eating(0, true);
+
+        let fared = monitor.fared();
+        assert_eq!((fared[0].meals, fared[0].failures), (2, 1));
+        assert_eq!((fared[1].meals, fared[1].failures), (0, 0));
+        // only the first meal was preceded by getting hungry
+        assert_eq!(fared[0].waits.count(), 1);
+        assert!(!monitor.stuck(Duration::ZERO));
+    }
+}
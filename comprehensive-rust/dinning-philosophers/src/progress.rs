@@ -0,0 +1,50 @@
+use dinning_philosophers::monitor::Event;
+use dinning_philosophers::name;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const EVERY: Duration = Duration::from_secs(1);
+// how often to check whether the dinner is over
+const POLL: Duration = Duration::from_millis(50);
+
+// Prints how many meals everyone has had every `EVERY`, counting them from
+// `events`, until `done` says the dinner is over. Only this thread prints, so
+// lines never get mixed up however busy the table is.
+pub(crate) fn run(events: mpsc::Receiver<Event>, seats: usize, done: impl Fn() -> bool) {
+    let start = Instant::now();
+    let mut meals = vec![0; seats];
+    let mut next = start + EVERY;
+    while !done() {
+        match events.recv_timeout(POLL) {
+            Ok(event) if event.what == "eat" => meals[event.seat] += 1,
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        if Instant::now() >= next {
+            println!("{}", line(start.elapsed(), &meals));
+            next += EVERY;
+        }
+    }
+}
+
+fn line(elapsed: Duration, meals: &[usize]) -> String {
+    let meals: Vec<_> = meals
+        .iter()
+        .enumerate()
+        .map(|(i, meals)| format!("{} {meals}", name(i)))
+        .collect();
+    format!("[{elapsed:.1?}] meals: {}", meals.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_everyones_meals() {
+        assert_eq!(
+            line(Duration::from_millis(2500), &[3, 0, 4]),
+            "[2.5s] meals: Socrates 3, Hypatia 0, Plato 4"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// SplitMix64: fast and small, and the same seed always gives the same numbers.
+/// Not for anything that needs to be unpredictable.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// The generator for the philosopher in `seat`, so that what each of them
+    /// draws doesn't depend on how their draws interleave.
+    pub fn for_seat(seed: u64, seat: usize) -> Self {
+        Self(mix(seed ^ mix(seat as u64 + 1)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GAMMA);
+        mix(self.0)
+    }
+
+    /// Uniformly in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+
+    /// A duration from `distribution` averaging `mean`.
+    pub fn draw(&mut self, mean: Duration, distribution: Distribution) -> Duration {
+        match distribution {
+            Distribution::Fixed => mean,
+            Distribution::Uniform { jitter } => {
+                mean.mul_f64(1.0 - jitter + 2.0 * jitter * self.next_f64())
+            }
+            // by inverting the exponential's distribution function
+            Distribution::Exponential => mean.mul_f64(-(1.0 - self.next_f64()).ln()),
+        }
+    }
+}
+
+/// How meal and thinking times vary around their mean.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Distribution {
+    /// Always the mean, so that runs are the same unless asked otherwise
+    #[default]
+    Fixed,
+    /// Anywhere within `jitter` times the mean either side of it
+    Uniform { jitter: f64 },
+    /// Mostly short, now and then several times the mean
+    Exponential,
+}
+
+// the jitter of "uniform" without one
+const DEFAULT_JITTER: f64 = 0.5;
+
+impl Distribution {
+    /// Parses "fixed", "exponential", "uniform" or "uniform:JITTER", the
+    /// jitter between 0 and 1 and a half by default.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            None if s == "fixed" => Ok(Distribution::Fixed),
+            None if s == "uniform" => Ok(Distribution::Uniform {
+                jitter: DEFAULT_JITTER,
+            }),
+            None if s == "exponential" => Ok(Distribution::Exponential),
+            Some(("uniform", jitter)) => match jitter.parse() {
+                Ok(jitter) if (0.0..=1.0).contains(&jitter) => Ok(Distribution::Uniform { jitter }),
+                _ => Err(format!("jitter must be between 0 and 1: {jitter}")),
+            },
+            _ => Err(format!(
+                "expected fixed, uniform[:JITTER] or exponential: {s}"
+            )),
+        }
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let draw = |mut rng: Rng| -> Vec<u64> { (0..5).map(|_| rng.next_u64()).collect() };
+        assert_eq!(draw(Rng::new(7)), draw(Rng::new(7)));
+        assert_ne!(draw(Rng::new(7)), draw(Rng::new(8)));
+        assert_ne!(draw(Rng::for_seat(7, 0)), draw(Rng::for_seat(7, 1)));
+        // the reference output for seed 0
+        assert_eq!(Rng::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+
+        let mut rng = Rng::new(7);
+        let mean = Duration::from_millis(10);
+        assert!((0..1000)
+            .map(|_| rng.draw(mean, Distribution::Uniform { jitter: 0.5 }))
+            .all(|d| d >= mean / 2 && d < mean * 3 / 2));
+    }
+
+    #[test]
+    fn draws_from_each_distribution() {
+        let mut rng = Rng::new(7);
+        let mean = Duration::from_millis(10);
+        let mut draws = |distribution| -> Vec<Duration> {
+            (0..10_000).map(|_| rng.draw(mean, distribution)).collect()
+        };
+        let average = |draws: &[Duration]| draws.iter().sum::<Duration>() / draws.len() as u32;
+
+        assert!(draws(Distribution::Fixed).iter().all(|&d| d == mean));
+
+        let uniform = draws(Distribution::Uniform { jitter: 0.1 });
+        assert!(uniform
+            .iter()
+            .all(|&d| d >= mean * 9 / 10 && d < mean * 11 / 10));
+
+        let exponential = draws(Distribution::Exponential);
+        let average = average(&exponential);
+        assert!(average > mean * 9 / 10 && average < mean * 11 / 10);
+        assert!(exponential.iter().any(|&d| d > mean * 4));
+    }
+
+    #[test]
+    fn parses_distributions() {
+        assert_eq!(Distribution::parse("fixed"), Ok(Distribution::Fixed));
+        assert_eq!(
+            Distribution::parse("uniform"),
+            Ok(Distribution::Uniform { jitter: 0.5 })
+        );
+        assert_eq!(Distribution::default(), Distribution::Fixed);
+        assert_eq!(
+            Distribution::parse("uniform:0.2"),
+            Ok(Distribution::Uniform { jitter: 0.2 })
+        );
+        assert!(Distribution::parse("uniform:2").is_err());
+        assert!(Distribution::parse("exponential:1").is_err());
+        assert!(Distribution::parse("normal").is_err());
+    }
+}
@@ -0,0 +1,985 @@
+use crate::monitor::Monitor;
+use crate::Rng;
+use clap::ValueEnum;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::sync::{mpsc, Arc, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Loom's locks when model checking, so that it can explore every way the
+// philosophers' threads interleave.
+#[cfg(feature = "loom")]
+use loom::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(not(feature = "loom"))]
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+#[derive(Debug)]
+pub(crate) struct Chopstick;
+
+/// Something philosophers share and must hold to eat: the chopsticks either side
+/// of them and, if there are any, a sauce bowl. Ordered chopsticks first, then
+/// bowls, each by number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Chopstick(usize),
+    SauceBowl(usize),
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Chopstick(c) => write!(f, "chopstick {c}"),
+            Resource::SauceBowl(b) => write!(f, "sauce bowl {b}"),
+        }
+    }
+}
+
+// What is on the table: a chopstick per seat and `bowls` sauce bowls.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Layout {
+    pub(crate) seats: usize,
+    pub(crate) bowls: usize,
+}
+
+impl Layout {
+    // What the philosopher in `seat` needs to eat: chopsticks `seat` (left)
+    // and `seat + 1` (right), wrapping around, and bowl `seat % bowls`.
+    pub(crate) fn needs(self, seat: usize) -> Vec<Resource> {
+        let (left, right) = neighbours(seat, self.seats);
+        let mut needs = vec![Resource::Chopstick(left), Resource::Chopstick(right)];
+        if self.bowls > 0 {
+            needs.push(Resource::SauceBowl(seat % self.bowls));
+        }
+        needs
+    }
+}
+
+// A way for philosophers sitting around a table to share what is on it without
+// deadlocking.
+pub(crate) trait Strategy: Send + Sync {
+    // Picks up everything the philosopher in `seat` needs, calls `meal` and
+    // puts it all down again.
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut());
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Kind {
+    /// Everyone picks up their lower-numbered chopstick first
+    Ordering,
+    /// Philosophers ask a waiter before picking up chopsticks, one at a time
+    Waiter,
+    /// The waiter owns the chopsticks and philosophers message it for them,
+    /// sharing no locks
+    Actor,
+    /// At most all but one philosopher may reach for chopsticks at once
+    SeatLimit,
+    /// Chandy–Misra: chopsticks are clean or dirty and handed over on request
+    ChandyMisra,
+    /// One lock over the whole table: eat only while neither neighbour is
+    Condvar,
+    /// Give up on the second chopstick after a while and retry later
+    Backoff,
+    /// Left chopstick first, then right; deadlocks sooner or later
+    Naive,
+}
+
+impl Kind {
+    // `seed` is for the strategies that make random choices.
+    pub(crate) fn build(
+        self,
+        layout: Layout,
+        monitor: Arc<Monitor>,
+        seed: u64,
+    ) -> Arc<dyn Strategy> {
+        match self {
+            Kind::Ordering => Arc::new(ResourceOrdering::new(layout, monitor)),
+            Kind::Waiter => Arc::new(Waiter::new(layout, monitor)),
+            Kind::Actor => Arc::new(Actor::new(layout, monitor)),
+            Kind::SeatLimit => Arc::new(SeatLimit::new(layout, monitor)),
+            Kind::ChandyMisra => Arc::new(ChandyMisra::new(layout, monitor)),
+            Kind::Condvar => Arc::new(CondvarTable::new(layout, monitor)),
+            Kind::Backoff => Arc::new(Backoff::new(layout, monitor, seed)),
+            Kind::Naive => Arc::new(Naive::new(layout, monitor)),
+        }
+    }
+
+    /// Whether the strategy can share sauce bowls as well as chopsticks.
+    pub fn handles_bowls(self) -> bool {
+        !matches!(self, Kind::ChandyMisra)
+    }
+}
+
+// What is on the table, with a lock each, telling the monitor who picks up and
+// puts down what.
+struct Resources {
+    layout: Layout,
+    // the chopsticks by number, then the bowls
+    locks: Vec<Mutex<Resource>>,
+    monitor: Arc<Monitor>,
+}
+
+// Something in someone's hands, put down again when dropped.
+struct Held<'a> {
+    guard: MutexGuard<'a, Resource>,
+    monitor: &'a Monitor,
+    seat: usize,
+}
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        self.monitor.put_down(self.seat, *self.guard);
+    }
+}
+
+impl Resources {
+    fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        let chopsticks = (0..layout.seats).map(Resource::Chopstick);
+        let bowls = (0..layout.bowls).map(Resource::SauceBowl);
+        Self {
+            layout,
+            locks: chopsticks.chain(bowls).map(Mutex::new).collect(),
+            monitor,
+        }
+    }
+
+    fn needs(&self, seat: usize) -> Vec<Resource> {
+        self.layout.needs(seat)
+    }
+
+    fn lock(&self, resource: Resource) -> &Mutex<Resource> {
+        match resource {
+            Resource::Chopstick(c) => &self.locks[c],
+            Resource::SauceBowl(b) => &self.locks[self.layout.seats + b],
+        }
+    }
+
+    fn held<'a>(&'a self, seat: usize, guard: MutexGuard<'a, Resource>) -> Held<'a> {
+        self.monitor.picked_up(seat, *guard);
+        Held {
+            guard,
+            monitor: &self.monitor,
+            seat,
+        }
+    }
+
+    fn pick_up(&self, seat: usize, resource: Resource) -> Held<'_> {
+        self.monitor.waiting(seat, Some(resource));
+        let guard = self.lock(resource).lock().unwrap();
+        self.held(seat, guard)
+    }
+
+    // Like `pick_up`, but stops waiting once `give_up` returns true, which is
+    // asked every so often.
+    fn try_pick_up(
+        &self,
+        seat: usize,
+        resource: Resource,
+        mut give_up: impl FnMut() -> bool,
+    ) -> Option<Held<'_>> {
+        const POLL: Duration = Duration::from_micros(100);
+        self.monitor.waiting(seat, Some(resource));
+        loop {
+            match self.lock(resource).try_lock() {
+                Ok(guard) => return Some(self.held(seat, guard)),
+                Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+                Err(TryLockError::WouldBlock) if give_up() => {
+                    self.monitor.gave_up(seat);
+                    return None;
+                }
+                Err(TryLockError::WouldBlock) => thread::sleep(POLL),
+            }
+        }
+    }
+}
+
+// The left and right chopsticks of `seat`.
+fn neighbours(seat: usize, seats: usize) -> (usize, usize) {
+    (seat, (seat + 1) % seats)
+}
+
+// Everything is picked up in the same order, lower-numbered chopstick first
+// and bowls last, so the last philosopher reaches right before left and no
+// cycle of waits can form.
+pub(crate) struct ResourceOrdering {
+    resources: Resources,
+}
+
+impl ResourceOrdering {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        Self {
+            resources: Resources::new(layout, monitor),
+        }
+    }
+}
+
+impl Strategy for ResourceOrdering {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let mut needs = self.resources.needs(seat);
+        needs.sort();
+        let _held: Vec<_> = needs
+            .into_iter()
+            .map(|resource| self.resources.pick_up(seat, resource))
+            .collect();
+        meal();
+    }
+}
+
+// Only the philosopher talking to the waiter may pick anything up, so no one
+// is ever left holding one chopstick while their neighbour holds the other.
+pub(crate) struct Waiter {
+    waiter: Mutex<()>,
+    resources: Resources,
+}
+
+impl Waiter {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        Self {
+            waiter: Mutex::new(()),
+            resources: Resources::new(layout, monitor),
+        }
+    }
+}
+
+impl Strategy for Waiter {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let _held: Vec<_> = {
+            let _asked = self.waiter.lock().unwrap();
+            let needs = self.resources.needs(seat).into_iter();
+            needs
+                .map(|resource| self.resources.pick_up(seat, resource))
+                .collect()
+        };
+        meal();
+    }
+}
+
+// Everything on the table belongs to a waiter thread, and philosophers send it
+// messages to ask for what they need and to give it back. The waiter hands out
+// all a philosopher needs at once or queues them until it is free, so nothing
+// is shared but the channel and nobody holds half of what they need.
+pub(crate) struct Actor {
+    layout: Layout,
+    requests: mpsc::Sender<Request>,
+    monitor: Arc<Monitor>,
+}
+
+enum Request {
+    // answered once everything the seat needs is theirs
+    PickUp(usize, mpsc::Sender<()>),
+    PutDown(usize),
+}
+
+impl Actor {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        let (requests, inbox) = mpsc::channel();
+        // stops once the strategy, and with it the sender, is dropped
+        thread::spawn(move || serve(layout, inbox));
+        Self {
+            layout,
+            requests,
+            monitor,
+        }
+    }
+}
+
+// The waiter, serving requests in the order they came in, except that anyone
+// whose things are all free goes ahead of those whose things aren't.
+fn serve(layout: Layout, inbox: mpsc::Receiver<Request>) {
+    let mut taken = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    for request in inbox {
+        match request {
+            Request::PickUp(seat, reply) => queue.push_back((seat, reply)),
+            Request::PutDown(seat) => {
+                for resource in layout.needs(seat) {
+                    taken.remove(&resource);
+                }
+            }
+        }
+        queue.retain(|(seat, reply)| {
+            let needs = layout.needs(*seat);
+            if needs.iter().any(|resource| taken.contains(resource)) {
+                return true;
+            }
+            taken.extend(needs);
+            // a philosopher never leaves without their answer
+            reply.send(()).unwrap();
+            false
+        });
+    }
+}
+
+impl Strategy for Actor {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let needs = self.layout.needs(seat);
+        let (reply, answer) = mpsc::channel();
+        // only the waiter knows what is missing; say the first of it
+        self.monitor.waiting(seat, Some(needs[0]));
+        self.requests.send(Request::PickUp(seat, reply)).unwrap();
+        answer.recv().unwrap();
+
+        for &resource in &needs {
+            self.monitor.picked_up(seat, resource);
+        }
+        meal();
+        for &resource in &needs {
+            self.monitor.put_down(seat, resource);
+        }
+        self.requests.send(Request::PutDown(seat)).unwrap();
+    }
+}
+
+// A counting semaphore, as std has none.
+struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+// A permit taken from a semaphore, given back when dropped.
+struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.released.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.permits.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+// Everyone picks up their left chopstick first, as in the naive strategy, but
+// only with one of the `seats - 1` permits the table has. Someone is always
+// left out of the cycle, so at least one of those reaching for chopsticks
+// gets both.
+pub(crate) struct SeatLimit {
+    seats: Semaphore,
+    resources: Resources,
+}
+
+impl SeatLimit {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        // one seat would leave no permits, and the lone philosopher waiting
+        // for one forever
+        assert!(
+            layout.seats >= 2,
+            "a seat limit needs at least two seats, not {}",
+            layout.seats
+        );
+        Self {
+            seats: Semaphore::new(layout.seats - 1),
+            resources: Resources::new(layout, monitor),
+        }
+    }
+}
+
+impl Strategy for SeatLimit {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let needs = self.resources.needs(seat);
+        // waiting for a permit is as good as waiting for the left chopstick
+        self.resources.monitor.waiting(seat, Some(needs[0]));
+        let _permit = self.seats.acquire();
+        let _held: Vec<_> = needs
+            .into_iter()
+            .map(|resource| self.resources.pick_up(seat, resource))
+            .collect();
+        meal();
+    }
+}
+
+// Each chopstick is held by one of the two philosophers sharing it, and is
+// either clean or dirty; eating dirties both. Philosophers keep what they know
+// of their chopsticks to themselves and only send each other messages: a
+// request for a chopstick, or the chopstick itself. A dirty chopstick is
+// cleaned and handed over when asked for, unless its holder is eating, in
+// which case the request waits until they are done; a clean one is kept until
+// its holder has eaten with it. So once someone asks, their neighbour eats at
+// most once more before them, and nobody starves. Starting with every
+// chopstick dirty and held by the lower-numbered of its two philosophers, the
+// waits can't form a cycle. Only works for pairs of neighbours sharing
+// chopsticks, not for sauce bowls.
+pub(crate) struct ChandyMisra {
+    seats: Vec<Seat>,
+    monitor: Arc<Monitor>,
+}
+
+// The chopsticks either side of a philosopher, as indices into `Hands`' arrays.
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+
+// A philosopher's mailbox: messages are handled as they are delivered, as a
+// philosopher answers requests even while thinking.
+struct Seat {
+    hands: Mutex<Hands>,
+    // notified when a chopstick arrives
+    arrived: Condvar,
+}
+
+// What a philosopher knows of their chopsticks, by side.
+#[derive(Default)]
+struct Hands {
+    eating: bool,
+    holding: [bool; 2],
+    dirty: [bool; 2],
+    // the neighbour on that side asked for the chopstick and is waiting for it
+    requested: [bool; 2],
+    // we asked for the chopstick and are waiting for it
+    asked: [bool; 2],
+}
+
+enum Message {
+    Request,
+    Chopstick,
+}
+
+impl ChandyMisra {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        assert_eq!(layout.bowls, 0, "Chandy–Misra can't share sauce bowls");
+        let seats = layout.seats;
+        let holder = |c: usize| c.min((c + seats - 1) % seats);
+        let seats = (0..seats)
+            .map(|seat| {
+                let (left, right) = neighbours(seat, seats);
+                Seat {
+                    hands: Mutex::new(Hands {
+                        holding: [holder(left) == seat, holder(right) == seat],
+                        dirty: [true; 2],
+                        ..Hands::default()
+                    }),
+                    arrived: Condvar::new(),
+                }
+            })
+            .collect();
+        Self { seats, monitor }
+    }
+
+    // The neighbour on `side` of `seat`, and which side of them `seat` is.
+    fn neighbour(&self, seat: usize, side: usize) -> (usize, usize) {
+        let seats = self.seats.len();
+        match side {
+            LEFT => ((seat + seats - 1) % seats, RIGHT),
+            _ => ((seat + 1) % seats, LEFT),
+        }
+    }
+
+    // Delivers `message` about the chopstick on `side` of `to`, from the
+    // neighbour on that side.
+    fn send(&self, to: usize, side: usize, message: Message) {
+        let seat = &self.seats[to];
+        let mut hands = seat.hands.lock().unwrap();
+        match message {
+            Message::Chopstick => {
+                hands.holding[side] = true;
+                hands.dirty[side] = false;
+                hands.asked[side] = false;
+                drop(hands);
+                seat.arrived.notify_one();
+            }
+            Message::Request if hands.holding[side] && hands.dirty[side] && !hands.eating => {
+                hands.holding[side] = false;
+                drop(hands);
+                let (from, their_side) = self.neighbour(to, side);
+                self.send(from, their_side, Message::Chopstick);
+            }
+            Message::Request => hands.requested[side] = true,
+        }
+    }
+}
+
+impl Strategy for ChandyMisra {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let (left, right) = neighbours(seat, self.seats.len());
+        let chopsticks = [left, right].map(Resource::Chopstick);
+        let me = &self.seats[seat];
+
+        let mut hands = me.hands.lock().unwrap();
+        loop {
+            let missing = [LEFT, RIGHT].into_iter().find(|&side| !hands.holding[side]);
+            self.monitor
+                .waiting(seat, missing.map(|side| chopsticks[side]));
+            if missing.is_none() {
+                break;
+            }
+            let ask: Vec<_> = [LEFT, RIGHT]
+                .into_iter()
+                .filter(|&side| !hands.holding[side] && !hands.asked[side])
+                .collect();
+            if ask.is_empty() {
+                hands = me.arrived.wait(hands).unwrap();
+                continue;
+            }
+            // never hold our lock while messaging a neighbour, who may be
+            // messaging us
+            for &side in &ask {
+                hands.asked[side] = true;
+            }
+            drop(hands);
+            for side in ask {
+                let (to, their_side) = self.neighbour(seat, side);
+                self.send(to, their_side, Message::Request);
+            }
+            hands = me.hands.lock().unwrap();
+        }
+        hands.eating = true;
+        drop(hands);
+
+        for chopstick in chopsticks {
+            self.monitor.picked_up(seat, chopstick);
+        }
+        meal();
+        for chopstick in chopsticks {
+            self.monitor.put_down(seat, chopstick);
+        }
+
+        let mut hands = me.hands.lock().unwrap();
+        hands.eating = false;
+        hands.dirty = [true; 2];
+        let give: Vec<_> = [LEFT, RIGHT]
+            .into_iter()
+            .filter(|&side| hands.requested[side])
+            .collect();
+        for &side in &give {
+            hands.requested[side] = false;
+            hands.holding[side] = false;
+        }
+        drop(hands);
+        for side in give {
+            let (to, their_side) = self.neighbour(seat, side);
+            self.send(to, their_side, Message::Chopstick);
+        }
+    }
+}
+
+// The classic monitor solution: a single lock over what everyone is doing, and
+// a philosopher only starts eating when none of their rivals, who need some of
+// the same things, is eating; without bowls those are just the two
+// neighbours. Whoever finishes checks whether a hungry rival can now eat, and
+// if so lets them and wakes them up. Nothing stops two rivals from taking
+// turns to starve a third.
+pub(crate) struct CondvarTable {
+    needs: Vec<Vec<Resource>>,
+    rivals: Vec<Vec<usize>>,
+    states: Mutex<Vec<State>>,
+    // one per seat, to wake exactly the philosopher whose turn it is
+    turns: Vec<Condvar>,
+    monitor: Arc<Monitor>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Thinking,
+    Hungry,
+    Eating,
+}
+
+impl CondvarTable {
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        let seats = layout.seats;
+        let needs: Vec<_> = (0..seats).map(|seat| layout.needs(seat)).collect();
+        let rivals = (0..seats)
+            .map(|seat| {
+                let shares = |other: &usize| needs[*other].iter().any(|r| needs[seat].contains(r));
+                (0..seats)
+                    .filter(|&other| other != seat)
+                    .filter(shares)
+                    .collect()
+            })
+            .collect();
+        Self {
+            needs,
+            rivals,
+            states: Mutex::new(vec![State::Thinking; seats]),
+            turns: (0..seats).map(|_| Condvar::new()).collect(),
+            monitor,
+        }
+    }
+
+    // Lets the philosopher in `seat` eat if they are hungry and none of their
+    // rivals is eating.
+    fn try_serve(&self, states: &mut [State], seat: usize) -> bool {
+        let free = self.rivals[seat]
+            .iter()
+            .all(|&r| states[r] != State::Eating);
+        if states[seat] == State::Hungry && free {
+            states[seat] = State::Eating;
+        }
+        states[seat] == State::Eating
+    }
+}
+
+impl Strategy for CondvarTable {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let needs = &self.needs[seat];
+        let mut states = self.states.lock().unwrap();
+        states[seat] = State::Hungry;
+        while !self.try_serve(&mut states, seat) {
+            let eating = self.rivals[seat]
+                .iter()
+                .filter(|&&r| states[r] == State::Eating);
+            let busy = eating
+                .flat_map(|&r| &self.needs[r])
+                .find(|r| needs.contains(r));
+            self.monitor.waiting(seat, busy.copied());
+            states = self.turns[seat].wait(states).unwrap();
+        }
+        drop(states);
+
+        for &resource in needs {
+            self.monitor.picked_up(seat, resource);
+        }
+        meal();
+        for &resource in needs {
+            self.monitor.put_down(seat, resource);
+        }
+
+        let mut states = self.states.lock().unwrap();
+        states[seat] = State::Thinking;
+        for &rival in &self.rivals[seat] {
+            if self.try_serve(&mut states, rival) {
+                self.turns[rival].notify_one();
+            }
+        }
+    }
+}
+
+// Philosophers pick up their left chopstick, and if the rest doesn't come free
+// within `PATIENCE`, put everything down again and wait a random while, drawn
+// from the dinner's seed, before retrying. The randomness keeps neighbours from
+// retrying in lockstep.
+pub(crate) struct Backoff {
+    resources: Resources,
+    // by seat
+    rngs: Vec<Mutex<Rng>>,
+}
+
+impl Backoff {
+    const PATIENCE: Duration = Duration::from_millis(2);
+    // the longest wait before the first retry, doubling with each one after
+    const FIRST_BACKOFF: Duration = Duration::from_millis(1);
+    const MAX_DOUBLINGS: u32 = 5;
+
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>, seed: u64) -> Self {
+        // seats past the table's, so as not to repeat the draws of the
+        // philosophers' meal and thinking times
+        let rngs = (0..layout.seats)
+            .map(|seat| Mutex::new(Rng::for_seat(seed, layout.seats + seat)))
+            .collect();
+        Self {
+            resources: Resources::new(layout, monitor),
+            rngs,
+        }
+    }
+}
+
+impl Strategy for Backoff {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let needs = self.resources.needs(seat);
+        for attempt in 0.. {
+            {
+                let _left = self.resources.pick_up(seat, needs[0]);
+                let deadline = Instant::now() + Self::PATIENCE;
+                let rest: Option<Vec<_>> = needs[1..]
+                    .iter()
+                    .map(|&r| {
+                        self.resources
+                            .try_pick_up(seat, r, || Instant::now() >= deadline)
+                    })
+                    .collect();
+                if let Some(_rest) = rest {
+                    meal();
+                    return;
+                }
+            }
+            let doublings = attempt.min(Self::MAX_DOUBLINGS);
+            let max = Self::FIRST_BACKOFF * 2_u32.pow(doublings);
+            let wait = max.mul_f64(self.rngs[seat].lock().unwrap().next_f64());
+            thread::sleep(wait);
+        }
+    }
+}
+
+// Everyone picks up their left chopstick, then their right one (then their
+// bowl). Once they all hold their left one at the same time, nobody ever gets
+// a right one: this deadlocks, for the watchdog to catch. Philosophers the
+// watchdog preempts put everything down again and start over.
+pub(crate) struct Naive {
+    resources: Resources,
+}
+
+impl Naive {
+    const STEP_ASIDE: Duration = Duration::from_millis(1);
+
+    pub(crate) fn new(layout: Layout, monitor: Arc<Monitor>) -> Self {
+        Self {
+            resources: Resources::new(layout, monitor),
+        }
+    }
+}
+
+impl Strategy for Naive {
+    fn dine(&self, seat: usize, meal: &mut dyn FnMut()) {
+        let needs = self.resources.needs(seat);
+        let monitor = &self.resources.monitor;
+        loop {
+            let left = self.resources.pick_up(seat, needs[0]);
+            let rest: Option<Vec<_>> = needs[1..]
+                .iter()
+                .map(|&r| {
+                    self.resources
+                        .try_pick_up(seat, r, || monitor.take_preempted(seat))
+                })
+                .collect();
+            if let Some(_rest) = rest {
+                meal();
+                return;
+            }
+            drop(left);
+            // long enough for the neighbour waiting on it to pick it up
+            thread::sleep(Self::STEP_ASIDE);
+        }
+    }
+}
+
+// These run the strategies on plain threads, which loom's locks don't allow.
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use super::*;
+    use crate::monitor::{self, OnDeadlock};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[test]
+    #[should_panic(expected = "at least two seats")]
+    fn seat_limit_needs_two_seats() {
+        let layout = Layout { seats: 1, bowls: 0 };
+        Kind::SeatLimit.build(layout, Arc::new(Monitor::new(1)), 0);
+    }
+
+    #[test]
+    fn neighbours_never_eat_together() {
+        const SEATS: usize = 5;
+        const ROUNDS: usize = 50;
+
+        let kinds = Kind::value_variants().iter();
+        for kind in kinds.filter(|kind| !matches!(kind, Kind::Naive)) {
+            let layout = Layout {
+                seats: SEATS,
+                bowls: 0,
+            };
+            let strategy = kind.build(layout, Arc::new(Monitor::new(SEATS)), 0);
+            let eating: Arc<Vec<_>> =
+                Arc::new((0..SEATS).map(|_| AtomicBool::new(false)).collect());
+            let meals = Arc::new(AtomicUsize::new(0));
+
+            let threads: Vec<_> = (0..SEATS)
+                .map(|seat| {
+                    let (strategy, eating, meals) =
+                        (strategy.clone(), eating.clone(), meals.clone());
+                    thread::spawn(move || {
+                        for _ in 0..ROUNDS {
+                            strategy.dine(seat, &mut || {
+                                let (left, right) =
+                                    ((seat + SEATS - 1) % SEATS, (seat + 1) % SEATS);
+                                eating[seat].store(true, Ordering::SeqCst);
+                                assert!(!eating[left].load(Ordering::SeqCst), "{kind:?}");
+                                assert!(!eating[right].load(Ordering::SeqCst), "{kind:?}");
+                                thread::yield_now();
+                                eating[seat].store(false, Ordering::SeqCst);
+                                meals.fetch_add(1, Ordering::Relaxed);
+                            });
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+            assert_eq!(meals.load(Ordering::Relaxed), SEATS * ROUNDS, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn one_sauce_bowl_feeds_one_at_a_time() {
+        const SEATS: usize = 5;
+        const ROUNDS: usize = 20;
+        let layout = Layout {
+            seats: SEATS,
+            bowls: 1,
+        };
+
+        let kinds = Kind::value_variants().iter();
+        for kind in kinds.filter(|kind| kind.handles_bowls() && !matches!(kind, Kind::Naive)) {
+            let strategy = kind.build(layout, Arc::new(Monitor::new(SEATS)), 0);
+            let eaters = Arc::new(AtomicUsize::new(0));
+
+            let threads: Vec<_> = (0..SEATS)
+                .map(|seat| {
+                    let (strategy, eaters) = (strategy.clone(), eaters.clone());
+                    thread::spawn(move || {
+                        for _ in 0..ROUNDS {
+                            strategy.dine(seat, &mut || {
+                                let before = eaters.fetch_add(1, Ordering::SeqCst);
+                                assert_eq!(before, 0, "{kind:?}");
+                                thread::yield_now();
+                                eaters.fetch_sub(1, Ordering::SeqCst);
+                            });
+                        }
+                    })
+                })
+                .collect();
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn chandy_misra_bounds_the_wait() {
+        const SEATS: usize = 3;
+        const ROUNDS: usize = 100;
+        let layout = Layout {
+            seats: SEATS,
+            bowls: 0,
+        };
+        let strategy = Arc::new(ChandyMisra::new(layout, Arc::new(Monitor::new(SEATS))));
+        // meals seat 0 started while seat 1 was asking for their chopstick
+        let meals_while_asked = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+
+        // seat 0 eats again as soon as they can, and would starve seat 1 if
+        // they never handed over the chopstick they share
+        let greedy = {
+            let (strategy, meals_while_asked, done) =
+                (strategy.clone(), meals_while_asked.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    strategy.dine(0, &mut || {
+                        let hands = strategy.seats[0].hands.lock().unwrap();
+                        if hands.requested[RIGHT] {
+                            let meals = meals_while_asked.fetch_add(1, Ordering::SeqCst) + 1;
+                            assert!(meals <= 1, "seat 1 waited {meals} of seat 0's meals");
+                        }
+                    });
+                }
+            })
+        };
+        for _ in 0..ROUNDS {
+            strategy.dine(1, &mut || meals_while_asked.store(0, Ordering::SeqCst));
+        }
+        done.store(true, Ordering::SeqCst);
+        greedy.join().unwrap();
+    }
+
+    #[test]
+    fn the_watchdog_breaks_deadlocks() {
+        const SEATS: usize = 3;
+        let monitor = Arc::new(Monitor::new(SEATS));
+        let layout = Layout {
+            seats: SEATS,
+            bowls: 0,
+        };
+        let strategy = Kind::Naive.build(layout, monitor.clone(), 0);
+        let _watchdog = monitor::watch(
+            monitor.clone(),
+            Duration::from_millis(20),
+            OnDeadlock::Recover,
+        );
+
+        let threads: Vec<_> = (0..SEATS)
+            .map(|seat| {
+                let (strategy, monitor) = (strategy.clone(), monitor.clone());
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        strategy.dine(seat, &mut || {
+                            monitor.eating(seat, true);
+                            thread::yield_now();
+                            monitor.eating(seat, false);
+                        });
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::atomic::{AtomicBool, Ordering};
+
+    fn dine_once(strategy: &dyn Strategy, eating: &[AtomicBool], seat: usize) {
+        let seats = eating.len();
+        strategy.dine(seat, &mut || {
+            eating[seat].store(true, Ordering::SeqCst);
+            assert!(!eating[(seat + seats - 1) % seats].load(Ordering::SeqCst));
+            assert!(!eating[(seat + 1) % seats].load(Ordering::SeqCst));
+            eating[seat].store(false, Ordering::SeqCst);
+        });
+    }
+
+    // Three philosophers eating once, with a thread each.
+    fn dinner(kind: Kind) -> impl Fn() + Send + Sync + 'static {
+        const SEATS: usize = 3;
+        move || {
+            let layout = Layout {
+                seats: SEATS,
+                bowls: 0,
+            };
+            let strategy = kind.build(layout, Arc::new(Monitor::new(SEATS)), 0);
+            let eating: Arc<Vec<_>> =
+                Arc::new((0..SEATS).map(|_| AtomicBool::new(false)).collect());
+            let threads: Vec<_> = (1..SEATS)
+                .map(|seat| {
+                    let (strategy, eating) = (strategy.clone(), eating.clone());
+                    loom::thread::spawn(move || dine_once(&*strategy, &eating, seat))
+                })
+                .collect();
+            dine_once(&*strategy, &eating, 0);
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    // The dinner in every interleaving loom can find: no two neighbours eat
+    // together, and nobody is left waiting forever, which loom reports as a
+    // deadlock whether it comes from a cycle of locks or a missed wakeup.
+    // Backoff and naive are left out, as they poll and sleep, and so is the
+    // actor, whose channels loom doesn't model.
+    #[test]
+    fn no_deadlock_or_missed_wakeup() {
+        for kind in [Kind::Ordering, Kind::Waiter, Kind::Condvar] {
+            loom::model(dinner(kind));
+        }
+    }
+
+    // The semaphore's lock on top of the chopsticks', and the messages
+    // Chandy–Misra's philosophers pass, make for too many interleavings to try
+    // them all, so only those with a few preemptions each are.
+    #[test]
+    fn seat_limit_and_chandy_misra_never_deadlock() {
+        for kind in [Kind::SeatLimit, Kind::ChandyMisra] {
+            let mut builder = loom::model::Builder::new();
+            builder.preemption_bound = Some(3);
+            builder.check(dinner(kind));
+        }
+    }
+}
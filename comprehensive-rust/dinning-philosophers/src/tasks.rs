@@ -0,0 +1,130 @@
+use crate::monitor::Monitor;
+use crate::strategy::{Chopstick, Resource};
+use crate::{name, Config, Distribution, Rng, Stop};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time;
+
+// The same philosophers as the threaded ones, as tasks that await their
+// chopsticks instead of blocking a thread on them.
+struct Philosopher {
+    name: String,
+    seat: usize,
+    monitor: Arc<Monitor>,
+    // the two below, lower-numbered first
+    chopsticks: (Resource, Resource),
+    left_chopstick: Arc<Mutex<Chopstick>>,
+    right_chopstick: Arc<Mutex<Chopstick>>,
+    thoughts: mpsc::Sender<String>,
+    eat_time: Duration,
+    think_time: Duration,
+    distribution: Distribution,
+    // meals after every thought
+    priority: usize,
+    rng: Rng,
+    stop: Stop,
+}
+
+impl Philosopher {
+    async fn think(&mut self) {
+        self.monitor.thinking(self.seat);
+        time::sleep(self.rng.draw(self.think_time, self.distribution)).await;
+        self.thoughts
+            .send(format!("Eureka! {} has a new idea!", &self.name))
+            .await
+            .unwrap();
+    }
+
+    async fn eat(&mut self) {
+        let eat_time = self.rng.draw(self.eat_time, self.distribution);
+        self.monitor.hungry(self.seat);
+        let (first, second) = self.chopsticks;
+        let _l_ch = self.left_chopstick.lock().await;
+        self.monitor.picked_up(self.seat, first);
+        let _r_ch = self.right_chopstick.lock().await;
+        self.monitor.picked_up(self.seat, second);
+        self.monitor.eating(self.seat, true);
+        time::sleep(eat_time).await;
+        self.monitor.eating(self.seat, false);
+        self.monitor.put_down(self.seat, second);
+        self.monitor.put_down(self.seat, first);
+    }
+}
+
+// Runs the dinner on a tokio runtime, returning everyone's thoughts. Only
+// resource ordering is implemented: everyone picks up their lower-numbered
+// chopstick first. There is no watchdog, nobody ever gives up on a chopstick,
+// and there are no sauce bowls.
+pub(crate) fn run(config: &Config, monitor: Arc<Monitor>, stop: &Stop) -> Vec<String> {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(dine(config, monitor, stop))
+}
+
+async fn dine(config: &Config, monitor: Arc<Monitor>, stop: &Stop) -> Vec<String> {
+    let (tx, mut rx) = mpsc::channel(config.philosophers);
+    let chopsticks: Vec<_> = (0..config.philosophers)
+        .map(|_| Arc::new(Mutex::new(Chopstick)))
+        .collect();
+
+    for i in 0..config.philosophers {
+        let next = (i + 1) % config.philosophers;
+        let mut philosopher = Philosopher {
+            name: name(i),
+            seat: i,
+            monitor: monitor.clone(),
+            chopsticks: (
+                Resource::Chopstick(i.min(next)),
+                Resource::Chopstick(i.max(next)),
+            ),
+            left_chopstick: chopsticks[i.min(next)].clone(),
+            right_chopstick: chopsticks[i.max(next)].clone(),
+            thoughts: tx.clone(),
+            eat_time: config.eat_time(i),
+            think_time: config.think_time(i),
+            distribution: config.distribution,
+            priority: config.priority(i),
+            rng: Rng::for_seat(config.seed, i),
+            stop: stop.clone(),
+        };
+        let rounds = config.rounds;
+        tokio::spawn(async move {
+            'dinner: for _ in 0..rounds {
+                philosopher.think().await;
+                for _ in 0..philosopher.priority {
+                    if philosopher.stop.is_stopped() {
+                        break 'dinner;
+                    }
+                    philosopher.eat().await;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut thoughts = Vec::new();
+    while let Some(thought) = rx.recv().await {
+        thoughts.push(thought);
+    }
+    thoughts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everyone_gets_to_eat() {
+        let config = Config {
+            philosophers: 7,
+            eat_time: Duration::ZERO,
+            ..Config::default()
+        };
+        let monitor = Arc::new(Monitor::new(7));
+        assert_eq!(
+            run(&config, monitor.clone(), &Stop::default()).len(),
+            7 * 100
+        );
+        assert!(monitor.fared().iter().all(|fared| fared.meals == 100));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::monitor::Monitor;
+use crate::strategy::Strategy;
+use crate::{name, Config, Distribution, Rng, Stop};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Philosopher {
+    name: String,
+    seat: usize,
+    table: Arc<dyn Strategy>,
+    monitor: Arc<Monitor>,
+    thoughts: mpsc::Sender<String>,
+    eat_time: Duration,
+    think_time: Duration,
+    distribution: Distribution,
+    // meals after every thought
+    priority: usize,
+    rng: Rng,
+    stop: Stop,
+}
+
+impl Philosopher {
+    fn think(&mut self) {
+        self.monitor.thinking(self.seat);
+        thread::sleep(self.rng.draw(self.think_time, self.distribution));
+        self.thoughts
+            .send(format!("Eureka! {} has a new idea!", &self.name))
+            .unwrap();
+    }
+
+    fn eat(&mut self) {
+        let eat_time = self.rng.draw(self.eat_time, self.distribution);
+        self.monitor.hungry(self.seat);
+        self.table.dine(self.seat, &mut || {
+            self.monitor.eating(self.seat, true);
+            thread::sleep(eat_time);
+            self.monitor.eating(self.seat, false);
+        });
+    }
+}
+
+// Runs the dinner at `table` with a thread per philosopher, returning
+// everyone's thoughts. Philosophers leave early once `stop` is, after
+// finishing whatever they are eating; if the watchdog finds them deadlocked,
+// this returns without them.
+pub(crate) fn run(
+    config: &Config,
+    table: Arc<dyn Strategy>,
+    monitor: Arc<Monitor>,
+    stop: &Stop,
+) -> Vec<String> {
+    let (tx, rx) = mpsc::channel();
+
+    // Create philosophers
+    let philosophers: Vec<_> = (0..config.philosophers)
+        .map(|i| Philosopher {
+            name: name(i),
+            seat: i,
+            table: table.clone(),
+            monitor: monitor.clone(),
+            thoughts: tx.clone(),
+            eat_time: config.eat_time(i),
+            think_time: config.think_time(i),
+            distribution: config.distribution,
+            priority: config.priority(i),
+            rng: Rng::for_seat(config.seed, i),
+            stop: stop.clone(),
+        })
+        .collect();
+
+    // Make each of them think and eat `rounds` times
+    let rounds = config.rounds;
+    for mut philosopher in philosophers {
+        thread::spawn(move || {
+            'dinner: for _ in 0..rounds {
+                philosopher.think();
+                for _ in 0..philosopher.priority {
+                    if philosopher.stop.is_stopped() {
+                        break 'dinner;
+                    }
+                    philosopher.eat();
+                }
+            }
+        });
+    }
+
+    // Output their thoughts
+    const POLL: Duration = Duration::from_millis(10);
+    drop(tx);
+    let mut thoughts = Vec::new();
+    loop {
+        match rx.recv_timeout(POLL) {
+            Ok(thought) => thoughts.push(thought),
+            Err(RecvTimeoutError::Timeout) if monitor.deadlocked() => break,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    thoughts
+}
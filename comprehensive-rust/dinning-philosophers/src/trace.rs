@@ -0,0 +1,106 @@
+use crate::name;
+use crate::strategy::Resource;
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A file of what every philosopher did and when, one JSON object per line, for
+/// analysing or visualising a run afterwards.
+pub struct Trace {
+    file: Mutex<BufWriter<File>>,
+    start: Instant,
+}
+
+impl Trace {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(File::create(path)?)),
+            start: Instant::now(),
+        })
+    }
+
+    // Records that the philosopher in `seat` did `event` (think, hungry,
+    // pickup-left, pickup-right, pickup-bowl, eat or putdown), with the
+    // chopstick or sauce bowl involved if any.
+    pub(crate) fn record(&self, seat: usize, event: &str, resource: Option<Resource>) {
+        let time_us = self.start.elapsed().as_micros() as u64;
+        let mut record = json!({
+            "time_us": time_us,
+            "philosopher": name(seat),
+            "seat": seat,
+            "event": event,
+        });
+        match resource {
+            Some(Resource::Chopstick(c)) => record["chopstick"] = c.into(),
+            Some(Resource::SauceBowl(b)) => record["sauce_bowl"] = b.into(),
+            None => {}
+        }
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{record}") {
+            eprintln!("could not write to the trace: {e}");
+        }
+    }
+
+    pub(crate) fn flush(&self) {
+        if let Err(e) = self.file.lock().unwrap().flush() {
+            eprintln!("could not write to the trace: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::Monitor;
+    use serde_json::Value;
+    use std::fs;
+
+    #[test]
+    fn records_a_meal() {
+        let path = std::env::temp_dir().join(format!("philosophers-{}.json", std::process::id()));
+        let monitor = Monitor::new(2).with_trace(Trace::create(&path).unwrap());
+        monitor.thinking(1);
+        monitor.hungry(1);
+        monitor.picked_up(1, Resource::Chopstick(0));
+        monitor.picked_up(1, Resource::Chopstick(1));
+        monitor.picked_up(1, Resource::SauceBowl(0));
+        monitor.eating(1, true);
+        monitor.eating(1, false);
+        monitor.put_down(1, Resource::SauceBowl(0));
+        monitor.flush();
+
+        let trace = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let records: Vec<Value> = trace
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let events: Vec<_> = records
+            .iter()
+            .map(|r| r["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "think",
+                "hungry",
+                "pickup-right",
+                "pickup-left",
+                "pickup-bowl",
+                "eat",
+                "putdown"
+            ]
+        );
+        assert_eq!(records[2]["philosopher"], "Hypatia");
+        assert_eq!(records[2]["chopstick"], 0);
+        assert!(records[0].get("chopstick").is_none());
+        assert_eq!(records[6]["sauce_bowl"], 0);
+        assert!(records[6].get("chopstick").is_none());
+        assert!(records
+            .windows(2)
+            .all(|w| w[0]["time_us"].as_u64() <= w[1]["time_us"].as_u64()));
+    }
+}
@@ -0,0 +1,175 @@
+use dinning_philosophers::monitor::Event;
+use dinning_philosophers::name;
+use dinning_philosophers::strategy::Resource;
+use ratatui::crossterm::event::{self, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table as Grid};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::sync::mpsc;
+use std::time::Duration;
+
+const FRAME: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Thinking,
+    Hungry,
+    Eating,
+}
+
+// The table as the events describe it.
+struct Table {
+    states: Vec<State>,
+    meals: Vec<usize>,
+    // who holds each chopstick
+    holders: Vec<Option<usize>>,
+}
+
+impl Table {
+    fn new(seats: usize) -> Self {
+        Self {
+            states: vec![State::Thinking; seats],
+            meals: vec![0; seats],
+            holders: vec![None; seats],
+        }
+    }
+
+    fn apply(&mut self, event: Event) {
+        let chopstick = match event.resource {
+            Some(Resource::Chopstick(c)) => Some(c),
+            _ => None,
+        };
+        match (event.what, chopstick) {
+            ("think", _) => self.states[event.seat] = State::Thinking,
+            ("hungry", _) => self.states[event.seat] = State::Hungry,
+            ("eat", _) => {
+                self.states[event.seat] = State::Eating;
+                self.meals[event.seat] += 1;
+            }
+            ("pickup-left" | "pickup-right", Some(c)) => self.holders[c] = Some(event.seat),
+            ("putdown", Some(c)) => self.holders[c] = None,
+            _ => {}
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, done: bool) {
+        let [grid, chopsticks, help] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let rows = self.states.iter().enumerate().map(|(seat, state)| {
+            let (label, style) = match state {
+                State::Thinking => ("thinking", Style::new()),
+                State::Hungry => ("hungry", Style::new().yellow()),
+                State::Eating => ("eating", Style::new().green().bold()),
+            };
+            let holding = self.holders.iter().enumerate();
+            let holding: Vec<_> = holding
+                .filter(|(_, holder)| **holder == Some(seat))
+                .map(|(c, _)| c.to_string())
+                .collect();
+            Row::new([
+                name(seat),
+                label.to_string(),
+                self.meals[seat].to_string(),
+                holding.join(", "),
+            ])
+            .style(style)
+        });
+        let widths = [
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Fill(1),
+        ];
+        let header = Row::new(["Philosopher", "", "Meals", "Holding"]).bold();
+        let table = Grid::new(rows, widths)
+            .header(header)
+            .block(Block::bordered().title("Dinner"));
+        frame.render_widget(table, grid);
+
+        let held = self
+            .holders
+            .iter()
+            .filter(|holder| holder.is_some())
+            .count();
+        let summary = format!("{held} of {} chopsticks in use", self.holders.len());
+        let chopsticks_pane = Paragraph::new(summary).block(Block::bordered());
+        frame.render_widget(chopsticks_pane, chopsticks);
+
+        let hint = if done {
+            "Dinner's over. Press q to see the report."
+        } else {
+            "Press q to stop watching."
+        };
+        frame.render_widget(Line::from(hint.italic()), help);
+    }
+}
+
+// Shows `events` as they come in until the user quits. `done` says whether
+// the dinner is over.
+pub(crate) fn run(
+    events: mpsc::Receiver<Event>,
+    seats: usize,
+    done: impl Fn() -> bool,
+) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let res = show(&mut terminal, Table::new(seats), events, done);
+    ratatui::restore();
+    res
+}
+
+fn show(
+    terminal: &mut DefaultTerminal,
+    mut table: Table,
+    events: mpsc::Receiver<Event>,
+    done: impl Fn() -> bool,
+) -> io::Result<()> {
+    loop {
+        while let Ok(event) = events.try_recv() {
+            table.apply(event);
+        }
+        let done = done();
+        terminal.draw(|frame| table.draw(frame, done))?;
+
+        if !event::poll(FRAME)? {
+            continue;
+        }
+        if let event::Event::Key(key) = event::read()? {
+            let quit = matches!(key.code, KeyCode::Char('q') | KeyCode::Esc);
+            if key.kind == KeyEventKind::Press && quit {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_events() {
+        let mut table = Table::new(3);
+        let event = |seat, what, chopstick: Option<usize>| Event {
+            seat,
+            what,
+            resource: chopstick.map(Resource::Chopstick),
+        };
+        table.apply(event(1, "hungry", None));
+        table.apply(event(1, "pickup-left", Some(1)));
+        table.apply(event(1, "pickup-right", Some(2)));
+        table.apply(event(1, "eat", None));
+        table.apply(event(1, "putdown", Some(2)));
+
+        assert!(table.states[1] == State::Eating);
+        assert_eq!(table.meals, [0, 1, 0]);
+        assert_eq!(table.holders, [None, Some(1), None]);
+    }
+}